@@ -0,0 +1,160 @@
+use lfj::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use lfj::data::{Data, ImdbData};
+use std::hint::black_box;
+use std::time::Instant;
+
+const RUNS: usize = 20;
+
+/// Median wall time of `f` over `RUNS` runs, in seconds.
+fn median_secs<T>(mut f: impl FnMut() -> T) -> f32 {
+    let mut samples: Vec<f32> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(f());
+            start.elapsed().as_secs_f32()
+        })
+        .collect();
+    samples.sort_by(f32::total_cmp);
+    samples[RUNS / 2]
+}
+
+// Compares the `aggregate` accumulators with the hand-written `MIN`s they replaced in q1a-d,
+// q14a-c and q33a. Each variant takes the `MIN`s of the columns those queries select, fed with
+// every row of the tables they come from, zipped row by row.
+fn main() {
+    let db = ImdbData::new();
+    let data = Data::new(&db);
+    let t = &data.t;
+    let mc = &data.mc;
+    let mi_idx = &data.mi_idx;
+    let cn = &data.cn;
+
+    println!("queries,hand_written,generic");
+
+    // MIN(mc.note), MIN(t.title), MIN(t.production_year)
+    let rows = || {
+        mc.note
+            .iter()
+            .zip(t.title.iter())
+            .zip(t.production_year.iter())
+    };
+    let hand_written = || {
+        let mut res: Option<(&str, &str, &i32)> = None;
+        for ((note, title), production_year) in rows() {
+            if let Some(note) = note
+                && let Some(production_year) = production_year
+            {
+                res = match res {
+                    Some((old_note, old_title, old_production_year)) => Some((
+                        note.as_str().min(old_note),
+                        title.as_str().min(old_title),
+                        production_year.min(old_production_year),
+                    )),
+                    None => Some((note.as_str(), title.as_str(), production_year)),
+                };
+            }
+        }
+        res
+    };
+    let generic = || {
+        let mut res = <(Min<&str>, Min<&str>, Min<&i32>)>::default();
+        for ((note, title), production_year) in rows() {
+            if let Some(note) = note
+                && let Some(production_year) = production_year
+            {
+                res.update((note.as_str(), title.as_str(), production_year));
+            }
+        }
+        res.finish().transpose()
+    };
+    assert_eq!(hand_written(), generic());
+    println!(
+        "1a-d,{:},{:}",
+        median_secs(hand_written),
+        median_secs(generic)
+    );
+
+    // MIN(mi_idx.info), MIN(t.title)
+    let rows = || mi_idx.info.iter().zip(t.title.iter());
+    let hand_written = || {
+        let mut res: Option<(&str, &str)> = None;
+        for (info, title) in rows() {
+            res = match res {
+                Some((old_info, old_title)) => {
+                    Some((info.as_str().min(old_info), title.as_str().min(old_title)))
+                }
+                None => Some((info.as_str(), title.as_str())),
+            };
+        }
+        res
+    };
+    let generic = || {
+        let mut res = <(Min<&str>, Min<&str>)>::default();
+        for (info, title) in rows() {
+            res.update((info.as_str(), title.as_str()));
+        }
+        res.finish().transpose()
+    };
+    assert_eq!(hand_written(), generic());
+    println!(
+        "14a-c,{:},{:}",
+        median_secs(hand_written),
+        median_secs(generic)
+    );
+
+    // MIN(cn1.name), MIN(cn2.name), MIN(mi_idx1.info), MIN(mi_idx2.info), MIN(t1.title),
+    // MIN(t2.title), with the second of each pair read backwards
+    let rows = || {
+        cn.name
+            .iter()
+            .zip(cn.name.iter().rev())
+            .zip(mi_idx.info.iter().zip(mi_idx.info.iter().rev()))
+            .zip(t.title.iter().zip(t.title.iter().rev()))
+    };
+    let hand_written = || {
+        let mut res: Option<(&str, &str, &str, &str, &str, &str)> = None;
+        for ((names, infos), titles) in rows() {
+            let [n1, n2, r1, r2, t1, t2] =
+                [names.0, names.1, infos.0, infos.1, titles.0, titles.1].map(String::as_str);
+            res = match res {
+                Some((old_n1, old_n2, old_r1, old_r2, old_t1, old_t2)) => Some((
+                    old_n1.min(n1),
+                    old_n2.min(n2),
+                    old_r1.min(r1),
+                    old_r2.min(r2),
+                    old_t1.min(t1),
+                    old_t2.min(t2),
+                )),
+                None => Some((n1, n2, r1, r2, t1, t2)),
+            };
+        }
+        res
+    };
+    let generic = || {
+        let mut res = <(
+            Min<&str>,
+            Min<&str>,
+            Min<&str>,
+            Min<&str>,
+            Min<&str>,
+            Min<&str>,
+        )>::default();
+        for ((names, infos), titles) in rows() {
+            res.update((
+                names.0.as_str(),
+                names.1.as_str(),
+                infos.0.as_str(),
+                infos.1.as_str(),
+                titles.0.as_str(),
+                titles.1.as_str(),
+            ));
+        }
+        res.finish().transpose()
+    };
+    assert_eq!(hand_written(), generic());
+    println!(
+        "33a,{:},{:}",
+        median_secs(hand_written),
+        median_secs(generic)
+    );
+}
@@ -0,0 +1,113 @@
+use lfj::data::{Data, ImdbData};
+use lfj::dense::{DenseMap, DenseSet};
+use std::hint::black_box;
+use std::time::Instant;
+
+const RUNS: usize = 20;
+
+/// Median wall time of `f` over `RUNS` runs, in seconds.
+fn median_secs<T>(mut f: impl FnMut() -> T) -> f32 {
+    let mut samples: Vec<f32> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(f());
+            start.elapsed().as_secs_f32()
+        })
+        .collect();
+    samples.sort_by(f32::total_cmp);
+    samples[RUNS / 2]
+}
+
+// Compares `ahash` maps and sets with `DenseMap` and `DenseSet` on the primary-key lookups that
+// recur across JOB: building a map or set over a dimension table's ids, then probing it with a
+// fact table's foreign keys. Each variant counts the probes that hit.
+fn main() {
+    let db = ImdbData::new();
+    let data = Data::new(&db);
+    let t = &data.t;
+    let n = &data.n;
+    let k = &data.k;
+    let ci = &data.ci;
+    let mk = &data.mk;
+
+    println!("lookup,hash,dense");
+
+    let hash = || {
+        let t_m: ahash::HashMap<&i32, &str> =
+            t.id.iter()
+                .zip(t.title.iter())
+                .zip(t.production_year.iter())
+                .filter(|(_, production_year)| production_year.is_some_and(|year| year > 2000))
+                .map(|((id, title), _)| (id, title.as_str()))
+                .collect();
+        ci.movie_id.iter().filter(|id| t_m.contains_key(id)).count()
+    };
+    let dense = || {
+        let t_m: DenseMap<&str> =
+            t.id.iter()
+                .zip(t.title.iter())
+                .zip(t.production_year.iter())
+                .filter(|(_, production_year)| production_year.is_some_and(|year| year > 2000))
+                .map(|((id, title), _)| (id, title.as_str()))
+                .collect();
+        ci.movie_id.iter().filter(|id| t_m.contains_key(id)).count()
+    };
+    assert_eq!(hash(), dense());
+    println!(
+        "title_by_ci_movie_id,{:},{:}",
+        median_secs(hash),
+        median_secs(dense)
+    );
+
+    let hash = || {
+        let n_m: ahash::HashMap<&i32, &str> =
+            n.id.iter()
+                .zip(n.name.iter())
+                .map(|(id, name)| (id, name.as_str()))
+                .collect();
+        ci.person_id
+            .iter()
+            .filter(|id| n_m.contains_key(id))
+            .count()
+    };
+    let dense = || {
+        let n_m: DenseMap<&str> =
+            n.id.iter()
+                .zip(n.name.iter())
+                .map(|(id, name)| (id, name.as_str()))
+                .collect();
+        ci.person_id
+            .iter()
+            .filter(|id| n_m.contains_key(id))
+            .count()
+    };
+    assert_eq!(hash(), dense());
+    println!(
+        "name_by_ci_person_id,{:},{:}",
+        median_secs(hash),
+        median_secs(dense)
+    );
+
+    let hash = || {
+        let k_s: ahash::HashSet<&i32> =
+            k.id.iter()
+                .zip(k.keyword.iter())
+                .filter_map(|(id, keyword)| keyword.contains("love").then_some(id))
+                .collect();
+        mk.keyword_id.iter().filter(|id| k_s.contains(id)).count()
+    };
+    let dense = || {
+        let k_s: DenseSet =
+            k.id.iter()
+                .zip(k.keyword.iter())
+                .filter_map(|(id, keyword)| keyword.contains("love").then_some(id))
+                .collect();
+        mk.keyword_id.iter().filter(|id| k_s.contains(id)).count()
+    };
+    assert_eq!(hash(), dense());
+    println!(
+        "keyword_by_mk_keyword_id,{:},{:}",
+        median_secs(hash),
+        median_secs(dense)
+    );
+}
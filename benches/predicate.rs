@@ -0,0 +1,113 @@
+use lfj::data::{Data, ImdbData};
+use lfj::predicate::Pred;
+use std::hint::black_box;
+use std::time::Instant;
+
+const RUNS: usize = 20;
+
+/// Median wall time of `f` over `RUNS` runs, in seconds.
+fn median_secs<T>(mut f: impl FnMut() -> T) -> f32 {
+    let mut samples: Vec<f32> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(f());
+            start.elapsed().as_secs_f32()
+        })
+        .collect();
+    samples.sort_by(f32::total_cmp);
+    samples[RUNS / 2]
+}
+
+// Compares the closure style used by the query modules with `Pred` on the `title` filters that
+// recur across JOB. Each variant produces the ids of the qualifying titles.
+fn main() {
+    let db = ImdbData::new();
+    let data = Data::new(&db);
+    let t = &data.t;
+    let kt = &data.kt;
+
+    let kt_id = *kt
+        .kind
+        .iter()
+        .zip(kt.id.iter())
+        .find(|(kind, _)| *kind == "movie")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    println!("filter,closure,vectorized");
+
+    let closure = || -> Vec<i32> {
+        t.id.iter()
+            .zip(t.production_year.iter())
+            .filter_map(|(id, production_year)| {
+                production_year
+                    .filter(|production_year| (2005..=2010).contains(production_year))
+                    .map(|_| *id)
+            })
+            .collect()
+    };
+    let vectorized = || -> Vec<i32> {
+        Pred::between(&t.production_year, 2005, 2010)
+            .eval()
+            .iter()
+            .map(|row| t.id[row])
+            .collect()
+    };
+    assert_eq!(closure(), vectorized());
+    println!(
+        "production_year_between,{:},{:}",
+        median_secs(closure),
+        median_secs(vectorized)
+    );
+
+    let closure = || -> Vec<i32> {
+        t.id.iter()
+            .zip(t.kind_id.iter())
+            .filter_map(|(id, kind_id)| (*kind_id == kt_id).then_some(*id))
+            .collect()
+    };
+    let vectorized = || -> Vec<i32> {
+        Pred::eq(&t.kind_id, kt_id)
+            .eval()
+            .iter()
+            .map(|row| t.id[row])
+            .collect()
+    };
+    assert_eq!(closure(), vectorized());
+    println!(
+        "kind_id_eq,{:},{:}",
+        median_secs(closure),
+        median_secs(vectorized)
+    );
+
+    let closure = || -> Vec<i32> {
+        t.id.iter()
+            .zip(t.production_year.iter())
+            .zip(t.kind_id.iter())
+            .filter_map(|((id, production_year), kind_id)| {
+                if let Some(production_year) = production_year
+                    && (2005..=2010).contains(production_year)
+                    && *kind_id == kt_id
+                {
+                    Some(*id)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    };
+    let vectorized = || -> Vec<i32> {
+        Pred::between(&t.production_year, 2005, 2010)
+            .and(Pred::eq(&t.kind_id, kt_id))
+            .eval()
+            .iter()
+            .map(|row| t.id[row])
+            .collect()
+    };
+    assert_eq!(closure(), vectorized());
+    println!(
+        "production_year_between_and_kind_id_eq,{:},{:}",
+        median_secs(closure),
+        median_secs(vectorized)
+    );
+}
@@ -0,0 +1,53 @@
+use lfj::data::{Data, ImdbData};
+use lfj::{o13a, o22a, o28a};
+use polars::prelude::*;
+use std::hint::black_box;
+use std::time::Instant;
+
+const RUNS: usize = 10;
+
+/// Median wall time of `f` over `RUNS` runs, in seconds.
+fn median_secs<T>(mut f: impl FnMut() -> T) -> f32 {
+    let mut samples: Vec<f32> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(f());
+            start.elapsed().as_secs_f32()
+        })
+        .collect();
+    samples.sort_by(f32::total_cmp);
+    samples[RUNS / 2]
+}
+
+// Compares the hash-join plans of the star-shaped queries with bitmap plans that intersect each
+// table's qualifying movies at once. The queries print their own timings as they run, so the
+// summary goes to stderr.
+fn main() -> PolarsResult<()> {
+    let db = ImdbData::new();
+    let data = Data::new(&db);
+
+    eprintln!("query,hash,bitmap");
+
+    assert_eq!(o13a::q13a(&data)?, o13a::q13a_bitmap(&data)?);
+    eprintln!(
+        "13a,{:},{:}",
+        median_secs(|| o13a::q13a(&data)),
+        median_secs(|| o13a::q13a_bitmap(&data))
+    );
+
+    assert_eq!(o22a::q22a(&data)?, o22a::q22a_bitmap(&data)?);
+    eprintln!(
+        "22a,{:},{:}",
+        median_secs(|| o22a::q22a(&data)),
+        median_secs(|| o22a::q22a_bitmap(&data))
+    );
+
+    assert_eq!(o28a::q28a(&data)?, o28a::q28a_bitmap(&data)?);
+    eprintln!(
+        "28a,{:},{:}",
+        median_secs(|| o28a::q28a(&data)),
+        median_secs(|| o28a::q28a_bitmap(&data))
+    );
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::process::Command;
+
+/// Lists the `QUERY` entries of the query files `src/o<number><variant>.rs` in `entries.rs`, and
+/// the `OPERATORS` each of them uses in `uses.rs`, which registry.rs includes. lib.rs declares the modules, so a query file missing there is a
+/// compile error rather than a query that silently never runs.
+/// Also sets `LFJ_COMMIT` and `LFJ_PROFILE` for the results files to record, the commit as of the
+/// last build that changed `src`.
+fn main() {
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rustc-env=LFJ_COMMIT={}", commit());
+    println!(
+        "cargo:rustc-env=LFJ_PROFILE={}",
+        std::env::var("PROFILE").unwrap()
+    );
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut queries: Vec<(u32, String)> = std::fs::read_dir(&src)
+        .unwrap()
+        .filter_map(|entry| {
+            let name = entry.unwrap().file_name().into_string().ok()?;
+            let id = name.strip_prefix('o')?.strip_suffix(".rs")?.to_string();
+            let number = id
+                .strip_suffix(|c: char| c.is_ascii_lowercase())?
+                .parse()
+                .ok()?;
+            Some((number, id))
+        })
+        .collect();
+    queries.sort();
+
+    let mut entries = String::from("&[\n");
+    let mut uses = String::from("&[\n");
+    for (_, id) in &queries {
+        writeln!(entries, "    crate::o{id}::QUERY,").unwrap();
+        let source = std::fs::read_to_string(src.join(format!("o{id}.rs"))).unwrap();
+        let operators: Vec<&str> = OPERATORS
+            .into_iter()
+            .filter(|operator| mentions(&source, operator))
+            .collect();
+        if !operators.is_empty() {
+            writeln!(uses, "    ({id:?}, &{operators:?}),").unwrap();
+        }
+    }
+    entries.push(']');
+    uses.push(']');
+
+    let out = Path::new(&std::env::var("OUT_DIR").unwrap()).to_path_buf();
+    std::fs::write(out.join("entries.rs"), entries).unwrap();
+    std::fs::write(out.join("uses.rs"), uses).unwrap();
+}
+
+/// The operators whose use by each query `uses.rs` records, for `registry::Entry::uses`.
+const OPERATORS: [&str; 4] = ["Filter", "GraceTable", "I32Map", "I32Set"];
+
+/// Whether the code of `source`, outside `//` comments, names `word`.
+fn mentions(source: &str, word: &str) -> bool {
+    source.lines().any(|line| {
+        let code = line.split("//").next().unwrap_or_default();
+        code.split(|c: char| !c.is_alphanumeric() && c != '_')
+            .any(|token| token == word)
+    })
+}
+
+/// The abbreviated hash of the checked out commit, with `-dirty` if tracked files have changed
+/// since, or `unknown` outside a git checkout.
+fn commit() -> String {
+    let git = |args: &[&str]| {
+        let output = Command::new("git").args(args).output().ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    match (
+        git(&["rev-parse", "--short=12", "HEAD"]),
+        git(&["status", "--porcelain", "--untracked-files=no"]),
+    ) {
+        (Some(commit), Some(changes)) if !changes.is_empty() => format!("{commit}-dirty"),
+        (Some(commit), _) => commit,
+        (None, _) => "unknown".to_string(),
+    }
+}
@@ -0,0 +1,32 @@
+SELECT MIN(t.title) AS complete_downey_ironman_movie
+FROM complete_cast AS cc,
+comp_cast_type AS cct1,
+comp_cast_type AS cct2,
+char_name AS chn,
+cast_info AS ci,
+keyword AS k,
+kind_type AS kt,
+movie_keyword AS mk,
+name AS n,
+title AS t
+WHERE cct1.kind = 'cast'
+AND cct2.kind like '%complete%'
+AND chn.name not like '%Sherlock%'
+and (chn.name like '%Tony%Stark%' or chn.name like '%Iron%Man%')
+AND k.keyword in
+('superhero', 'sequel', 'second-part', 'marvel-comics', 'based-on-comic', 'tv-special', 'fight', 'violence')
+AND kt.kind = 'movie'
+AND n.name LIKE '%Downey%Robert%'
+AND t.production_year > 2000
+AND kt.id = t.kind_id
+AND t.id = mk.movie_id
+AND t.id = ci.movie_id
+AND t.id = cc.movie_id
+AND mk.movie_id = ci.movie_id
+AND mk.movie_id = cc.movie_id
+AND ci.movie_id = cc.movie_id
+AND chn.id = ci.person_role_id
+AND n.id = ci.person_id
+AND k.id = mk.keyword_id
+AND cct1.id = cc.subject_id
+AND cct2.id = cc.status_id;
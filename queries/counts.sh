@@ -0,0 +1,10 @@
+#!/bin/sh
+# Regenerates counts.csv, the reference COUNT(*) of every query, with DuckDB.
+# Run from the project root with a DuckDB database holding the IMDB tables (see imdb/README).
+set -e
+db=${1:?usage: queries/counts.sh <duckdb database>}
+echo "query,count" > queries/counts.csv
+for f in queries/*.sql; do
+    count=$(perl -0pe 's/SELECT.*?FROM/SELECT COUNT(*) FROM/s' "$f" | duckdb -csv -noheader "$db")
+    echo "$(basename "$f" .sql),$count" >> queries/counts.csv
+done
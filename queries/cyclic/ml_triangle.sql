@@ -0,0 +1,7 @@
+SELECT MIN(ml1.movie_id) AS first_movie
+FROM movie_link AS ml1,
+     movie_link AS ml2,
+     movie_link AS ml3
+WHERE ml1.linked_movie_id = ml2.movie_id
+  AND ml2.linked_movie_id = ml3.movie_id
+  AND ml3.linked_movie_id = ml1.movie_id;
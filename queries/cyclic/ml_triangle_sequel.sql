@@ -0,0 +1,12 @@
+SELECT MIN(t.title) AS movie_title
+FROM link_type AS lt,
+     movie_link AS ml1,
+     movie_link AS ml2,
+     movie_link AS ml3,
+     title AS t
+WHERE lt.link IN ('sequel', 'follows', 'followed by')
+  AND lt.id = ml1.link_type_id
+  AND ml1.linked_movie_id = ml2.movie_id
+  AND ml2.linked_movie_id = ml3.movie_id
+  AND ml3.linked_movie_id = ml1.movie_id
+  AND t.id = ml1.movie_id;
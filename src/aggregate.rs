@@ -0,0 +1,402 @@
+use ahash::HashSet;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::ops::Add;
+
+/// A value fed to an aggregate, where `None` stands for SQL NULL.
+///
+/// Implemented for both `T` and `Option<T>`, so accumulators over non-nullable columns don't need
+/// their inputs wrapped in `Some`.
+pub trait Nullable<T> {
+    fn into_option(self) -> Option<T>;
+}
+
+impl<T> Nullable<T> for T {
+    fn into_option(self) -> Option<T> {
+        Some(self)
+    }
+}
+
+impl<T> Nullable<T> for Option<T> {
+    fn into_option(self) -> Option<T> {
+        self
+    }
+}
+
+/// The state of an aggregate function such as `MIN` or `COUNT`.
+///
+/// `Default` is the state before any row has been seen. States built over disjoint parts of the
+/// input, e.g. by different threads, are combined with `merge`.
+pub trait Aggregate: Default {
+    type Output;
+
+    fn merge(&mut self, other: Self);
+
+    fn finish(self) -> Self::Output;
+}
+
+/// An aggregate that can be updated with rows of type `I`.
+///
+/// Tuples of aggregates take tuples of inputs, so `(Min<&str>, Max<&i32>)` is updated with
+/// `(title, production_year)` and finishes to `(Option<&str>, Option<&i32>)`.
+pub trait Accumulate<I>: Aggregate {
+    fn update(&mut self, input: I);
+}
+
+/// `MIN(x)`, ignoring NULLs. Finishes to `None` if every input was NULL.
+#[derive(Clone, Debug)]
+pub struct Min<T>(Option<T>);
+
+impl<T> Default for Min<T> {
+    fn default() -> Self {
+        Min(None)
+    }
+}
+
+impl<T: Ord> Aggregate for Min<T> {
+    type Output = Option<T>;
+
+    fn merge(&mut self, other: Self) {
+        if let Some(value) = other.0 {
+            self.update(value);
+        }
+    }
+
+    fn finish(self) -> Option<T> {
+        self.0
+    }
+}
+
+impl<T: Ord, I: Nullable<T>> Accumulate<I> for Min<T> {
+    fn update(&mut self, input: I) {
+        if let Some(value) = input.into_option() {
+            self.0 = Some(match self.0.take() {
+                Some(old) => old.min(value),
+                None => value,
+            });
+        }
+    }
+}
+
+/// `MAX(x)`, ignoring NULLs. Finishes to `None` if every input was NULL.
+#[derive(Clone, Debug)]
+pub struct Max<T>(Option<T>);
+
+impl<T> Default for Max<T> {
+    fn default() -> Self {
+        Max(None)
+    }
+}
+
+impl<T: Ord> Aggregate for Max<T> {
+    type Output = Option<T>;
+
+    fn merge(&mut self, other: Self) {
+        if let Some(value) = other.0 {
+            self.update(value);
+        }
+    }
+
+    fn finish(self) -> Option<T> {
+        self.0
+    }
+}
+
+impl<T: Ord, I: Nullable<T>> Accumulate<I> for Max<T> {
+    fn update(&mut self, input: I) {
+        if let Some(value) = input.into_option() {
+            self.0 = Some(match self.0.take() {
+                Some(old) => old.max(value),
+                None => value,
+            });
+        }
+    }
+}
+
+/// `COUNT(*)`, counting every row whatever its values.
+#[derive(Clone, Debug, Default)]
+pub struct CountStar(u64);
+
+impl Aggregate for CountStar {
+    type Output = u64;
+
+    fn merge(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+
+    fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+impl<I> Accumulate<I> for CountStar {
+    fn update(&mut self, _: I) {
+        self.0 += 1;
+    }
+}
+
+/// `COUNT(x)`, counting the rows where `x` is not NULL.
+#[derive(Clone, Debug)]
+pub struct Count<T>(u64, PhantomData<T>);
+
+impl<T> Default for Count<T> {
+    fn default() -> Self {
+        Count(0, PhantomData)
+    }
+}
+
+impl<T> Aggregate for Count<T> {
+    type Output = u64;
+
+    fn merge(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+
+    fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+impl<T, I: Nullable<T>> Accumulate<I> for Count<T> {
+    fn update(&mut self, input: I) {
+        if input.into_option().is_some() {
+            self.0 += 1;
+        }
+    }
+}
+
+/// `COUNT(DISTINCT x)`, ignoring NULLs.
+#[derive(Clone, Debug)]
+pub struct CountDistinct<T>(HashSet<T>);
+
+impl<T> Default for CountDistinct<T> {
+    fn default() -> Self {
+        CountDistinct(HashSet::default())
+    }
+}
+
+impl<T: Hash + Eq> Aggregate for CountDistinct<T> {
+    type Output = u64;
+
+    fn merge(&mut self, other: Self) {
+        self.0.extend(other.0);
+    }
+
+    fn finish(self) -> u64 {
+        self.0.len() as u64
+    }
+}
+
+impl<T: Hash + Eq, I: Nullable<T>> Accumulate<I> for CountDistinct<T> {
+    fn update(&mut self, input: I) {
+        if let Some(value) = input.into_option() {
+            self.0.insert(value);
+        }
+    }
+}
+
+/// `SUM(x)`, ignoring NULLs. Finishes to `None` if every input was NULL.
+///
+/// The sum is computed in `T`, so sum `i32` columns as `Sum<i64>` to avoid overflow.
+#[derive(Clone, Debug)]
+pub struct Sum<T>(Option<T>);
+
+impl<T> Default for Sum<T> {
+    fn default() -> Self {
+        Sum(None)
+    }
+}
+
+impl<T: Add<Output = T>> Aggregate for Sum<T> {
+    type Output = Option<T>;
+
+    fn merge(&mut self, other: Self) {
+        if let Some(value) = other.0 {
+            self.update(value);
+        }
+    }
+
+    fn finish(self) -> Option<T> {
+        self.0
+    }
+}
+
+impl<T: Add<Output = T>, I: Nullable<T>> Accumulate<I> for Sum<T> {
+    fn update(&mut self, input: I) {
+        if let Some(value) = input.into_option() {
+            self.0 = Some(match self.0.take() {
+                Some(old) => old + value,
+                None => value,
+            });
+        }
+    }
+}
+
+/// `AVG(x)`, ignoring NULLs. Finishes to `None` if every input was NULL.
+#[derive(Clone, Debug)]
+pub struct Avg<T> {
+    sum: f64,
+    count: u64,
+    _value: PhantomData<T>,
+}
+
+impl<T> Default for Avg<T> {
+    fn default() -> Self {
+        Avg {
+            sum: 0.0,
+            count: 0,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<T> Aggregate for Avg<T> {
+    type Output = Option<f64>;
+
+    fn merge(&mut self, other: Self) {
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+
+    fn finish(self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+}
+
+impl<T: Into<f64>, I: Nullable<T>> Accumulate<I> for Avg<T> {
+    fn update(&mut self, input: I) {
+        if let Some(value) = input.into_option() {
+            self.sum += value.into();
+            self.count += 1;
+        }
+    }
+}
+
+/// Turns a tuple of per-column results into one row that is present only if every column is.
+///
+/// The query functions return `None` for a `MIN` over an empty join result, rather than a row of
+/// NULLs, and this converts the output of a tuple of `Min`s to that shape.
+pub trait Transpose {
+    type Row;
+
+    fn transpose(self) -> Option<Self::Row>;
+}
+
+macro_rules! impl_tuple {
+    ($($a:ident $i:ident $n:tt),+) => {
+        impl<$($a: Aggregate),+> Aggregate for ($($a,)+) {
+            type Output = ($($a::Output,)+);
+
+            fn merge(&mut self, other: Self) {
+                $(self.$n.merge(other.$n);)+
+            }
+
+            fn finish(self) -> Self::Output {
+                ($(self.$n.finish(),)+)
+            }
+        }
+
+        impl<$($a: Accumulate<$i>, $i),+> Accumulate<($($i,)+)> for ($($a,)+) {
+            fn update(&mut self, input: ($($i,)+)) {
+                $(self.$n.update(input.$n);)+
+            }
+        }
+
+        impl<$($a),+> Transpose for ($(Option<$a>,)+) {
+            type Row = ($($a,)+);
+
+            fn transpose(self) -> Option<Self::Row> {
+                Some(($(self.$n?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple!(A IA 0, B IB 1);
+impl_tuple!(A IA 0, B IB 1, C IC 2);
+impl_tuple!(A IA 0, B IB 1, C IC 2, D ID 3);
+impl_tuple!(A IA 0, B IB 1, C IC 2, D ID 3, E IE 4);
+impl_tuple!(A IA 0, B IB 1, C IC 2, D ID 3, E IE 4, F IF 5);
+impl_tuple!(A IA 0, B IB 1, C IC 2, D ID 3, E IE 4, F IF 5, G IG 6);
+impl_tuple!(A IA 0, B IB 1, C IC 2, D ID 3, E IE 4, F IF 5, G IG 6, H IH 7);
+
+#[cfg(test)]
+mod test_aggregate {
+    use super::*;
+
+    #[test]
+    fn test_null_ignoring() {
+        let production_year = [Some(2005), None, Some(1999), None];
+
+        let mut min = Min::<i32>::default();
+        let mut max = Max::<i32>::default();
+        let mut count = Count::<i32>::default();
+        let mut count_star = CountStar::default();
+        let mut sum = Sum::<i64>::default();
+        let mut avg = Avg::<i32>::default();
+        for year in production_year {
+            min.update(year);
+            max.update(year);
+            count.update(year);
+            count_star.update(year);
+            sum.update(year.map(i64::from));
+            avg.update(year);
+        }
+        assert_eq!(min.finish(), Some(1999));
+        assert_eq!(max.finish(), Some(2005));
+        assert_eq!(count.finish(), 2);
+        assert_eq!(count_star.finish(), 4);
+        assert_eq!(sum.finish(), Some(4004));
+        assert_eq!(avg.finish(), Some(2002.0));
+
+        let mut all_null = (
+            Min::<i32>::default(),
+            Sum::<i64>::default(),
+            Avg::<i32>::default(),
+        );
+        all_null.update((None::<i32>, None::<i64>, None::<i32>));
+        assert_eq!(all_null.finish(), (None, None, None));
+    }
+
+    #[test]
+    fn test_tuple() {
+        let rows = [
+            ("b", "Movie 2", &2001),
+            ("a", "Movie 3", &2003),
+            ("c", "Movie 1", &1999),
+        ];
+
+        let mut res = <(Min<&str>, Min<&str>, Min<&i32>)>::default();
+        assert_eq!(res.clone().finish().transpose(), None);
+        for row in rows {
+            res.update(row);
+        }
+        assert_eq!(res.finish().transpose(), Some(("a", "Movie 1", &1999)));
+
+        let mut mixed = <(Max<&str>, CountDistinct<&str>)>::default();
+        for (name, _, _) in rows.into_iter().chain(rows) {
+            mixed.update((name, name));
+        }
+        assert_eq!(mixed.finish(), (Some("c"), 3));
+    }
+
+    #[test]
+    fn test_merge() {
+        let values: Vec<i32> = (0..100).map(|i| (i * 37) % 101).collect();
+
+        let mut whole = <(Min<i32>, Max<i32>, CountDistinct<i32>, Sum<i64>)>::default();
+        for &v in &values {
+            whole.update((v, v, v % 10, i64::from(v)));
+        }
+
+        let mut merged = <(Min<i32>, Max<i32>, CountDistinct<i32>, Sum<i64>)>::default();
+        for chunk in values.chunks(7) {
+            let mut part = <(Min<i32>, Max<i32>, CountDistinct<i32>, Sum<i64>)>::default();
+            for &v in chunk {
+                part.update((v, v, v % 10, i64::from(v)));
+            }
+            merged.merge(part);
+        }
+        assert_eq!(merged.finish(), whole.finish());
+    }
+}
@@ -0,0 +1,663 @@
+use crate::cancel::{self, Outcome};
+use crate::data::{Data, ImdbData};
+use crate::golden;
+use crate::registry::{Answer, Entry, QUERIES, select};
+use crate::results::{self, Machine};
+use crate::timer::{self, Phase, Phases};
+use polars::prelude::*;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Instant, SystemTime};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+    /// Aligned columns for reading in a terminal.
+    Table,
+}
+
+impl FromStr for Format {
+    type Err = PolarsError;
+
+    fn from_str(s: &str) -> PolarsResult<Self> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "table" => Ok(Format::Table),
+            _ => polars_bail!(InvalidOperation: "unknown format {s}, expected csv, json or table"),
+        }
+    }
+}
+
+/// The order in which a round runs the selected queries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// The order of `QUERIES`.
+    Fixed,
+    /// A different random order every round, from the given seed.
+    Shuffle(u64),
+}
+
+impl FromStr for Order {
+    type Err = PolarsError;
+
+    /// Parses `fixed`, `shuffle` with a seed from the clock, or `shuffle:<seed>`.
+    fn from_str(s: &str) -> PolarsResult<Self> {
+        match s.split_once(':') {
+            None if s == "fixed" => Ok(Order::Fixed),
+            None if s == "shuffle" => {
+                let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+                Ok(Order::Shuffle(now.map_or(1, |now| now.as_nanos() as u64)))
+            }
+            Some(("shuffle", seed)) => match seed.parse() {
+                Ok(seed) => Ok(Order::Shuffle(seed)),
+                Err(_) => polars_bail!(InvalidOperation: "seed {seed} is not an integer"),
+            },
+            _ => polars_bail!(
+                InvalidOperation: "unknown order {s}, expected fixed, shuffle or shuffle:<seed>"
+            ),
+        }
+    }
+}
+
+impl Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Order::Fixed => write!(f, "fixed"),
+            Order::Shuffle(seed) => write!(f, "shuffle:{seed}"),
+        }
+    }
+}
+
+/// How the data gets into memory for each query, and which of that time the samples report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Load {
+    /// Read and build the data once and run every query against it, after `--warmups` rounds,
+    /// and at least one, so that the pages and caches are warm.
+    Warm,
+    /// Read the tables once, but drop and rebuild `Data` before every query.
+    ColdInProcess,
+    /// Read the tables that the query needs from the parquet files and build `Data` from them
+    /// before every query.
+    EndToEnd,
+}
+
+impl FromStr for Load {
+    type Err = PolarsError;
+
+    fn from_str(s: &str) -> PolarsResult<Self> {
+        match s {
+            "warm" => Ok(Load::Warm),
+            "cold-in-process" => Ok(Load::ColdInProcess),
+            "end-to-end" => Ok(Load::EndToEnd),
+            _ => polars_bail!(
+                InvalidOperation: "unknown load {s}, expected warm, cold-in-process or end-to-end"
+            ),
+        }
+    }
+}
+
+impl Display for Load {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Load::Warm => "warm",
+            Load::ColdInProcess => "cold-in-process",
+            Load::EndToEnd => "end-to-end",
+        })
+    }
+}
+
+/// What the benchmark runs and where it writes its samples.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Query ids, families or globs; all queries if empty.
+    pub queries: Vec<String>,
+    pub repetitions: usize,
+    /// Rounds of every selected query run before the measured ones and not recorded.
+    pub warmups: usize,
+    pub format: Format,
+    /// Standard output if `None`.
+    pub output: Option<PathBuf>,
+    pub order: Order,
+    /// Where to write the results file, if anywhere.
+    pub results: Option<PathBuf>,
+    pub load: Load,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            queries: Vec::new(),
+            repetitions: 5,
+            warmups: 0,
+            format: Format::Csv,
+            output: None,
+            order: Order::Fixed,
+            results: None,
+            load: Load::Warm,
+        }
+    }
+}
+
+impl Options {
+    /// The unrecorded rounds before the recorded ones: `warmups`, but at least one for a warm
+    /// load, whose first round would otherwise fault in the data and fill the caches.
+    pub fn warmup_rounds(&self) -> usize {
+        match self.load {
+            Load::Warm => self.warmups.max(1),
+            Load::ColdInProcess | Load::EndToEnd => self.warmups,
+        }
+    }
+
+    /// The file at `output`, or standard output.
+    pub fn output(&self) -> PolarsResult<Box<dyn Write>> {
+        Ok(match &self.output {
+            Some(path) => Box::new(File::create(path)?),
+            None => Box::new(std::io::stdout().lock()),
+        })
+    }
+}
+
+/// How a query run ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    /// The query's result, formatted by `Answer`.
+    Done(String),
+    Timeout,
+    Failed(String),
+}
+
+/// One measured run of a query.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub query: &'static str,
+    pub repetition: usize,
+    pub status: Status,
+    /// The time of the measured phases, if the query finished.
+    pub seconds: Option<f64>,
+    /// The time of each phase, if the query finished.
+    pub phases: Option<Phases>,
+    /// The time of the whole call, including setup.
+    pub total_seconds: f64,
+    /// The time of reading and building the data before the call, unless it was loaded once for
+    /// all queries.
+    pub load_seconds: Option<f64>,
+}
+
+/// The outcome of a benchmark run, and the exit code that reports it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Success,
+    /// A query failed or timed out.
+    Failure,
+    /// A query returned another answer than the golden file has, or runs of a query returned
+    /// different results.
+    Mismatch,
+}
+
+impl Verdict {
+    /// 0 for success, 2 for a failure and 3 for a mismatch, leaving 1 to errors of the driver
+    /// itself such as bad arguments or missing data.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Verdict::Success => 0,
+            Verdict::Failure => 2,
+            Verdict::Mismatch => 3,
+        }
+    }
+}
+
+/// Runs `query` once under the configured timeout.
+pub fn run_once(db: &Data, query: &'static Entry, repetition: usize) -> Sample {
+    let start = Instant::now();
+    let (outcome, phases) = timer::capture(|| cancel::with_timeout(|| (query.run)(db)));
+    let total_seconds = start.elapsed().as_secs_f64();
+    let status = match outcome {
+        Ok(Outcome::Done(answer)) => Status::Done(answer.to_string()),
+        Ok(Outcome::Timeout) => Status::Timeout,
+        Err(e) => Status::Failed(e.to_string()),
+    };
+    let phases = match status {
+        Status::Done(_) => Some(phases),
+        _ => None,
+    };
+    Sample {
+        query: query.id,
+        repetition,
+        status,
+        seconds: phases.map(|phases| phases.measured().as_secs_f64()),
+        phases,
+        total_seconds,
+        load_seconds: None,
+    }
+}
+
+/// Runs the selected queries in `options.warmup_rounds()` unrecorded rounds and then
+/// `options.repetitions` recorded ones over the data in `dir`, loaded as `options.load` says, and
+/// writes the samples, the results file if `options.results` asks for one, and a summary of the
+/// times of each query to standard error if there are repetitions to summarize.
+pub fn run(dir: &Path, options: &Options) -> PolarsResult<Verdict> {
+    let machine = Machine::detect();
+    let mut queries = select(&options.queries)?;
+    let golden = golden::read(Path::new(golden::PATH))?;
+    let mut state = match options.order {
+        Order::Fixed => 0,
+        Order::Shuffle(seed) => {
+            eprintln!("shuffling with --order shuffle:{seed}");
+            seed | 1
+        }
+    };
+    let start = Instant::now();
+    let db = match options.load {
+        Load::Warm | Load::ColdInProcess => Some(ImdbData::load(dir)?),
+        Load::EndToEnd => None,
+    };
+    let read_seconds = start.elapsed().as_secs_f64();
+    let warm = match (&db, options.load) {
+        (Some(db), Load::Warm) => {
+            let data = Data::new(db);
+            let seconds = start.elapsed().as_secs_f64();
+            eprintln!(
+                "loaded the data in {seconds:.3}s: read {read_seconds:.3}s, build {:.3}s",
+                seconds - read_seconds
+            );
+            Some(data)
+        }
+        (Some(_), _) => {
+            eprintln!("read the data in {read_seconds:.3}s");
+            None
+        }
+        (None, _) => None,
+    };
+    let warmups = options.warmup_rounds();
+    let mut samples = Vec::new();
+    for round in 0..warmups + options.repetitions {
+        if state != 0 {
+            // Fisher-Yates with xorshift64
+            for i in (1..queries.len()).rev() {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                queries.swap(i, (state % (i as u64 + 1)) as usize);
+            }
+        }
+        for &query in &queries {
+            let repetition = round.saturating_sub(warmups);
+            let sample = match (&warm, &db) {
+                (Some(data), _) => run_once(data, query, repetition),
+                (None, db) => {
+                    let start = Instant::now();
+                    let data = match db {
+                        Some(db) => Data::new(db),
+                        None => {
+                            let tables: Vec<&str> =
+                                query.relations.iter().map(|&(_, table)| table).collect();
+                            Data::new(&ImdbData::load_tables(dir, &tables)?)
+                        }
+                    };
+                    let load_seconds = start.elapsed().as_secs_f64();
+                    Sample {
+                        load_seconds: Some(load_seconds),
+                        ..run_once(&data, query, repetition)
+                    }
+                }
+            };
+            if round >= warmups {
+                samples.push(sample);
+            }
+        }
+    }
+    samples.sort_by_key(|sample| {
+        let position = QUERIES.iter().position(|entry| entry.id == sample.query);
+        (position, sample.repetition)
+    });
+
+    write(&mut options.output()?, &samples, options.format)?;
+    if let Some(path) = &options.results {
+        results::write(&mut File::create(path)?, &machine, options, &samples)?;
+    }
+    if options.repetitions > 1 {
+        results::write_summary(&mut std::io::stderr().lock(), &results::summarize(&samples))?;
+    }
+    for (sample, expected) in wrong_answers(&samples, &golden) {
+        eprintln!(
+            "{} returned {}, but {} has {expected}",
+            sample.query,
+            match &sample.status {
+                Status::Done(answer) => answer.as_str(),
+                _ => "",
+            },
+            golden::PATH
+        );
+    }
+    Ok(verdict(&samples, &golden))
+}
+
+/// The finished samples whose answer differs from that of their query in `golden`, with the
+/// expected answer.
+fn wrong_answers<'a>(
+    samples: &'a [Sample],
+    golden: &'a [(String, Answer)],
+) -> impl Iterator<Item = (&'a Sample, &'a Answer)> {
+    samples.iter().filter_map(|sample| {
+        let Status::Done(answer) = &sample.status else {
+            return None;
+        };
+        let (_, expected) = golden.iter().find(|(id, _)| id == sample.query)?;
+        (*answer != expected.to_string()).then_some((sample, expected))
+    })
+}
+
+/// Failure if any sample failed, else mismatch if a sample's answer differs from the answer of
+/// its query in `golden` or two samples of a query differ.
+pub fn verdict(samples: &[Sample], golden: &[(String, Answer)]) -> Verdict {
+    if samples
+        .iter()
+        .any(|sample| !matches!(sample.status, Status::Done(_)))
+    {
+        return Verdict::Failure;
+    }
+    let mismatch = wrong_answers(samples, golden).next().is_some()
+        || samples
+            .windows(2)
+            .any(|pair| pair[0].query == pair[1].query && pair[0].status != pair[1].status);
+    if mismatch {
+        Verdict::Mismatch
+    } else {
+        Verdict::Success
+    }
+}
+
+/// Writes `query,repetition,status,seconds,total_seconds,load_seconds,<phases>,result` records,
+/// with the time of every phase in seconds.
+pub fn write(out: &mut impl Write, samples: &[Sample], format: Format) -> PolarsResult<()> {
+    let rows: Vec<Vec<String>> = samples
+        .iter()
+        .map(|sample| {
+            let (status, result) = match &sample.status {
+                Status::Done(result) => ("ok", result.as_str()),
+                Status::Timeout => ("timeout", ""),
+                Status::Failed(message) => ("failed", message.as_str()),
+            };
+            let mut row = vec![
+                sample.query.to_string(),
+                sample.repetition.to_string(),
+                status.to_string(),
+                sample.seconds.map_or(String::new(), |s| s.to_string()),
+                sample.total_seconds.to_string(),
+                sample.load_seconds.map_or(String::new(), |s| s.to_string()),
+            ];
+            row.extend(Phase::ALL.map(|phase| {
+                sample.phases.map_or(String::new(), |phases| {
+                    phases.get(phase).as_secs_f64().to_string()
+                })
+            }));
+            row.push(result.to_string());
+            row
+        })
+        .collect();
+    let phases = Phase::ALL.map(|phase| phase.to_string());
+    let mut header = vec![
+        "query",
+        "repetition",
+        "status",
+        "seconds",
+        "total_seconds",
+        "load_seconds",
+    ];
+    header.extend(phases.iter().map(String::as_str));
+    header.push("result");
+
+    match format {
+        Format::Csv => {
+            writeln!(out, "{}", header.join(","))?;
+            for row in &rows {
+                let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+        }
+        Format::Json => {
+            writeln!(out, "[")?;
+            for (i, (row, sample)) in rows.iter().zip(samples).enumerate() {
+                let seconds = sample.seconds.map_or("null".to_string(), |s| s.to_string());
+                let load_seconds = sample
+                    .load_seconds
+                    .map_or("null".to_string(), |s| s.to_string());
+                write!(
+                    out,
+                    "  {{\"query\":{},\"repetition\":{},\"status\":{},\"seconds\":{seconds},\
+                     \"total_seconds\":{},\"load_seconds\":{load_seconds},\"phases\":{},\
+                     \"result\":{}}}",
+                    json_string(&row[0]),
+                    row[1],
+                    json_string(&row[2]),
+                    row[4],
+                    sample
+                        .phases
+                        .as_ref()
+                        .map_or("null".to_string(), phases_json),
+                    json_string(&row[row.len() - 1])
+                )?;
+                writeln!(out, "{}", if i + 1 < rows.len() { "," } else { "" })?;
+            }
+            writeln!(out, "]")?;
+        }
+        Format::Table => table(out, &header, &rows)?,
+    }
+    Ok(())
+}
+
+/// Writes the `header` and `rows` of a report in `format`. JSON has an object per row, with
+/// numbers and booleans unquoted and empty fields as `null`.
+pub fn write_rows<R: AsRef<[String]>>(
+    out: &mut impl Write,
+    format: Format,
+    header: &[&str],
+    rows: &[R],
+) -> PolarsResult<()> {
+    match format {
+        Format::Csv => {
+            writeln!(out, "{}", header.join(","))?;
+            for row in rows {
+                let fields: Vec<String> = row.as_ref().iter().map(|f| csv_field(f)).collect();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+        }
+        Format::Json => {
+            writeln!(out, "[")?;
+            for (i, row) in rows.iter().enumerate() {
+                let fields: Vec<String> = header
+                    .iter()
+                    .zip(row.as_ref())
+                    .map(|(name, field)| format!("{}:{}", json_string(name), json_value(field)))
+                    .collect();
+                let separator = if i + 1 < rows.len() { "," } else { "" };
+                writeln!(out, "  {{{}}}{separator}", fields.join(","))?;
+            }
+            writeln!(out, "]")?;
+        }
+        Format::Table => table(out, header, rows)?,
+    }
+    Ok(())
+}
+
+fn json_value(field: &str) -> String {
+    let number = field.parse::<f64>().is_ok_and(f64::is_finite);
+    if field.is_empty() {
+        "null".to_string()
+    } else if number || field == "true" || field == "false" {
+        field.to_string()
+    } else {
+        json_string(field)
+    }
+}
+
+/// The time of every phase in seconds, as a JSON object.
+pub fn phases_json(phases: &Phases) -> String {
+    let fields: Vec<String> = Phase::ALL
+        .iter()
+        .map(|&phase| format!("\"{phase}\":{}", phases.get(phase).as_secs_f64()))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Writes `header` and `rows` in columns aligned for reading in a terminal.
+pub fn table<R: AsRef<[String]>>(
+    out: &mut impl Write,
+    header: &[&str],
+    rows: &[R],
+) -> PolarsResult<()> {
+    let mut widths: Vec<usize> = header.iter().map(|field| field.len()).collect();
+    for row in rows {
+        for (width, field) in widths.iter_mut().zip(row.as_ref()) {
+            *width = (*width).max(field.chars().count());
+        }
+    }
+    let line = |fields: &mut dyn Iterator<Item = &str>| {
+        let padded: Vec<String> = fields
+            .zip(&widths)
+            .map(|(field, width)| format!("{field:width$}"))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    writeln!(out, "{}", line(&mut header.iter().copied()))?;
+    for row in rows {
+        writeln!(
+            out,
+            "{}",
+            line(&mut row.as_ref().iter().map(String::as_str))
+        )?;
+    }
+    Ok(())
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test_bench {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_write() -> PolarsResult<()> {
+        let phases = Phases([0.25, 0.125, 0.25, 0.125, 0.0].map(Duration::from_secs_f64));
+        let samples = [
+            Sample {
+                query: "1a",
+                repetition: 0,
+                status: Status::Done("Some(\"a, \\\"b\\\"\")".to_string()),
+                seconds: Some(0.5),
+                phases: Some(phases),
+                total_seconds: 0.75,
+                load_seconds: None,
+            },
+            Sample {
+                query: "1a",
+                repetition: 1,
+                status: Status::Timeout,
+                seconds: None,
+                phases: None,
+                total_seconds: 2.0,
+                load_seconds: Some(1.5),
+            },
+        ];
+        let mut csv = Vec::new();
+        write(&mut csv, &samples, Format::Csv)?;
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "query,repetition,status,seconds,total_seconds,load_seconds,setup,lookup,build,probe,aggregate,result\n\
+             1a,0,ok,0.5,0.75,,0.25,0.125,0.25,0.125,0,\"Some(\"\"a, \\\"\"b\\\"\"\"\")\"\n\
+             1a,1,timeout,,2,1.5,,,,,,\n"
+        );
+        let mut json = Vec::new();
+        write(&mut json, &samples, Format::Json)?;
+        let json = String::from_utf8(json).unwrap();
+        assert!(
+            json.contains(
+                "\"seconds\":null,\"total_seconds\":2,\"load_seconds\":1.5,\"phases\":null"
+            )
+        );
+        assert!(json.contains(
+            r#""phases":{"setup":0.25,"lookup":0.125,"build":0.25,"probe":0.125,"aggregate":0}"#
+        ));
+        assert!(json.contains(r#""result":"Some(\"a, \\\"b\\\"\")""#));
+        assert_eq!(verdict(&samples, &[]), Verdict::Failure);
+        assert_eq!(verdict(&samples[..1], &[]), Verdict::Success);
+        let answer = |value: &str| Answer(Some(vec![value.to_string()]));
+        let sample = |repetition, value| Sample {
+            repetition,
+            status: Status::Done(answer(value).to_string()),
+            ..samples[0].clone()
+        };
+        let golden = [("1a".to_string(), answer("a"))];
+        assert_eq!(verdict(&[sample(0, "a")], &golden), Verdict::Success);
+        assert_eq!(verdict(&[sample(0, "b")], &golden), Verdict::Mismatch);
+        assert_eq!(
+            verdict(&[sample(0, "a"), sample(1, "b")], &[]),
+            Verdict::Mismatch
+        );
+        for load in [Load::Warm, Load::ColdInProcess, Load::EndToEnd] {
+            assert_eq!(load.to_string().parse::<Load>()?, load);
+        }
+        assert!("cold".parse::<Load>().is_err());
+        let options = |load, warmups| Options {
+            load,
+            warmups,
+            ..Options::default()
+        };
+        assert_eq!(options(Load::Warm, 0).warmup_rounds(), 1);
+        assert_eq!(options(Load::Warm, 3).warmup_rounds(), 3);
+        assert_eq!(options(Load::EndToEnd, 0).warmup_rounds(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_rows() -> PolarsResult<()> {
+        let header = ["query", "seconds", "same_result", "order"];
+        let rows = [
+            ["1a", "0.5", "true", "t, mc"].map(String::from),
+            ["1b", "", "false", "NaN"].map(String::from),
+        ];
+        let mut csv = Vec::new();
+        write_rows(&mut csv, Format::Csv, &header, &rows)?;
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "query,seconds,same_result,order\n1a,0.5,true,\"t, mc\"\n1b,,false,NaN\n"
+        );
+        let mut json = Vec::new();
+        write_rows(&mut json, Format::Json, &header, &rows)?;
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "[\n  {\"query\":\"1a\",\"seconds\":0.5,\"same_result\":true,\"order\":\"t, mc\"},\n  \
+             {\"query\":\"1b\",\"seconds\":null,\"same_result\":false,\"order\":\"NaN\"}\n]\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,343 @@
+use crate::bench::{self, Format};
+use crate::data::Data;
+use crate::predicate::Selection;
+use crate::registry::Entry;
+use crate::timer::{self, Phase};
+use ahash::HashSet;
+use polars::prelude::*;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A register-blocked Bloom filter over `i32` keys: each key sets and tests bits in a single
+/// 64-bit word, so a lookup touches one cache line and needs no loop over memory.
+#[derive(Clone, Debug)]
+pub struct BloomFilter {
+    blocks: Vec<u64>,
+    /// `64 - log2(blocks.len())`, the shift that takes a hash to a block index.
+    shift: u32,
+    /// Bits set per key, at most 10 so that they all come from one 64-bit hash.
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// An empty filter sized so that, once `expected` keys are inserted, roughly a `fpr`
+    /// fraction of absent keys test positive.
+    pub fn new(expected: usize, fpr: f64) -> Self {
+        assert!(
+            fpr > 0.0 && fpr < 1.0,
+            "false-positive rate {fpr} not in (0, 1)"
+        );
+        // The optimum for a classic filter is -log2(fpr) / ln 2 bits per key. Confining each key
+        // to one word skews the load between words, which a quarter more bits makes up for.
+        let bits_per_key = -fpr.log2() / std::f64::consts::LN_2 * 1.25;
+        let bits = (expected.max(1) as f64 * bits_per_key).ceil() as usize;
+        let blocks = bits.div_ceil(64).next_power_of_two();
+        let hashes = (bits_per_key * std::f64::consts::LN_2).round() as u32;
+        BloomFilter {
+            blocks: vec![0; blocks],
+            shift: 64 - blocks.trailing_zeros(),
+            hashes: hashes.clamp(1, 10),
+        }
+    }
+
+    pub fn from_keys(keys: &[i32], fpr: f64) -> Self {
+        let mut bloom = BloomFilter::new(keys.len(), fpr);
+        for &key in keys {
+            bloom.insert(key);
+        }
+        bloom
+    }
+
+    /// The block and the bits within it for `key`.
+    #[inline]
+    fn locate(&self, key: i32) -> (usize, u64) {
+        let hash = (key as u32 as u64 ^ 0x5851_f42d_4c95_7f2d).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        // A 64-bit shift is undefined for a single block, where every key maps to block 0.
+        let block = hash.checked_shr(self.shift).unwrap_or(0) as usize;
+        let mut bits = hash.wrapping_mul(0xd6e8_feb8_6659_fd93);
+        let mut mask = 0;
+        for _ in 0..self.hashes {
+            mask |= 1 << (bits & 63);
+            bits >>= 6;
+        }
+        (block, mask)
+    }
+
+    pub fn insert(&mut self, key: i32) {
+        let (block, mask) = self.locate(key);
+        self.blocks[block] |= mask;
+    }
+
+    /// Whether `key` may have been inserted. Never false for an inserted key.
+    #[inline]
+    pub fn contains(&self, key: i32) -> bool {
+        let (block, mask) = self.locate(key);
+        self.blocks[block] & mask == mask
+    }
+
+    /// Size of the filter in bits.
+    pub fn bits(&self) -> usize {
+        self.blocks.len() * 64
+    }
+}
+
+/// How a semijoin `Filter` tests membership.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// A hash set of the keys.
+    Exact,
+    /// One bit per id between the smallest and largest key.
+    Bitmap,
+    /// A Bloom filter with the given false-positive rate in front of a hash set, so that scans
+    /// can discard most non-matching rows without probing the hash set.
+    Bloom(f64),
+}
+
+impl FromStr for Strategy {
+    type Err = PolarsError;
+
+    /// Parses `exact`, `bitmap`, `bloom` or `bloom:<fpr>`.
+    fn from_str(s: &str) -> PolarsResult<Self> {
+        match s.split_once(':') {
+            None if s == "exact" => Ok(Strategy::Exact),
+            None if s == "bitmap" => Ok(Strategy::Bitmap),
+            None if s == "bloom" => Ok(Strategy::Bloom(0.01)),
+            Some(("bloom", fpr)) => match fpr.parse() {
+                Ok(fpr) if fpr > 0.0 && fpr < 1.0 => Ok(Strategy::Bloom(fpr)),
+                _ => polars_bail!(InvalidOperation: "false-positive rate {fpr} not in (0, 1)"),
+            },
+            _ => polars_bail!(
+                InvalidOperation: "unknown filter {s}, expected exact, bitmap, bloom or bloom:<fpr>"
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strategy::Exact => write!(f, "exact"),
+            Strategy::Bitmap => write!(f, "bitmap"),
+            Strategy::Bloom(fpr) => write!(f, "bloom:{fpr}"),
+        }
+    }
+}
+
+static STRATEGY: RwLock<Strategy> = RwLock::new(Strategy::Exact);
+
+/// The strategy that `Filter::new` uses, `Strategy::Exact` unless set otherwise.
+pub fn strategy() -> Strategy {
+    *STRATEGY.read().unwrap()
+}
+
+pub fn set_strategy(strategy: Strategy) {
+    *STRATEGY.write().unwrap() = strategy;
+}
+
+/// Rows passed to `Filter::reduce` and the candidates it kept, for measuring false positives.
+static PROBED: AtomicU64 = AtomicU64::new(0);
+static CANDIDATES: AtomicU64 = AtomicU64::new(0);
+
+/// The rows probed and candidates kept by `Filter::reduce` since the last call.
+pub fn take_stats() -> (u64, u64) {
+    (
+        PROBED.swap(0, Ordering::Relaxed),
+        CANDIDATES.swap(0, Ordering::Relaxed),
+    )
+}
+
+/// The set of keys on the build side of a semijoin, with the membership test chosen by a
+/// `Strategy`. Scans call `reduce` on their key column to pre-filter it, then `confirm` on each
+/// remaining row before probing anything else.
+pub enum Filter {
+    Exact(HashSet<i32>),
+    Bitmap {
+        min: i32,
+        bits: Selection,
+    },
+    Bloom {
+        bloom: BloomFilter,
+        exact: HashSet<i32>,
+    },
+}
+
+impl Filter {
+    /// A filter over `keys` using the configured `strategy()`.
+    pub fn new(keys: impl IntoIterator<Item = i32>) -> Self {
+        Filter::with_strategy(strategy(), keys)
+    }
+
+    pub fn with_strategy(strategy: Strategy, keys: impl IntoIterator<Item = i32>) -> Self {
+        let exact: HashSet<i32> = keys.into_iter().collect();
+        match strategy {
+            Strategy::Exact => Filter::Exact(exact),
+            Strategy::Bitmap => {
+                let min = exact.iter().copied().min().unwrap_or(0);
+                let max = exact.iter().copied().max().unwrap_or(-1);
+                let mut bits = Selection::empty((max as i64 - min as i64 + 1) as usize);
+                for &key in &exact {
+                    bits.insert((key as i64 - min as i64) as usize);
+                }
+                Filter::Bitmap { min, bits }
+            }
+            Strategy::Bloom(fpr) => {
+                let mut bloom = BloomFilter::new(exact.len(), fpr);
+                for &key in &exact {
+                    bloom.insert(key);
+                }
+                Filter::Bloom { bloom, exact }
+            }
+        }
+    }
+
+    /// Number of keys in the set.
+    pub fn len(&self) -> usize {
+        match self {
+            Filter::Exact(exact) | Filter::Bloom { exact, .. } => exact.len(),
+            Filter::Bitmap { bits, .. } => bits.count(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `key` is in the set.
+    pub fn contains(&self, key: i32) -> bool {
+        match self {
+            Filter::Exact(exact) => exact.contains(&key),
+            Filter::Bitmap { min, bits } => {
+                key >= *min && bits.contains((key as i64 - *min as i64) as usize)
+            }
+            Filter::Bloom { bloom, exact } => bloom.contains(key) && exact.contains(&key),
+        }
+    }
+
+    /// The rows of `keys` that may be in the set: exactly those for the exact and bitmap
+    /// strategies, and a superset with false positives for Bloom filters.
+    pub fn reduce(&self, keys: &[i32]) -> Selection {
+        let selection = match self {
+            Filter::Bloom { bloom, .. } => Selection::from_values(keys, |&key| bloom.contains(key)),
+            _ => Selection::from_values(keys, |&key| self.contains(key)),
+        };
+        PROBED.fetch_add(keys.len() as u64, Ordering::Relaxed);
+        CANDIDATES.fetch_add(selection.count() as u64, Ordering::Relaxed);
+        selection
+    }
+
+    /// Whether `key`, from a row kept by `reduce`, is in the set.
+    #[inline]
+    pub fn confirm(&self, key: i32) -> bool {
+        match self {
+            Filter::Bloom { exact, .. } => exact.contains(&key),
+            _ => true,
+        }
+    }
+}
+
+/// Runs those of `queries` that probe a `Filter` with each strategy and writes
+/// `query,strategy,seconds,build,probe,candidates,false_positive_rate,same_result` rows to
+/// `out` in `format`, with the measured time and the time of the build and probe phases in
+/// seconds. The false-positive rate is measured against the candidates of the exact strategy,
+/// and a query that probes no filter with it is left out.
+pub fn report(
+    db: &Data,
+    queries: &[&'static Entry],
+    out: &mut impl Write,
+    format: Format,
+) -> PolarsResult<()> {
+    let strategies = [
+        Strategy::Exact,
+        Strategy::Bitmap,
+        Strategy::Bloom(0.1),
+        Strategy::Bloom(0.01),
+        Strategy::Bloom(0.001),
+    ];
+    let configured = strategy();
+    let header = [
+        "query",
+        "strategy",
+        "seconds",
+        "build",
+        "probe",
+        "candidates",
+        "false_positive_rate",
+        "same_result",
+    ];
+    let mut rows = Vec::new();
+
+    for query in queries {
+        let mut expected = None;
+        let mut matches = 0;
+        for strategy in strategies {
+            set_strategy(strategy);
+            take_stats();
+            let (result, phases) = timer::capture(|| (query.run)(db));
+            let result = result?;
+            let (probed, candidates) = take_stats();
+            if probed == 0 {
+                break;
+            }
+            let expected = expected.get_or_insert_with(|| {
+                matches = candidates;
+                result.clone()
+            });
+            let false_positives = (candidates - matches) as f64 / (probed - matches).max(1) as f64;
+            rows.push([
+                query.id.to_string(),
+                strategy.to_string(),
+                phases.measured().as_secs_f64().to_string(),
+                phases.get(Phase::Build).as_secs_f64().to_string(),
+                phases.get(Phase::Probe).as_secs_f64().to_string(),
+                candidates.to_string(),
+                false_positives.to_string(),
+                (result == *expected).to_string(),
+            ]);
+        }
+    }
+
+    set_strategy(configured);
+    bench::write_rows(out, format, &header, &rows)
+}
+
+#[cfg(test)]
+mod test_bloom {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let keys: Vec<i32> = (0..10_000).map(|i| i * 7).collect();
+        for fpr in [0.1, 0.01, 0.001] {
+            let bloom = BloomFilter::from_keys(&keys, fpr);
+            assert!(keys.iter().all(|&key| bloom.contains(key)));
+            let absent = (0..100_000)
+                .map(|i| i * 7 + 3)
+                .filter(|&key| bloom.contains(key))
+                .count();
+            let observed = absent as f64 / 100_000.0;
+            assert!(observed < fpr * 2.0, "rate {observed} for target {fpr}");
+        }
+    }
+
+    #[test]
+    fn test_filters_agree() {
+        let keys: Vec<i32> = (0..5_000).map(|i| (i * 7919) % 20_011 - 100).collect();
+        let probes: Vec<i32> = (-500..25_000).collect();
+        let exact = Filter::with_strategy(Strategy::Exact, keys.iter().copied());
+        let expected: Vec<usize> = exact.reduce(&probes).iter().collect();
+        for strategy in ["bitmap", "bloom", "bloom:0.2"] {
+            let filter = Filter::with_strategy(strategy.parse().unwrap(), keys.iter().copied());
+            let reduced = filter.reduce(&probes);
+            let confirmed: Vec<usize> = reduced
+                .iter()
+                .filter(|&row| filter.confirm(probes[row]))
+                .collect();
+            assert_eq!(confirmed, expected, "{strategy}");
+            assert!(expected.iter().all(|&row| filter.contains(probes[row])));
+        }
+        assert!("bloom:2".parse::<Strategy>().is_err());
+        assert!("hash".parse::<Strategy>().is_err());
+    }
+}
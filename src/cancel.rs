@@ -0,0 +1,227 @@
+use polars::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Calls to `poll` between two reads of the clock.
+const POLL_INTERVAL: u32 = 1024;
+
+/// Cancels a query when its deadline passes or `cancel` is called, possibly from another thread.
+/// Queries and executors notice it cooperatively, through `check`, `is_cancelled` or `poll` on
+/// the thread that runs them.
+#[derive(Clone, Debug, Default)]
+pub struct Token {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl Token {
+    /// A token that is only cancelled by `cancel`.
+    pub fn new() -> Self {
+        Token::default()
+    }
+
+    /// A token that is cancelled once `timeout` has passed, or by `cancel`.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Token {
+            cancelled: Arc::default(),
+            deadline: Instant::now().checked_add(timeout),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return true;
+        }
+        let expired = self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        if expired {
+            self.cancel();
+        }
+        expired
+    }
+}
+
+thread_local! {
+    /// The token of the query running on this thread.
+    static CURRENT: RefCell<Option<Token>> = const { RefCell::new(None) };
+    /// Calls to `poll` left until it next checks the token.
+    static COUNTDOWN: Cell<u32> = const { Cell::new(POLL_INTERVAL) };
+}
+
+/// The token of the query running on this thread, for handing to threads it spawns.
+pub fn current() -> Option<Token> {
+    CURRENT.with_borrow(Clone::clone)
+}
+
+/// Whether the query running on this thread, if any, has been cancelled.
+pub fn is_cancelled() -> bool {
+    CURRENT.with_borrow(|token| token.as_ref().is_some_and(Token::is_cancelled))
+}
+
+/// Like `is_cancelled`, but only looks at the token every `POLL_INTERVAL` calls, so that it is
+/// cheap enough to call once per tuple in the inner loops of executors. Once it has seen the
+/// cancellation it looks on every call, so that nested loops all stop promptly.
+#[inline]
+pub fn poll() -> bool {
+    let left = COUNTDOWN.get() - 1;
+    if left > 0 {
+        COUNTDOWN.set(left);
+        return false;
+    }
+    let cancelled = is_cancelled();
+    COUNTDOWN.set(if cancelled { 1 } else { POLL_INTERVAL });
+    cancelled
+}
+
+/// Fails if the query running on this thread has been cancelled.
+pub fn check() -> PolarsResult<()> {
+    if is_cancelled() {
+        polars_bail!(ComputeError: "query cancelled");
+    }
+    Ok(())
+}
+
+/// How a query run by `run` ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome<T> {
+    Done(T),
+    /// The token was cancelled while the query ran, and whatever it returned was discarded.
+    Timeout,
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for Outcome<T> {
+    /// The result, or `timeout`, as the harnesses write it to their CSV output.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Done(result) => result.fmt(f),
+            Outcome::Timeout => write!(f, "timeout"),
+        }
+    }
+}
+
+/// Runs `f` with `token` as the token of this thread. Executors that noticed the cancellation
+/// may have stopped early and returned partial results, so once the token is cancelled, or its
+/// deadline has passed even if nothing polled it, the result of `f` is dropped, error or not, for
+/// `Outcome::Timeout`.
+pub fn run<T>(token: Token, f: impl FnOnce() -> PolarsResult<T>) -> PolarsResult<Outcome<T>> {
+    let previous = CURRENT.replace(Some(token.clone()));
+    let result = f();
+    CURRENT.set(previous);
+    COUNTDOWN.set(POLL_INTERVAL);
+    if token.is_cancelled() {
+        return Ok(Outcome::Timeout);
+    }
+    result.map(Outcome::Done)
+}
+
+/// The per-query timeout in milliseconds, 0 for none.
+static TIMEOUT: AtomicU64 = AtomicU64::new(0);
+
+/// The per-query timeout of the harnesses, `None` unless set.
+pub fn timeout() -> Option<Duration> {
+    match TIMEOUT.load(Ordering::Relaxed) {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    }
+}
+
+pub fn set_timeout(timeout: Option<Duration>) {
+    let millis = timeout.map_or(0, |timeout| (timeout.as_millis() as u64).max(1));
+    TIMEOUT.store(millis, Ordering::Relaxed);
+}
+
+/// Runs `f` under a token with the configured `timeout()`.
+pub fn with_timeout<T>(f: impl FnOnce() -> PolarsResult<T>) -> PolarsResult<Outcome<T>> {
+    let token = timeout().map_or_else(Token::new, Token::with_timeout);
+    run(token, f)
+}
+
+#[cfg(test)]
+mod test_cancel {
+    use super::*;
+    use crate::{registry, test_support, timer};
+
+    #[test]
+    fn test_timeout() {
+        let outcome = run(Token::with_timeout(Duration::from_millis(20)), || {
+            let mut spins = 0u64;
+            while !poll() {
+                spins += 1;
+            }
+            Ok(spins)
+        });
+        assert_eq!(outcome.unwrap(), Outcome::Timeout);
+        assert!(!is_cancelled());
+
+        let outcome = run(Token::with_timeout(Duration::from_millis(20)), || {
+            loop {
+                check()?;
+            }
+        });
+        assert_eq!(outcome.unwrap(), Outcome::<()>::Timeout);
+
+        let outcome = run(Token::with_timeout(Duration::from_secs(3600)), || {
+            check()?;
+            Ok(1)
+        });
+        assert_eq!(outcome.unwrap(), Outcome::Done(1));
+
+        let outcome: PolarsResult<Outcome<()>> =
+            run(Token::new(), || polars_bail!(ComputeError: "failed"));
+        assert!(outcome.is_err());
+
+        // Finishes after its deadline without ever polling.
+        let outcome = run(Token::with_timeout(Duration::from_millis(5)), || {
+            std::thread::sleep(Duration::from_millis(20));
+            Ok(1)
+        });
+        assert_eq!(outcome.unwrap(), Outcome::Timeout);
+    }
+
+    /// A hand-written query under a token that has already expired polls it in its first scan
+    /// and stops there, long before it would have finished.
+    #[test]
+    fn test_expired_query() -> PolarsResult<()> {
+        let Some(data) = test_support::data() else {
+            return Ok(());
+        };
+        let query = registry::get("1a")?;
+        let start = Instant::now();
+        let (outcome, _) = timer::capture(|| run(Token::new(), || (query.run)(data)));
+        let full = start.elapsed();
+        assert!(matches!(outcome?, Outcome::Done(_)));
+
+        let start = Instant::now();
+        let (outcome, _) =
+            timer::capture(|| run(Token::with_timeout(Duration::ZERO), || (query.run)(data)));
+        assert_eq!(outcome?, Outcome::Timeout);
+        assert!(start.elapsed() < full, "{:?} >= {full:?}", start.elapsed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel_from_another_thread() {
+        let token = Token::new();
+        let canceller = token.clone();
+        let outcome = std::thread::scope(|s| {
+            s.spawn(move || {
+                std::thread::sleep(Duration::from_millis(10));
+                canceller.cancel();
+            });
+            run(token, || {
+                while !is_cancelled() {
+                    std::hint::spin_loop();
+                }
+                Ok(())
+            })
+        });
+        assert_eq!(outcome.unwrap(), Outcome::Timeout);
+    }
+}
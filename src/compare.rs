@@ -0,0 +1,300 @@
+use crate::bench::table;
+use crate::json::Value;
+use crate::results::{self, Recorded};
+use crate::stats::{Summary, mann_whitney, min_p_value};
+use polars::prelude::*;
+use std::fmt::{self, Display};
+use std::io::Write;
+use std::path::Path;
+
+/// When a difference between two results files counts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+    /// The relative change of the median below which a query is unchanged, e.g. 0.05 for 5%.
+    pub change: f64,
+    /// The p-value of the Mann-Whitney test below which a change is significant.
+    pub alpha: f64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            change: 0.05,
+            alpha: 0.05,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    /// Finished in the old results but not in the new ones.
+    Failed,
+    /// Significantly slower by more than the threshold.
+    Regression,
+    Unchanged,
+    /// Significantly faster by more than the threshold, or finished only in the new results.
+    Improvement,
+    /// Only in the old results.
+    Removed,
+    /// Only in the new results.
+    Added,
+}
+
+impl Change {
+    /// Whether the change should fail a check.
+    pub fn is_regression(self) -> bool {
+        matches!(self, Change::Failed | Change::Regression)
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Change::Failed => "failed",
+            Change::Regression => "regression",
+            Change::Unchanged => "unchanged",
+            Change::Improvement => "improvement",
+            Change::Removed => "removed",
+            Change::Added => "added",
+        })
+    }
+}
+
+/// A query's times in two results files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub query: String,
+    /// The median in-query times, if the query finished.
+    pub old: Option<f64>,
+    pub new: Option<f64>,
+    /// The old median over the new one, above 1 if the query got faster.
+    pub speedup: Option<f64>,
+    pub p_value: Option<f64>,
+    pub change: Change,
+}
+
+/// Compares the queries of two results files, in order of their change and then of speedup, so
+/// that failures and the worst regressions come first.
+pub fn compare(old: &[Recorded], new: &[Recorded], thresholds: Thresholds) -> Vec<Comparison> {
+    let median = |recorded: Option<&Recorded>| {
+        recorded
+            .and_then(|recorded| Summary::of(&recorded.seconds))
+            .map(|summary| summary.median)
+    };
+    let added = new
+        .iter()
+        .filter(|new| !old.iter().any(|old| old.query == new.query));
+    let mut comparisons: Vec<Comparison> = old
+        .iter()
+        .chain(added)
+        .map(|recorded| recorded.query.as_str())
+        .map(|query| {
+            let old = old.iter().find(|old| old.query == query);
+            let new = new.iter().find(|new| new.query == query);
+            let ok = |recorded: Option<&Recorded>| recorded.is_some_and(|r| r.status == "ok");
+            let (old_median, new_median) = (median(old), median(new));
+            let speedup = old_median.zip(new_median).map(|(old, new)| old / new);
+            let p_value = old
+                .zip(new)
+                .filter(|_| speedup.is_some())
+                .map(|(old, new)| mann_whitney(&old.seconds, &new.seconds));
+            let change = match (old, new) {
+                (Some(_), None) => Change::Removed,
+                (None, _) => Change::Added,
+                _ if ok(old) && !ok(new) => Change::Failed,
+                _ if !ok(old) && ok(new) => Change::Improvement,
+                _ => match speedup.zip(p_value) {
+                    Some((speedup, p)) if p < thresholds.alpha => {
+                        if 1.0 / speedup > 1.0 + thresholds.change {
+                            Change::Regression
+                        } else if speedup > 1.0 + thresholds.change {
+                            Change::Improvement
+                        } else {
+                            Change::Unchanged
+                        }
+                    }
+                    _ => Change::Unchanged,
+                },
+            };
+            Comparison {
+                query: query.to_string(),
+                old: old_median,
+                new: new_median,
+                speedup,
+                p_value,
+                change,
+            }
+        })
+        .collect();
+    comparisons.sort_by(|a, b| {
+        let speedup = |c: &Comparison| c.speedup.unwrap_or(f64::INFINITY);
+        a.change
+            .cmp(&b.change)
+            .then(speedup(a).total_cmp(&speedup(b)))
+    });
+    comparisons
+}
+
+/// Writes `query,old_median,new_median,speedup,p_value,change` in aligned columns.
+pub fn write(out: &mut impl Write, comparisons: &[Comparison]) -> PolarsResult<()> {
+    let header = [
+        "query",
+        "old_median",
+        "new_median",
+        "speedup",
+        "p_value",
+        "change",
+    ];
+    let show =
+        |x: Option<f64>, precision: usize| x.map_or(String::new(), |x| format!("{x:.precision$}"));
+    let rows: Vec<[String; 6]> = comparisons
+        .iter()
+        .map(|c| {
+            [
+                c.query.clone(),
+                show(c.old, 6),
+                show(c.new, 6),
+                show(c.speedup, 3),
+                show(c.p_value, 4),
+                c.change.to_string(),
+            ]
+        })
+        .collect();
+    table(out, &header, &rows)
+}
+
+/// The queries finished in both `old` and `new` with too few samples for the Mann-Whitney test
+/// to reach `alpha`, which it could then never report as changed.
+pub fn underpowered<'a>(old: &'a [Recorded], new: &[Recorded], alpha: f64) -> Vec<&'a str> {
+    old.iter()
+        .filter(|old| old.status == "ok")
+        .filter_map(|old| {
+            let new = new
+                .iter()
+                .find(|new| new.query == old.query && new.status == "ok")?;
+            (min_p_value(old.seconds.len(), new.seconds.len()) >= alpha)
+                .then_some(old.query.as_str())
+        })
+        .collect()
+}
+
+/// Compares the results files `old` and `new`, writes the report to `out` and returns whether a
+/// query regressed. Warns on standard error if the files come from different machines or
+/// build profiles, whose times are not comparable, and fails if a query has too few samples in
+/// either file for the test to reach `thresholds.alpha`.
+pub fn run(
+    old: &Path,
+    new: &Path,
+    thresholds: Thresholds,
+    out: &mut impl Write,
+) -> PolarsResult<bool> {
+    let (old, new) = (results::read(old)?, results::read(new)?);
+    for field in ["cpu", "profile", "threads"] {
+        let (a, b) = (old.machine.get(field), new.machine.get(field));
+        if a != b {
+            let show = |v: Option<&Value>| match v {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Number(n)) => n.to_string(),
+                _ => "?".to_string(),
+            };
+            eprintln!("warning: {field} differs: {} and {}", show(a), show(b));
+        }
+    }
+    let underpowered = underpowered(&old.queries, &new.queries, thresholds.alpha);
+    if let Some(query) = underpowered.first() {
+        let needed = (1..)
+            .find(|&n| min_p_value(n, n) < thresholds.alpha)
+            .unwrap();
+        polars_bail!(
+            ComputeError: "{} queries, e.g. {query}, have too few samples for a p-value below {}; \
+            record them with --repetitions {needed} or more",
+            underpowered.len(), thresholds.alpha
+        );
+    }
+    let comparisons = compare(&old.queries, &new.queries, thresholds);
+    write(out, &comparisons)?;
+    let regressions = comparisons
+        .iter()
+        .filter(|c| c.change.is_regression())
+        .count();
+    eprintln!(
+        "{regressions} of {} queries failed or regressed",
+        comparisons.len()
+    );
+    Ok(regressions > 0)
+}
+
+#[cfg(test)]
+mod test_compare {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let recorded = |query: &str, status: &str, base: f64| Recorded {
+            query: query.to_string(),
+            status: status.to_string(),
+            seconds: (0..10).map(|i| base + 0.001 * i as f64).collect(),
+        };
+        let old = [
+            recorded("1a", "ok", 1.0),
+            recorded("1b", "ok", 1.0),
+            recorded("1c", "ok", 1.0),
+            recorded("1d", "ok", 1.0),
+            recorded("2a", "ok", 1.0),
+        ];
+        let new = [
+            recorded("1a", "ok", 1.5),
+            recorded("1b", "ok", 0.5),
+            recorded("1c", "ok", 1.001),
+            recorded("1d", "ok", 1.2),
+            recorded("2b", "ok", 1.0),
+        ];
+        let comparisons = compare(&old, &new, Thresholds::default());
+        let changes: Vec<(&str, Change)> = comparisons
+            .iter()
+            .map(|c| (c.query.as_str(), c.change))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("1a", Change::Regression),
+                ("1d", Change::Regression),
+                ("1c", Change::Unchanged),
+                ("1b", Change::Improvement),
+                ("2a", Change::Removed),
+                ("2b", Change::Added),
+            ]
+        );
+
+        let strict = Thresholds {
+            change: 0.3,
+            alpha: 0.05,
+        };
+        let changes: Vec<Change> = compare(&old[..1], &[recorded("1a", "timeout", 0.0)], strict)
+            .into_iter()
+            .chain(compare(&old[3..4], &new[3..4], strict))
+            .map(|c| c.change)
+            .collect();
+        assert_eq!(changes, [Change::Failed, Change::Unchanged]);
+        let single = |query: &str| Recorded {
+            seconds: vec![1.0],
+            ..recorded(query, "ok", 1.0)
+        };
+        assert_eq!(underpowered(&old, &new, 0.05), Vec::<&str>::new());
+        assert_eq!(underpowered(&[single("1a")], &old, 0.05), ["1a"]);
+        assert!(Change::Failed.is_regression() && !Change::Added.is_regression());
+
+        // Queries too fast to time have a NaN speedup, which still sorts.
+        let zero = |query: &str| Recorded {
+            seconds: vec![0.0; 10],
+            ..recorded(query, "ok", 0.0)
+        };
+        let zeros = [zero("3a"), zero("3b")];
+        let comparisons = compare(&zeros, &zeros, strict);
+        assert!(
+            comparisons
+                .iter()
+                .all(|c| c.speedup.is_some_and(f64::is_nan))
+        );
+    }
+}
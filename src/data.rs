@@ -1,4 +1,6 @@
+use crate::predicate::Column;
 use polars::prelude::*;
+use std::path::Path;
 
 pub struct ImdbData {
     pub an: DataFrame,
@@ -1352,6 +1354,121 @@ impl Data {
             },
         }
     }
+
+    /// Looks up a column by its SQL table and column name, e.g. `("title", "production_year")`.
+    pub fn column(&self, table: &str, column: &str) -> Option<Column<'_>> {
+        Some(match (table, column) {
+            ("cast_info", "id") => (&self.ci.id).into(),
+            ("cast_info", "person_id") => (&self.ci.person_id).into(),
+            ("cast_info", "movie_id") => (&self.ci.movie_id).into(),
+            ("cast_info", "person_role_id") => (&self.ci.person_role_id).into(),
+            ("cast_info", "note") => (&self.ci.note).into(),
+            ("cast_info", "nr_order") => (&self.ci.nr_order).into(),
+            ("cast_info", "role_id") => (&self.ci.role_id).into(),
+            ("char_name", "id") => (&self.chn.id).into(),
+            ("char_name", "name") => (&self.chn.name).into(),
+            ("char_name", "imdb_index") => (&self.chn.imdb_index).into(),
+            ("char_name", "imdb_id") => (&self.chn.imdb_id).into(),
+            ("char_name", "name_pcode_nf") => (&self.chn.name_pcode_nf).into(),
+            ("char_name", "surname_pcode") => (&self.chn.surname_pcode).into(),
+            ("char_name", "md5sum") => (&self.chn.md5sum).into(),
+            ("title", "id") => (&self.t.id).into(),
+            ("title", "title") => (&self.t.title).into(),
+            ("title", "imdb_index") => (&self.t.imdb_index).into(),
+            ("title", "kind_id") => (&self.t.kind_id).into(),
+            ("title", "production_year") => (&self.t.production_year).into(),
+            ("title", "imdb_id") => (&self.t.imdb_id).into(),
+            ("title", "phonetic_code") => (&self.t.phonetic_code).into(),
+            ("title", "episode_of_id") => (&self.t.episode_of_id).into(),
+            ("title", "season_nr") => (&self.t.season_nr).into(),
+            ("title", "episode_nr") => (&self.t.episode_nr).into(),
+            ("title", "series_years") => (&self.t.series_years).into(),
+            ("title", "md5sum") => (&self.t.md5sum).into(),
+            ("kind_type", "id") => (&self.kt.id).into(),
+            ("kind_type", "kind") => (&self.kt.kind).into(),
+            ("name", "id") => (&self.n.id).into(),
+            ("name", "name") => (&self.n.name).into(),
+            ("name", "imdb_index") => (&self.n.imdb_index).into(),
+            ("name", "imdb_id") => (&self.n.imdb_id).into(),
+            ("name", "gender") => (&self.n.gender).into(),
+            ("name", "name_pcode_cf") => (&self.n.name_pcode_cf).into(),
+            ("name", "name_pcode_nf") => (&self.n.name_pcode_nf).into(),
+            ("name", "surname_pcode") => (&self.n.surname_pcode).into(),
+            ("name", "md5sum") => (&self.n.md5sum).into(),
+            ("complete_cast", "id") => (&self.cc.id).into(),
+            ("complete_cast", "movie_id") => (&self.cc.movie_id).into(),
+            ("complete_cast", "subject_id") => (&self.cc.subject_id).into(),
+            ("complete_cast", "status_id") => (&self.cc.status_id).into(),
+            ("comp_cast_type", "id") => (&self.cct.id).into(),
+            ("comp_cast_type", "kind") => (&self.cct.kind).into(),
+            ("movie_keyword", "id") => (&self.mk.id).into(),
+            ("movie_keyword", "movie_id") => (&self.mk.movie_id).into(),
+            ("movie_keyword", "keyword_id") => (&self.mk.keyword_id).into(),
+            ("keyword", "id") => (&self.k.id).into(),
+            ("keyword", "keyword") => (&self.k.keyword).into(),
+            ("keyword", "phonetic_code") => (&self.k.phonetic_code).into(),
+            ("aka_name", "id") => (&self.an.id).into(),
+            ("aka_name", "person_id") => (&self.an.person_id).into(),
+            ("aka_name", "name") => (&self.an.name).into(),
+            ("aka_name", "imdb_index") => (&self.an.imdb_index).into(),
+            ("aka_name", "name_pcode_cf") => (&self.an.name_pcode_cf).into(),
+            ("aka_name", "name_pcode_nf") => (&self.an.name_pcode_nf).into(),
+            ("aka_name", "surname_pcode") => (&self.an.surname_pcode).into(),
+            ("aka_name", "md5sum") => (&self.an.md5sum).into(),
+            ("role_type", "id") => (&self.rt.id).into(),
+            ("role_type", "role") => (&self.rt.role).into(),
+            ("movie_companies", "id") => (&self.mc.id).into(),
+            ("movie_companies", "movie_id") => (&self.mc.movie_id).into(),
+            ("movie_companies", "company_id") => (&self.mc.company_id).into(),
+            ("movie_companies", "company_type_id") => (&self.mc.company_type_id).into(),
+            ("movie_companies", "note") => (&self.mc.note).into(),
+            ("company_name", "id") => (&self.cn.id).into(),
+            ("company_name", "name") => (&self.cn.name).into(),
+            ("company_name", "country_code") => (&self.cn.country_code).into(),
+            ("company_name", "imdb_id") => (&self.cn.imdb_id).into(),
+            ("company_name", "name_pcode_nf") => (&self.cn.name_pcode_nf).into(),
+            ("company_name", "name_pcode_sf") => (&self.cn.name_pcode_sf).into(),
+            ("company_name", "md5sum") => (&self.cn.md5sum).into(),
+            ("info_type", "id") => (&self.it.id).into(),
+            ("info_type", "info") => (&self.it.info).into(),
+            ("movie_info_idx", "id") => (&self.mi_idx.id).into(),
+            ("movie_info_idx", "movie_id") => (&self.mi_idx.movie_id).into(),
+            ("movie_info_idx", "info_type_id") => (&self.mi_idx.info_type_id).into(),
+            ("movie_info_idx", "info") => (&self.mi_idx.info).into(),
+            ("movie_info_idx", "note") => (&self.mi_idx.note).into(),
+            ("link_type", "id") => (&self.lt.id).into(),
+            ("link_type", "link") => (&self.lt.link).into(),
+            ("movie_link", "id") => (&self.ml.id).into(),
+            ("movie_link", "movie_id") => (&self.ml.movie_id).into(),
+            ("movie_link", "linked_movie_id") => (&self.ml.linked_movie_id).into(),
+            ("movie_link", "link_type_id") => (&self.ml.link_type_id).into(),
+            ("movie_info", "id") => (&self.mi.id).into(),
+            ("movie_info", "movie_id") => (&self.mi.movie_id).into(),
+            ("movie_info", "info_type_id") => (&self.mi.info_type_id).into(),
+            ("movie_info", "info") => (&self.mi.info).into(),
+            ("movie_info", "note") => (&self.mi.note).into(),
+            ("company_type", "id") => (&self.ct.id).into(),
+            ("company_type", "kind") => (&self.ct.kind).into(),
+            ("person_info", "id") => (&self.pi.id).into(),
+            ("person_info", "person_id") => (&self.pi.person_id).into(),
+            ("person_info", "info_type_id") => (&self.pi.info_type_id).into(),
+            ("person_info", "info") => (&self.pi.info).into(),
+            ("person_info", "note") => (&self.pi.note).into(),
+            ("aka_title", "id") => (&self.at.id).into(),
+            ("aka_title", "movie_id") => (&self.at.movie_id).into(),
+            ("aka_title", "title") => (&self.at.title).into(),
+            ("aka_title", "imdb_index") => (&self.at.imdb_index).into(),
+            ("aka_title", "kind_id") => (&self.at.kind_id).into(),
+            ("aka_title", "production_year") => (&self.at.production_year).into(),
+            ("aka_title", "phonetic_code") => (&self.at.phonetic_code).into(),
+            ("aka_title", "episode_of_id") => (&self.at.episode_of_id).into(),
+            ("aka_title", "season_nr") => (&self.at.season_nr).into(),
+            ("aka_title", "episode_nr") => (&self.at.episode_nr).into(),
+            ("aka_title", "note") => (&self.at.note).into(),
+            ("aka_title", "md5sum") => (&self.at.md5sum).into(),
+            _ => return None,
+        })
+    }
 }
 
 impl Default for ImdbData {
@@ -1361,73 +1478,56 @@ impl Default for ImdbData {
 }
 
 impl ImdbData {
+    /// Loads the tables from `imdb/`, panicking if one cannot be read.
     pub fn new() -> Self {
-        let an = ParquetReader::new(std::fs::File::open("imdb/aka_name.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let at = ParquetReader::new(std::fs::File::open("imdb/aka_title.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let ci = ParquetReader::new(std::fs::File::open("imdb/cast_info.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let chn = ParquetReader::new(std::fs::File::open("imdb/char_name.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let cct = ParquetReader::new(std::fs::File::open("imdb/comp_cast_type.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let cn = ParquetReader::new(std::fs::File::open("imdb/company_name.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let ct = ParquetReader::new(std::fs::File::open("imdb/company_type.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let cc = ParquetReader::new(std::fs::File::open("imdb/complete_cast.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let it = ParquetReader::new(std::fs::File::open("imdb/info_type.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let k = ParquetReader::new(std::fs::File::open("imdb/keyword.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let kt = ParquetReader::new(std::fs::File::open("imdb/kind_type.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let lt = ParquetReader::new(std::fs::File::open("imdb/link_type.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let mc = ParquetReader::new(std::fs::File::open("imdb/movie_companies.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let mi_idx =
-            ParquetReader::new(std::fs::File::open("imdb/movie_info_idx.parquet").unwrap())
+        ImdbData::load("imdb").unwrap()
+    }
+
+    /// Loads the tables from the parquet files in `dir`.
+    pub fn load(dir: impl AsRef<Path>) -> PolarsResult<Self> {
+        ImdbData::load_tables(dir, &ImdbData::TABLES)
+    }
+
+    /// Loads the tables named in `tables` from the parquet files in `dir`, and of the others only
+    /// their columns, without rows.
+    pub fn load_tables(dir: impl AsRef<Path>, tables: &[&str]) -> PolarsResult<Self> {
+        for table in tables {
+            polars_ensure!(
+                ImdbData::TABLES.contains(table),
+                ComputeError: "unknown table {table}"
+            );
+        }
+        let read = |table: &str| {
+            let path = dir.as_ref().join(format!("{table}.parquet"));
+            let file = std::fs::File::open(&path)
+                .map_err(|e| polars_err!(ComputeError: "cannot open {}: {e}", path.display()))?;
+            ParquetReader::new(file)
+                .with_slice((!tables.contains(&table)).then_some((0, 0)))
                 .finish()
-                .unwrap();
-        let mi = ParquetReader::new(std::fs::File::open("imdb/movie_info.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let mk = ParquetReader::new(std::fs::File::open("imdb/movie_keyword.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let ml = ParquetReader::new(std::fs::File::open("imdb/movie_link.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let n = ParquetReader::new(std::fs::File::open("imdb/name.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let pi = ParquetReader::new(std::fs::File::open("imdb/person_info.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let rt = ParquetReader::new(std::fs::File::open("imdb/role_type.parquet").unwrap())
-            .finish()
-            .unwrap();
-        let t = ParquetReader::new(std::fs::File::open("imdb/title.parquet").unwrap())
-            .finish()
-            .unwrap();
+        };
+        let an = read("aka_name")?;
+        let at = read("aka_title")?;
+        let ci = read("cast_info")?;
+        let chn = read("char_name")?;
+        let cct = read("comp_cast_type")?;
+        let cn = read("company_name")?;
+        let ct = read("company_type")?;
+        let cc = read("complete_cast")?;
+        let it = read("info_type")?;
+        let k = read("keyword")?;
+        let kt = read("kind_type")?;
+        let lt = read("link_type")?;
+        let mc = read("movie_companies")?;
+        let mi_idx = read("movie_info_idx")?;
+        let mi = read("movie_info")?;
+        let mk = read("movie_keyword")?;
+        let ml = read("movie_link")?;
+        let n = read("name")?;
+        let pi = read("person_info")?;
+        let rt = read("role_type")?;
+        let t = read("title")?;
 
-        ImdbData {
+        Ok(ImdbData {
             an,
             at,
             ci,
@@ -1449,6 +1549,115 @@ impl ImdbData {
             pi,
             rt,
             t,
+        })
+    }
+
+    /// The SQL names of the tables, which are also the stems of their parquet files.
+    pub const TABLES: [&'static str; 21] = [
+        "aka_name",
+        "aka_title",
+        "cast_info",
+        "char_name",
+        "comp_cast_type",
+        "company_name",
+        "company_type",
+        "complete_cast",
+        "info_type",
+        "keyword",
+        "kind_type",
+        "link_type",
+        "movie_companies",
+        "movie_info_idx",
+        "movie_info",
+        "movie_keyword",
+        "movie_link",
+        "name",
+        "person_info",
+        "role_type",
+        "title",
+    ];
+
+    /// Looks up a table by its SQL name, e.g. `"movie_info_idx"`.
+    pub fn table(&self, name: &str) -> Option<&DataFrame> {
+        Some(match name {
+            "aka_name" => &self.an,
+            "aka_title" => &self.at,
+            "cast_info" => &self.ci,
+            "char_name" => &self.chn,
+            "comp_cast_type" => &self.cct,
+            "company_name" => &self.cn,
+            "company_type" => &self.ct,
+            "complete_cast" => &self.cc,
+            "info_type" => &self.it,
+            "keyword" => &self.k,
+            "kind_type" => &self.kt,
+            "link_type" => &self.lt,
+            "movie_companies" => &self.mc,
+            "movie_info_idx" => &self.mi_idx,
+            "movie_info" => &self.mi,
+            "movie_keyword" => &self.mk,
+            "movie_link" => &self.ml,
+            "name" => &self.n,
+            "person_info" => &self.pi,
+            "role_type" => &self.rt,
+            "title" => &self.t,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+impl ImdbData {
+    /// Tables without columns, for tests to fill in.
+    pub fn empty() -> Self {
+        ImdbData {
+            an: DataFrame::empty(),
+            at: DataFrame::empty(),
+            ci: DataFrame::empty(),
+            chn: DataFrame::empty(),
+            cct: DataFrame::empty(),
+            cn: DataFrame::empty(),
+            ct: DataFrame::empty(),
+            cc: DataFrame::empty(),
+            it: DataFrame::empty(),
+            k: DataFrame::empty(),
+            kt: DataFrame::empty(),
+            lt: DataFrame::empty(),
+            mc: DataFrame::empty(),
+            mi_idx: DataFrame::empty(),
+            mi: DataFrame::empty(),
+            mk: DataFrame::empty(),
+            ml: DataFrame::empty(),
+            n: DataFrame::empty(),
+            pi: DataFrame::empty(),
+            rt: DataFrame::empty(),
+            t: DataFrame::empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_data {
+    use super::*;
+
+    #[test]
+    fn test_load_tables() -> PolarsResult<()> {
+        let dir = std::env::temp_dir().join(format!("lfj-data-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        for table in ImdbData::TABLES {
+            let mut df = df!("id" => [1, 2, 3], "name" => ["a", "b", "c"])?;
+            let file = std::fs::File::create(dir.join(format!("{table}.parquet")))?;
+            ParquetWriter::new(file).finish(&mut df)?;
         }
+        let loaded = ImdbData::load_tables(&dir, &["title", "kind_type"]);
+        let unknown = ImdbData::load_tables(&dir, &["titles"]);
+        std::fs::remove_dir_all(&dir)?;
+        let loaded = loaded?;
+        assert_eq!(loaded.t.height(), 3);
+        assert_eq!(loaded.kt.height(), 3);
+        assert_eq!(loaded.ci.height(), 0);
+        assert_eq!(loaded.ci.schema(), loaded.t.schema());
+        assert!(unknown.is_err());
+        Ok(())
     }
 }
@@ -0,0 +1,211 @@
+use crate::predicate::Selection;
+use ahash::{HashMap, HashSet};
+
+/// Slots a `DenseMap` may allocate per entry before it falls back to hashing.
+const MAP_SLOTS_PER_ENTRY: usize = 4;
+
+/// Bits a `DenseSet` may allocate per key before it falls back to hashing. A hash set spends
+/// well over 64 bits per `i32` key, so up to this point the bitmap is also smaller.
+const SET_BITS_PER_KEY: usize = 64;
+
+/// An integer id, possibly behind references, so that lookups accept whatever the query's
+/// iterators yield, as `HashMap<&i32, _>::get` does.
+pub trait DenseKey: Copy {
+    fn id(self) -> i32;
+}
+
+impl DenseKey for i32 {
+    #[inline]
+    fn id(self) -> i32 {
+        self
+    }
+}
+
+impl<T: DenseKey> DenseKey for &T {
+    #[inline]
+    fn id(self) -> i32 {
+        (*self).id()
+    }
+}
+
+/// The smallest key and the number of ids from it to the largest key, if `keys` fit in at most
+/// `per_key` slots each.
+fn dense_range(
+    keys: impl Iterator<Item = i32>,
+    count: usize,
+    per_key: usize,
+) -> Option<(i32, usize)> {
+    let (min, max) = keys.fold((i32::MAX, i32::MIN), |(min, max), key| {
+        (min.min(key), max.max(key))
+    });
+    let range = (max as i64 - min as i64 + 1).max(0) as usize;
+    (range <= count.max(1) * per_key).then_some((min, range))
+}
+
+/// A map from integer ids to values, stored as an array indexed by id when the ids are dense, as
+/// primary keys are, and as a hash map otherwise. The choice is made from the ids' range and
+/// count when the map is collected.
+#[derive(Clone, Debug)]
+pub enum DenseMap<V> {
+    Dense { min: i32, values: Vec<Option<V>> },
+    Sparse(HashMap<i32, V>),
+}
+
+impl<K: DenseKey, V> FromIterator<(K, V)> for DenseMap<V> {
+    /// Collects the entries, keeping the last value of a repeated id like `HashMap` does.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let entries: Vec<(i32, V)> = entries
+            .into_iter()
+            .map(|(key, value)| (key.id(), value))
+            .collect();
+        match dense_range(
+            entries.iter().map(|(id, _)| *id),
+            entries.len(),
+            MAP_SLOTS_PER_ENTRY,
+        ) {
+            Some((min, range)) => {
+                let mut values: Vec<Option<V>> = (0..range).map(|_| None).collect();
+                for (id, value) in entries {
+                    values[(id as i64 - min as i64) as usize] = Some(value);
+                }
+                DenseMap::Dense { min, values }
+            }
+            None => DenseMap::Sparse(entries.into_iter().collect()),
+        }
+    }
+}
+
+impl<V> DenseMap<V> {
+    #[inline]
+    pub fn get(&self, key: &impl DenseKey) -> Option<&V> {
+        match self {
+            DenseMap::Dense { min, values } => values
+                .get((key.id() as i64 - *min as i64) as usize)
+                .and_then(Option::as_ref),
+            DenseMap::Sparse(map) => map.get(&key.id()),
+        }
+    }
+
+    pub fn contains_key(&self, key: &impl DenseKey) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            DenseMap::Dense { values, .. } => values.iter().filter(|value| value.is_some()).count(),
+            DenseMap::Sparse(map) => map.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_dense(&self) -> bool {
+        matches!(self, DenseMap::Dense { .. })
+    }
+}
+
+/// A set of integer ids, stored as a bitmap over their range when they are dense and as a hash
+/// set otherwise, chosen like `DenseMap`.
+#[derive(Clone, Debug)]
+pub enum DenseSet {
+    Dense { min: i32, bits: Selection },
+    Sparse(HashSet<i32>),
+}
+
+impl<K: DenseKey> FromIterator<K> for DenseSet {
+    fn from_iter<I: IntoIterator<Item = K>>(keys: I) -> Self {
+        let keys: Vec<i32> = keys.into_iter().map(DenseKey::id).collect();
+        match dense_range(keys.iter().copied(), keys.len(), SET_BITS_PER_KEY) {
+            Some((min, range)) => {
+                let mut bits = Selection::empty(range);
+                for id in keys {
+                    bits.insert((id as i64 - min as i64) as usize);
+                }
+                DenseSet::Dense { min, bits }
+            }
+            None => DenseSet::Sparse(keys.into_iter().collect()),
+        }
+    }
+}
+
+impl DenseSet {
+    #[inline]
+    pub fn contains(&self, key: &impl DenseKey) -> bool {
+        match self {
+            DenseSet::Dense { min, bits } => {
+                bits.contains((key.id() as i64 - *min as i64) as usize)
+            }
+            DenseSet::Sparse(set) => set.contains(&key.id()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            DenseSet::Dense { bits, .. } => bits.count(),
+            DenseSet::Sparse(set) => set.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_dense(&self) -> bool {
+        matches!(self, DenseSet::Dense { .. })
+    }
+}
+
+#[cfg(test)]
+mod test_dense {
+    use super::*;
+
+    #[test]
+    fn test_dense_map() {
+        let ids: Vec<i32> = (10..1010).collect();
+        let map: DenseMap<&i32> = ids.iter().map(|id| (id, id)).collect();
+        assert!(map.is_dense());
+        assert_eq!(map.get(&10), Some(&&10));
+        assert_eq!(map.get(&&1009), Some(&&1009));
+        assert_eq!(map.get(&9), None);
+        assert_eq!(map.get(&1010), None);
+        assert_eq!(map.get(&i32::MIN), None);
+
+        let map: DenseMap<i32> = [(5, 1), (-3, 2), (5, 3)].into_iter().collect();
+        assert!(map.is_dense());
+        assert_eq!(map.get(&5), Some(&3));
+        assert_eq!(map.get(&-3), Some(&2));
+        assert!(!map.contains_key(&0));
+
+        let map: DenseMap<i32> = [(i32::MIN, 1), (i32::MAX, 2)].into_iter().collect();
+        assert!(!map.is_dense());
+        assert_eq!(map.get(&i32::MAX), Some(&2));
+        assert_eq!(map.get(&0), None);
+
+        let map: DenseMap<i32> = std::iter::empty::<(i32, i32)>().collect();
+        assert_eq!(map.get(&0), None);
+    }
+
+    #[test]
+    fn test_dense_set() {
+        let ids: Vec<i32> = (0..1000).map(|i| i * 3).collect();
+        let set: DenseSet = ids.iter().collect();
+        assert!(set.is_dense());
+        for id in -5..3005 {
+            assert_eq!(
+                set.contains(&id),
+                (0..3000).contains(&id) && id % 3 == 0,
+                "{id}"
+            );
+        }
+
+        let set: DenseSet = [0, 1_000_000].into_iter().collect();
+        assert!(!set.is_dense());
+        assert!(set.contains(&1_000_000));
+        assert!(!set.contains(&1));
+
+        let set: DenseSet = std::iter::empty::<i32>().collect();
+        assert!(!set.contains(&0));
+    }
+}
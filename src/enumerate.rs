@@ -0,0 +1,531 @@
+use crate::cancel;
+use crate::data::Data;
+use crate::explain;
+use crate::like::LikePattern;
+use crate::predicate::{Column, Pred, Scalar, Selection};
+use crate::registry::QUERIES;
+use crate::sql::{ColumnRef, Expr, Query};
+use ahash::HashMap;
+use polars::prelude::*;
+use std::time::Instant;
+
+/// Enumerates the un-aggregated join result of a JOB query.
+///
+/// Every relation is filtered with its predicates first. The join then binds one relation at a
+/// time, always picking the smallest filtered relation connected to the ones already bound, and
+/// looks up its matching rows in a hash index on one join column; any other join conditions to
+/// bound relations are checked on the candidates. Output tuples are passed to a callback as row
+/// numbers, one per relation in `FROM` order, so the result is never materialized.
+pub struct JoinEnumerator<'a> {
+    db: &'a Data,
+    tables: Vec<&'a str>,
+    aliases: Vec<&'a str>,
+    join: Join<'a>,
+}
+
+/// The order in which relations are bound and how each one is accessed.
+pub(crate) struct Join<'a> {
+    steps: Vec<Step<'a>>,
+}
+
+struct Step<'a> {
+    relation: usize,
+    access: Access<'a>,
+    /// `(bound relation, its column, column of this relation)` pairs that must be equal.
+    checks: Vec<(usize, Column<'a>, Column<'a>)>,
+}
+
+enum Access<'a> {
+    /// Every selected row of the relation.
+    Scan(Vec<u32>),
+    /// The selected rows whose join column equals `key` of the `bound` relation.
+    Probe {
+        bound: usize,
+        key: Column<'a>,
+        index: HashMap<i32, Vec<u32>>,
+    },
+}
+
+impl<'a> JoinEnumerator<'a> {
+    pub fn new(db: &'a Data, sql: &'a str) -> PolarsResult<Self> {
+        let query = Query::parse(sql)?;
+        let (selections, edges) = bind(db, &query)?;
+        Ok(JoinEnumerator {
+            db,
+            tables: query.relations.iter().map(|r| r.table).collect(),
+            aliases: query.relations.iter().map(|r| r.alias).collect(),
+            join: Join::new(selections, edges),
+        })
+    }
+
+    /// The aliases of the relations, in the order their rows appear in output tuples.
+    pub fn aliases(&self) -> &[&'a str] {
+        &self.aliases
+    }
+
+    /// Looks up a column of the relation with the given alias, to read the values of a tuple.
+    pub fn column(&self, alias: &str, column: &str) -> Option<Column<'a>> {
+        let relation = self.aliases.iter().position(|a| *a == alias)?;
+        self.db.column(self.tables[relation], column)
+    }
+
+    /// Calls `f` with every tuple of the join result, as row numbers indexed like `aliases()`.
+    pub fn for_each(&self, f: impl FnMut(&[u32])) {
+        self.join.for_each(f);
+    }
+
+    /// `COUNT(*)` of the join result.
+    pub fn count(&self) -> u64 {
+        explain::node("enumerate", self.join.rows(), || {
+            let mut count = 0;
+            self.for_each(|_| count += 1);
+            count
+        })
+    }
+}
+
+impl<'a> Join<'a> {
+    /// Plans the join of relations with the given selected rows over `(left, column, right,
+    /// column)` equi-join edges, binding next the smallest relation connected to the bound ones.
+    pub(crate) fn new(selections: Vec<Selection>, edges: Vec<Edge<'a>>) -> Self {
+        let counts: Vec<usize> = selections.iter().map(Selection::count).collect();
+        let mut bound = vec![false; selections.len()];
+        let mut order = Vec::with_capacity(selections.len());
+
+        while order.len() < selections.len() {
+            let connected = |r: usize| {
+                edges
+                    .iter()
+                    .any(|&(a, _, b, _)| (a == r && bound[b]) || (b == r && bound[a]))
+            };
+            let unbound = (0..selections.len()).filter(|&r| !bound[r]);
+            let relation = unbound
+                .clone()
+                .filter(|&r| connected(r))
+                .min_by_key(|&r| counts[r])
+                .or_else(|| unbound.min_by_key(|&r| counts[r]))
+                .unwrap();
+            order.push(relation);
+            bound[relation] = true;
+        }
+
+        Join::with_order(selections, edges, &order)
+    }
+
+    /// Plans the join binding relations in `order`, a permutation of the relations. Each one is
+    /// probed on its first join condition with an earlier relation and checked on the others,
+    /// or scanned if it has none, which makes a cross product.
+    pub(crate) fn with_order(
+        selections: Vec<Selection>,
+        edges: Vec<Edge<'a>>,
+        order: &[usize],
+    ) -> Self {
+        assert_eq!(order.len(), selections.len(), "order is not a permutation");
+        let mut bound = vec![false; selections.len()];
+        let mut steps = Vec::with_capacity(selections.len());
+
+        for &relation in order {
+            assert!(!bound[relation], "order is not a permutation");
+            // (bound relation, its column, column of `relation`)
+            let mut conditions = edges.iter().filter_map(|(a, a_column, b, b_column)| {
+                if *a == relation && bound[*b] {
+                    Some((*b, *b_column, *a_column))
+                } else if *b == relation && bound[*a] {
+                    Some((*a, *a_column, *b_column))
+                } else {
+                    None
+                }
+            });
+            let access = match conditions.next() {
+                Some((bound, key, column)) => {
+                    let name = format_args!("index relation {relation}");
+                    let count = selections[relation].count();
+                    let index = explain::node(name, count, || {
+                        let mut index: HashMap<i32, Vec<u32>> = HashMap::default();
+                        for row in selections[relation].iter() {
+                            if let Some(value) = value(&column, row) {
+                                index.entry(value).or_default().push(row as u32);
+                            }
+                        }
+                        index
+                    });
+                    Access::Probe { bound, key, index }
+                }
+                None => Access::Scan(selections[relation].to_indices()),
+            };
+            steps.push(Step {
+                relation,
+                access,
+                checks: conditions.collect(),
+            });
+            bound[relation] = true;
+        }
+
+        Join { steps }
+    }
+
+    /// Number of selected rows over all relations.
+    fn rows(&self) -> usize {
+        self.steps
+            .iter()
+            .map(|step| match &step.access {
+                Access::Scan(rows) => rows.len(),
+                Access::Probe { index, .. } => index.values().map(Vec::len).sum(),
+            })
+            .sum()
+    }
+
+    pub(crate) fn for_each(&self, mut f: impl FnMut(&[u32])) {
+        let mut rows = vec![0; self.steps.len()];
+        self.visit(0, &mut rows, &mut f);
+    }
+
+    fn visit(&self, depth: usize, rows: &mut [u32], f: &mut impl FnMut(&[u32])) {
+        let Some(step) = self.steps.get(depth) else {
+            f(rows);
+            return;
+        };
+        let candidates = match &step.access {
+            Access::Scan(candidates) => candidates,
+            Access::Probe { bound, key, index } => {
+                match value(key, rows[*bound] as usize).and_then(|key| index.get(&key)) {
+                    Some(candidates) => candidates,
+                    None => return,
+                }
+            }
+        };
+        'candidates: for &row in candidates {
+            if cancel::poll() {
+                return;
+            }
+            for (bound, bound_column, column) in &step.checks {
+                let expected = value(bound_column, rows[*bound] as usize);
+                if expected.is_none() || expected != value(column, row as usize) {
+                    continue 'candidates;
+                }
+            }
+            rows[step.relation] = row;
+            self.visit(depth + 1, rows, f);
+        }
+    }
+}
+
+/// An equi-join condition `(left relation, its column, right relation, its column)`.
+pub(crate) type Edge<'a> = (usize, Column<'a>, usize, Column<'a>);
+
+/// Resolves the columns of `query` in `db`, and returns the rows of every relation that pass its
+/// filters together with the join conditions between relations.
+pub(crate) fn bind<'a>(
+    db: &'a Data,
+    query: &Query<'a>,
+) -> PolarsResult<(Vec<Selection>, Vec<Edge<'a>>)> {
+    let column = |column: &ColumnRef| -> PolarsResult<Column<'a>> {
+        let table = query.relations[query.relation(column.alias)?].table;
+        db.column(table, column.column)
+            .ok_or_else(|| polars_err!(SQLInterface: "unknown column {}.{}", table, column.column))
+    };
+
+    let mut filters: Vec<Vec<Pred<'a>>> = vec![Vec::new(); query.relations.len()];
+    for expr in &query.filters {
+        let relation = query.relation(expr.alias().unwrap())?;
+        filters[relation].push(pred(expr, &column)?);
+    }
+    let selections: Vec<Selection> = query
+        .relations
+        .iter()
+        .zip(filters)
+        .map(|(relation, preds)| {
+            let len = db.column(relation.table, "id").map_or(0, |id| id.len());
+            explain::node(
+                format_args!("filter {}", relation.alias),
+                len,
+                || match preds.len() {
+                    0 => Selection::full(len),
+                    1 => preds[0].eval(),
+                    _ => Pred::And(preds).eval(),
+                },
+            )
+        })
+        .collect();
+
+    let mut edges = Vec::new();
+    for (left, right) in &query.joins {
+        let (left_column, right_column) = (column(left)?, column(right)?);
+        for (column, side) in [(&left_column, left), (&right_column, right)] {
+            if !matches!(column, Column::Int(_) | Column::NullableInt(_)) {
+                return Err(polars_err!(
+                    SQLInterface: "join on non-integer column {}.{}", side.alias, side.column
+                ));
+            }
+        }
+        edges.push((
+            query.relation(left.alias)?,
+            left_column,
+            query.relation(right.alias)?,
+            right_column,
+        ));
+    }
+    Ok((selections, edges))
+}
+
+/// The join columns of a query grouped into variables, the classes of columns that the join
+/// conditions, taken transitively, require to be equal.
+pub(crate) struct Variables<'a> {
+    /// For every relation, its join columns labelled by variable, one column per variable.
+    pub(crate) relations: Vec<Vec<(usize, Column<'a>)>>,
+    /// For every variable, the columns it stands for.
+    pub(crate) names: Vec<Vec<ColumnRef<'a>>>,
+}
+
+/// Groups the join columns of `query` into variables. Where two columns of one relation get the
+/// same variable, its selection is narrowed to the rows where they are equal.
+pub(crate) fn variables<'a>(
+    query: &Query<'a>,
+    selections: &mut [Selection],
+    edges: Vec<Edge<'a>>,
+) -> Variables<'a> {
+    fn find(parent: &mut [usize], x: usize) -> usize {
+        if parent[x] != x {
+            parent[x] = find(parent, parent[x]);
+        }
+        parent[x]
+    }
+
+    let mut columns: Vec<(usize, ColumnRef<'a>, Column<'a>)> = Vec::new();
+    let mut parent: Vec<usize> = Vec::new();
+    for ((left, right), (left_relation, left_column, right_relation, right_column)) in
+        query.joins.iter().zip(edges)
+    {
+        let mut id = |relation: usize, name: ColumnRef<'a>, column: Column<'a>| match columns
+            .iter()
+            .position(|(_, c, _)| *c == name)
+        {
+            Some(id) => id,
+            None => {
+                columns.push((relation, name, column));
+                parent.push(parent.len());
+                parent.len() - 1
+            }
+        };
+        let left = id(left_relation, *left, left_column);
+        let right = id(right_relation, *right, right_column);
+        let (left, right) = (find(&mut parent, left), find(&mut parent, right));
+        parent[left] = right;
+    }
+
+    let mut roots: Vec<usize> = (0..columns.len()).map(|c| find(&mut parent, c)).collect();
+    roots.sort_unstable();
+    roots.dedup();
+    let mut relations: Vec<Vec<(usize, Column<'a>)>> = vec![Vec::new(); query.relations.len()];
+    let mut names: Vec<Vec<ColumnRef<'a>>> = vec![Vec::new(); roots.len()];
+    for (c, (relation, name, column)) in columns.into_iter().enumerate() {
+        let var = roots.binary_search(&find(&mut parent, c)).unwrap();
+        names[var].push(name);
+        match relations[relation].iter().find(|(v, _)| *v == var) {
+            Some((_, other)) => selections[relation].retain(|row| {
+                let a = value(&column, row);
+                a.is_some() && a == value(other, row)
+            }),
+            None => relations[relation].push((var, column)),
+        }
+    }
+    Variables { relations, names }
+}
+
+/// Prints `query,count,seconds` for the join result of every query in `QUERIES`, enumerated
+/// tuple by tuple, with `timeout` for the count of queries that exceed `cancel::timeout()`.
+pub fn count_all(db: &Data) -> PolarsResult<()> {
+    for query in QUERIES {
+        let _explain = explain::query(query.id);
+        let start = Instant::now();
+        let count = cancel::with_timeout(|| Ok(JoinEnumerator::new(db, query.sql)?.count()))?;
+        println!("{},{count},{:}", query.id, start.elapsed().as_secs_f32());
+    }
+    Ok(())
+}
+
+/// The join key in `row` of an integer column, `None` for NULL.
+pub(crate) fn value(column: &Column, row: usize) -> Option<i32> {
+    match column {
+        Column::Int(values) => Some(values[row]),
+        Column::NullableInt(values) => values[row],
+        Column::Str(_) | Column::NullableStr(_) => None,
+    }
+}
+
+fn pred<'a>(
+    expr: &Expr<'a>,
+    column: &impl Fn(&ColumnRef) -> PolarsResult<Column<'a>>,
+) -> PolarsResult<Pred<'a>> {
+    let typed = |column_ref: &ColumnRef, scalars: &[&Scalar]| -> PolarsResult<Column<'a>> {
+        let column = column(column_ref)?;
+        let is_int = matches!(column, Column::Int(_) | Column::NullableInt(_));
+        if scalars
+            .iter()
+            .any(|scalar| matches!(scalar, Scalar::Int(_)) != is_int)
+        {
+            return Err(polars_err!(
+                SQLInterface: "type mismatch in filter on {}.{}", column_ref.alias, column_ref.column
+            ));
+        }
+        Ok(column)
+    };
+    let all = |exprs: &[Expr<'a>]| -> PolarsResult<Vec<Pred<'a>>> {
+        exprs.iter().map(|expr| pred(expr, column)).collect()
+    };
+
+    Ok(match expr {
+        Expr::Cmp(c, op, value) => Pred::cmp(typed(c, &[value])?, *op, *value),
+        Expr::Between(c, lo, hi) => Pred::between(typed(c, &[lo, hi])?, *lo, *hi),
+        Expr::In(c, values) => {
+            let scalars: Vec<&Scalar> = values.iter().collect();
+            Pred::is_in(typed(c, &scalars)?, values.iter().copied())
+        }
+        Expr::Like(c, pattern) => Pred::like(
+            typed(c, &[&Scalar::Str(pattern)])?,
+            LikePattern::compile(pattern)?,
+        ),
+        Expr::IsNull(c) => Pred::is_null(column(c)?),
+        Expr::And(exprs) => Pred::And(all(exprs)?),
+        Expr::Or(exprs) => Pred::Or(all(exprs)?),
+        Expr::Not(expr) => !pred(expr, column)?,
+        Expr::Join(left, right) => {
+            return Err(polars_err!(
+                SQLInterface: "comparison of {}.{} with {}.{} in a filter",
+                left.alias, left.column, right.alias, right.column
+            ));
+        }
+    })
+}
+
+#[cfg(test)]
+mod test_enumerate {
+    use super::*;
+    use crate::registry;
+    use crate::test_support;
+
+    #[test]
+    fn test_enumerate_matches_nested_loops() {
+        // r(a), s(a, b), t(b) plus an edge t.b = r.a closing a cycle
+        let r_a = vec![1, 2, 3, 4];
+        let s_a = vec![Some(1), Some(1), Some(2), None, Some(4)];
+        let s_b = vec![1, 2, 2, 3, 4];
+        let t_b = vec![Some(1), Some(2), None, Some(4), Some(1)];
+        let selections = vec![
+            Selection::from_values(&r_a, |&a| a != 3),
+            Selection::full(s_a.len()),
+            Selection::full(t_b.len()),
+        ];
+        let edges = vec![
+            (0, Column::from(&r_a), 1, Column::from(&s_a)),
+            (1, Column::from(&s_b), 2, Column::from(&t_b)),
+            (2, Column::from(&t_b), 0, Column::from(&r_a)),
+        ];
+
+        let mut expected = Vec::new();
+        for (r, &a) in r_a.iter().enumerate() {
+            for (s, (&s_a, &s_b)) in s_a.iter().zip(&s_b).enumerate() {
+                for (t, &t_b) in t_b.iter().enumerate() {
+                    if a != 3 && s_a == Some(a) && Some(s_b) == t_b && t_b == Some(a) {
+                        expected.push(vec![r as u32, s as u32, t as u32]);
+                    }
+                }
+            }
+        }
+
+        let join = Join::new(selections.clone(), edges.clone());
+        let mut tuples = Vec::new();
+        join.for_each(|rows| tuples.push(rows.to_vec()));
+        tuples.sort();
+        assert_eq!(tuples, expected);
+
+        for order in [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ] {
+            let join = Join::with_order(selections.clone(), edges.clone(), &order);
+            let mut tuples = Vec::new();
+            join.for_each(|rows| tuples.push(rows.to_vec()));
+            tuples.sort();
+            assert_eq!(tuples, expected, "{order:?}");
+        }
+    }
+
+    #[test]
+    fn test_cross_product_times_out() {
+        // 10^12 tuples, which would take hours to enumerate
+        let join = Join::new(vec![Selection::full(10_000); 3], Vec::new());
+        let token = cancel::Token::with_timeout(std::time::Duration::from_millis(50));
+        let start = Instant::now();
+        let outcome = cancel::run(token, || {
+            let mut count = 0u64;
+            join.for_each(|_| count += 1);
+            Ok(count)
+        });
+        assert_eq!(outcome.unwrap(), cancel::Outcome::Timeout);
+        assert!(start.elapsed().as_secs() < 5);
+    }
+
+    /// The reference counts in `queries/counts.csv`, which `queries/counts.sh` generates with
+    /// DuckDB.
+    fn reference_counts() -> PolarsResult<Vec<(String, u64)>> {
+        std::fs::read_to_string("queries/counts.csv")?
+            .lines()
+            .skip(1)
+            .map(|line| match line.split_once(',') {
+                Some((query, count)) if count.parse::<u64>().is_ok() => {
+                    Ok((query.to_string(), count.parse().unwrap()))
+                }
+                _ => polars_bail!(ComputeError: "bad line in queries/counts.csv: {line}"),
+            })
+            .collect()
+    }
+
+    /// The reference counts cover every registered query, so that a partial file fails instead of
+    /// checking some queries only. A file with no counts yet is skipped, since generating them
+    /// needs DuckDB and the IMDB data; `test_counts` fails on it once the data is there.
+    #[test]
+    fn test_counts_file() -> PolarsResult<()> {
+        let mut listed: Vec<String> = reference_counts()?.into_iter().map(|(q, _)| q).collect();
+        if listed.is_empty() {
+            eprintln!(
+                "skipping test_counts_file: queries/counts.csv is empty, run queries/counts.sh"
+            );
+            return Ok(());
+        }
+        listed.sort();
+        let mut registered: Vec<String> = registry::QUERIES
+            .iter()
+            .map(|entry| entry.id.to_string())
+            .collect();
+        registered.sort();
+        assert_eq!(
+            listed, registered,
+            "queries/counts.csv must list every query, run queries/counts.sh"
+        );
+        Ok(())
+    }
+
+    /// Compares `COUNT(*)` of every query with its reference count.
+    #[test]
+    fn test_counts() -> PolarsResult<()> {
+        let counts = reference_counts()?;
+        let Some(data) = test_support::data() else {
+            return Ok(());
+        };
+        assert_eq!(
+            counts.len(),
+            registry::QUERIES.len(),
+            "queries/counts.csv must list every query, run queries/counts.sh"
+        );
+        for (query, count) in counts {
+            let enumerator = JoinEnumerator::new(data, registry::get(&query)?.sql)?;
+            assert_eq!(enumerator.count(), count, "{query}");
+        }
+        Ok(())
+    }
+}
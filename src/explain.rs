@@ -0,0 +1,384 @@
+use crate::bloom::Filter;
+use crate::dense::{DenseMap, DenseSet};
+use crate::hashtable::{I32Map, I32Set};
+use crate::parallel::{PartitionedMap, PartitionedSet};
+use crate::predicate::Selection;
+use crate::roaring::RoaringSet;
+use crate::spill::GraceTable;
+use std::cell::RefCell;
+use std::fmt::{Display, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, Instant};
+
+/// How finished plans are reported, if at all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Off,
+    /// An `EXPLAIN ANALYZE`-style tree on stderr.
+    Tree,
+    /// One JSON object per query on stderr.
+    Json,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(0);
+
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Relaxed) {
+        0 => Mode::Off,
+        1 => Mode::Tree,
+        _ => Mode::Json,
+    }
+}
+
+pub fn set_mode(mode: Mode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// A step of a query plan as it ran: the rows it read and produced, and the time it took,
+/// including its children's.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub name: String,
+    pub rows_in: Option<u64>,
+    pub rows_out: Option<u64>,
+    pub elapsed: Duration,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    fn new(name: String, rows_in: Option<u64>) -> Self {
+        Node {
+            name,
+            rows_in,
+            rows_out: None,
+            elapsed: Duration::ZERO,
+            children: Vec::new(),
+        }
+    }
+
+    /// Renders the plan as an indented tree, one node per line.
+    pub fn tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, "", "");
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, first: &str, rest: &str) {
+        write!(out, "{first}{}", self.name).unwrap();
+        if let Some(rows) = self.rows_in {
+            write!(out, "  rows in={rows}").unwrap();
+        }
+        if let Some(rows) = self.rows_out {
+            write!(out, "  rows out={rows}").unwrap();
+        }
+        writeln!(out, "  time={:.6}s", self.elapsed.as_secs_f64()).unwrap();
+        for (i, child) in self.children.iter().enumerate() {
+            let (first, next) = if i + 1 == self.children.len() {
+                ("└─ ", "   ")
+            } else {
+                ("├─ ", "│  ")
+            };
+            child.write_tree(out, &format!("{rest}{first}"), &format!("{rest}{next}"));
+        }
+    }
+
+    /// Renders the plan as a JSON object.
+    pub fn json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    fn write_json(&self, out: &mut String) {
+        out.push_str("{\"name\":\"");
+        for c in self.name.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+        for (key, rows) in [("rows_in", self.rows_in), ("rows_out", self.rows_out)] {
+            match rows {
+                Some(rows) => write!(out, ",\"{key}\":{rows}").unwrap(),
+                None => write!(out, ",\"{key}\":null").unwrap(),
+            }
+        }
+        write!(
+            out,
+            ",\"seconds\":{},\"children\":[",
+            self.elapsed.as_secs_f64()
+        )
+        .unwrap();
+        for (i, child) in self.children.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            child.write_json(out);
+        }
+        out.push_str("]}");
+    }
+}
+
+thread_local! {
+    /// The nodes of the query being explained on this thread, innermost last, with the times
+    /// they started.
+    static STACK: RefCell<Vec<(Node, Instant)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Explains a query while in scope, reporting its plan according to `mode()` when dropped.
+/// Nodes recorded with `node` on the same thread become part of its plan. A query started while
+/// another is explained on the same thread becomes a node of that one's plan instead.
+pub struct Query {
+    active: bool,
+    nested: bool,
+}
+
+/// Starts explaining the query `name`. Does nothing unless explaining is enabled.
+pub fn query(name: impl Display) -> Query {
+    let active = mode() != Mode::Off;
+    let nested = active && open(&name, None);
+    if active && !nested {
+        STACK.with_borrow_mut(|stack| {
+            stack.push((Node::new(name.to_string(), None), Instant::now()))
+        });
+    }
+    Query { active, nested }
+}
+
+impl Query {
+    /// Ends the query, returning its plan instead of reporting it.
+    pub fn finish(mut self) -> Option<Node> {
+        self.end()
+    }
+
+    fn end(&mut self) -> Option<Node> {
+        if !std::mem::take(&mut self.active) {
+            return None;
+        }
+        if self.nested {
+            close(None);
+            return None;
+        }
+        let (mut root, start) = STACK.with_borrow_mut(|stack| stack.pop())?;
+        root.elapsed = start.elapsed();
+        Some(root)
+    }
+}
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        if let Some(root) = self.end() {
+            match mode() {
+                Mode::Tree => eprint!("{}", root.tree()),
+                Mode::Json => eprintln!("{}", root.json()),
+                Mode::Off => {}
+            }
+        }
+    }
+}
+
+/// Runs `f` as the plan node `name`, which reads `rows_in` rows and produces `f`'s result, and
+/// records it if a query is being explained on this thread. Otherwise only checks a flag, so
+/// queries can stay instrumented when explaining is off.
+#[inline]
+pub fn node<T: Cardinality>(name: impl Display, rows_in: usize, f: impl FnOnce() -> T) -> T {
+    if !open(name, Some(rows_in as u64)) {
+        return f();
+    }
+    let result = f();
+    close(Some(result.cardinality()));
+    result
+}
+
+/// Starts the plan node `name` under the innermost open node, returning whether a query is being
+/// explained on this thread and so whether the node must be ended with `close`.
+pub(crate) fn open(name: impl Display, rows_in: Option<u64>) -> bool {
+    if mode() == Mode::Off || STACK.with_borrow(Vec::is_empty) {
+        return false;
+    }
+    STACK.with_borrow_mut(|stack| {
+        stack.push((Node::new(name.to_string(), rows_in), Instant::now()))
+    });
+    true
+}
+
+/// Ends the innermost node started by `open`, which produced `rows_out` rows if known.
+pub(crate) fn close(rows_out: Option<u64>) {
+    STACK.with_borrow_mut(|stack| {
+        let (mut node, start) = stack.pop().unwrap();
+        node.elapsed = start.elapsed();
+        node.rows_out = rows_out;
+        stack.last_mut().unwrap().0.children.push(node);
+    });
+}
+
+/// The number of rows or entries in the result of a plan node.
+pub trait Cardinality {
+    fn cardinality(&self) -> u64;
+}
+
+impl<T: Cardinality> Cardinality for &T {
+    fn cardinality(&self) -> u64 {
+        (*self).cardinality()
+    }
+}
+
+impl<T> Cardinality for Option<T> {
+    fn cardinality(&self) -> u64 {
+        self.is_some() as u64
+    }
+}
+
+impl<T> Cardinality for Vec<T> {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<T, S> Cardinality for std::collections::HashSet<T, S> {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<K, V, S> Cardinality for std::collections::HashMap<K, V, S> {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<V> Cardinality for DenseMap<V> {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl Cardinality for DenseSet {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<V: Copy + Default> Cardinality for I32Map<V> {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl Cardinality for I32Set {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<K: std::hash::Hash + Eq + Send> Cardinality for PartitionedSet<K> {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<K: std::hash::Hash + Eq + Send, V: Send> Cardinality for PartitionedMap<K, V> {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl Cardinality for Filter {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl Cardinality for Selection {
+    fn cardinality(&self) -> u64 {
+        self.count() as u64
+    }
+}
+
+impl Cardinality for RoaringSet {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl Cardinality for GraceTable {
+    fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl Cardinality for u64 {
+    /// A count computed by the node, such as the size of a join result.
+    fn cardinality(&self) -> u64 {
+        *self
+    }
+}
+
+#[cfg(test)]
+mod test_explain {
+    use super::*;
+    use crate::timer::{Phase, QueryTimer};
+
+    #[test]
+    fn test_explain() {
+        // Explaining is process-wide, but plans are recorded per thread, so other tests that
+        // run instrumented code concurrently are unaffected.
+        set_mode(Mode::Tree);
+        let query = query("q");
+        let set = node("s: filter", 10, || {
+            node("inner", 10, || {
+                (0..10).filter(|i| i % 2 == 0).collect::<Vec<i32>>()
+            })
+        });
+        let min = node(format_args!("min over {}", "t"), 3, || set.first().copied());
+        assert_eq!(min, Some(0));
+        let plan = query.finish().unwrap();
+
+        assert_eq!(plan.name, "q");
+        assert_eq!(plan.children.len(), 2);
+        assert_eq!(plan.children[0].rows_in, Some(10));
+        assert_eq!(plan.children[0].rows_out, Some(5));
+        assert_eq!(plan.children[0].children[0].name, "inner");
+        assert_eq!(plan.children[1].name, "min over t");
+        assert_eq!(plan.children[1].rows_out, Some(1));
+
+        let tree = plan.tree();
+        let lines: Vec<&str> = tree.lines().collect();
+        assert!(lines[0].starts_with("q  time="));
+        assert!(lines[1].starts_with("├─ s: filter  rows in=10  rows out=5  time="));
+        assert!(lines[2].starts_with("│  └─ inner  rows in=10  rows out=5  time="));
+        assert!(lines[3].starts_with("└─ min over t  rows in=3  rows out=1  time="));
+
+        let json = plan.json();
+        assert!(
+            json.starts_with("{\"name\":\"q\",\"rows_in\":null,\"rows_out\":null,\"seconds\":")
+        );
+        assert!(json.contains("{\"name\":\"inner\",\"rows_in\":10,\"rows_out\":5,\"seconds\":"));
+        assert_eq!(json.matches('{').count(), 4);
+
+        // Outside an explained query, nodes only run their closure.
+        assert_eq!(node("unrecorded", 0, || Some(1)), Some(1));
+        assert!(STACK.with_borrow(Vec::is_empty));
+
+        // Every query explains its phases through its timer, with its nodes inside them.
+        let query = self::query("timed");
+        let mut timer = QueryTimer::start("timed");
+        timer.phase(Phase::Build);
+        node("h: build", 4, || vec![1, 2]);
+        timer.phase(Phase::Probe);
+        timer.finish();
+        let plan = query.finish().unwrap();
+        set_mode(Mode::Off);
+        let phases: Vec<&str> = plan.children[0]
+            .children
+            .iter()
+            .map(|n| n.name.as_str())
+            .collect();
+        assert_eq!(phases, ["setup", "build", "probe"]);
+        assert_eq!(plan.children[0].children[1].children[0].name, "h: build");
+        assert!(STACK.with_borrow(Vec::is_empty));
+    }
+}
@@ -0,0 +1,169 @@
+use crate::bench::{self, json_string};
+use crate::data::Data;
+use crate::json::{self, Value};
+use crate::registry::{Answer, Entry, QUERIES};
+use crate::timer;
+use polars::prelude::*;
+use std::io::Write;
+use std::path::Path;
+
+/// The golden file, relative to the project root like `queries/` and `imdb/`.
+pub const PATH: &str = "queries/golden.json";
+
+/// Reads a golden file: a JSON object that maps every query id to its answer on the IMDB data,
+/// the values of its `MIN` columns as strings or `null` if no row qualifies.
+pub fn read(path: &Path) -> PolarsResult<Vec<(String, Answer)>> {
+    let context = |message: &str| polars_err!(ComputeError: "{}: {message}", path.display());
+    let text = std::fs::read_to_string(path).map_err(|e| context(&e.to_string()))?;
+    let document = json::parse(&text).map_err(|e| context(&e.to_string()))?;
+    document
+        .as_object()
+        .ok_or_else(|| context("not an object"))?
+        .iter()
+        .map(|(query, answer)| {
+            let answer = match answer {
+                Value::Null => Answer(None),
+                Value::Array(values) => Answer(Some(
+                    values
+                        .iter()
+                        .map(|value| value.as_str().map(str::to_string))
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| {
+                            context(&format!("{query} has a value that is no string"))
+                        })?,
+                )),
+                _ => return Err(context(&format!("{query} is neither an array nor null"))),
+            };
+            Ok((query.clone(), answer))
+        })
+        .collect()
+}
+
+/// Writes `answers` as a golden file, one query per line.
+pub fn write(out: &mut impl Write, answers: &[(String, Answer)]) -> PolarsResult<()> {
+    writeln!(out, "{{")?;
+    for (i, (query, answer)) in answers.iter().enumerate() {
+        let answer = match &answer.0 {
+            None => "null".to_string(),
+            Some(values) => {
+                let values: Vec<String> = values.iter().map(|value| json_string(value)).collect();
+                format!("[{}]", values.join(", "))
+            }
+        };
+        let comma = if i + 1 < answers.len() { "," } else { "" };
+        writeln!(out, "  {}: {answer}{comma}", json_string(query))?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// Runs `queries` and stores their answers in the golden file at `path`, keeping the answers of
+/// the other queries. Prints `query,old,new` for every answer that changed, and fails without
+/// writing anything if a query fails.
+pub fn update(data: &Data, queries: &[&Entry], path: &Path) -> PolarsResult<()> {
+    let mut answers = if path.exists() {
+        read(path)?
+    } else {
+        Vec::new()
+    };
+    println!("query,old,new");
+    let mut changed = 0;
+    for query in queries {
+        let (answer, _) = timer::capture(|| (query.run)(data));
+        let answer = answer?;
+        let old = answers.iter().position(|(id, _)| id == query.id);
+        if old.is_some_and(|i| answers[i].1 == answer) {
+            continue;
+        }
+        changed += 1;
+        println!(
+            "{},{},{}",
+            query.id,
+            bench::csv_field(&old.map_or(String::new(), |i| answers[i].1.to_string())),
+            bench::csv_field(&answer.to_string())
+        );
+        match old {
+            Some(i) => answers[i].1 = answer,
+            None => answers.push((query.id.to_string(), answer)),
+        }
+    }
+    // Queries in the order of the registry, and any that it no longer has last.
+    answers.sort_by_key(|(id, _)| {
+        QUERIES
+            .iter()
+            .position(|entry| entry.id == id)
+            .unwrap_or(QUERIES.len())
+    });
+    let mut out = Vec::new();
+    write(&mut out, &answers)?;
+    std::fs::write(path, out)?;
+    eprintln!(
+        "{changed} of {} answers changed in {}",
+        queries.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_golden {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_golden_file() -> PolarsResult<()> {
+        let golden = read(Path::new(PATH))?;
+        let ids: Vec<&str> = golden.iter().map(|(id, _)| id.as_str()).collect();
+        let registered: Vec<&str> = QUERIES.iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, registered);
+
+        let mut out = Vec::new();
+        write(&mut out, &golden)?;
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            std::fs::read_to_string(PATH)?
+        );
+
+        for invalid in ["[]", r#"{"1a": "x"}"#, r#"{"1a": [1]}"#] {
+            let path = std::env::temp_dir().join(format!("lfj-golden-{}.json", std::process::id()));
+            std::fs::write(&path, invalid)?;
+            let result = read(&path);
+            std::fs::remove_file(&path)?;
+            assert!(result.is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+
+    /// Runs every registered query on one load of the IMDB data and compares it with the golden
+    /// file, reporting all the queries that differ at once.
+    #[test]
+    fn test_queries() -> PolarsResult<()> {
+        let golden = read(Path::new(PATH))?;
+        let Some(data) = test_support::data() else {
+            return Ok(());
+        };
+        let mut failures = Vec::new();
+        for query in QUERIES {
+            let expected = golden
+                .iter()
+                .find(|(id, _)| id == query.id)
+                .map(|(_, answer)| answer);
+            match ((query.run)(data), expected) {
+                (Ok(answer), Some(expected)) if answer == *expected => {}
+                (Ok(answer), Some(expected)) => {
+                    failures.push(format!("{}: {answer}, expected {expected}", query.id))
+                }
+                (Ok(_), None) => failures.push(format!("{}: not in {PATH}", query.id)),
+                (Err(e), _) => failures.push(format!("{}: {e}", query.id)),
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "{} of {} queries differ from {PATH}, run --update-golden if that is intended:\n{}",
+            failures.len(),
+            QUERIES.len(),
+            failures.join("\n")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+use crate::bench::{self, Format};
+use crate::data::Data;
+use crate::dense::DenseKey;
+use crate::registry::Entry;
+use crate::timer;
+use ahash::HashMap;
+use polars::prelude::*;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Keys hashed and prefetched together before any of them is resolved.
+const BATCH: usize = 16;
+
+/// Marks an empty slot. The key itself is stored outside the slots.
+const EMPTY: i32 = i32::MIN;
+
+/// How `I32Map` and `I32Set` store their entries and probe them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Probe {
+    /// An `ahash` map, probed one key at a time.
+    Ahash,
+    /// An open-addressing table, probed in batches that prefetch their slots.
+    Prefetch,
+}
+
+impl FromStr for Probe {
+    type Err = PolarsError;
+
+    fn from_str(s: &str) -> PolarsResult<Self> {
+        match s {
+            "ahash" => Ok(Probe::Ahash),
+            "prefetch" => Ok(Probe::Prefetch),
+            _ => polars_bail!(InvalidOperation: "unknown probe {s}, expected ahash or prefetch"),
+        }
+    }
+}
+
+impl std::fmt::Display for Probe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Probe::Ahash => write!(f, "ahash"),
+            Probe::Prefetch => write!(f, "prefetch"),
+        }
+    }
+}
+
+static AHASH: AtomicBool = AtomicBool::new(false);
+
+/// The probe that new tables use, `Probe::Prefetch` unless set otherwise.
+pub fn probe() -> Probe {
+    if AHASH.load(Ordering::Relaxed) {
+        Probe::Ahash
+    } else {
+        Probe::Prefetch
+    }
+}
+
+pub fn set_probe(probe: Probe) {
+    AHASH.store(probe == Probe::Ahash, Ordering::Relaxed);
+}
+
+#[inline]
+fn prefetch<T>(p: *const T) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: prefetching is only a hint and never faults, whatever the address.
+    unsafe {
+        std::arch::x86_64::_mm_prefetch::<{ std::arch::x86_64::_MM_HINT_T0 }>(p as *const i8);
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = p;
+}
+
+/// A linear-probing hash table from `i32` keys to values, with keys and values in separate
+/// arrays so that probing scans 16 keys per cache line and touches a value only on a hit.
+#[derive(Clone, Debug)]
+struct OpenTable<V> {
+    keys: Vec<i32>,
+    values: Vec<V>,
+    /// `64 - log2(keys.len())`, the shift that takes a hash to a slot.
+    shift: u32,
+    /// The value of the key `EMPTY`, which cannot be stored in the slots.
+    empty: Option<V>,
+}
+
+impl<V: Copy + Default> OpenTable<V> {
+    fn new(entries: Vec<(i32, V)>) -> Self {
+        // At most half full, so that probe sequences stay short.
+        let slots = (entries.len() * 2).max(BATCH).next_power_of_two();
+        let mut table = OpenTable {
+            keys: vec![EMPTY; slots],
+            values: vec![V::default(); slots],
+            shift: 64 - slots.trailing_zeros(),
+            empty: None,
+        };
+        for (key, value) in entries {
+            if key == EMPTY {
+                table.empty = Some(value);
+                continue;
+            }
+            let mut slot = table.slot(key);
+            while table.keys[slot] != EMPTY && table.keys[slot] != key {
+                slot = (slot + 1) & (slots - 1);
+            }
+            table.keys[slot] = key;
+            table.values[slot] = value;
+        }
+        table
+    }
+
+    #[inline]
+    fn slot(&self, key: i32) -> usize {
+        ((key as u32 as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> self.shift) as usize
+    }
+
+    /// Looks `key` up starting from its home `slot`.
+    #[inline]
+    fn find(&self, mut slot: usize, key: i32) -> Option<&V> {
+        if key == EMPTY {
+            return self.empty.as_ref();
+        }
+        loop {
+            match self.keys[slot] {
+                k if k == key => return Some(&self.values[slot]),
+                EMPTY => return None,
+                _ => slot = (slot + 1) & (self.keys.len() - 1),
+            }
+        }
+    }
+
+    #[inline]
+    fn get(&self, key: i32) -> Option<&V> {
+        self.find(self.slot(key), key)
+    }
+
+    fn len(&self) -> usize {
+        self.keys.iter().filter(|&&key| key != EMPTY).count() + self.empty.is_some() as usize
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Table<V> {
+    Ahash(HashMap<i32, V>),
+    Open(OpenTable<V>),
+}
+
+/// A map from `i32` keys to values, stored as chosen by `probe()` when it is collected. Besides
+/// single lookups it probes whole columns with `probe_rows`, which with `Probe::Prefetch` hashes a
+/// batch of keys and prefetches their slots before resolving any of them, so that the cache
+/// misses of a batch overlap instead of stalling one after another.
+#[derive(Clone, Debug)]
+pub struct I32Map<V> {
+    table: Table<V>,
+}
+
+impl<K: DenseKey, V: Copy + Default> FromIterator<(K, V)> for I32Map<V> {
+    /// Collects the entries, keeping the last value of a repeated key like `HashMap` does.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let entries = entries.into_iter().map(|(key, value)| (key.id(), value));
+        let table = match probe() {
+            Probe::Ahash => Table::Ahash(entries.collect()),
+            Probe::Prefetch => Table::Open(OpenTable::new(entries.collect())),
+        };
+        I32Map { table }
+    }
+}
+
+impl<V: Copy + Default> I32Map<V> {
+    #[inline]
+    pub fn get(&self, key: &impl DenseKey) -> Option<&V> {
+        match &self.table {
+            Table::Ahash(map) => map.get(&key.id()),
+            Table::Open(table) => table.get(key.id()),
+        }
+    }
+
+    pub fn contains_key(&self, key: &impl DenseKey) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.table {
+            Table::Ahash(map) => map.len(),
+            Table::Open(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Looks up `keys[row]` for each of `rows`, calling `f(row, value)` on every hit in the
+    /// order of `rows`.
+    pub fn probe_rows(
+        &self,
+        keys: &[i32],
+        rows: impl IntoIterator<Item = usize>,
+        mut f: impl FnMut(usize, &V),
+    ) {
+        match &self.table {
+            Table::Ahash(map) => {
+                for row in rows {
+                    if let Some(value) = map.get(&keys[row]) {
+                        f(row, value);
+                    }
+                }
+            }
+            Table::Open(table) => {
+                let mut rows = rows.into_iter();
+                let mut batch = [(0, 0); BATCH];
+                loop {
+                    let mut n = 0;
+                    for (entry, row) in batch.iter_mut().zip(&mut rows) {
+                        let slot = table.slot(keys[row]);
+                        prefetch(&table.keys[slot]);
+                        *entry = (row, slot);
+                        n += 1;
+                    }
+                    for &(row, slot) in &batch[..n] {
+                        if let Some(value) = table.find(slot, keys[row]) {
+                            f(row, value);
+                        }
+                    }
+                    if n < BATCH {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Looks up every key in `keys` like `probe_rows`.
+    pub fn probe_all(&self, keys: &[i32], f: impl FnMut(usize, &V)) {
+        self.probe_rows(keys, 0..keys.len(), f);
+    }
+}
+
+/// A set of `i32` keys, stored and probed like `I32Map`.
+#[derive(Clone, Debug)]
+pub struct I32Set {
+    map: I32Map<()>,
+}
+
+impl<K: DenseKey> FromIterator<K> for I32Set {
+    fn from_iter<I: IntoIterator<Item = K>>(keys: I) -> Self {
+        I32Set {
+            map: keys.into_iter().map(|key| (key, ())).collect(),
+        }
+    }
+}
+
+impl I32Set {
+    #[inline]
+    pub fn contains(&self, key: &impl DenseKey) -> bool {
+        self.map.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Calls `f(row)` for each of `rows` whose `keys[row]` is in the set, in the order of `rows`.
+    pub fn probe_rows(
+        &self,
+        keys: &[i32],
+        rows: impl IntoIterator<Item = usize>,
+        mut f: impl FnMut(usize),
+    ) {
+        self.map.probe_rows(keys, rows, |row, _| f(row));
+    }
+
+    /// Checks every key in `keys` like `probe_rows`.
+    pub fn probe_all(&self, keys: &[i32], f: impl FnMut(usize)) {
+        self.probe_rows(keys, 0..keys.len(), f);
+    }
+}
+
+/// Runs those of `queries` that build an `I32Set` or `I32Map` with each probe, and writes
+/// `query,ahash_seconds,prefetch_seconds,speedup` rows to `out` in `format`, with the measured
+/// times, without the setup phase, and the ahash time over the prefetch time.
+pub fn report(
+    db: &Data,
+    queries: &[&'static Entry],
+    out: &mut impl Write,
+    format: Format,
+) -> PolarsResult<()> {
+    let configured = probe();
+    let mut rows = Vec::new();
+    for query in queries
+        .iter()
+        .filter(|query| query.uses("I32Set") || query.uses("I32Map"))
+    {
+        let mut results = vec![];
+        let mut seconds = vec![];
+        for probe in [Probe::Ahash, Probe::Prefetch] {
+            set_probe(probe);
+            let (result, phases) = timer::capture(|| (query.run)(db));
+            results.push(result?);
+            seconds.push(phases.measured().as_secs_f64());
+        }
+        polars_ensure!(
+            results[0] == results[1],
+            ComputeError: "{} differs between probes: {} and {}", query.id, results[0], results[1]
+        );
+        rows.push([
+            query.id.to_string(),
+            seconds[0].to_string(),
+            seconds[1].to_string(),
+            (seconds[0] / seconds[1]).to_string(),
+        ]);
+    }
+    set_probe(configured);
+    let header = ["query", "ahash_seconds", "prefetch_seconds", "speedup"];
+    bench::write_rows(out, format, &header, &rows)
+}
+
+#[cfg(test)]
+mod test_hashtable {
+    use super::*;
+
+    #[test]
+    fn test_i32_map() {
+        // Both probes in one test, since the probe is process-wide and tests run concurrently.
+        for probe in [Probe::Ahash, Probe::Prefetch] {
+            set_probe(probe);
+            let entries: Vec<(i32, i32)> = (0..5_000)
+                .map(|i| ((i * 7919) % 100_003 - 50_000, i))
+                .chain([(EMPTY, -1), (3, -2), (3, -3)])
+                .collect();
+            let map: I32Map<i32> = entries.iter().map(|&(key, value)| (key, value)).collect();
+            let expected: HashMap<i32, i32> = entries.iter().copied().collect();
+            assert_eq!(map.len(), expected.len());
+            for key in -60_000..60_000 {
+                assert_eq!(map.get(&key), expected.get(&key), "{probe} {key}");
+            }
+            assert_eq!(map.get(&EMPTY), Some(&-1));
+
+            let keys: Vec<i32> = (-60_000..60_000).chain([EMPTY]).collect();
+            let mut hits = vec![];
+            map.probe_rows(&keys, (0..keys.len()).step_by(3), |row, value| {
+                hits.push((row, *value))
+            });
+            let expected_hits: Vec<(usize, i32)> = (0..keys.len())
+                .step_by(3)
+                .filter_map(|row| expected.get(&keys[row]).map(|value| (row, *value)))
+                .collect();
+            assert_eq!(hits, expected_hits, "{probe}");
+
+            let set: I32Set = entries.iter().map(|(key, _)| key).collect();
+            let mut rows = vec![];
+            set.probe_all(&keys, |row| rows.push(row));
+            assert!(rows.iter().all(|&row| expected.contains_key(&keys[row])));
+            assert_eq!(rows.len(), expected.len());
+        }
+        set_probe(Probe::Prefetch);
+    }
+}
@@ -0,0 +1,242 @@
+use polars::prelude::*;
+
+/// A JSON value, as read from the files this crate writes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in the order of the document.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The member `key` of an object, `None` for other values and missing members.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a JSON document.
+pub fn parse(text: &str) -> PolarsResult<Value> {
+    let mut parser = Parser { text, pos: 0 };
+    let value = parser.value()?;
+    parser.whitespace();
+    polars_ensure!(
+        parser.pos == text.len(),
+        ComputeError: "trailing characters at offset {} of JSON", parser.pos
+    );
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn error<T>(&self, expected: &str) -> PolarsResult<T> {
+        polars_bail!(ComputeError: "expected {expected} at offset {} of JSON", self.pos)
+    }
+
+    fn expect(&mut self, token: &str) -> PolarsResult<()> {
+        if self.text[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            Ok(())
+        } else {
+            self.error(token)
+        }
+    }
+
+    fn value(&mut self) -> PolarsResult<Value> {
+        self.whitespace();
+        match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(values));
+                        }
+                        _ => return self.error(", or ]"),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.whitespace();
+                    let key = self.string()?;
+                    self.whitespace();
+                    self.expect(":")?;
+                    members.push((key, self.value()?));
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(members));
+                        }
+                        _ => return self.error(", or }"),
+                    }
+                }
+            }
+            _ => {
+                let rest = &self.text[self.pos..];
+                let len = rest
+                    .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+                    .unwrap_or(rest.len());
+                match rest[..len].parse() {
+                    Ok(n) if len > 0 => {
+                        self.pos += len;
+                        Ok(Value::Number(n))
+                    }
+                    _ => self.error("a value"),
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> PolarsResult<String> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(s);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        let unit = u32::from_str_radix(&hex, 16).ok();
+                        match unit.and_then(char::from_u32) {
+                            Some(c) => s.push(c),
+                            None => {
+                                self.pos += i;
+                                return self.error("a \\u escape of a scalar value");
+                            }
+                        }
+                    }
+                    _ => {
+                        self.pos += i;
+                        return self.error("an escape");
+                    }
+                },
+                c => s.push(c),
+            }
+        }
+        self.pos = self.text.len();
+        self.error("\"")
+    }
+}
+
+#[cfg(test)]
+mod test_json {
+    use super::*;
+    use crate::bench::json_string;
+
+    #[test]
+    fn test_parse() -> PolarsResult<()> {
+        let value = parse(r#" {"a": [1, -2.5e3, null, true], "b": {"c": "x\"\\\né"}, "d": []} "#)?;
+        assert_eq!(
+            value.get("a").and_then(Value::as_array),
+            Some(
+                &[
+                    Value::Number(1.0),
+                    Value::Number(-2500.0),
+                    Value::Null,
+                    Value::Bool(true)
+                ][..]
+            )
+        );
+        assert_eq!(
+            value
+                .get("b")
+                .and_then(|b| b.get("c"))
+                .and_then(Value::as_str),
+            Some("x\"\\\né")
+        );
+        assert_eq!(value.get("d"), Some(&Value::Array(Vec::new())));
+        assert_eq!(value.get("e"), None);
+
+        let s = "a \"quoted\"\tline\u{1}";
+        assert_eq!(parse(&json_string(s))?, Value::String(s.to_string()));
+
+        for invalid in ["", "[1,]", "{\"a\" 1}", "\"open", "[1] 2", "nul"] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+}
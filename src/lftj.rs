@@ -0,0 +1,508 @@
+use crate::cancel::{self, Outcome};
+use crate::data::Data;
+use crate::enumerate::{JoinEnumerator, bind, value, variables};
+use crate::predicate::{Column, Selection};
+use crate::registry::QUERIES;
+use crate::sql::{ColumnRef, Query};
+use polars::prelude::*;
+use std::time::Instant;
+
+/// The selected rows of a relation sorted on its join variables, read as a trie with one level
+/// per variable.
+struct Trie {
+    /// `levels[d][i]` is the value of the `d`-th variable in the `i`-th tuple.
+    levels: Vec<Vec<i32>>,
+    /// The row number of the `i`-th tuple.
+    rows: Vec<u32>,
+}
+
+impl Trie {
+    /// Sorts the selected rows of `columns`, skipping rows with a NULL in any of them.
+    fn new(selection: &Selection, columns: &[Column]) -> Self {
+        let mut tuples: Vec<(Vec<i32>, u32)> = selection
+            .iter()
+            .filter_map(|row| {
+                let key = columns
+                    .iter()
+                    .map(|column| value(column, row))
+                    .collect::<Option<Vec<i32>>>()?;
+                Some((key, row as u32))
+            })
+            .collect();
+        tuples.sort_unstable();
+
+        let mut levels = vec![Vec::with_capacity(tuples.len()); columns.len()];
+        let mut rows = Vec::with_capacity(tuples.len());
+        for (key, row) in tuples {
+            for (level, value) in levels.iter_mut().zip(key) {
+                level.push(value);
+            }
+            rows.push(row);
+        }
+        Trie { levels, rows }
+    }
+}
+
+/// A cursor into a `Trie`, positioned on a key at each open level.
+struct TrieIter<'t> {
+    trie: &'t Trie,
+    /// `(position, end)` of every open level, where `position..end` are the tuples left that
+    /// agree with the keys of the levels above.
+    open: Vec<(usize, usize)>,
+}
+
+impl<'t> TrieIter<'t> {
+    fn new(trie: &'t Trie) -> Self {
+        TrieIter {
+            trie,
+            open: Vec::with_capacity(trie.levels.len()),
+        }
+    }
+
+    /// Descends to the first key below the current one.
+    fn open(&mut self) {
+        let range = match self.open.last() {
+            Some(&(position, end)) => (position, self.run_end(position, end)),
+            None => (0, self.trie.rows.len()),
+        };
+        self.open.push(range);
+    }
+
+    fn up(&mut self) {
+        self.open.pop();
+    }
+
+    fn key(&self) -> i32 {
+        let &(position, _) = self.open.last().unwrap();
+        self.trie.levels[self.open.len() - 1][position]
+    }
+
+    fn at_end(&self) -> bool {
+        let &(position, end) = self.open.last().unwrap();
+        position >= end
+    }
+
+    /// Moves to the next distinct key.
+    fn next(&mut self) {
+        let &(position, end) = self.open.last().unwrap();
+        let next = self.run_end(position, end);
+        self.open.last_mut().unwrap().0 = next;
+    }
+
+    /// Moves to the first key at least `key`.
+    fn seek(&mut self, key: i32) {
+        let &(position, end) = self.open.last().unwrap();
+        let level = &self.trie.levels[self.open.len() - 1];
+        let next = position + gallop(&level[position..end], |value| value < key);
+        self.open.last_mut().unwrap().0 = next;
+    }
+
+    /// The rows whose keys equal the current key of every open level.
+    fn rows(&self) -> &'t [u32] {
+        let &(position, end) = self.open.last().unwrap();
+        &self.trie.rows[position..self.run_end(position, end)]
+    }
+
+    /// End of the run of tuples at the current level with the same key as `position`.
+    fn run_end(&self, position: usize, end: usize) -> usize {
+        let level = &self.trie.levels[self.open.len() - 1];
+        let key = level[position];
+        position + gallop(&level[position..end], |value| value <= key)
+    }
+}
+
+/// The number of leading elements of `values` that satisfy `pred`, which must hold for a prefix,
+/// found by exponential then binary search so that short skips stay cheap.
+fn gallop(values: &[i32], pred: impl Fn(i32) -> bool) -> usize {
+    let mut bound = 1;
+    while bound < values.len() && pred(values[bound]) {
+        bound *= 2;
+    }
+    let lo = bound / 2;
+    let hi = (bound + 1).min(values.len());
+    lo + values[lo..hi].partition_point(|&value| pred(value))
+}
+
+/// A Leapfrog Triejoin over the tries of the relations, binding one variable at a time in a
+/// fixed order.
+struct Leapfrog {
+    tries: Vec<Trie>,
+    /// The relations with each variable, in variable order.
+    participants: Vec<Vec<usize>>,
+    /// Relations with no join variables, which are joined as a cross product.
+    unjoined: Vec<(usize, Vec<u32>)>,
+}
+
+impl Leapfrog {
+    /// Builds the tries for `relations`, given as `(variable, column)` join columns, with the
+    /// variables bound in `order`.
+    fn new(selections: &[Selection], relations: &[Vec<(usize, Column)>], order: &[usize]) -> Self {
+        let rank = |var: usize| order.iter().position(|&v| v == var).unwrap();
+        let mut participants = vec![Vec::new(); order.len()];
+        let mut tries = Vec::new();
+        let mut unjoined = Vec::new();
+        for (r, (selection, columns)) in selections.iter().zip(relations).enumerate() {
+            let mut columns = columns.clone();
+            columns.sort_by_key(|&(var, _)| rank(var));
+            for &(var, _) in &columns {
+                participants[rank(var)].push(r);
+            }
+            if columns.is_empty() {
+                unjoined.push((r, selection.to_indices()));
+            }
+            let columns: Vec<Column> = columns.into_iter().map(|(_, column)| column).collect();
+            tries.push(Trie::new(selection, &columns));
+        }
+        Leapfrog {
+            tries,
+            participants,
+            unjoined,
+        }
+    }
+
+    /// Calls `f` with the matching rows of every relation, one slice per relation, for every
+    /// binding of the variables that all relations agree on.
+    fn for_each_binding(&self, mut f: impl FnMut(&[&[u32]])) {
+        let mut iters: Vec<TrieIter> = self.tries.iter().map(TrieIter::new).collect();
+        let mut rows: Vec<&[u32]> = vec![&[]; self.tries.len()];
+        for (r, unjoined) in &self.unjoined {
+            rows[*r] = unjoined;
+        }
+        self.bind(0, &mut iters, &mut rows, &mut f);
+    }
+
+    fn bind<'t>(
+        &self,
+        depth: usize,
+        iters: &mut [TrieIter<'t>],
+        rows: &mut Vec<&'t [u32]>,
+        f: &mut impl FnMut(&[&[u32]]),
+    ) {
+        let Some(participants) = self.participants.get(depth) else {
+            f(rows);
+            return;
+        };
+        for &r in participants {
+            iters[r].open();
+        }
+        self.leapfrog(participants, iters, |iters| {
+            for &r in participants {
+                if iters[r].open.len() == iters[r].trie.levels.len() {
+                    rows[r] = iters[r].rows();
+                }
+            }
+            self.bind(depth + 1, iters, rows, f);
+        });
+        for &r in participants {
+            iters[r].up();
+        }
+    }
+
+    /// Calls `found` with the iterators of `participants` positioned on each key they all have.
+    fn leapfrog<'t>(
+        &self,
+        participants: &[usize],
+        iters: &mut [TrieIter<'t>],
+        mut found: impl FnMut(&mut [TrieIter<'t>]),
+    ) {
+        if participants.iter().any(|&r| iters[r].at_end()) {
+            return;
+        }
+        let mut order = participants.to_vec();
+        order.sort_by_key(|&r| iters[r].key());
+        let k = order.len();
+        let mut max = iters[order[k - 1]].key();
+        let mut p = 0;
+        loop {
+            if cancel::poll() {
+                return;
+            }
+            let r = order[p];
+            let key = iters[r].key();
+            if key == max {
+                found(iters);
+                iters[r].next();
+            } else {
+                iters[r].seek(max);
+            }
+            if iters[r].at_end() {
+                return;
+            }
+            max = iters[r].key();
+            p = (p + 1) % k;
+        }
+    }
+}
+
+/// Enumerates the join result of a query with Leapfrog Triejoin, the worst-case optimal join
+/// algorithm of Veldhuizen (ICDT 2014).
+///
+/// The join columns are grouped into variables, the classes of columns that the equalities
+/// require to be equal, so the transitive `movie_id` equalities of a query become a single
+/// variable that every relation with a `movie_id` is intersected on at once.
+pub struct Lftj<'a> {
+    db: &'a Data,
+    tables: Vec<&'a str>,
+    aliases: Vec<&'a str>,
+    /// The columns of every variable, in binding order.
+    variables: Vec<Vec<ColumnRef<'a>>>,
+    leapfrog: Leapfrog,
+}
+
+impl<'a> Lftj<'a> {
+    /// Prepares `sql` with the variables bound in `order`, where each entry names a column of the
+    /// variable, e.g. `["t.id", "k.id"]`. Variables not mentioned follow, the ones shared by the
+    /// most relations first, and by default that is the whole order.
+    pub fn new(db: &'a Data, sql: &'a str, order: &[&str]) -> PolarsResult<Self> {
+        let query = Query::parse(sql)?;
+        let (mut selections, edges) = bind(db, &query)?;
+        let variables = variables(&query, &mut selections, edges);
+
+        let mut rank: Vec<usize> = Vec::new();
+        for name in order {
+            let var = variables
+                .names
+                .iter()
+                .position(|columns| {
+                    columns
+                        .iter()
+                        .any(|c| format!("{}.{}", c.alias, c.column) == *name)
+                })
+                .ok_or_else(|| polars_err!(SQLInterface: "no join variable for {name}"))?;
+            if !rank.contains(&var) {
+                rank.push(var);
+            }
+        }
+        let mut rest: Vec<usize> = (0..variables.names.len())
+            .filter(|var| !rank.contains(var))
+            .collect();
+        rest.sort_by_key(|&var| std::cmp::Reverse(variables.names[var].len()));
+        rank.extend(rest);
+
+        Ok(Lftj {
+            db,
+            tables: query.relations.iter().map(|r| r.table).collect(),
+            aliases: query.relations.iter().map(|r| r.alias).collect(),
+            variables: rank
+                .iter()
+                .map(|&var| variables.names[var].clone())
+                .collect(),
+            leapfrog: Leapfrog::new(&selections, &variables.relations, &rank),
+        })
+    }
+
+    /// The aliases of the relations, in the order their rows appear in output tuples.
+    pub fn aliases(&self) -> &[&'a str] {
+        &self.aliases
+    }
+
+    /// The variables in binding order, each as the `alias.column` names it stands for.
+    pub fn variables(&self) -> Vec<Vec<String>> {
+        self.variables
+            .iter()
+            .map(|columns| {
+                columns
+                    .iter()
+                    .map(|c| format!("{}.{}", c.alias, c.column))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Looks up a column of the relation with the given alias, to read the values of a tuple.
+    pub fn column(&self, alias: &str, column: &str) -> Option<Column<'a>> {
+        let relation = self.aliases.iter().position(|a| *a == alias)?;
+        self.db.column(self.tables[relation], column)
+    }
+
+    /// Calls `f` with every tuple of the join result, as row numbers indexed like `aliases()`.
+    pub fn for_each(&self, mut f: impl FnMut(&[u32])) {
+        let mut tuple = vec![0; self.aliases.len()];
+        self.leapfrog.for_each_binding(|rows| {
+            product(rows, 0, &mut tuple, &mut f);
+        });
+    }
+
+    /// `COUNT(*)` of the join result, computed from the number of matching rows per relation
+    /// without expanding their product.
+    pub fn count(&self) -> u64 {
+        let mut count = 0;
+        self.leapfrog.for_each_binding(|rows| {
+            count += rows.iter().map(|rows| rows.len() as u64).product::<u64>();
+        });
+        count
+    }
+}
+
+/// Calls `f` with every combination of one row from each of `rows`.
+fn product(rows: &[&[u32]], i: usize, tuple: &mut [u32], f: &mut impl FnMut(&[u32])) {
+    if i == rows.len() {
+        f(tuple);
+        return;
+    }
+    for &row in rows[i] {
+        if cancel::poll() {
+            return;
+        }
+        tuple[i] = row;
+        product(rows, i + 1, tuple, f);
+    }
+}
+
+/// Prints `query,hash_count,hash_seconds,lftj_count,lftj_seconds` for the cyclic JOB variants
+/// (33a-c) and the queries in `queries/cyclic/`, counting each with the hash-based
+/// `JoinEnumerator` and with Leapfrog Triejoin, and fails if the counts differ. A count that
+/// exceeds `cancel::timeout()` is printed as `timeout` and not compared.
+pub fn compare_all(db: &Data) -> PolarsResult<()> {
+    let mut queries: Vec<(String, String)> = QUERIES
+        .iter()
+        .filter(|query| query.family == 33)
+        .map(|query| (query.id.to_string(), query.sql.to_string()))
+        .collect();
+    let mut cyclic = Vec::new();
+    for entry in std::fs::read_dir("queries/cyclic")? {
+        let path = entry?.path();
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        cyclic.push((name, std::fs::read_to_string(&path)?));
+    }
+    cyclic.sort();
+    queries.extend(cyclic);
+
+    println!("query,hash_count,hash_seconds,lftj_count,lftj_seconds");
+    for (name, sql) in &queries {
+        let start = Instant::now();
+        let hash_count = cancel::with_timeout(|| Ok(JoinEnumerator::new(db, sql)?.count()))?;
+        let hash_seconds = start.elapsed().as_secs_f32();
+
+        let start = Instant::now();
+        let lftj_count = cancel::with_timeout(|| Ok(Lftj::new(db, sql, &[])?.count()))?;
+        let lftj_seconds = start.elapsed().as_secs_f32();
+
+        println!("{name},{hash_count},{hash_seconds:},{lftj_count},{lftj_seconds:}");
+        if let (Outcome::Done(hash_count), Outcome::Done(lftj_count)) = (hash_count, lftj_count)
+            && hash_count != lftj_count
+        {
+            return Err(polars_err!(
+                ComputeError: "{name}: hash join found {hash_count} results, LFTJ {lftj_count}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_lftj {
+    use super::*;
+    use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+    use crate::enumerate::Join;
+    use crate::o33a::q33a;
+    use crate::test_support;
+
+    #[test]
+    fn test_gallop() {
+        let values = [1, 2, 2, 3, 5, 8, 13, 21, 34];
+        for key in 0..40 {
+            let expected = values.iter().filter(|&&value| value < key).count();
+            assert_eq!(gallop(&values, |value| value < key), expected, "{key}");
+        }
+        assert_eq!(gallop(&[], |value| value < 1), 0);
+    }
+
+    #[test]
+    fn test_triangle_matches_hash_join() {
+        // edges(a, b) joined with itself three times into triangles a -> b -> c -> a
+        let mut state: u32 = 7;
+        let mut next = || {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as i32 % 12
+        };
+        let from: Vec<i32> = (0..200).map(|_| next()).collect();
+        let to: Vec<Option<i32>> = (0..200)
+            .map(|i| if i % 17 == 0 { None } else { Some(next()) })
+            .collect();
+        let selection = Selection::from_values(&from, |&a| a != 5);
+        let selections = vec![selection.clone(), selection.clone(), selection];
+
+        let (a, b, c) = (0, 1, 2);
+        let relations = vec![
+            vec![(a, Column::from(&from)), (b, Column::from(&to))],
+            vec![(b, Column::from(&from)), (c, Column::from(&to))],
+            vec![(c, Column::from(&from)), (a, Column::from(&to))],
+        ];
+        let edges = vec![
+            (0, Column::from(&to), 1, Column::from(&from)),
+            (1, Column::from(&to), 2, Column::from(&from)),
+            (2, Column::from(&to), 0, Column::from(&from)),
+        ];
+
+        let mut expected = Vec::new();
+        Join::new(selections.clone(), edges).for_each(|rows| expected.push(rows.to_vec()));
+        expected.sort();
+        assert!(!expected.is_empty());
+
+        for order in [[a, b, c], [c, a, b], [b, c, a]] {
+            let leapfrog = Leapfrog::new(&selections, &relations, &order);
+            let mut tuples = Vec::new();
+            let mut tuple = vec![0; 3];
+            leapfrog.for_each_binding(|rows| {
+                product(rows, 0, &mut tuple, &mut |rows| tuples.push(rows.to_vec()))
+            });
+            tuples.sort();
+            assert_eq!(tuples, expected, "{order:?}");
+        }
+    }
+
+    #[test]
+    fn test_lftj_q33a() -> Result<(), PolarsError> {
+        let Some(data) = test_support::data() else {
+            return Ok(());
+        };
+        let sql = std::fs::read_to_string("queries/33a.sql")?;
+        let lftj = Lftj::new(data, &sql, &["t1.id", "t2.id"])?;
+        assert_eq!(lftj.count(), JoinEnumerator::new(data, &sql)?.count());
+
+        let name = |alias| match lftj.column(alias, "name") {
+            Some(Column::Str(values)) => values,
+            _ => unreachable!(),
+        };
+        let info = |alias| match lftj.column(alias, "info") {
+            Some(Column::Str(values)) => values,
+            _ => unreachable!(),
+        };
+        let title = |alias| match lftj.column(alias, "title") {
+            Some(Column::Str(values)) => values,
+            _ => unreachable!(),
+        };
+        let aliases = lftj.aliases();
+        let at = |alias| aliases.iter().position(|a| *a == alias).unwrap();
+        let (cn1, cn2, mi_idx1, mi_idx2, t1, t2) = (
+            name("cn1"),
+            name("cn2"),
+            info("mi_idx1"),
+            info("mi_idx2"),
+            title("t1"),
+            title("t2"),
+        );
+
+        let mut res = <(
+            Min<&str>,
+            Min<&str>,
+            Min<&str>,
+            Min<&str>,
+            Min<&str>,
+            Min<&str>,
+        )>::default();
+        lftj.for_each(|rows| {
+            res.update((
+                cn1[rows[at("cn1")] as usize].as_str(),
+                cn2[rows[at("cn2")] as usize].as_str(),
+                mi_idx1[rows[at("mi_idx1")] as usize].as_str(),
+                mi_idx2[rows[at("mi_idx2")] as usize].as_str(),
+                t1[rows[at("t1")] as usize].as_str(),
+                t2[rows[at("t2")] as usize].as_str(),
+            ))
+        });
+        assert_eq!(res.finish().transpose(), q33a(data)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+use memchr::memmem::Finder;
+use polars::prelude::{PolarsResult, polars_err};
+
+/// A compiled SQL `LIKE` (or `ILIKE`) pattern.
+///
+/// `%` matches any sequence of characters, `_` matches exactly one character, and the escape
+/// character (`\` by default, as in Postgres) makes the following character literal. Compilation
+/// picks the cheapest matcher that implements the pattern, so `'(200%'` becomes a prefix
+/// comparison and `'%Downey%Robert%'` becomes two `memmem` searches that must succeed in order.
+#[derive(Clone, Debug)]
+pub struct LikePattern {
+    matcher: Matcher,
+    case_insensitive: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    /// `abc`
+    Exact(Vec<u8>),
+    /// `abc%`
+    Prefix(Vec<u8>),
+    /// `%abc`
+    Suffix(Vec<u8>),
+    /// `%abc%`
+    Contains(Box<Finder<'static>>),
+    /// `a%b%c`, `%b%c%`, ...: an optional anchored prefix and suffix around substrings that must
+    /// occur in order.
+    Ordered {
+        prefix: Vec<u8>,
+        middle: Vec<Finder<'static>>,
+        suffix: Vec<u8>,
+    },
+    /// Anything containing `_`.
+    General(Vec<Token>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(Vec<u8>),
+    One,
+    Many,
+}
+
+impl LikePattern {
+    /// Compiles `pattern` with `LIKE` semantics and `\` as the escape character.
+    pub fn compile(pattern: &str) -> PolarsResult<Self> {
+        Self::compile_with(pattern, Some('\\'), false)
+    }
+
+    /// Compiles `pattern` with `ILIKE` semantics and `\` as the escape character.
+    pub fn compile_ilike(pattern: &str) -> PolarsResult<Self> {
+        Self::compile_with(pattern, Some('\\'), true)
+    }
+
+    /// Compiles `pattern` with an explicit escape character (`None` for `ESCAPE ''`).
+    ///
+    /// Fails if the pattern ends with the escape character, which Postgres rejects as well.
+    pub fn compile_with(
+        pattern: &str,
+        escape: Option<char>,
+        case_insensitive: bool,
+    ) -> PolarsResult<Self> {
+        let pattern = if case_insensitive {
+            pattern.to_lowercase()
+        } else {
+            pattern.to_string()
+        };
+        let tokens = tokenize(&pattern, escape)?;
+        Ok(LikePattern {
+            matcher: Matcher::new(tokens),
+            case_insensitive,
+        })
+    }
+
+    /// Returns whether `s` matches the pattern.
+    ///
+    /// For `ILIKE` patterns the input is lowercased first, which allocates.
+    pub fn matches(&self, s: &[u8]) -> bool {
+        if self.case_insensitive {
+            let lowered = if s.is_ascii() {
+                s.to_ascii_lowercase()
+            } else {
+                String::from_utf8_lossy(s).to_lowercase().into_bytes()
+            };
+            self.matcher.matches(&lowered)
+        } else {
+            self.matcher.matches(s)
+        }
+    }
+}
+
+fn tokenize(pattern: &str, escape: Option<char>) -> PolarsResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut literal = Vec::new();
+    let mut chars = pattern.chars();
+    let mut buf = [0; 4];
+
+    while let Some(c) = chars.next() {
+        let token = if Some(c) == escape {
+            let escaped = chars.next().ok_or_else(|| {
+                polars_err!(InvalidOperation: "LIKE pattern {pattern:?} ends with the escape character")
+            })?;
+            literal.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+            continue;
+        } else if c == '%' {
+            Token::Many
+        } else if c == '_' {
+            Token::One
+        } else {
+            literal.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        };
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(std::mem::take(&mut literal)));
+        }
+        // `%%` is the same as `%`
+        if !(token == Token::Many && tokens.last() == Some(&Token::Many)) {
+            tokens.push(token);
+        }
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+impl Matcher {
+    fn new(tokens: Vec<Token>) -> Self {
+        if tokens.contains(&Token::One) {
+            return Matcher::General(tokens);
+        }
+
+        match tokens.as_slice() {
+            [] => return Matcher::Exact(Vec::new()),
+            [Token::Literal(lit)] => return Matcher::Exact(lit.clone()),
+            [Token::Literal(lit), Token::Many] => return Matcher::Prefix(lit.clone()),
+            [Token::Many, Token::Literal(lit)] => return Matcher::Suffix(lit.clone()),
+            [Token::Many, Token::Literal(lit), Token::Many] => {
+                return Matcher::Contains(Box::new(Finder::new(lit).into_owned()));
+            }
+            _ => {}
+        }
+
+        let mut literals = tokens.iter().filter_map(|token| match token {
+            Token::Literal(lit) => Some(lit.as_slice()),
+            _ => None,
+        });
+        let prefix = match tokens.first() {
+            Some(Token::Literal(_)) => literals.next().unwrap().to_vec(),
+            _ => Vec::new(),
+        };
+        let mut middle: Vec<&[u8]> = literals.collect();
+        let suffix = match tokens.last() {
+            Some(Token::Literal(_)) => middle.pop().unwrap().to_vec(),
+            _ => Vec::new(),
+        };
+        Matcher::Ordered {
+            prefix,
+            middle: middle
+                .into_iter()
+                .map(|lit| Finder::new(lit).into_owned())
+                .collect(),
+            suffix,
+        }
+    }
+
+    fn matches(&self, s: &[u8]) -> bool {
+        match self {
+            Matcher::Exact(lit) => s == lit.as_slice(),
+            Matcher::Prefix(lit) => s.starts_with(lit),
+            Matcher::Suffix(lit) => s.ends_with(lit),
+            Matcher::Contains(finder) => finder.find(s).is_some(),
+            Matcher::Ordered {
+                prefix,
+                middle,
+                suffix,
+            } => {
+                if s.len() < prefix.len() + suffix.len()
+                    || !s.starts_with(prefix)
+                    || !s.ends_with(suffix)
+                {
+                    return false;
+                }
+                let mut rest = &s[prefix.len()..s.len() - suffix.len()];
+                for finder in middle {
+                    match finder.find(rest) {
+                        Some(pos) => rest = &rest[pos + finder.needle().len()..],
+                        None => return false,
+                    }
+                }
+                true
+            }
+            Matcher::General(tokens) => matches_general(tokens, s),
+        }
+    }
+}
+
+/// Wildcard matching that backtracks only to the most recent `%`, which is enough because a later
+/// `%` can absorb anything an earlier one could.
+fn matches_general(tokens: &[Token], s: &[u8]) -> bool {
+    let mut t = 0;
+    let mut i = 0;
+    // (token after the last `%`, input position that `%` currently extends to)
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    loop {
+        if t < tokens.len() {
+            match &tokens[t] {
+                Token::Many => {
+                    backtrack = Some((t + 1, i));
+                    t += 1;
+                    continue;
+                }
+                Token::One if i < s.len() => {
+                    i = next_char(s, i);
+                    t += 1;
+                    continue;
+                }
+                Token::Literal(lit) if s[i..].starts_with(lit) => {
+                    i += lit.len();
+                    t += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        } else if i == s.len() {
+            return true;
+        }
+
+        match backtrack {
+            Some((bt, bi)) if bi < s.len() => {
+                let bi = next_char(s, bi);
+                backtrack = Some((bt, bi));
+                t = bt;
+                i = bi;
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// Position of the character after the one starting at `i`, so that `_` matches a whole UTF-8
+/// character rather than a byte.
+fn next_char(s: &[u8], i: usize) -> usize {
+    let len = match s[i] {
+        b if b < 0x80 => 1,
+        b if b >> 5 == 0b110 => 2,
+        b if b >> 4 == 0b1110 => 3,
+        b if b >> 3 == 0b11110 => 4,
+        _ => 1,
+    };
+    (i + len).min(s.len())
+}
+
+#[cfg(test)]
+mod test_like {
+    use super::*;
+    use regex::Regex;
+
+    fn reference(pattern: &str, case_insensitive: bool) -> Regex {
+        let mut re = String::from(if case_insensitive { "(?si)^" } else { "(?s)^" });
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => re.push_str(&regex::escape(&chars.next().unwrap().to_string())),
+                '%' => re.push_str(".*"),
+                '_' => re.push('.'),
+                c => re.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        re.push('$');
+        Regex::new(&re).unwrap()
+    }
+
+    fn strings(alphabet: &[&str], max_len: usize) -> Vec<String> {
+        let mut all = vec![String::new()];
+        let mut frontier = vec![String::new()];
+        for _ in 0..max_len {
+            frontier = frontier
+                .iter()
+                .flat_map(|s| alphabet.iter().map(move |c| format!("{s}{c}")))
+                .collect();
+            all.extend(frontier.iter().cloned());
+        }
+        all
+    }
+
+    #[test]
+    fn test_like_exhaustive() {
+        let patterns = strings(&["a", "b", "%", "_", "\\_"], 4);
+        let inputs = strings(&["a", "b", "é", "_"], 5);
+        for pattern in &patterns {
+            let like = LikePattern::compile(pattern).unwrap();
+            let re = reference(pattern, false);
+            for input in &inputs {
+                assert_eq!(
+                    like.matches(input.as_bytes()),
+                    re.is_match(input),
+                    "{input:?} LIKE {pattern:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_ilike_exhaustive() {
+        let patterns = strings(&["a", "B", "%", "_"], 4);
+        let inputs = strings(&["A", "b", "É", "é"], 4);
+        for pattern in &patterns {
+            let like = LikePattern::compile_ilike(pattern).unwrap();
+            let re = reference(pattern, true);
+            for input in &inputs {
+                assert_eq!(
+                    like.matches(input.as_bytes()),
+                    re.is_match(input),
+                    "{input:?} ILIKE {pattern:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_like_job_patterns() {
+        let cases = [
+            ("%Downey%Robert%", "Downey Jr., Robert", true),
+            ("%Downey%Robert%", "Robert Downey Jr.", false),
+            ("(200%", "(2006) (USA) (TV)", true),
+            ("(200%", " (2006)", false),
+            ("%(worldwide)%", "(2008) (worldwide) (TV)", true),
+            ("USA:% 200%", "USA: 1 May 2005", true),
+            ("USA:% 200%", "USA: 1 May 1999", false),
+            ("%-%", "kung-fu", true),
+            ("%Warner%", "Warner Bros.", true),
+            ("Z%", "Zorro", true),
+            ("Z%", "zorro", false),
+        ];
+        for (pattern, input, expected) in cases {
+            assert_eq!(
+                LikePattern::compile(pattern)
+                    .unwrap()
+                    .matches(input.as_bytes()),
+                expected,
+                "{input:?} LIKE {pattern:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_like_escape() {
+        let like = LikePattern::compile_with("100!%%", Some('!'), false).unwrap();
+        assert!(like.matches(b"100% sure"));
+        assert!(!like.matches(b"1000"));
+        let like = LikePattern::compile_with("a\\%", None, false).unwrap();
+        assert!(like.matches(b"a\\bc"));
+        assert!(LikePattern::compile("100\\").is_err());
+        assert!(LikePattern::compile_with("100!", Some('!'), false).is_err());
+    }
+}
@@ -1,121 +1,254 @@
 use lfj::*;
+use polars::prelude::{PolarsResult, polars_bail, polars_ensure};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
 
-fn main() -> Result<(), polars::prelude::PolarsError> {
+const USAGE: &str = "\
+usage: lfj [options] [mode]
+       lfj compare [--threshold <percent>] [--alpha <p>] <old results> <new results>
+
+Runs the JOB queries and writes one sample per query and repetition, or compares two files
+written by --results and reports the queries that failed or got significantly slower.
+
+options:
+  --query <pattern>      query id (17a), family (17) or glob (1*), repeatable; all if absent
+  --data <dir>           directory of the IMDB parquet files [imdb]
+  --repetitions <n>      measured runs of every query [5]
+  --warmups <n>          unmeasured runs of every query before them [0, or 1 if warm]
+  --format <format>      csv, json or table [csv]
+  --output <file>        where to write the samples, or the report of a mode [standard output]
+  --order <order>        fixed, shuffle or shuffle:<seed> [fixed]
+  --results <file>       write the samples, their statistics and the machine as JSON
+  --load <load>          warm, cold-in-process or end-to-end, see below [warm]
+  --timeout <seconds>    cancel queries that take longer
+  --threads <n>          threads of the parallel operators
+  --filter <strategy>    semijoin filters: exact, bitmap, bloom or bloom:<fpr>
+  --probe <probe>        i32 hash tables: ahash or prefetch
+  --memory-budget <mib>  spill hash tables past this size, only for queries that can (2a)
+  --explain, --explain-json
+                         print the plans of the queries to stderr
+
+loads, with the time of loading in the load_seconds of every sample:
+  warm               read and build the data once for all queries, warm after --warmups
+  cold-in-process    read the tables once, but rebuild the data before every query
+  end-to-end         read and build only the tables of the query before every query
+
+modes, instead of the benchmark:
+  --sweep <threads>  --orders <limit>  --bloom  --prefetch  --enumerate  --lftj  --subjoins
+  --verify           check the selected queries against the Polars lazy oracle
+  --update-golden    store the answers of the selected queries in queries/golden.json
+  --throughput <n>   run the selected queries from 1, 2, 4 ... n client threads at once
+
+throughput options:
+  --duration <seconds>   how long every thread count runs [10]
+  --mix <mix>            uniform, or <pattern>:<weight>,... to weight the queries [uniform]
+
+compare options:
+  --threshold <percent>  slowdown of the median that counts as a regression [5]
+  --alpha <p>            significance level of the Mann-Whitney test [0.05]
+
+exit codes: 0 success, 1 bad arguments or data, 2 a query failed or timed out,
+3 a query returned another answer than queries/golden.json, runs of a query returned
+different results, or --verify found a difference,
+4 compare found a regression
+";
+
+/// A report that runs instead of the benchmark.
+enum Mode {
+    Sweep(usize),
+    Orders(usize),
+    Bloom,
+    Prefetch,
+    Enumerate,
+    Lftj,
+    Subjoins,
+    Verify,
+    UpdateGolden,
+    Throughput,
+}
+
+fn main() -> PolarsResult<ExitCode> {
     // let _ = test_read::test_read();
-    let db = data::ImdbData::new();
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if(|arg| arg == "compare").is_some() {
+        return compare(args);
+    }
+    let mut options = bench::Options::default();
+    let mut data_dir = PathBuf::from("imdb");
+    let mut throughput = throughput::Options::default();
+    let mut mode = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--query" => options
+                .queries
+                .extend(value(&arg, args.next())?.split(',').map(String::from)),
+            "--data" => data_dir = value(&arg, args.next())?.into(),
+            "--repetitions" => options.repetitions = positive(&arg, args.next())?,
+            "--warmups" => options.warmups = number(&arg, args.next())?,
+            "--format" => options.format = value(&arg, args.next())?.parse()?,
+            "--output" => options.output = Some(value(&arg, args.next())?.into()),
+            "--order" => options.order = value(&arg, args.next())?.parse()?,
+            "--results" => options.results = Some(value(&arg, args.next())?.into()),
+            "--load" => options.load = value(&arg, args.next())?.parse()?,
+            "--threads" => parallel::set_threads(positive(&arg, args.next())?),
+            "--filter" => bloom::set_strategy(value(&arg, args.next())?.parse()?),
+            "--probe" => hashtable::set_probe(value(&arg, args.next())?.parse()?),
+            "--memory-budget" => spill::set_budget(Some(memory_budget(args.next())?)),
+            "--timeout" => cancel::set_timeout(Some(timeout(args.next())?)),
+            "--explain" => explain::set_mode(explain::Mode::Tree),
+            "--explain-json" => explain::set_mode(explain::Mode::Json),
+            "--sweep" => mode = Some(Mode::Sweep(positive(&arg, args.next())?)),
+            "--orders" => mode = Some(Mode::Orders(positive(&arg, args.next())?)),
+            "--bloom" => mode = Some(Mode::Bloom),
+            "--prefetch" => mode = Some(Mode::Prefetch),
+            "--enumerate" => mode = Some(Mode::Enumerate),
+            "--lftj" => mode = Some(Mode::Lftj),
+            "--subjoins" => mode = Some(Mode::Subjoins),
+            "--verify" => mode = Some(Mode::Verify),
+            "--update-golden" => mode = Some(Mode::UpdateGolden),
+            "--throughput" => {
+                throughput.threads = positive(&arg, args.next())?;
+                mode = Some(Mode::Throughput);
+            }
+            "--duration" => throughput.duration = Duration::from_secs_f64(real(&arg, args.next())?),
+            "--mix" => throughput.mix = value(&arg, args.next())?.parse()?,
+            "--help" | "-h" => {
+                print!("{USAGE}");
+                return Ok(ExitCode::SUCCESS);
+            }
+            _ => polars_bail!(InvalidOperation: "unknown argument {arg}, see --help"),
+        }
+    }
+    // Check the patterns before spending time on loading.
+    let selected = registry::select(&options.queries)?;
+    if spill::budget().is_some()
+        && let Some(query) = selected.iter().find(|entry| !entry.uses("GraceTable"))
+    {
+        polars_bail!(
+            InvalidOperation: "--memory-budget applies only to queries that build a spill::GraceTable, \
+            not to {}; select them with --query",
+            query.id
+        );
+    }
+    let Some(mode) = mode else {
+        return Ok(ExitCode::from(bench::run(&data_dir, &options)?.exit_code()));
+    };
+
+    let db = data::ImdbData::load(&data_dir)?;
     let data = data::Data::new(&db);
-    o1a::q1a(&data)?;
-    o1b::q1b(&data)?;
-    o1c::q1c(&data)?;
-    o1d::q1d(&data)?;
-    o2a::q2a(&data)?;
-    o2b::q2b(&data)?;
-    o2c::q2c(&data)?;
-    o2d::q2d(&data)?;
-    o3a::q3a(&data)?;
-    o3b::q3b(&data)?;
-    o3c::q3c(&data)?;
-    o4a::q4a(&data)?;
-    o4b::q4b(&data)?;
-    o4c::q4c(&data)?;
-    o5a::q5a(&data)?;
-    o5b::q5b(&data)?;
-    o5c::q5c(&data)?;
-    o6a::q6a(&data)?;
-    o6b::q6b(&data)?;
-    o6c::q6c(&data)?;
-    o6d::q6d(&data)?;
-    o6e::q6e(&data)?;
-    o6f::q6f(&data)?;
-    o7a::q7a(&data)?;
-    o7b::q7b(&data)?;
-    o7c::q7c(&data)?;
-    o8a::q8a(&data)?;
-    o8b::q8b(&data)?;
-    o8c::q8c(&data)?;
-    o8d::q8d(&data)?;
-    o9a::q9a(&data)?;
-    o9b::q9b(&data)?;
-    o9c::q9c(&data)?;
-    o9d::q9d(&data)?;
-    o10a::q10a(&data)?;
-    o10b::q10b(&data)?;
-    o10c::q10c(&data)?;
-    o11a::q11a(&data)?;
-    o11b::q11b(&data)?;
-    o11c::q11c(&data)?;
-    o11d::q11d(&data)?;
-    o12a::q12a(&data)?;
-    o12b::q12b(&data)?;
-    o12c::q12c(&data)?;
-    o13a::q13a(&data)?;
-    o13b::q13b(&data)?;
-    o13c::q13c(&data)?;
-    o13d::q13d(&data)?;
-    o14a::q14a(&data)?;
-    o14b::q14b(&data)?;
-    o14c::q14c(&data)?;
-    o15a::q15a(&data)?;
-    o15b::q15b(&data)?;
-    o15c::q15c(&data)?;
-    o15d::q15d(&data)?;
-    o16a::q16a(&data)?;
-    o16b::q16b(&data)?;
-    o16c::q16c(&data)?;
-    o16d::q16d(&data)?;
-    o17a::q17a(&data)?;
-    o17b::q17b(&data)?;
-    o17c::q17c(&data)?;
-    o17d::q17d(&data)?;
-    o17e::q17e(&data)?;
-    o17f::q17f(&data)?;
-    o18a::q18a(&data)?;
-    o18b::q18b(&data)?;
-    o18c::q18c(&data)?;
-    o19a::q19a(&data)?;
-    o19b::q19b(&data)?;
-    o19c::q19c(&data)?;
-    o19d::q19d(&data)?;
-    o20a::q20a(&data)?;
-    o20b::q20b(&data)?;
-    o20c::q20c(&data)?;
-    o21a::q21a(&data)?;
-    o21b::q21b(&data)?;
-    o21c::q21c(&data)?;
-    o22a::q22a(&data)?;
-    o22b::q22b(&data)?;
-    o22c::q22c(&data)?;
-    o22d::q22d(&data)?;
-    o23a::q23a(&data)?;
-    o23b::q23b(&data)?;
-    o23c::q23c(&data)?;
-    o24a::q24a(&data)?;
-    o24b::q24b(&data)?;
-    o25a::q25a(&data)?;
-    o25b::q25b(&data)?;
-    o25c::q25c(&data)?;
-    o26a::q26a(&data)?;
-    o26b::q26b(&data)?;
-    o26c::q26c(&data)?;
-    o27a::q27a(&data)?;
-    o27b::q27b(&data)?;
-    o27c::q27c(&data)?;
-    o28a::q28a(&data)?;
-    o28b::q28b(&data)?;
-    o28c::q28c(&data)?;
-    o29a::q29a(&data)?;
-    o29b::q29b(&data)?;
-    o29c::q29c(&data)?;
-    o30a::q30a(&data)?;
-    o30b::q30b(&data)?;
-    o30c::q30c(&data)?;
-    o31a::q31a(&data)?;
-    o31b::q31b(&data)?;
-    o31c::q31c(&data)?;
-    o32a::q32a(&data)?;
-    o32b::q32b(&data)?;
-    o33a::q33a(&data)?;
-    o33b::q33b(&data)?;
-    o33c::q33c(&data)?;
-    Ok(())
+    match mode {
+        Mode::Sweep(threads) => parallel::sweep(
+            &data,
+            &registry::select(&options.queries)?,
+            threads,
+            &mut options.output()?,
+            options.format,
+        )?,
+        Mode::Orders(limit) => {
+            orders::report(&data, limit, &mut options.output()?, options.format)?
+        }
+        Mode::Bloom => bloom::report(
+            &data,
+            &registry::select(&options.queries)?,
+            &mut options.output()?,
+            options.format,
+        )?,
+        Mode::Prefetch => hashtable::report(
+            &data,
+            &registry::select(&options.queries)?,
+            &mut options.output()?,
+            options.format,
+        )?,
+        Mode::Enumerate => enumerate::count_all(&data)?,
+        Mode::Lftj => lftj::compare_all(&data)?,
+        Mode::Subjoins => subjoin::print_all(&data)?,
+        Mode::Verify => {
+            let queries = registry::select(&options.queries)?;
+            if !oracle::verify(&db, &data, &queries)? {
+                return Ok(ExitCode::from(3));
+            }
+        }
+        Mode::UpdateGolden => golden::update(
+            &data,
+            &registry::select(&options.queries)?,
+            Path::new(golden::PATH),
+        )?,
+        Mode::Throughput => throughput::report(
+            &data,
+            &registry::select(&options.queries)?,
+            &throughput,
+            &mut options.output()?,
+            options.format,
+        )?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn compare(mut args: impl Iterator<Item = String>) -> PolarsResult<ExitCode> {
+    let mut thresholds = compare::Thresholds::default();
+    let mut files = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threshold" => thresholds.change = real(&arg, args.next())? / 100.0,
+            "--alpha" => {
+                thresholds.alpha = real(&arg, args.next())?;
+                polars_ensure!(thresholds.alpha < 1.0, InvalidOperation: "--alpha needs a p-value");
+            }
+            _ if arg.starts_with("--") => {
+                polars_bail!(InvalidOperation: "unknown argument {arg}, see --help")
+            }
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+    let [old, new] = files.as_slice() else {
+        polars_bail!(InvalidOperation: "compare needs two results files, see --help");
+    };
+    let regressed = compare::run(old, new, thresholds, &mut std::io::stdout().lock())?;
+    Ok(ExitCode::from(if regressed { 4 } else { 0 }))
+}
+
+fn value(arg: &str, value: Option<String>) -> PolarsResult<String> {
+    match value {
+        Some(value) => Ok(value),
+        None => polars_bail!(InvalidOperation: "{arg} needs a value"),
+    }
+}
+
+fn number(arg: &str, value: Option<String>) -> PolarsResult<usize> {
+    match value.and_then(|n| n.parse().ok()) {
+        Some(n) => Ok(n),
+        None => polars_bail!(InvalidOperation: "{arg} needs a number"),
+    }
+}
+
+fn positive(arg: &str, value: Option<String>) -> PolarsResult<usize> {
+    match value.and_then(|n| n.parse().ok()) {
+        Some(n) if n > 0 => Ok(n),
+        _ => polars_bail!(InvalidOperation: "{arg} needs a positive number"),
+    }
+}
+
+fn real(arg: &str, value: Option<String>) -> PolarsResult<f64> {
+    match value.and_then(|x| x.parse::<f64>().ok()) {
+        Some(x) if x > 0.0 && x.is_finite() => Ok(x),
+        _ => polars_bail!(InvalidOperation: "{arg} needs a positive number"),
+    }
+}
+
+/// A budget given in MiB, in bytes.
+fn memory_budget(value: Option<String>) -> PolarsResult<usize> {
+    match value.and_then(|mib| mib.parse::<usize>().ok()) {
+        Some(mib) if mib > 0 => Ok(mib.saturating_mul(1 << 20)),
+        _ => polars_bail!(InvalidOperation: "--memory-budget needs a positive number of MiB"),
+    }
+}
+
+fn timeout(value: Option<String>) -> PolarsResult<Duration> {
+    match value.and_then(|seconds| seconds.parse::<f64>().ok()) {
+        Some(seconds) if seconds > 0.0 && seconds.is_finite() => {
+            Ok(Duration::from_secs_f64(seconds))
+        }
+        _ => polars_bail!(InvalidOperation: "--timeout needs a positive number of seconds"),
+    }
 }
@@ -1,9 +1,13 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::HashMap;
+use crate::dense::{DenseMap, DenseSet};
+use crate::like::LikePattern;
+use crate::parallel;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::HashSet;
-use memchr::memmem::Finder;
 use polars::error::PolarsError;
-use std::time::Instant;
 
 // imdb.q10a_cast_info(person_id,movie_id,person_role_id,role_id)
 // |imdb_int.char_name(person_role_id)
@@ -13,6 +17,7 @@ use std::time::Instant;
 // |||imdb_int.company_type(company_type_id)
 // |imdb.q10a_role_type(role_id)
 pub fn q10a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("10a");
     let chn = &db.chn;
     let ci = &db.ci;
     let cn = &db.cn;
@@ -22,22 +27,32 @@ pub fn q10a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let rt = &db.rt;
     let t = &db.t;
 
-    let voice = Finder::new("(voice)");
-    let uncredited = Finder::new("(uncredited)");
+    let voice = LikePattern::compile("%(voice)%")?;
+    let uncredited = LikePattern::compile("%(uncredited)%")?;
 
-    let start = Instant::now();
+    timer.phase(Phase::Lookup);
+    let rt_id = rt
+        .role
+        .iter()
+        .zip(rt.id.iter())
+        .find(|(role, _)| *role == "actor")
+        .map(|(_, id)| id)
+        .unwrap();
 
-    let chn_m: HashMap<i32, &str> = chn
+    timer.phase(Phase::Build);
+    let chn_m: DenseMap<&str> = chn
         .id
         .iter()
         .zip(chn.name.iter())
+        .take_while(|_| !cancel::poll())
         .map(|(id, name)| (*id, name.as_str()))
         .collect();
 
-    let cn_s: HashSet<&i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -50,21 +65,15 @@ pub fn q10a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .movie_id
         .iter()
         .zip(mc.company_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(movie_id, company_id)| cn_s.contains(&company_id).then_some(movie_id))
         .collect();
 
-    let rt_id = rt
-        .role
-        .iter()
-        .zip(rt.id.iter())
-        .find(|(role, _)| *role == "actor")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let t_m: HashMap<i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.production_year.iter())
             .zip(t.title.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((movie_id, production_year), title)| {
                 if let Some(production_year) = production_year
                     && mc_s.contains(&movie_id)
@@ -77,71 +86,43 @@ pub fn q10a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mut res: Option<(&str, &str)> = None;
-
-    for (((mid, person_role_id), role_id), note) in ci
-        .movie_id
-        .iter()
-        .zip(ci.person_role_id.iter())
-        .zip(ci.role_id.iter())
-        .zip(ci.note.iter())
-    {
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.movie_id.len(), |row, res| {
+        let mid = &ci.movie_id[row];
+        let person_role_id = &ci.person_role_id[row];
+        let role_id = &ci.role_id[row];
+        let note = &ci.note[row];
         if let Some(person_role_id) = person_role_id
             && let Some(note) = note
-            && voice.find(note.as_bytes()).is_some()
-            && uncredited.find(note.as_bytes()).is_some()
+            && voice.matches(note.as_bytes())
+            && uncredited.matches(note.as_bytes())
             && let Some(character_name) = chn_m.get(&person_role_id)
             && let Some(title) = t_m.get(&mid)
             && rt_id == role_id
         {
-            res = match res {
-                Some((old_character_name, old_title)) => Some((
-                    character_name.min(&old_character_name),
-                    title.min(&old_title),
-                )),
-                None => Some((character_name, title)),
-            };
+            res.update((*character_name, *title));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    println!("10a,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 10a
-// SELECT MIN(chn.name) AS uncredited_voiced_character,
-//        MIN(t.title) AS russian_movie
-// FROM char_name AS chn,
-//      cast_info AS ci,
-//      company_name AS cn,
-//      company_type AS ct,
-//      movie_companies AS mc,
-//      role_type AS rt,
-//      title AS t
-// WHERE ci.note LIKE '%(voice)%'
-//   AND ci.note LIKE '%(uncredited)%'
-//   AND cn.country_code = '[ru]'
-//   AND rt.role = 'actor'
-//   AND t.production_year > 2005
-//   AND t.id = mc.movie_id
-//   AND t.id = ci.movie_id
-//   AND ci.movie_id = mc.movie_id
-//   AND chn.id = ci.person_role_id
-//   AND rt.id = ci.role_id
-//   AND cn.id = mc.company_id
-//   AND ct.id = mc.company_type_id;
-#[cfg(test)]
-mod test_10a {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q10a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q10a(&data)?;
-        assert_eq!(res, Some(("Actor", "12 Rounds")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "10a",
+    family: 10,
+    sql: include_str!("../queries/10a.sql"),
+    relations: &[
+        ("chn", "char_name"),
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("mc", "movie_companies"),
+        ("rt", "role_type"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q10a(db)?.into()),
+};
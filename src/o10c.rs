@@ -1,10 +1,16 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
+use crate::dense::{DenseMap, DenseSet};
+use crate::like::LikePattern;
+use crate::parallel;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
+use ahash::HashSet;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q10c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("10c");
     let chn = &db.chn;
     let ci = &db.ci;
     let cn = &db.cn;
@@ -13,25 +19,26 @@ pub fn q10c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     // let rt = &db.rt;
     let t = &db.t;
 
-    let producer = Finder::new("(producer)");
+    let producer = LikePattern::compile("%(producer)%")?;
 
     // Due to ct and mc form a PK-FK join and there are no selection predicates on ct, we can drop
     // ct from the join.
     // let ct_s: Vec<i32> = ct.column("id")?.i32()?.into_iter().flatten().collect();
 
-    let start = Instant::now();
-
-    let chn_m: HashMap<&i32, &str> = chn
+    timer.phase(Phase::Build);
+    let chn_m: DenseMap<&str> = chn
         .id
         .iter()
         .zip(chn.name.iter())
+        .take_while(|_| !cancel::poll())
         .map(|(id, name)| (id, name.as_str()))
         .collect();
 
-    let cn_s: HashSet<&i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -44,6 +51,7 @@ pub fn q10c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .movie_id
         .iter()
         .zip(mc.company_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(movie_id, company_id)| cn_s.contains(&company_id).then_some(movie_id))
         .collect();
 
@@ -55,10 +63,11 @@ pub fn q10c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     //     .filter_map(|id| id)
     //     .collect();
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.production_year.iter())
             .zip(t.title.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((movie_id, production_year), title)| {
                 if let Some(production_year) = production_year
                     && mc_s.contains(&movie_id)
@@ -71,62 +80,40 @@ pub fn q10c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mut res: Option<(&str, &str)> = None;
-
-    for ((mid, person_role_id), note) in ci
-        .movie_id
-        .iter()
-        .zip(ci.person_role_id.iter())
-        .zip(ci.note.iter())
-    {
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.movie_id.len(), |row, res| {
+        let mid = &ci.movie_id[row];
+        let person_role_id = &ci.person_role_id[row];
+        let note = &ci.note[row];
         if let Some(person_role_id) = person_role_id
             && let Some(note) = note
-            && producer.find(note.as_bytes()).is_some()
+            && producer.matches(note.as_bytes())
             && let Some(character_name) = chn_m.get(&person_role_id)
             && let Some(title) = t_m.get(&mid)
         {
-            res = match res {
-                Some((old_character_name, old_title)) => Some((
-                    character_name.min(&old_character_name),
-                    title.min(&old_title),
-                )),
-                None => Some((character_name, title)),
-            };
+            res.update((*character_name, *title));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    println!("10c,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 10c
-// SELECT MIN(chn.name) AS character, MIN(t.title) AS movie_with_american_producer
-// FROM char_name AS chn, cast_info AS ci, company_name AS cn, company_type AS ct, movie_companies AS mc, role_type AS rt, title AS t
-// WHERE ci.note like '%(producer)%'
-// AND cn.country_code = '[us]'
-// AND t.production_year > 1990
-// AND t.id = mc.movie_id
-// AND t.id = ci.movie_id
-// AND ci.movie_id = mc.movie_id
-// AND chn.id = ci.person_role_id
-// AND rt.id = ci.role_id
-// AND cn.id = mc.company_id
-// AND ct.id = mc.company_type_id;
-#[cfg(test)]
-mod test_q10c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q10c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-
-        assert_eq!(
-            q10c(&data)?,
-            Some(("Himself", "Evil Eyes: Behind the Scenes"))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "10c",
+    family: 10,
+    sql: include_str!("../queries/10c.sql"),
+    relations: &[
+        ("chn", "char_name"),
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("mc", "movie_companies"),
+        ("rt", "role_type"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q10c(db)?.into()),
+};
@@ -1,10 +1,15 @@
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
+use crate::dense::DenseMap;
+use crate::like::LikePattern;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
+use ahash::HashMap;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q11b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("11b");
     let cn = &db.cn;
     let ct = &db.ct;
     let k = &db.k;
@@ -14,29 +19,12 @@ pub fn q11b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let ml = &db.ml;
     let t = &db.t;
 
-    let film = Finder::new("Film");
-    let warner = Finder::new("Warner");
-    let follow = Finder::new("follows");
-    let money = Finder::new("Money");
-
-    let start = Instant::now();
-
-    let cn_m: HashMap<&i32, &str> = cn
-        .id
-        .iter()
-        .zip(cn.name.iter())
-        .zip(cn.country_code.iter())
-        .filter_map(|((id, name), country_code)| {
-            country_code
-                .as_deref()
-                .filter(|&code| {
-                    (film.find(name.as_bytes()).is_some() || warner.find(name.as_bytes()).is_some())
-                        && code != "[pl]"
-                })
-                .map(|_| (id, name.as_str()))
-        })
-        .collect();
+    let film = LikePattern::compile("%Film%")?;
+    let warner = LikePattern::compile("%Warner%")?;
+    let follow = LikePattern::compile("%follows%")?;
+    let money = LikePattern::compile("%Money%")?;
 
+    timer.phase(Phase::Lookup);
     let ct_id = ct
         .kind
         .iter()
@@ -53,45 +41,63 @@ pub fn q11b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s: HashSet<&i32> = mk
-        .movie_id
-        .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| (k_id == keyword_id).then_some(movie_id))
-        .collect();
-
-    let lt_m: HashMap<&i32, &str> = lt
+    let lt_m: DenseMap<&str> = lt
         .id
         .iter()
         .zip(lt.link.iter())
         .filter_map(|(id, link)| {
             follow
-                .find(link.as_bytes())
-                .is_some()
+                .matches(link.as_bytes())
                 .then_some((id, link.as_str()))
         })
         .collect();
 
+    timer.phase(Phase::Build);
+    let cn_m: DenseMap<&str> = cn
+        .id
+        .iter()
+        .zip(cn.name.iter())
+        .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|((id, name), country_code)| {
+            country_code
+                .as_deref()
+                .filter(|&code| {
+                    (film.matches(name.as_bytes()) || warner.matches(name.as_bytes()))
+                        && code != "[pl]"
+                })
+                .map(|_| (id, name.as_str()))
+        })
+        .collect();
+
+    let mk_s: PartitionedSet<&i32> = PartitionedSet::build(mk.movie_id.len(), |row| {
+        let movie_id = &mk.movie_id[row];
+        let keyword_id = &mk.keyword_id[row];
+        (k_id == keyword_id).then_some(movie_id)
+    });
+
     let ml_m: HashMap<&i32, Vec<&str>> = ml
         .movie_id
         .iter()
         .zip(ml.link_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(mid, lt_id)| lt_m.get(&lt_id).map(|link| (mid, link)))
         .fold(HashMap::default(), |mut acc, (mid, link)| {
             acc.entry(mid).or_default().push(link);
             acc
         });
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 if let Some(production_year) = production_year
                     && mk_s.contains(&id)
                     && ml_m.contains_key(&id)
                     && *production_year == 1998
-                    && money.find(title.as_bytes()).is_some()
+                    && money.matches(title.as_bytes())
                 {
                     Some((id, title.as_str()))
                 } else {
@@ -100,6 +106,7 @@ pub fn q11b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for (((mid, cid), company_type_id), note) in mc
@@ -109,6 +116,9 @@ pub fn q11b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(mc.company_type_id.iter())
         .zip(mc.note.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if ct_id == company_type_id
             && let Some(links) = ml_m.get(&mid)
             && let Some(name) = cn_m.get(&cid)
@@ -126,36 +136,24 @@ pub fn q11b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    println!("11b,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 11b
-// SELECT MIN(cn.name) AS from_company, MIN(lt.link) AS movie_link_type, MIN(t.title) AS sequel_movie
-// FROM company_name AS cn,
-// company_type AS ct,
-// keyword AS k,
-// link_type AS lt,
-// movie_companies AS mc,
-// movie_keyword AS mk,
-// movie_link AS ml,
-// title AS t
-// WHERE cn.country_code !='[pl]' AND (cn.name LIKE '%Film%' OR cn.name LIKE '%Warner%') AND ct.kind ='production companies' AND k.keyword ='sequel' AND lt.link LIKE '%follows%' AND mc.note IS NULL AND t.production_year  = 1998 and t.title like '%Money%' AND lt.id = ml.link_type_id AND ml.movie_id = t.id AND t.id = mk.movie_id AND mk.keyword_id = k.id AND t.id = mc.movie_id AND mc.company_type_id = ct.id AND mc.company_id = cn.id AND ml.movie_id = mk.movie_id AND ml.movie_id = mc.movie_id AND mk.movie_id = mc.movie_id;
-#[cfg(test)]
-mod test_q11b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q11b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-
-        assert_eq!(
-            q11b(&data)?,
-            Some(("Filmlance International AB", "follows", "The Money Man"))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "11b",
+    family: 11,
+    sql: include_str!("../queries/11b.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("k", "keyword"),
+        ("lt", "link_type"),
+        ("mc", "movie_companies"),
+        ("mk", "movie_keyword"),
+        ("ml", "movie_link"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q11b(db)?.into()),
+};
@@ -1,9 +1,14 @@
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
+use crate::dense::{DenseMap, DenseSet};
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
+use ahash::HashSet;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q11d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("11d");
     let cn = &db.cn;
     let ct = &db.ct;
     let k = &db.k;
@@ -13,13 +18,25 @@ pub fn q11d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let ml = &db.ml;
     let t = &db.t;
 
-    let start = Instant::now();
+    let target_keywords: HashSet<&str> = ["revenge", "sequel", "based-on-novel"]
+        .into_iter()
+        .collect();
 
-    let cn_m: HashMap<&i32, &str> = cn
+    timer.phase(Phase::Lookup);
+    let ct_s: HashSet<i32> = ct
+        .kind
+        .iter()
+        .zip(ct.id.iter().copied())
+        .filter_map(|(kind_opt, id_opt)| (kind_opt != "production companies").then_some(id_opt))
+        .collect();
+
+    timer.phase(Phase::Build);
+    let cn_m: DenseMap<&str> = cn
         .id
         .iter()
         .zip(cn.name.iter())
         .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((id, name), country_code)| {
             country_code
                 .as_deref()
@@ -28,40 +45,31 @@ pub fn q11d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         })
         .collect();
 
-    let ct_s: HashSet<i32> = ct
-        .kind
-        .iter()
-        .zip(ct.id.iter().copied())
-        .filter_map(|(kind_opt, id_opt)| (kind_opt != "production companies").then_some(id_opt))
-        .collect();
-
-    let target_keywords: HashSet<&str> = ["revenge", "sequel", "based-on-novel"]
-        .into_iter()
-        .collect();
-
-    let k_s: HashSet<&i32> =
+    let k_s: DenseSet =
         k.id.iter()
             .zip(k.keyword.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(id, keyword)| target_keywords.contains(keyword.as_str()).then_some(id))
             .collect();
 
-    let mk_s: HashSet<&i32> = mk
-        .movie_id
-        .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| k_s.contains(keyword_id).then_some(movie_id))
-        .collect();
+    let mk_s: PartitionedSet<&i32> = PartitionedSet::build(mk.movie_id.len(), |row| {
+        let movie_id = &mk.movie_id[row];
+        let keyword_id = &mk.keyword_id[row];
+        k_s.contains(keyword_id).then_some(movie_id)
+    });
 
     let ml_s: HashSet<&i32> = ml
         .movie_id
         .iter()
+        .take_while(|_| !cancel::poll())
         .filter_map(|mid| mk_s.contains(&mid).then_some(mid))
         .collect();
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 if let Some(production_year) = production_year
                     && ml_s.contains(&id)
@@ -74,6 +82,7 @@ pub fn q11d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for (((mid, cid), company_type_id), note) in mc
@@ -83,6 +92,9 @@ pub fn q11d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(mc.company_type_id.iter())
         .zip(mc.note.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(note) = note
             && ct_s.contains(&company_type_id)
             && let Some(name) = cn_m.get(&cid)
@@ -99,59 +111,24 @@ pub fn q11d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    println!("11d,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 11d
-// SELECT MIN(cn.name) AS from_company,
-// MIN(mc.note) AS production_note,
-// MIN(t.title) AS movie_based_on_book
-// FROM company_name AS cn,
-// company_type AS ct,s
-// keyword AS k,
-// link_type AS lt,
-// movie_companies AS mc,
-// movie_keyword AS mk,
-// movie_link AS ml,
-// title AS t
-// WHERE cn.country_code !='[pl]'
-// AND ct.kind != 'production companies'
-// AND ct.kind IS NOT NULL
-// AND k.keyword IN ('sequel',
-// 'revenge',
-// 'based-on-novel')
-// AND mc.note IS NOT NULL
-// AND t.production_year > 1950
-// AND lt.id = ml.link_type_id
-// AND ml.movie_id = t.id
-// AND t.id = mk.movie_id
-// AND mk.keyword_id = k.id
-// AND t.id = mc.movie_id
-// AND mc.company_type_id = ct.id
-// AND mc.company_id = cn.id
-// AND ml.movie_id = mk.movie_id
-// AND ml.movie_id = mc.movie_id
-// AND mk.movie_id = mc.movie_id;
-#[cfg(test)]
-mod test_q11d {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q11d() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-
-        assert_eq!(
-            q11d(&data)?,
-            Some((
-                "13th Street",
-                "(1954) (UK) (TV)",
-                "...denn sie wissen nicht, was sie tun"
-            ))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "11d",
+    family: 11,
+    sql: include_str!("../queries/11d.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("k", "keyword"),
+        ("lt", "link_type"),
+        ("mc", "movie_companies"),
+        ("mk", "movie_keyword"),
+        ("ml", "movie_link"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q11d(db)?.into()),
+};
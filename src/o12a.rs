@@ -1,9 +1,12 @@
-use std::time::Instant;
-
 use ahash::{HashMap, HashMapExt, HashSet};
 use polars::error::PolarsError;
 
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::DenseMap;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 
 // imdb_int.movie_companies(movie_id,company_id,company_type_id)
 // |imdb.q12a_title(movie_id,kind_id)
@@ -14,6 +17,7 @@ use crate::data::Data;
 // |imdb.q12a_movie_info_idx2(movie_id,info_type_id2)
 // ||imdb.q12a_info_type2(info_type_id2)
 pub fn q12a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("12a");
     let cn = &db.cn;
     let ct = &db.ct;
     let it = &db.it;
@@ -22,8 +26,9 @@ pub fn q12a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let mi_idx = &db.mi_idx;
     let t = &db.t;
 
-    let start = Instant::now();
+    let target_info: HashSet<&str> = ["Drama", "Horror"].into_iter().collect();
 
+    timer.phase(Phase::Lookup);
     let mut it1_id: &i32 = &0;
     let mut it2_id: &i32 = &0;
 
@@ -35,11 +40,21 @@ pub fn q12a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
+    let ct_id = ct
+        .kind
+        .iter()
+        .zip(ct.id.iter())
+        .find(|(kind, _)| *kind == "production companies")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    timer.phase(Phase::Build);
     let mi_idx_m: HashMap<&i32, Vec<&str>> = mi_idx
         .movie_id
         .iter()
         .zip(mi_idx.info.iter())
         .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, info), info_type_id)| {
             (info.as_str() > "8.0" && it2_id == info_type_id).then_some((movie_id, info))
         })
@@ -48,31 +63,19 @@ pub fn q12a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let target_info: HashSet<&str> = ["Drama", "Horror"].into_iter().collect();
-
-    let mi_s: HashSet<&i32> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((movie_id, info), info_type_id)| {
-            (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
-        })
-        .collect();
-
-    let ct_id = ct
-        .kind
-        .iter()
-        .zip(ct.id.iter())
-        .find(|(kind, _)| *kind == "production companies")
-        .map(|(_, id)| id)
-        .unwrap();
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.movie_id.len(), |row| {
+        let movie_id = &mi.movie_id[row];
+        let info = &mi.info[row];
+        let info_type_id = &mi.info_type_id[row];
+        (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
+    });
 
     let cn_m: HashMap<&i32, Vec<&str>> = cn
         .id
         .iter()
         .zip(cn.name.iter())
         .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, name), country_code)| {
             country_code
                 .as_ref()
@@ -84,10 +87,11 @@ pub fn q12a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 if let Some(production_year) = production_year
                     && (2005..=2008).contains(production_year)
@@ -99,6 +103,7 @@ pub fn q12a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for ((movie_id, company_id), company_type_id) in mc
@@ -107,6 +112,9 @@ pub fn q12a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(mc.company_id.iter())
         .zip(mc.company_type_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(title) = t_m.get(&movie_id)
             && let Some(names) = cn_m.get(&company_id)
             && ct_id == company_type_id
@@ -128,53 +136,24 @@ pub fn q12a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    let duration = start.elapsed().as_secs_f32();
-    println!("12a,{duration:}");
+    timer.finish();
 
     Ok(res)
 }
 
-// JOB Query 12a
-// SELECT MIN(cn.name) AS movie_company,
-//        MIN(mi_idx.info) AS rating,
-//        MIN(t.title) AS drama_horror_movie
-// FROM company_name AS cn,
-//      company_type AS ct,
-//      info_type AS it1,
-//      info_type AS it2,
-//      movie_companies AS mc,
-//      movie_info AS mi,
-//      movie_info_idx AS mi_idx,
-//      title AS t
-// WHERE cn.country_code = '[us]'
-//   AND ct.kind = 'production companies'
-//   AND it1.info = 'genres'
-//   AND it2.info = 'rating'
-//   AND mi.info IN ('Drama',
-//                   'Horror')
-//   AND mi_idx.info > '8.0'
-//   AND t.production_year BETWEEN 2005 AND 2008
-//   AND t.id = mi.movie_id
-//   AND t.id = mi_idx.movie_id
-//   AND mi.info_type_id = it1.id
-//   AND mi_idx.info_type_id = it2.id
-//   AND t.id = mc.movie_id
-//   AND ct.id = mc.company_type_id
-//   AND cn.id = mc.company_id
-//   AND mc.movie_id = mi.movie_id
-//   AND mc.movie_id = mi_idx.movie_id
-//   AND mi.movie_id = mi_idx.movie_id;
-#[cfg(test)]
-mod test_q12a {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q12a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q12a(&data)?;
-        assert_eq!(res, Some(("10th Grade Reunion Films", "8.1", "3:20")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "12a",
+    family: 12,
+    sql: include_str!("../queries/12a.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("mi_idx", "movie_info_idx"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q12a(db)?.into()),
+};
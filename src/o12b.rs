@@ -1,10 +1,15 @@
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
+use crate::dense::{DenseMap, DenseSet};
+use crate::like::LikePattern;
+use crate::parallel::PartitionedMap;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
+use ahash::HashSet;
 use polars::error::PolarsError;
-use std::time::Instant;
 
 pub fn q12b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("12b");
     let cn = &db.cn;
     let ct = &db.ct;
     let it = &db.it;
@@ -13,11 +18,10 @@ pub fn q12b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let mi_idx = &db.mi_idx;
     let t = &db.t;
 
-    let birdemic = Finder::new("Birdemic");
-    let movie = Finder::new("Movie");
-
-    let start = Instant::now();
+    let birdemic = LikePattern::compile("Birdemic%")?;
+    let movie = LikePattern::compile("%Movie%")?;
 
+    timer.phase(Phase::Lookup);
     let mut it1_id: &i32 = &0;
     let mut it2_id: &i32 = &0;
 
@@ -29,26 +33,6 @@ pub fn q12b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         }
     }
 
-    let mi_idx_s: HashSet<&i32> = mi_idx
-        .movie_id
-        .iter()
-        .zip(mi_idx.info_type_id.iter())
-        .filter_map(|(movie_id, info_type_id)| (it2_id == info_type_id).then_some(movie_id))
-        .collect();
-
-    let mi_m: HashMap<i32, Vec<&str>> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((movie_id, info), info_type_id)| {
-            (it1_id == info_type_id).then_some((*movie_id, info.as_str()))
-        })
-        .fold(HashMap::default(), |mut acc, (movie_id, info)| {
-            acc.entry(movie_id).or_default().push(info);
-            acc
-        });
-
     let ct_s: HashSet<&i32> = ct
         .kind
         .iter()
@@ -58,10 +42,33 @@ pub fn q12b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         })
         .collect();
 
-    let cn_s: HashSet<i32> = cn
+    timer.phase(Phase::Build);
+    let mi_idx_s: HashSet<&i32> = mi_idx
+        .movie_id
+        .iter()
+        .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|(movie_id, info_type_id)| (it2_id == info_type_id).then_some(movie_id))
+        .collect();
+
+    let mi_m: PartitionedMap<i32, Vec<&str>> = PartitionedMap::build(
+        mi.movie_id.len(),
+        |row| {
+            let movie_id = &mi.movie_id[row];
+            let info = &mi.info[row];
+            let info_type_id = &mi.info_type_id[row];
+            let entry: Option<(i32, &str)> =
+                { (it1_id == info_type_id).then_some((*movie_id, info.as_str())) };
+            entry.map(|(movie_id, info)| (movie_id, vec![info]))
+        },
+        |values, more| values.extend(more),
+    );
+
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -70,15 +77,15 @@ pub fn q12b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         })
         .collect();
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 if let Some(production_year) = production_year
                     && *production_year > 2000
-                    && (birdemic.find(title.as_bytes()) == Some(0)
-                        || movie.find(title.as_bytes()).is_some())
+                    && (birdemic.matches(title.as_bytes()) || movie.matches(title.as_bytes()))
                 {
                     Some((id, title.as_str()))
                 } else {
@@ -87,6 +94,7 @@ pub fn q12b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str)> = None;
 
     for ((movie_id, company_id), company_type_id) in mc
@@ -95,6 +103,9 @@ pub fn q12b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .zip(mc.company_id.iter())
         .zip(mc.company_type_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(title) = t_m.get(&movie_id)
             && ct_s.contains(&company_type_id)
             && cn_s.contains(&company_id)
@@ -111,49 +122,24 @@ pub fn q12b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         }
     }
 
-    println!("12b,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 12b
-// SELECT MIN(mi.info) AS budget, MIN(t.title) AS unsuccsessful_movie
-// FROM company_name AS cn,
-// company_type AS ct,
-// info_type AS it1,
-// info_type AS it2,
-// movie_companies AS mc,
-// movie_info AS mi,
-// movie_info_idx AS mi_idx,
-// title AS t
-// WHERE cn.country_code = '[us]'
-// AND ct.kind is not NULL
-// and (ct.kind = 'production companies' or ct.kind = 'distributors')
-// AND it1.info = 'budget'
-// AND it2.info = 'bottom 10 rank'
-// AND t.production_year > 2000
-// AND (t.title LIKE 'Birdemic%' OR t.title LIKE '%Movie%')
-// AND t.id = mi.movie_id
-// AND t.id = mi_idx.movie_id
-// AND mi.info_type_id = it1.id
-// AND mi_idx.info_type_id = it2.id
-// AND t.id = mc.movie_id
-// AND ct.id = mc.company_type_id
-// AND cn.id = mc.company_id
-// AND mc.movie_id = mi.movie_id
-// AND mc.movie_id = mi_idx.movie_id
-// AND mi.movie_id = mi_idx.movie_id;
-#[cfg(test)]
-mod test_q12b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q12b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q12b(&data)?;
-        assert_eq!(res, Some(("$10,000", "Birdemic: Shock and Terror")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "12b",
+    family: 12,
+    sql: include_str!("../queries/12b.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("mi_idx", "movie_info_idx"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q12b(db)?.into()),
+};
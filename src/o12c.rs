@@ -1,9 +1,14 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::DenseMap;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
 use polars::error::PolarsError;
-use std::time::Instant;
 
 pub fn q12c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("12c");
     let cn = &db.cn;
     let ct = &db.ct;
     let it = &db.it;
@@ -12,8 +17,11 @@ pub fn q12c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let mi_idx = &db.mi_idx;
     let t = &db.t;
 
-    let start = Instant::now();
+    let target_info: HashSet<&str> = ["Drama", "Horror", "Western", "Family"]
+        .into_iter()
+        .collect();
 
+    timer.phase(Phase::Lookup);
     let mut it1_id: &i32 = &0;
     let mut it2_id: &i32 = &0;
 
@@ -25,11 +33,21 @@ pub fn q12c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
+    let ct_id = ct
+        .kind
+        .iter()
+        .zip(ct.id.iter())
+        .find(|(kind, _)| *kind == "production companies")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    timer.phase(Phase::Build);
     let mi_idx_m: HashMap<&i32, Vec<&str>> = mi_idx
         .movie_id
         .iter()
         .zip(mi_idx.info.iter())
         .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, info), info_type_id)| {
             (info.as_str() > "7.0" && it2_id == info_type_id).then_some((movie_id, info))
         })
@@ -38,33 +56,19 @@ pub fn q12c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let target_info: HashSet<&str> = ["Drama", "Horror", "Western", "Family"]
-        .into_iter()
-        .collect();
-
-    let mi_s: HashSet<&i32> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((movie_id, info), info_type_id)| {
-            (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
-        })
-        .collect();
-
-    let ct_id = ct
-        .kind
-        .iter()
-        .zip(ct.id.iter())
-        .find(|(kind, _)| *kind == "production companies")
-        .map(|(_, id)| id)
-        .unwrap();
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.movie_id.len(), |row| {
+        let movie_id = &mi.movie_id[row];
+        let info = &mi.info[row];
+        let info_type_id = &mi.info_type_id[row];
+        (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
+    });
 
     let cn_m: HashMap<&i32, Vec<&str>> = cn
         .id
         .iter()
         .zip(cn.name.iter())
         .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, name), country_code)| {
             country_code
                 .as_ref()
@@ -76,10 +80,11 @@ pub fn q12c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 if let Some(production_year) = production_year
                     && (2000..=2010).contains(production_year)
@@ -91,6 +96,7 @@ pub fn q12c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for ((movie_id, company_id), company_type_id) in mc
@@ -99,6 +105,9 @@ pub fn q12c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(mc.company_id.iter())
         .zip(mc.company_type_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(title) = t_m.get(&movie_id)
             && ct_id == company_type_id
             && mi_s.contains(&movie_id)
@@ -120,49 +129,24 @@ pub fn q12c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    println!("12c,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 12c
-// SELECT MIN(cn.name) AS movie_company, MIN(mi_idx.info) AS rating, MIN(t.title) AS mainstream_movie
-// FROM company_name AS cn,
-// company_type AS ct,
-// info_type AS it1,
-// info_type AS it2,
-// movie_companies AS mc,
-// movie_info AS mi,
-// movie_info_idx AS mi_idx,
-// title AS t
-// WHERE cn.country_code = '[us]'
-// AND ct.kind = 'production companies'
-// AND it1.info = 'genres'
-// AND it2.info = 'rating'
-// AND mi.info in ('Drama', 'Horror', 'Western', 'Family')
-// AND mi_idx.info > '7.0'
-// AND t.production_year between 2000 and 2010
-// AND t.id = mi.movie_id
-// AND t.id = mi_idx.movie_id
-// AND mi.info_type_id = it1.id
-// AND mi_idx.info_type_id = it2.id
-// AND t.id = mc.movie_id
-// AND ct.id = mc.company_type_id
-// AND cn.id = mc.company_id
-// AND mc.movie_id = mi.movie_id
-// AND mc.movie_id = mi_idx.movie_id
-// AND mi.movie_id = mi_idx.movie_id;
-#[cfg(test)]
-mod test_12c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q12c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q12c(&data)?;
-        assert_eq!(res, Some(("\"Oh That Gus!\"", "7.1", "$1.11")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "12c",
+    family: 12,
+    sql: include_str!("../queries/12c.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("mi_idx", "movie_info_idx"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q12c(db)?.into()),
+};
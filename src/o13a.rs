@@ -1,9 +1,16 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
+use crate::dense::{DenseMap, DenseSet};
+use crate::parallel::PartitionedMap;
+use crate::predicate::Pred;
+use crate::registry::Entry;
+use crate::roaring::RoaringSet;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q13a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("13a");
     let cn = &db.cn;
     let ct = &db.ct;
     let it = &db.it;
@@ -13,8 +20,7 @@ pub fn q13a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let mi_idx = &db.mi_idx;
     let t = &db.t;
 
-    let start = Instant::now();
-
+    timer.phase(Phase::Lookup);
     let mut it1_id: &i32 = &0;
     let mut it2_id: &i32 = &0;
 
@@ -26,11 +32,29 @@ pub fn q13a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
+    let kt_id = kt
+        .kind
+        .iter()
+        .zip(kt.id.iter())
+        .find(|(kind, _)| *kind == "movie")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    let ct_id = ct
+        .kind
+        .iter()
+        .zip(ct.id.iter())
+        .find(|(kind, _)| *kind == "production companies")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    timer.phase(Phase::Build);
     let mi_idx_m: ahash::HashMap<&i32, Vec<&str>> = mi_idx
         .movie_id
         .iter()
         .zip(mi_idx.info.iter())
         .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, info), info_type_id)| {
             (it1_id == info_type_id).then_some((movie_id, info))
         })
@@ -39,41 +63,36 @@ pub fn q13a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let kt_id = kt
-        .kind
-        .iter()
-        .zip(kt.id.iter())
-        .find(|(kind, _)| *kind == "movie")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.kind_id.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), kind_id)| {
                 (kt_id == kind_id && mi_idx_m.contains_key(&id)).then_some((id, title.as_str()))
             })
             .collect();
 
-    let mi_m: HashMap<i32, Vec<&str>> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((movie_id, info), info_type_id)| {
-            (it2_id == info_type_id && t_m.contains_key(&movie_id))
-                .then_some((*movie_id, info.as_str()))
-        })
-        .fold(HashMap::default(), |mut acc, (movie_id, info)| {
-            acc.entry(movie_id).or_default().push(info);
-            acc
-        });
+    let mi_m: PartitionedMap<i32, Vec<&str>> = PartitionedMap::build(
+        mi.movie_id.len(),
+        |row| {
+            let movie_id = &mi.movie_id[row];
+            let info = &mi.info[row];
+            let info_type_id = &mi.info_type_id[row];
+            let entry: Option<(i32, &str)> = {
+                (it2_id == info_type_id && t_m.contains_key(&movie_id))
+                    .then_some((*movie_id, info.as_str()))
+            };
+            entry.map(|(movie_id, info)| (movie_id, vec![info]))
+        },
+        |values, more| values.extend(more),
+    );
 
-    let cn_s: HashSet<&i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -82,14 +101,7 @@ pub fn q13a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         })
         .collect();
 
-    let ct_id = ct
-        .kind
-        .iter()
-        .zip(ct.id.iter())
-        .find(|(kind, _)| *kind == "production companies")
-        .map(|(_, id)| id)
-        .unwrap();
-
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for ((movie_id, company_id), company_type_id) in mc
@@ -98,6 +110,9 @@ pub fn q13a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(mc.company_id.iter())
         .zip(mc.company_type_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(title) = t_m.get(&movie_id)
             && ct_id == company_type_id
             && let Some(release_dates) = mi_m.get(&movie_id)
@@ -119,54 +134,142 @@ pub fn q13a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    let duration = start.elapsed().as_secs_f32();
-    println!("13a,{duration:}");
+    timer.finish();
 
     Ok(res)
 }
 
-// SELECT MIN(mi.info) AS release_date,
-//        MIN(miidx.info) AS rating,
-//        MIN(t.title) AS german_movie
-// FROM company_name AS cn,
-//      company_type AS ct,
-//      info_type AS it,
-//      info_type AS it2,
-//      kind_type AS kt,
-//      movie_companies AS mc,
-//      movie_info AS mi,
-//      movie_info_idx AS miidx,
-//      title AS t
-// WHERE cn.country_code ='[de]'
-//   AND ct.kind ='production companies'
-//   AND it.info ='rating'
-//   AND it2.info ='release dates'
-//   AND kt.kind ='movie'
-//   AND mi.movie_id = t.id
-//   AND it2.id = mi.info_type_id
-//   AND kt.id = t.kind_id
-//   AND mc.movie_id = t.id
-//   AND cn.id = mc.company_id
-//   AND ct.id = mc.company_type_id
-//   AND miidx.movie_id = t.id
-//   AND it.id = miidx.info_type_id
-//   AND mi.movie_id = miidx.movie_id
-//   AND mi.movie_id = mc.movie_id
-//   AND miidx.movie_id = mc.movie_id;
+/// q13a as a bitmap plan: each table's qualifying movies are computed independently and
+/// intersected at once, then each MIN is taken over its table's rows for the surviving movies.
+/// This is equivalent because every output column comes from one table joined on `movie_id`.
+pub fn q13a_bitmap(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("13a-bitmap");
+    let cn = &db.cn;
+    let ct = &db.ct;
+    let it = &db.it;
+    let kt = &db.kt;
+    let mc = &db.mc;
+    let mi = &db.mi;
+    let mi_idx = &db.mi_idx;
+    let t = &db.t;
+
+    timer.phase(Phase::Lookup);
+    let mut it1_id: i32 = 0;
+    let mut it2_id: i32 = 0;
+
+    for (id, info) in it.id.iter().zip(it.info.iter()) {
+        if info == "rating" {
+            it1_id = *id;
+        } else if info == "release dates" {
+            it2_id = *id;
+        }
+    }
+
+    let kt_id = kt
+        .kind
+        .iter()
+        .zip(kt.id.iter())
+        .find(|(kind, _)| *kind == "movie")
+        .map(|(_, id)| *id)
+        .unwrap();
+
+    let ct_id = ct
+        .kind
+        .iter()
+        .zip(ct.id.iter())
+        .find(|(kind, _)| *kind == "production companies")
+        .map(|(_, id)| *id)
+        .unwrap();
+
+    timer.phase(Phase::Build);
+    let cn_s: DenseSet = cn
+        .country_code
+        .iter()
+        .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|(country_code, id)| {
+            country_code
+                .as_ref()
+                .filter(|&code| code == "[de]")
+                .map(|_| id)
+        })
+        .collect();
+
+    let t_sel = Pred::eq(&t.kind_id, kt_id).eval();
+    let mi_idx_sel = Pred::eq(&mi_idx.info_type_id, it1_id).eval();
+    let mi_sel = Pred::eq(&mi.info_type_id, it2_id).eval();
+    let mut mc_sel = Pred::eq(&mc.company_type_id, ct_id).eval();
+    mc_sel.retain(|row| cn_s.contains(&mc.company_id[row]));
+
+    timer.phase(Phase::Probe);
+    let movies = RoaringSet::intersect_all(&[
+        t_sel.iter().map(|row| t.id[row]).collect(),
+        mi_idx_sel.iter().map(|row| mi_idx.movie_id[row]).collect(),
+        mi_sel.iter().map(|row| mi.movie_id[row]).collect(),
+        mc_sel.iter().map(|row| mc.movie_id[row]).collect(),
+    ]);
+
+    timer.phase(Phase::Aggregate);
+    let mut res = <(Min<&str>, Min<&str>, Min<&str>)>::default();
+    for row in mi_sel
+        .iter()
+        .filter(|&row| movies.contains(mi.movie_id[row]))
+    {
+        if cancel::poll() {
+            break;
+        }
+        res.0.update(mi.info[row].as_str());
+    }
+    for row in mi_idx_sel
+        .iter()
+        .filter(|&row| movies.contains(mi_idx.movie_id[row]))
+    {
+        if cancel::poll() {
+            break;
+        }
+        res.1.update(mi_idx.info[row].as_str());
+    }
+    for row in t_sel.iter().filter(|&row| movies.contains(t.id[row])) {
+        if cancel::poll() {
+            break;
+        }
+        res.2.update(t.title[row].as_str());
+    }
+
+    timer.finish();
+
+    Ok(res.finish().transpose())
+}
+
+pub const QUERY: Entry = Entry {
+    id: "13a",
+    family: 13,
+    sql: include_str!("../queries/13a.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("it", "info_type"),
+        ("it2", "info_type"),
+        ("kt", "kind_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("miidx", "movie_info_idx"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q13a(db)?.into()),
+};
 
 #[cfg(test)]
 mod test_q13a {
     use super::*;
-    use crate::data::ImdbData;
+    use crate::test_support;
 
     #[test]
-    fn test_q13a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        assert_eq!(
-            q13a(&data)?,
-            Some(("Afghanistan:24 June 2012", "1.0", "&Me"))
-        );
+    fn test_q13a_bitmap() -> Result<(), PolarsError> {
+        let Some(data) = test_support::data() else {
+            return Ok(());
+        };
+        assert_eq!(q13a_bitmap(data)?, q13a(data)?);
         Ok(())
     }
 }
@@ -1,10 +1,14 @@
+use crate::cancel;
 use crate::data::Data;
-use ahash::HashSet;
-use memchr::memmem::Finder;
+use crate::dense::DenseMap;
+use crate::like::LikePattern;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q13b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("13b");
     let cn = &db.cn;
     let ct = &db.ct;
     let it = &db.it;
@@ -14,11 +18,10 @@ pub fn q13b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let mi_idx = &db.mi_idx;
     let t = &db.t;
 
-    let champion = Finder::new("Champion");
-    let loser = Finder::new("Loser");
-
-    let start = Instant::now();
+    let champion = LikePattern::compile("%Champion%")?;
+    let loser = LikePattern::compile("%Loser%")?;
 
+    timer.phase(Phase::Lookup);
     let kt_id = kt
         .kind
         .iter()
@@ -27,19 +30,6 @@ pub fn q13b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let t_m: ahash::HashMap<&i32, &str> =
-        t.id.iter()
-            .zip(t.title.iter())
-            .zip(t.kind_id.iter())
-            .filter_map(|((id, title), kind_id)| {
-                (!title.is_empty()
-                    && kt_id == kind_id
-                    && (champion.find(title.as_bytes()).is_some()
-                        || loser.find(title.as_bytes()).is_some()))
-                .then_some((id, title.as_str()))
-            })
-            .collect();
-
     let mut it1_id: &i32 = &0;
     let mut it2_id: &i32 = &0;
 
@@ -51,11 +41,34 @@ pub fn q13b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
+    let ct_id = ct
+        .kind
+        .iter()
+        .zip(ct.id.iter())
+        .find(|(kind, _)| *kind == "production companies")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    timer.phase(Phase::Build);
+    let t_m: DenseMap<&str> =
+        t.id.iter()
+            .zip(t.title.iter())
+            .zip(t.kind_id.iter())
+            .take_while(|_| !cancel::poll())
+            .filter_map(|((id, title), kind_id)| {
+                (!title.is_empty()
+                    && kt_id == kind_id
+                    && (champion.matches(title.as_bytes()) || loser.matches(title.as_bytes())))
+                .then_some((id, title.as_str()))
+            })
+            .collect();
+
     let mi_idx_m: ahash::HashMap<&i32, Vec<&str>> = mi_idx
         .movie_id
         .iter()
         .zip(mi_idx.info.iter())
         .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, info), info_type_id)| {
             (it1_id == info_type_id && t_m.contains_key(&movie_id)).then_some((movie_id, info))
         })
@@ -64,20 +77,18 @@ pub fn q13b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let mi_s: HashSet<&i32> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info_type_id.iter())
-        .filter_map(|(movie_id, info_type_id)| {
-            (it2_id == info_type_id && t_m.contains_key(movie_id)).then_some(movie_id)
-        })
-        .collect();
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.movie_id.len(), |row| {
+        let movie_id = &mi.movie_id[row];
+        let info_type_id = &mi.info_type_id[row];
+        (it2_id == info_type_id && t_m.contains_key(movie_id)).then_some(movie_id)
+    });
 
     let cn_m: ahash::HashMap<&i32, Vec<&str>> = cn
         .id
         .iter()
         .zip(cn.name.iter())
         .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, name), country_code)| {
             country_code
                 .as_ref()
@@ -89,14 +100,7 @@ pub fn q13b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let ct_id = ct
-        .kind
-        .iter()
-        .zip(ct.id.iter())
-        .find(|(kind, _)| *kind == "production companies")
-        .map(|(_, id)| id)
-        .unwrap();
-
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for ((movie_id, company_id), company_type_id) in mc
@@ -105,6 +109,9 @@ pub fn q13b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(mc.company_id.iter())
         .zip(mc.company_type_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(title) = t_m.get(&movie_id)
             && ct_id == company_type_id
             && mi_s.contains(&movie_id)
@@ -126,39 +133,25 @@ pub fn q13b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    println!("13b,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 13b
-// SELECT MIN(cn.name) AS producing_company, MIN(miidx.info) AS rating, MIN(t.title) AS movie_about_winning
-// FROM company_name AS cn,
-// company_type AS ct,
-// info_type AS it,
-// info_type AS it2,
-// kind_type AS kt,
-// movie_companies AS mc,
-// movie_info AS mi,
-// movie_info_idx AS miidx,
-// title AS t
-// WHERE cn.country_code = '[us]'
-// AND ct.kind = 'production companies'
-// AND it.info = 'rating'
-// AND it2.info = 'release dates'
-// AND kt.kind = 'movie'
-// AND t.title != '' AND (t.title LIKE '%Champion%' OR t.title LIKE '%Loser%') AND mi.movie_id = t.id AND it2.id = mi.info_type_id AND kt.id = t.kind_id AND mc.movie_id = t.id AND cn.id = mc.company_id AND ct.id = mc.company_type_id AND miidx.movie_id = t.id AND it.id = miidx.info_type_id AND mi.movie_id = miidx.movie_id AND mi.movie_id = mc.movie_id AND miidx.movie_id = mc.movie_id;
-#[cfg(test)]
-mod test_q13b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q13b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q13b(&data)?;
-        assert_eq!(res, Some(("501audio", "1.8", "5 Time Champion")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "13b",
+    family: 13,
+    sql: include_str!("../queries/13b.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("it", "info_type"),
+        ("it2", "info_type"),
+        ("kt", "kind_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("miidx", "movie_info_idx"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q13b(db)?.into()),
+};
@@ -1,8 +1,13 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::DenseMap;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q13d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("13d");
     let cn = &db.cn;
     let ct = &db.ct;
     let it = &db.it;
@@ -12,8 +17,7 @@ pub fn q13d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let mi_idx = &db.mi_idx;
     let t = &db.t;
 
-    let start = Instant::now();
-
+    timer.phase(Phase::Lookup);
     let mut it1_id: &i32 = &0;
     let mut it2_id: &i32 = &0;
 
@@ -25,11 +29,29 @@ pub fn q13d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
+    let kt_id = kt
+        .kind
+        .iter()
+        .zip(kt.id.iter())
+        .find(|(kind, _)| *kind == "movie")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    let ct_id = ct
+        .kind
+        .iter()
+        .zip(ct.id.iter())
+        .find(|(kind, _)| *kind == "production companies")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    timer.phase(Phase::Build);
     let mi_idx_m: ahash::HashMap<&i32, Vec<&str>> = mi_idx
         .movie_id
         .iter()
         .zip(mi_idx.info.iter())
         .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, info), info_type_id)| {
             (it1_id == info_type_id).then_some((movie_id, info))
         })
@@ -38,27 +60,17 @@ pub fn q13d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let mi_s: ahash::HashSet<&i32> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info_type_id.iter())
-        .filter_map(|(movie_id, info_type_id)| {
-            (it2_id == info_type_id && mi_idx_m.contains_key(movie_id)).then_some(movie_id)
-        })
-        .collect();
-
-    let kt_id = kt
-        .kind
-        .iter()
-        .zip(kt.id.iter())
-        .find(|(kind, _)| *kind == "movie")
-        .map(|(_, id)| id)
-        .unwrap();
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.movie_id.len(), |row| {
+        let movie_id = &mi.movie_id[row];
+        let info_type_id = &mi.info_type_id[row];
+        (it2_id == info_type_id && mi_idx_m.contains_key(movie_id)).then_some(movie_id)
+    });
 
-    let t_m: ahash::HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.kind_id.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), kind_id)| {
                 (kt_id == kind_id && mi_s.contains(&id)).then_some((id, title.as_str()))
             })
@@ -69,6 +81,7 @@ pub fn q13d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .iter()
         .zip(cn.name.iter())
         .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, name), country_code)| {
             country_code
                 .as_ref()
@@ -80,14 +93,7 @@ pub fn q13d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let ct_id = ct
-        .kind
-        .iter()
-        .zip(ct.id.iter())
-        .find(|(kind, _)| *kind == "production companies")
-        .map(|(_, id)| id)
-        .unwrap();
-
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for ((movie_id, company_id), company_type_id) in mc
@@ -96,6 +102,9 @@ pub fn q13d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(mc.company_id.iter())
         .zip(mc.company_type_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(title) = t_m.get(&movie_id)
             && ct_id == company_type_id
             && mi_s.contains(&movie_id)
@@ -117,49 +126,25 @@ pub fn q13d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    println!("13d,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 13d
-// SELECT MIN(cn.name) AS producing_company, MIN(miidx.info) AS rating, MIN(t.title) AS movie
-// FROM company_name AS cn,
-// company_type AS ct,
-// info_type AS it,
-// info_type AS it2,
-// kind_type AS kt,
-// movie_companies AS mc,
-// movie_info AS mi,
-// movie_info_idx AS miidx,
-// title AS t
-// WHERE cn.country_code = '[us]'
-// AND ct.kind = 'production companies'
-// AND it.info = 'rating'
-// AND it2.info = 'release dates'
-// AND kt.kind = 'movie'
-// AND mi.movie_id = t.id
-// AND it2.id = mi.info_type_id
-// AND kt.id = t.kind_id
-// AND mc.movie_id = t.id
-// AND cn.id = mc.company_id
-// AND ct.id = mc.company_type_id
-// AND miidx.movie_id = t.id
-// AND it.id = miidx.info_type_id
-// AND mi.movie_id = miidx.movie_id
-// AND mi.movie_id = mc.movie_id
-// AND miidx.movie_id = mc.movie_id;
-#[cfg(test)]
-mod test_q13d {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q13d() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q13d(&data)?;
-        assert_eq!(res, Some(("\"O\" Films", "1.0", "#54 Meets #47")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "13d",
+    family: 13,
+    sql: include_str!("../queries/13d.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("it", "info_type"),
+        ("it2", "info_type"),
+        ("kt", "kind_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("miidx", "movie_info_idx"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q13d(db)?.into()),
+};
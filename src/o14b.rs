@@ -1,10 +1,17 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
+use crate::dense::{DenseMap, DenseSet};
+use crate::like::LikePattern;
+use crate::parallel::PartitionedSet;
+use crate::predicate::Pred;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
+use ahash::HashSet;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q14b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("14b");
     let it = &db.it;
     let k = &db.k;
     let kt = &db.kt;
@@ -13,12 +20,28 @@ pub fn q14b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let t = &db.t;
     let mk = &db.mk;
 
-    let mureder = Finder::new("murder");
-    let murder_c = Finder::new("Murder");
-    let mord = Finder::new("Mord");
+    let mureder = LikePattern::compile("%murder%")?;
+    let murder_c = LikePattern::compile("%Murder%")?;
+    let mord = LikePattern::compile("%Mord%")?;
 
-    let start = Instant::now();
+    let target_keywords: ahash::HashSet<&str> = ["murder", "murder-in-title"].into_iter().collect();
+
+    let target_info: HashSet<&str> = [
+        "Sweden",
+        "Norway",
+        "Germany",
+        "Denmark",
+        "Swedish",
+        "Denish",
+        "Norwegian",
+        "German",
+        "USA",
+        "American",
+    ]
+    .into_iter()
+    .collect();
 
+    timer.phase(Phase::Lookup);
     let mut it1_id: &i32 = &0;
     let mut it2_id: &i32 = &0;
 
@@ -30,15 +53,6 @@ pub fn q14b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         }
     }
 
-    let target_keywords: ahash::HashSet<&str> = ["murder", "murder-in-title"].into_iter().collect();
-
-    let k_s: HashSet<i32> = k
-        .keyword
-        .iter()
-        .zip(k.id.iter())
-        .filter_map(|(keyword, id)| target_keywords.contains(keyword.as_str()).then_some(*id))
-        .collect();
-
     let kt_id = kt
         .kind
         .iter()
@@ -47,126 +61,82 @@ pub fn q14b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let target_info: HashSet<&str> = [
-        "Sweden",
-        "Norway",
-        "Germany",
-        "Denmark",
-        "Swedish",
-        "Denish",
-        "Norwegian",
-        "German",
-        "USA",
-        "American",
-    ]
-    .into_iter()
-    .collect();
-
-    let mi_s: HashSet<&i32> = mi
-        .info
+    timer.phase(Phase::Build);
+    let k_s: DenseSet = k
+        .keyword
         .iter()
-        .zip(mi.movie_id.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((info, movie_id), info_type_id)| {
-            (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
-        })
+        .zip(k.id.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|(keyword, id)| target_keywords.contains(keyword.as_str()).then_some(*id))
         .collect();
 
-    let mk_s: HashSet<&i32> = mk
-        .movie_id
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.info.len(), |row| {
+        let info = &mi.info[row];
+        let movie_id = &mi.movie_id[row];
+        let info_type_id = &mi.info_type_id[row];
+        (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
+    });
+
+    let mk_s: PartitionedSet<&i32> = PartitionedSet::build(mk.movie_id.len(), |row| {
+        let movie_id = &mk.movie_id[row];
+        let keyword_id = &mk.keyword_id[row];
+        k_s.contains(keyword_id).then_some(movie_id)
+    });
+
+    let t_sel = Pred::gt(&t.production_year, 2010)
+        .and(Pred::eq(&t.kind_id, *kt_id))
+        .and(
+            Pred::like(&t.title, mureder)
+                .or(Pred::like(&t.title, murder_c))
+                .or(Pred::like(&t.title, mord)),
+        )
+        .eval();
+
+    let t_m: DenseMap<&str> = t_sel
         .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| k_s.contains(keyword_id).then_some(movie_id))
+        .take_while(|_| !cancel::poll())
+        .map(|row| (t.id[row], t.title[row].as_str()))
         .collect();
 
-    let t_m: HashMap<i32, &str> =
-        t.id.iter()
-            .zip(t.production_year.iter())
-            .zip(t.title.iter())
-            .zip(t.kind_id.iter())
-            .filter_map(|(((movie_id, production_year), title), kind_id)| {
-                if let Some(production_year) = production_year
-                    && *production_year > 2010
-                    && kt_id == kind_id
-                    && (mureder.find(title.as_bytes()).is_some()
-                        || murder_c.find(title.as_bytes()).is_some()
-                        || mord.find(title.as_bytes()).is_some())
-                {
-                    Some((*movie_id, title.as_str()))
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-    let mut res: Option<(&str, &str)> = None;
-
-    for ((info_type_id, movie_id), info) in mi_idx
-        .info_type_id
-        .iter()
-        .zip(mi_idx.movie_id.iter())
-        .zip(mi_idx.info.iter())
-    {
-        if it2_id == info_type_id
-            && info.as_str() > "6.0"
-            && let Some(title) = t_m.get(&movie_id)
-            && mi_s.contains(&movie_id)
-            && mk_s.contains(&movie_id)
+    let mi_idx_sel = Pred::eq(&mi_idx.info_type_id, *it2_id)
+        .and(Pred::gt(&mi_idx.info, "6.0"))
+        .eval();
+
+    timer.phase(Phase::Probe);
+    let mut res = <(Min<&str>, Min<&str>)>::default();
+
+    for row in mi_idx_sel.iter() {
+        if cancel::poll() {
+            break;
+        }
+        let movie_id = &mi_idx.movie_id[row];
+        let info = &mi_idx.info[row];
+        if let Some(title) = t_m.get(movie_id)
+            && mi_s.contains(movie_id)
+            && mk_s.contains(movie_id)
         {
-            res = match res {
-                Some((old_info, old_title)) => {
-                    Some((info.as_str().min(old_info), title.min(&old_title)))
-                }
-                None => Some((info, title)),
-            };
+            res.update((info.as_str(), *title));
         }
     }
 
-    println!("14b,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
-    Ok(res)
+    Ok(res.finish().transpose())
 }
 
-// -- JOB Query 14b
-// SELECT MIN(mi_idx.info) AS rating, MIN(t.title) AS western_dark_production
-// FROM info_type AS it1,
-// info_type AS it2,
-// keyword AS k,
-// kind_type AS kt,
-// movie_info AS mi,
-// movie_info_idx AS mi_idx,
-// movie_keyword AS mk,
-// title AS t
-// WHERE it1.info = 'countries'
-// AND it2.info = 'rating'
-// AND k.keyword in ('murder', 'murder-in-title')
-// AND kt.kind = 'movie'
-// AND mi.info IN
-// ('Sweden', 'Norway', 'Germany', 'Denmark', 'Swedish', 'Denish', 'Norwegian', 'German', 'USA', 'American')
-// AND mi_idx.info > '6.0'
-// AND t.production_year > 2010
-// and (t.title like '%murder%' or t.title like '%Murder%' or t.title like '%Mord%')
-// AND kt.id = t.kind_id
-// AND t.id = mi.movie_id
-// AND t.id = mk.movie_id
-// AND t.id = mi_idx.movie_id
-// AND mk.movie_id = mi.movie_id
-// AND mk.movie_id = mi_idx.movie_id
-// AND mi.movie_id = mi_idx.movie_id
-// AND k.id = mk.keyword_id
-// AND it1.id = mi.info_type_id
-// AND it2.id = mi_idx.info_type_id;
-#[cfg(test)]
-mod test_14b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q14b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q14b(&data)?;
-        assert_eq!(res, Some(("6.4", "Of Dolls and Murder")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "14b",
+    family: 14,
+    sql: include_str!("../queries/14b.sql"),
+    relations: &[
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("k", "keyword"),
+        ("kt", "kind_type"),
+        ("mi", "movie_info"),
+        ("mi_idx", "movie_info_idx"),
+        ("mk", "movie_keyword"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q14b(db)?.into()),
+};
@@ -1,9 +1,16 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
+use crate::dense::{DenseMap, DenseSet};
+use crate::parallel::PartitionedSet;
+use crate::predicate::Pred;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
+use ahash::HashSet;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q14c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("14c");
     let it = &db.it;
     let k = &db.k;
     let kt = &db.kt;
@@ -12,39 +19,12 @@ pub fn q14c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let t = &db.t;
     let mk = &db.mk;
 
-    let start = Instant::now();
-
-    let mut it1_id: &i32 = &0;
-    let mut it2_id: &i32 = &0;
-
-    for (id, info) in it.id.iter().zip(it.info.iter()) {
-        if info == "countries" {
-            it1_id = id;
-        } else if info == "rating" {
-            it2_id = id;
-        }
-    }
-
     let target_keywords: ahash::HashSet<&str> = ["murder", "murder-in-title", "blood", "violence"]
         .into_iter()
         .collect();
 
-    let k_s: HashSet<&i32> = k
-        .keyword
-        .iter()
-        .zip(k.id.iter())
-        .filter_map(|(keyword, id)| target_keywords.contains(keyword.as_str()).then_some(id))
-        .collect();
-
     let target_kind_type: ahash::HashSet<&str> = ["movie", "episode"].into_iter().collect();
 
-    let kt_s: HashSet<&i32> = kt
-        .kind
-        .iter()
-        .zip(kt.id.iter())
-        .filter_map(|(kind, id)| target_kind_type.contains(kind.as_str()).then_some(id))
-        .collect();
-
     let target_info: HashSet<&str> = [
         "Sweden",
         "Norway",
@@ -60,108 +40,96 @@ pub fn q14c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     .into_iter()
     .collect();
 
-    let mi_s: HashSet<&i32> = mi
-        .info
+    timer.phase(Phase::Lookup);
+    let mut it1_id: &i32 = &0;
+    let mut it2_id: &i32 = &0;
+
+    for (id, info) in it.id.iter().zip(it.info.iter()) {
+        if info == "countries" {
+            it1_id = id;
+        } else if info == "rating" {
+            it2_id = id;
+        }
+    }
+
+    let kt_s: HashSet<&i32> = kt
+        .kind
         .iter()
-        .zip(mi.movie_id.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((info, movie_id), info_type_id)| {
-            (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
-        })
+        .zip(kt.id.iter())
+        .filter_map(|(kind, id)| target_kind_type.contains(kind.as_str()).then_some(id))
         .collect();
 
-    let mk_s: HashSet<&i32> = mk
-        .movie_id
+    timer.phase(Phase::Build);
+    let k_s: DenseSet = k
+        .keyword
         .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| k_s.contains(keyword_id).then_some(movie_id))
+        .zip(k.id.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|(keyword, id)| target_keywords.contains(keyword.as_str()).then_some(id))
         .collect();
 
-    let t_m: HashMap<i32, &str> =
-        t.id.iter()
-            .zip(t.production_year.iter())
-            .zip(t.title.iter())
-            .zip(t.kind_id.iter())
-            .filter_map(|(((movie_id, production_year), title), kind_id)| {
-                if let Some(production_year) = production_year
-                    && *production_year > 2005
-                    && kt_s.contains(kind_id)
-                {
-                    Some((*movie_id, title.as_str()))
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-    let mut res: Option<(&str, &str)> = None;
-
-    for ((info_type_id, movie_id), info) in mi_idx
-        .info_type_id
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.info.len(), |row| {
+        let info = &mi.info[row];
+        let movie_id = &mi.movie_id[row];
+        let info_type_id = &mi.info_type_id[row];
+        (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
+    });
+
+    let mk_s: PartitionedSet<&i32> = PartitionedSet::build(mk.movie_id.len(), |row| {
+        let movie_id = &mk.movie_id[row];
+        let keyword_id = &mk.keyword_id[row];
+        k_s.contains(keyword_id).then_some(movie_id)
+    });
+
+    let t_sel = Pred::gt(&t.production_year, 2005)
+        .and(Pred::is_in(&t.kind_id, kt_s.iter().map(|&&id| id)))
+        .eval();
+
+    let t_m: DenseMap<&str> = t_sel
         .iter()
-        .zip(mi_idx.movie_id.iter())
-        .zip(mi_idx.info.iter())
-    {
-        if it2_id == info_type_id
-            && info.as_str() < "8.5"
-            && let Some(title) = t_m.get(&movie_id)
-            && mi_s.contains(&movie_id)
-            && mk_s.contains(&movie_id)
+        .take_while(|_| !cancel::poll())
+        .map(|row| (t.id[row], t.title[row].as_str()))
+        .collect();
+
+    let mi_idx_sel = Pred::eq(&mi_idx.info_type_id, *it2_id)
+        .and(Pred::lt(&mi_idx.info, "8.5"))
+        .eval();
+
+    timer.phase(Phase::Probe);
+    let mut res = <(Min<&str>, Min<&str>)>::default();
+
+    for row in mi_idx_sel.iter() {
+        if cancel::poll() {
+            break;
+        }
+        let movie_id = &mi_idx.movie_id[row];
+        let info = &mi_idx.info[row];
+        if let Some(title) = t_m.get(movie_id)
+            && mi_s.contains(movie_id)
+            && mk_s.contains(movie_id)
         {
-            res = match res {
-                Some((old_info, old_title)) => {
-                    Some((info.as_str().min(old_info), title.min(&old_title)))
-                }
-                None => Some((info, title)),
-            };
+            res.update((info.as_str(), *title));
         }
     }
 
-    println!("14c,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
-    Ok(res)
+    Ok(res.finish().transpose())
 }
 
-// -- JOB Query 14c
-// SELECT MIN(mi_idx.info) AS rating, MIN(t.title) AS north_european_dark_production
-// FROM info_type AS it1,
-// info_type AS it2,
-// keyword AS k,
-// kind_type AS kt,
-// movie_info AS mi,
-// movie_info_idx AS mi_idx,
-// movie_keyword AS mk,
-// title AS t
-// WHERE it1.info = 'countries'
-// AND it2.info = 'rating'
-// AND k.keyword is not null
-// and k.keyword in ('murder', 'murder-in-title', 'blood', 'violence')
-// AND kt.kind in ('movie', 'episode')
-// AND mi.info IN
-// ('Sweden', 'Norway', 'Germany', 'Denmark', 'Swedish', 'Danish', 'Norwegian', 'German', 'USA', 'American')
-// AND mi_idx.info < '8.5'
-// AND t.production_year > 2005
-// AND kt.id = t.kind_id
-// AND t.id = mi.movie_id
-// AND t.id = mk.movie_id
-// AND t.id = mi_idx.movie_id
-// AND mk.movie_id = mi.movie_id
-// AND mk.movie_id = mi_idx.movie_id
-// AND mi.movie_id = mi_idx.movie_id
-// AND k.id = mk.keyword_id
-// AND it1.id = mi.info_type_id
-// AND it2.id = mi_idx.info_type_id;
-#[cfg(test)]
-mod test_q14c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q14c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q14c(&data)?;
-        assert_eq!(res, Some(("1.0", "$lowdown")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "14c",
+    family: 14,
+    sql: include_str!("../queries/14c.sql"),
+    relations: &[
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("k", "keyword"),
+        ("kt", "kind_type"),
+        ("mi", "movie_info"),
+        ("mi_idx", "movie_info_idx"),
+        ("mk", "movie_keyword"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q14c(db)?.into()),
+};
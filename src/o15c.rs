@@ -1,10 +1,15 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::{DenseMap, DenseSet};
+use crate::hashtable::I32Set;
+use crate::like::LikePattern;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q15c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("15c");
     let at = &db.at;
     let cn = &db.cn;
     // let ct = &db.ct;
@@ -15,28 +20,37 @@ pub fn q15c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let mk = &db.mk;
     let t = &db.t;
 
-    let internet = Finder::new("internet");
-    let usa = Finder::new("USA:");
-    let two_hundred = Finder::new(" 200");
-    let one_nine_nine = Finder::new(" 199");
+    let internet = LikePattern::compile("%internet%")?;
+    let usa_one_nine_nine = LikePattern::compile("USA:% 199%")?;
+    let usa_two_hundred = LikePattern::compile("USA:% 200%")?;
 
     // Fk-PK optimization
     // let ct_s: HashSet<i32> = ct.column("id")?.i32()?.into_iter().flatten().collect();
 
-    let start = Instant::now();
+    timer.phase(Phase::Lookup);
+    let it_id: &i32 = it
+        .info
+        .iter()
+        .zip(it.id.iter())
+        .find(|(info, _)| *info == "release dates")
+        .map(|(_, id)| id)
+        .unwrap();
 
+    timer.phase(Phase::Build);
     let at_s: HashSet<&i32> = at.movie_id.iter().collect();
 
-    let mk_s: HashSet<&i32> = mk
+    let mk_s: I32Set = mk
         .movie_id
         .iter()
+        .take_while(|_| !cancel::poll())
         .filter_map(|movie_id| at_s.contains(movie_id).then_some(movie_id))
         .collect();
 
-    let cn_s: HashSet<i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -45,43 +59,26 @@ pub fn q15c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         })
         .collect();
 
-    let it_id: &i32 = it
-        .info
-        .iter()
-        .zip(it.id.iter())
-        .find(|(info, _)| *info == "release dates")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let mi_m: HashMap<&i32, Vec<&str>> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info.iter())
-        .zip(mi.info_type_id.iter())
-        .zip(mi.note.iter())
-        .filter_map(|(((movie_id, info), info_type_id), note)| {
-            if let Some(note) = note
-                && it_id == info_type_id
-                && internet.find(note.as_bytes()).is_some()
-                && usa.find(info.as_bytes()) == Some(0)
-                && (one_nine_nine.find(info.as_bytes()).is_some()
-                    || two_hundred.find(info.as_bytes()).is_some())
-                && mk_s.contains(movie_id)
-            {
-                Some((movie_id, info))
-            } else {
-                None
-            }
+    let mut mi_m: HashMap<&i32, Vec<&str>> = HashMap::default();
+    let rows = (0..mi.movie_id.len()).filter(|&row| {
+        mi.note[row].as_ref().is_some_and(|note| {
+            it_id == &mi.info_type_id[row]
+                && internet.matches(note.as_bytes())
+                && (usa_one_nine_nine.matches(mi.info[row].as_bytes())
+                    || usa_two_hundred.matches(mi.info[row].as_bytes()))
         })
-        .fold(HashMap::default(), |mut acc, (movie_id, info)| {
-            acc.entry(movie_id).or_default().push(info);
-            acc
-        });
-
-    let t_m: HashMap<i32, &str> =
+    });
+    mk_s.probe_rows(&mi.movie_id, rows, |row| {
+        mi_m.entry(&mi.movie_id[row])
+            .or_default()
+            .push(&mi.info[row]);
+    });
+
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.production_year.iter())
             .zip(t.title.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((movie_id, production_year), title)| {
                 if let Some(production_year) = production_year
                     && *production_year > 1990
@@ -94,9 +91,13 @@ pub fn q15c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str)> = None;
 
     for (mid, cid) in mc.movie_id.iter().zip(mc.company_id.iter()) {
+        if cancel::poll() {
+            break;
+        }
         if cn_s.contains(&cid)
             && let Some(title) = t_m.get(&mid)
             && let Some(info) = mi_m.get(&mid)
@@ -111,46 +112,25 @@ pub fn q15c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         }
     }
 
-    println!("15c,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 15c
-// SELECT MIN(mi.info) AS release_date, MIN(t.title) AS modern_american_internet_movie
-// FROM aka_title AS at, company_name AS cn, company_type AS ct, info_type AS it1, keyword AS k, movie_companies AS mc, movie_info AS mi, movie_keyword AS mk, title AS t
-// WHERE cn.country_code = '[us]'
-// AND it1.info = 'release dates'
-// AND mi.note like '%internet%'
-// AND mi.info is not NULL
-// and (mi.info like 'USA:% 199%'
-// or mi.info like 'USA:% 200%')
-// AND t.production_year > 1990
-// AND t.id = at.movie_id
-// AND t.id = mi.movie_id
-// AND t.id = mk.movie_id
-// AND t.id = mc.movie_id
-// AND mk.movie_id = mi.movie_id
-// AND mk.movie_id = mc.movie_id
-// AND mk.movie_id = at.movie_id
-// AND mi.movie_id = mc.movie_id
-// AND mi.movie_id = at.movie_id
-// AND mc.movie_id = at.movie_id
-// AND k.id = mk.keyword_id
-// AND it1.id = mi.info_type_id
-// AND cn.id = mc.company_id
-// AND ct.id = mc.company_type_id;
-#[cfg(test)]
-mod test_q15c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q15c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q15c(&data)?;
-        assert_eq!(res, Some(("USA:1 April 2003", "24: Day Six - Debrief")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "15c",
+    family: 15,
+    sql: include_str!("../queries/15c.sql"),
+    relations: &[
+        ("at", "aka_title"),
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("it1", "info_type"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("mk", "movie_keyword"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q15c(db)?.into()),
+};
@@ -1,10 +1,15 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::{DenseMap, DenseSet};
+use crate::hashtable::I32Set;
+use crate::like::LikePattern;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q15d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("15d");
     let at = &db.at;
     let cn = &db.cn;
     // let ct = &db.ct;
@@ -18,29 +23,40 @@ pub fn q15d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     // Fk-PK optimization
     // let ct_s: HashSet<i32> = ct.column("id")?.i32()?.into_iter().flatten().collect();
 
-    let internet = Finder::new("internet");
+    let internet = LikePattern::compile("%internet%")?;
 
-    let start = Instant::now();
-
-    let at_m: HashMap<&i32, Vec<&str>> =
-        at.movie_id
-            .iter()
-            .zip(at.title.iter())
-            .fold(HashMap::default(), |mut acc, (id, title)| {
-                acc.entry(id).or_default().push(title);
-                acc
-            });
+    timer.phase(Phase::Lookup);
+    let it_id: &i32 = it
+        .info
+        .iter()
+        .zip(it.id.iter())
+        .find(|(info, _)| *info == "release dates")
+        .map(|(_, id)| id)
+        .unwrap();
 
-    let mk_s: HashSet<&i32> = mk
+    timer.phase(Phase::Build);
+    let at_m: HashMap<&i32, Vec<&str>> = at
+        .movie_id
+        .iter()
+        .zip(at.title.iter())
+        .take_while(|_| !cancel::poll())
+        .fold(HashMap::default(), |mut acc, (id, title)| {
+            acc.entry(id).or_default().push(title);
+            acc
+        });
+
+    let mk_s: I32Set = mk
         .movie_id
         .iter()
+        .take_while(|_| !cancel::poll())
         .filter_map(|movie_id| at_m.contains_key(movie_id).then_some(movie_id))
         .collect();
 
-    let cn_s: HashSet<i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -49,36 +65,21 @@ pub fn q15d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         })
         .collect();
 
-    let it_id: &i32 = it
-        .info
-        .iter()
-        .zip(it.id.iter())
-        .find(|(info, _)| *info == "release dates")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let mi_s: HashSet<&i32> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info_type_id.iter())
-        .zip(mi.note.iter())
-        .filter_map(|((movie_id, info_type_id), note)| {
-            if let Some(note) = note
-                && internet.find(note.as_bytes()).is_some()
-                && it_id == info_type_id
-                && mk_s.contains(movie_id)
-            {
-                Some(movie_id)
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    let t_m: HashMap<i32, &str> =
+    let mut mi_s: HashSet<&i32> = HashSet::default();
+    let rows = (0..mi.movie_id.len()).filter(|&row| {
+        mi.note[row]
+            .as_ref()
+            .is_some_and(|note| internet.matches(note.as_bytes()) && it_id == &mi.info_type_id[row])
+    });
+    mk_s.probe_rows(&mi.movie_id, rows, |row| {
+        mi_s.insert(&mi.movie_id[row]);
+    });
+
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.production_year.iter())
             .zip(t.title.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((movie_id, production_year), title)| {
                 if let Some(production_year) = production_year
                     && *production_year > 1990
@@ -91,9 +92,13 @@ pub fn q15d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str)> = None;
 
     for (mid, cid) in mc.movie_id.iter().zip(mc.company_id.iter()) {
+        if cancel::poll() {
+            break;
+        }
         if cn_s.contains(&cid)
             && let Some(title) = t_m.get(&mid)
             && let Some(at_titles) = at_m.get(&mid)
@@ -108,44 +113,25 @@ pub fn q15d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         }
     }
 
-    println!("15d,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 15d
-// SELECT MIN(at.title) AS aka_title, MIN(t.title) AS internet_movie_title
-// FROM aka_title AS at, company_name AS cn, company_type AS ct, info_type AS it1, keyword AS k, movie_companies AS mc, movie_info AS mi, movie_keyword AS mk, title AS t
-// WHERE cn.country_code = '[us]'
-// AND it1.info = 'release dates'
-// AND mi.note like '%internet%'
-// AND t.production_year
-// > 1990
-// AND t.id = at.movie_id
-// AND t.id = mi.movie_id
-// AND t.id = mk.movie_id
-// AND t.id = mc.movie_id
-// AND mk.movie_id = mi.movie_id
-// AND mk.movie_id = mc.movie_id
-// AND mk.movie_id = at.movie_id
-// AND mi.movie_id = mc.movie_id
-// AND mi.movie_id = at.movie_id
-// AND mc.movie_id = at.movie_id
-// AND k.id = mk.keyword_id
-// AND it1.id = mi.info_type_id
-// AND cn.id = mc.company_id
-// AND ct.id = mc.company_type_id;
-#[cfg(test)]
-mod test_q15d {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q15d() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q15d(&data)?;
-        assert_eq!(res, Some(("(Not So) Instant Photo", "06/05")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "15d",
+    family: 15,
+    sql: include_str!("../queries/15d.sql"),
+    relations: &[
+        ("at", "aka_title"),
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("it1", "info_type"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("mk", "movie_keyword"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q15d(db)?.into()),
+};
@@ -1,9 +1,15 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::{DenseMap, DenseSet};
+use crate::parallel::{self, PartitionedSet};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q16a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("16a");
     let an = &db.an;
     let ci = &db.ci;
     let cn = &db.cn;
@@ -12,15 +18,7 @@ pub fn q16a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let t = &db.t;
     let mc = &db.mc;
 
-    let start = Instant::now();
-
-    let an_m: HashMap<&i32, &str> = an
-        .person_id
-        .iter()
-        .zip(an.name.iter())
-        .map(|(person_id, name)| (person_id, name.as_str()))
-        .collect();
-
+    timer.phase(Phase::Lookup);
     let k_id = k
         .keyword
         .iter()
@@ -29,17 +27,26 @@ pub fn q16a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s: HashSet<&i32> = mk
-        .movie_id
+    timer.phase(Phase::Build);
+    let an_m: HashMap<&i32, &str> = an
+        .person_id
         .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| (keyword_id == k_id).then_some(movie_id))
+        .zip(an.name.iter())
+        .take_while(|_| !cancel::poll())
+        .map(|(person_id, name)| (person_id, name.as_str()))
         .collect();
 
-    let t_m: HashMap<&i32, &str> =
+    let mk_s: PartitionedSet<&i32> = PartitionedSet::build(mk.movie_id.len(), |row| {
+        let movie_id = &mk.movie_id[row];
+        let keyword_id = &mk.keyword_id[row];
+        (keyword_id == k_id).then_some(movie_id)
+    });
+
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.episode_nr.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((movie_id, title), episode_nr)| {
                 if let Some(episode_nr) = episode_nr
                     && mk_s.contains(&movie_id)
@@ -52,10 +59,11 @@ pub fn q16a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
-    let cn_s: HashSet<i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -68,69 +76,44 @@ pub fn q16a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .company_id
         .iter()
         .zip(mc.movie_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(company_id, movie_id)| {
             (t_m.contains_key(&movie_id) && cn_s.contains(&company_id)).then_some(movie_id)
         })
         .collect();
 
-    let mut res: Option<(&str, &str)> = None;
-
-    for (person_id, movie_id) in ci.person_id.iter().zip(ci.movie_id.iter()) {
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.person_id.len(), |row, res| {
+        let person_id = &ci.person_id[row];
+        let movie_id = &ci.movie_id[row];
         if mc_s.contains(&movie_id)
             && let Some(title) = t_m.get(&movie_id)
             && let Some(name) = an_m.get(&person_id)
         {
-            res = match res {
-                Some((old_name, old_title)) => Some((name.min(&old_name), title.min(&old_title))),
-                None => Some((name, title)),
-            };
+            res.update((*name, *title));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    println!("16a,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 16a
-// SELECT MIN(an.name) AS cool_actor_pseudonym, MIN(t.title) AS series_named_after_char
-// FROM aka_name AS an,
-// cast_info AS ci,
-// company_name AS cn,
-// keyword AS k,
-// movie_companies AS mc,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE cn.country_code = '[us]'
-// AND k.keyword = 'character-name-in-title'
-// AND t.episode_nr >= 50
-// AND t.episode_nr < 100
-// AND an.person_id = n.id
-// AND n.id = ci.person_id
-// AND ci.movie_id = t.id
-// AND t.id = mk.movie_id
-// AND mk.keyword_id = k.id
-// AND t.id = mc.movie_id
-// AND mc.company_id = cn.id
-// AND an.person_id = ci.person_id
-// AND ci.movie_id = mc.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND mc.movie_id = mk.movie_id;
-#[cfg(test)]
-mod test_q16a {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q16a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q16a(&data)?;
-        assert_eq!(
-            res,
-            Some(("Adams, Stan", "Carol Burnett vs. Anthony Perkins"))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "16a",
+    family: 16,
+    sql: include_str!("../queries/16a.sql"),
+    relations: &[
+        ("an", "aka_name"),
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q16a(db)?.into()),
+};
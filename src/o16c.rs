@@ -1,9 +1,15 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::{DenseMap, DenseSet};
+use crate::parallel::{self, PartitionedSet};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q16c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("16c");
     let an = &db.an;
     let ci = &db.ci;
     let cn = &db.cn;
@@ -12,8 +18,16 @@ pub fn q16c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let t = &db.t;
     let mc = &db.mc;
 
-    let start = Instant::now();
+    timer.phase(Phase::Lookup);
+    let k_id = k
+        .keyword
+        .iter()
+        .zip(k.id.iter())
+        .find(|(keyword, _)| *keyword == "character-name-in-title")
+        .map(|(_, id)| id)
+        .unwrap();
 
+    timer.phase(Phase::Build);
     let an_m: HashMap<&i32, Vec<&str>> = an.person_id.iter().zip(an.name.iter()).fold(
         HashMap::<&i32, Vec<&str>>::default(),
         |mut acc, (person_id, name)| {
@@ -22,25 +36,17 @@ pub fn q16c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         },
     );
 
-    let k_id = k
-        .keyword
-        .iter()
-        .zip(k.id.iter())
-        .find(|(keyword, _)| *keyword == "character-name-in-title")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let mk_s: HashSet<&i32> = mk
-        .movie_id
-        .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| (keyword_id == k_id).then_some(movie_id))
-        .collect();
+    let mk_s: PartitionedSet<&i32> = PartitionedSet::build(mk.movie_id.len(), |row| {
+        let movie_id = &mk.movie_id[row];
+        let keyword_id = &mk.keyword_id[row];
+        (keyword_id == k_id).then_some(movie_id)
+    });
 
-    let cn_s: HashSet<i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -53,15 +59,17 @@ pub fn q16c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .company_id
         .iter()
         .zip(mc.movie_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(company_id, movie_id)| {
             (mk_s.contains(movie_id) && cn_s.contains(&company_id)).then_some(movie_id)
         })
         .collect();
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.episode_nr.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((movie_id, title), episode_nr)| {
                 if let Some(episode_nr) = episode_nr
                     && mc_s.contains(&movie_id)
@@ -74,62 +82,37 @@ pub fn q16c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mut res: Option<(&str, &str)> = None;
-
-    for (person_id, movie_id) in ci.person_id.iter().zip(ci.movie_id.iter()) {
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.person_id.len(), |row, res| {
+        let person_id = &ci.person_id[row];
+        let movie_id = &ci.movie_id[row];
         if let Some(title) = t_m.get(&movie_id)
             && let Some(name) = an_m.get(&person_id)
         {
-            res = match res {
-                Some((old_name, old_title)) => Some((
-                    name.iter().min().unwrap().min(&old_name),
-                    title.min(&old_title),
-                )),
-                None => Some((name.iter().min().unwrap(), title)),
-            };
+            res.update((*name.iter().min().unwrap(), *title));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    println!("16c,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 16c
-// SELECT MIN(an.name) AS cool_actor_pseudonym, MIN(t.title) AS series_named_after_char
-// FROM aka_name AS an,
-// cast_info AS ci,
-// company_name AS cn,
-// keyword AS k,
-// movie_companies AS mc,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE cn.country_code = '[us]'
-// AND k.keyword = 'character-name-in-title'
-// AND t.episode_nr < 100
-// AND an.person_id = n.id
-// AND n.id = ci.person_id
-// AND ci.movie_id = t.id
-// AND t.id = mk.movie_id
-// AND mk.keyword_id = k.id
-// AND t.id = mc.movie_id
-// AND mc.company_id = cn.id
-// AND an.person_id = ci.person_id
-// AND ci.movie_id = mc.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND mc.movie_id = mk.movie_id;
-#[cfg(test)]
-mod test_q16c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q16c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q16c(&data)?;
-        assert_eq!(res, Some(("\"Brooklyn\" Tony Danza", "(#1.5)")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "16c",
+    family: 16,
+    sql: include_str!("../queries/16c.sql"),
+    relations: &[
+        ("an", "aka_name"),
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q16c(db)?.into()),
+};
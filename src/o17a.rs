@@ -1,10 +1,16 @@
+use crate::aggregate::{Accumulate, Aggregate, Min};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
-use memchr::memmem;
+use crate::dense::DenseSet;
+use crate::explain;
+use crate::like::LikePattern;
+use crate::parallel::{self, PartitionedMap, PartitionedSet};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q17a(db: &Data) -> Result<Option<&str>, PolarsError> {
+    let mut timer = QueryTimer::start("17a");
     let ci = &db.ci;
     let k = &db.k;
     let mk = &db.mk;
@@ -20,10 +26,9 @@ pub fn q17a(db: &Data) -> Result<Option<&str>, PolarsError> {
     //     .into_iter()
     //     .flatten()
     //     .collect();
-    let b_finder = memmem::Finder::new("B");
-
-    let start = Instant::now();
+    let b_finder = LikePattern::compile("B%")?;
 
+    timer.phase(Phase::Lookup);
     let k_id = k
         .keyword
         .iter()
@@ -32,94 +37,80 @@ pub fn q17a(db: &Data) -> Result<Option<&str>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s: HashSet<i32> = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| (keyword_id == k_id).then_some(*movie_id))
-        .collect();
-
-    let cn_s: HashSet<i32> = cn
-        .id
-        .iter()
-        .zip(cn.country_code.iter())
-        .filter_map(|(id, country_code)| {
-            country_code
-                .as_ref()
-                .filter(|&country_code| country_code == "[us]")
-                .map(|_| *id)
+    timer.phase(Phase::Build);
+    let mk_s = explain::node("mk_s: build mk by keyword", mk.movie_id.len(), || {
+        PartitionedSet::build(mk.movie_id.len(), |row| {
+            (mk.keyword_id[row] == *k_id).then_some(mk.movie_id[row])
         })
-        .collect();
+    });
 
-    let mc_s: HashSet<i32> = mc
-        .company_id
-        .iter()
-        .zip(mc.movie_id.iter())
-        .filter_map(|(company_id, movie_id)| {
-            (mk_s.contains(&movie_id) && cn_s.contains(&company_id)).then_some(*movie_id)
-        })
-        .collect();
+    let cn_s: DenseSet = explain::node("cn_s: build cn by country", cn.id.len(), || {
+        cn.id
+            .iter()
+            .zip(cn.country_code.iter())
+            .take_while(|_| !cancel::poll())
+            .filter_map(|(id, country_code)| {
+                country_code
+                    .as_ref()
+                    .filter(|&country_code| country_code == "[us]")
+                    .map(|_| *id)
+            })
+            .collect()
+    });
 
-    let n_m: HashMap<i32, &str> =
-        n.id.iter()
-            .zip(n.name.iter())
-            .filter_map(|(id, name)| {
-                (b_finder.find(name.as_bytes()) == Some(0)).then_some((*id, name.as_str()))
+    let mc_s = explain::node(
+        "mc_s: build mc semijoin mk_s, cn_s",
+        mc.movie_id.len(),
+        || {
+            PartitionedSet::build(mc.movie_id.len(), |row| {
+                let movie_id = mc.movie_id[row];
+                (mk_s.contains(&movie_id) && cn_s.contains(&mc.company_id[row])).then_some(movie_id)
             })
-            .collect();
+        },
+    );
 
-    let res: Option<&str> = ci
-        .person_id
-        .iter()
-        .zip(ci.movie_id.iter())
-        .filter_map(|(person_id, movie_id)| {
-            if let Some(name) = n_m.get(&person_id)
-                && mc_s.contains(&movie_id)
+    let n_m = explain::node("n_m: build n by name", n.id.len(), || {
+        PartitionedMap::build(
+            n.id.len(),
+            |row| {
+                b_finder
+                    .matches(n.name[row].as_bytes())
+                    .then_some((n.id[row], n.name[row].as_str()))
+            },
+            |_, _| {},
+        )
+    });
+
+    timer.phase(Phase::Probe);
+    let res = explain::node("min: probe ci with n_m, mc_s", ci.person_id.len(), || {
+        parallel::probe::<Min<&str>>(ci.person_id.len(), |row, res| {
+            if let Some(name) = n_m.get(&ci.person_id[row])
+                && mc_s.contains(&ci.movie_id[row])
             {
-                Some(*name)
-            } else {
-                None
+                res.update(*name);
             }
         })
-        .min();
+        .finish()
+    });
 
-    println!("17a,{:}", start.elapsed().as_secs_f32());
+    cancel::check()?;
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 17a
-// SELECT MIN(n.name) AS member_in_charnamed_american_movie,
-//        MIN(n.name) AS a1
-// FROM cast_info AS ci,
-//      company_name AS cn,
-//      keyword AS k,
-//      movie_companies AS mc,
-//      movie_keyword AS mk,
-//      name AS n,
-//      title AS t
-// WHERE cn.country_code ='[us]'
-//   AND k.keyword ='character-name-in-title'
-//   AND n.name LIKE 'B%'
-//   AND n.id = ci.person_id
-//   AND ci.movie_id = t.id
-//   AND t.id = mk.movie_id
-//   AND mk.keyword_id = k.id
-//   AND t.id = mc.movie_id
-//   AND mc.company_id = cn.id
-//   AND ci.movie_id = mc.movie_id
-//   AND ci.movie_id = mk.movie_id
-//   AND mc.movie_id = mk.movie_id;
-#[cfg(test)]
-mod test_q17a {
-    use super::*;
-    use crate::data::{Data, ImdbData};
-
-    #[test]
-    fn test_q17a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        assert_eq!(q17a(&data)?, Some("B, Khaz"));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "17a",
+    family: 17,
+    sql: include_str!("../queries/17a.sql"),
+    relations: &[
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q17a(db)?.into()),
+};
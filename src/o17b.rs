@@ -1,9 +1,15 @@
+use crate::bloom::Filter;
+use crate::cancel;
 use crate::data::Data;
-use memchr::memmem::Finder;
+use crate::dense::DenseMap;
+use crate::like::LikePattern;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q17b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("17b");
     let ci = &db.ci;
     let k = &db.k;
     let mk = &db.mk;
@@ -27,10 +33,9 @@ pub fn q17b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     //     .flatten()
     //     .collect();
 
-    let z = Finder::new("Z");
-
-    let start = Instant::now();
+    let z = LikePattern::compile("Z%")?;
 
+    timer.phase(Phase::Lookup);
     let k_id = k
         .keyword
         .iter()
@@ -39,74 +44,56 @@ pub fn q17b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s: ahash::HashSet<i32> = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| (keyword_id == k_id).then_some(*movie_id))
-        .collect();
+    timer.phase(Phase::Build);
+    let mk_s: PartitionedSet<i32> = PartitionedSet::build(mk.keyword_id.len(), |row| {
+        let keyword_id = &mk.keyword_id[row];
+        let movie_id = &mk.movie_id[row];
+        (keyword_id == k_id).then_some(*movie_id)
+    });
 
-    let mc_s: ahash::HashSet<i32> = mc
-        .movie_id
-        .iter()
-        .filter_map(|movie_id| mk_s.contains(&movie_id).then_some(*movie_id))
-        .collect();
+    let mc_f = Filter::new(
+        mc.movie_id
+            .iter()
+            .take_while(|_| !cancel::poll())
+            .filter_map(|movie_id| mk_s.contains(&movie_id).then_some(*movie_id)),
+    );
 
-    let n_m: ahash::HashMap<&i32, &str> =
+    let n_m: DenseMap<&str> =
         n.id.iter()
             .zip(n.name.iter())
-            .filter_map(|(id, name)| {
-                (z.find(name.as_bytes()) == Some(0)).then_some((id, name.as_str()))
-            })
+            .take_while(|_| !cancel::poll())
+            .filter_map(|(id, name)| z.matches(name.as_bytes()).then_some((id, name.as_str())))
             .collect();
 
-    let res = ci
-        .person_id
+    timer.phase(Phase::Probe);
+    let res = mc_f
+        .reduce(&ci.movie_id)
         .iter()
-        .zip(ci.movie_id.iter())
-        .filter_map(|(person_id, movie_id)| {
-            n_m.get(&person_id)
-                .filter(|_| mc_s.contains(&movie_id))
+        .take_while(|_| !cancel::poll())
+        .filter_map(|row| {
+            n_m.get(&ci.person_id[row])
+                .filter(|_| mc_f.confirm(ci.movie_id[row]))
                 .copied()
         })
         .min();
 
-    println!("17b,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res.map(|name| (name, name)))
 }
 
-// -- JOB Query 17b
-// SELECT MIN(n.name) AS member_in_charnamed_movie, MIN(n.name) AS a1
-// FROM cast_info AS ci,
-// company_name AS cn,
-// keyword AS k,
-// movie_companies AS mc,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE k.keyword = 'character-name-in-title'
-// AND n.name LIKE 'Z%'
-// AND n.id = ci.person_id
-// AND ci.movie_id = t.id
-// AND t.id = mk.movie_id
-// AND mk.keyword_id = k.id
-// AND t.id = mc.movie_id
-// AND mc.company_id = cn.id
-// AND ci.movie_id = mc.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND mc.movie_id = mk.movie_id;
-#[cfg(test)]
-mod test_q17b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q17b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q17b(&data)?;
-        assert_eq!(res, Some(("Z'Dar, Robert", "Z'Dar, Robert")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "17b",
+    family: 17,
+    sql: include_str!("../queries/17b.sql"),
+    relations: &[
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q17b(db)?.into()),
+};
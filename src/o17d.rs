@@ -1,9 +1,16 @@
+use crate::aggregate::{Accumulate, Aggregate, Min};
+use crate::cancel;
 use crate::data::Data;
-use memchr::memmem::Finder;
+use crate::dense::DenseMap;
+use crate::hashtable::I32Set;
+use crate::like::LikePattern;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q17d(db: &Data) -> Result<Option<&str>, PolarsError> {
+    let mut timer = QueryTimer::start("17d");
     let ci = &db.ci;
     let k = &db.k;
     let mk = &db.mk;
@@ -12,7 +19,7 @@ pub fn q17d(db: &Data) -> Result<Option<&str>, PolarsError> {
     let mc = &db.mc;
     // let t = &db.t;
 
-    let bert = Finder::new("Bert");
+    let bert = LikePattern::compile("%Bert%")?;
     // FK-PK optimization: FK is ci.movie_id and PK is title.id
     // let t_s: HashSet<i32> = t
     //     .column("id")?
@@ -23,8 +30,7 @@ pub fn q17d(db: &Data) -> Result<Option<&str>, PolarsError> {
 
     // let cn_s: HashSet<i32> = cn.column("id")?.i32()?.into_iter().flatten().collect();
 
-    let start = Instant::now();
-
+    timer.phase(Phase::Lookup);
     let k_id = k
         .keyword
         .iter()
@@ -33,77 +39,52 @@ pub fn q17d(db: &Data) -> Result<Option<&str>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s: ahash::HashSet<i32> = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| (keyword_id == k_id).then_some(*movie_id))
-        .collect();
+    timer.phase(Phase::Build);
+    let mk_s: PartitionedSet<i32> = PartitionedSet::build(mk.keyword_id.len(), |row| {
+        let keyword_id = &mk.keyword_id[row];
+        let movie_id = &mk.movie_id[row];
+        (keyword_id == k_id).then_some(*movie_id)
+    });
 
-    let mc_s: ahash::HashSet<i32> = mc
+    let mc_s: I32Set = mc
         .movie_id
         .iter()
+        .take_while(|_| !cancel::poll())
         .filter_map(|movie_id| mk_s.contains(&movie_id).then_some(*movie_id))
         .collect();
 
-    let n_m: ahash::HashMap<&i32, &str> =
+    let n_m: DenseMap<&str> =
         n.id.iter()
             .zip(n.name.iter())
-            .filter_map(|(id, name)| {
-                bert.find(name.as_bytes())
-                    .is_some()
-                    .then_some((id, name.as_str()))
-            })
+            .take_while(|_| !cancel::poll())
+            .filter_map(|(id, name)| bert.matches(name.as_bytes()).then_some((id, name.as_str())))
             .collect();
 
-    let res = ci
-        .person_id
-        .iter()
-        .zip(ci.movie_id.iter())
-        .filter_map(|(person_id, movie_id)| {
-            // Check if this person has a "Bert" name and the movie is valid
-            n_m.get(&person_id)
-                .filter(|_| mc_s.contains(&movie_id))
-                .copied()
-        })
-        .min();
+    timer.phase(Phase::Probe);
+    let mut res = Min::<&str>::default();
+    let rows = (0..ci.person_id.len()).filter(|&row| n_m.contains_key(&ci.person_id[row]));
+    mc_s.probe_rows(&ci.movie_id, rows, |row| {
+        res.update(*n_m.get(&ci.person_id[row]).unwrap())
+    });
+    let res = res.finish();
 
-    println!("17d,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 17d
-// SELECT MIN(n.name) AS member_in_charnamed_movie
-// FROM cast_info AS ci,
-// company_name AS cn,
-// keyword AS k,
-// movie_companies AS mc,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE k.keyword = 'character-name-in-title'
-// AND n.name LIKE '%Bert%'
-// AND n.id = ci.person_id
-// AND ci.movie_id = t.id
-// AND t.id = mk.movie_id
-// AND mk.keyword_id = k.id
-// AND t.id = mc.movie_id
-// AND mc.company_id = cn.id
-// AND ci.movie_id = mc.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND mc.movie_id = mk.movie_id;
-#[cfg(test)]
-mod test_q17d {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q17d() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q17d(&data)?;
-        assert_eq!(res, Some("Abrahamsson, Bertil"));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "17d",
+    family: 17,
+    sql: include_str!("../queries/17d.sql"),
+    relations: &[
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q17d(db)?.into()),
+};
@@ -1,9 +1,15 @@
+use crate::aggregate::{Accumulate, Aggregate, Min};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
+use crate::dense::{DenseMap, DenseSet};
+use crate::hashtable::I32Set;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q17e(db: &Data) -> Result<Option<&str>, PolarsError> {
+    let mut timer = QueryTimer::start("17e");
     let ci = &db.ci;
     let k = &db.k;
     let mk = &db.mk;
@@ -20,14 +26,7 @@ pub fn q17e(db: &Data) -> Result<Option<&str>, PolarsError> {
     //     .flatten()
     //     .collect();
 
-    let start = Instant::now();
-
-    let n_m: HashMap<&i32, &str> =
-        n.id.iter()
-            .zip(n.name.iter())
-            .map(|(id, name)| (id, name.as_str()))
-            .collect();
-
+    timer.phase(Phase::Lookup);
     let k_id = k
         .keyword
         .iter()
@@ -36,17 +35,25 @@ pub fn q17e(db: &Data) -> Result<Option<&str>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s: ahash::HashSet<i32> = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| (keyword_id == k_id).then_some(*movie_id))
-        .collect();
+    timer.phase(Phase::Build);
+    let n_m: DenseMap<&str> =
+        n.id.iter()
+            .zip(n.name.iter())
+            .take_while(|_| !cancel::poll())
+            .map(|(id, name)| (id, name.as_str()))
+            .collect();
+
+    let mk_s: PartitionedSet<i32> = PartitionedSet::build(mk.keyword_id.len(), |row| {
+        let keyword_id = &mk.keyword_id[row];
+        let movie_id = &mk.movie_id[row];
+        (keyword_id == k_id).then_some(*movie_id)
+    });
 
-    let cn_s: HashSet<i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -55,66 +62,41 @@ pub fn q17e(db: &Data) -> Result<Option<&str>, PolarsError> {
         })
         .collect();
 
-    let mc_s: ahash::HashSet<&i32> = mc
+    let mc_s: I32Set = mc
         .movie_id
         .iter()
         .zip(mc.company_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(movie_id, company_id)| {
             (mk_s.contains(&movie_id) && cn_s.contains(&company_id)).then_some(movie_id)
         })
         .collect();
 
-    let res: Option<&str> = ci
-        .person_id
-        .iter()
-        .zip(ci.movie_id.iter())
-        .filter_map(|(person_id, movie_id)| {
-            if let Some(name) = n_m.get(&person_id)
-                && mc_s.contains(&movie_id)
-            {
-                Some(*name)
-            } else {
-                None
-            }
-        })
-        .min();
+    timer.phase(Phase::Probe);
+    let mut res = Min::<&str>::default();
+    let rows = (0..ci.person_id.len()).filter(|&row| n_m.contains_key(&ci.person_id[row]));
+    mc_s.probe_rows(&ci.movie_id, rows, |row| {
+        res.update(*n_m.get(&ci.person_id[row]).unwrap())
+    });
+    let res = res.finish();
 
-    println!("17e,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 17e
-// SELECT MIN(n.name) AS member_in_charnamed_movie
-// FROM cast_info AS ci,
-// company_name AS cn,
-// keyword AS k,
-// movie_companies AS mc,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE cn.country_code = '[us]'
-// AND k.keyword = 'character-name-in-title'
-// AND n.id = ci.person_id
-// AND ci.movie_id = t.id
-// AND t.id = mk.movie_id
-// AND mk.keyword_id = k.id
-// AND t.id = mc.movie_id
-// AND mc.company_id = cn.id
-// AND ci.movie_id = mc.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND mc.movie_id = mk.movie_id;
-#[cfg(test)]
-mod test_q17e {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q17e() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q17e(&data)?;
-        assert_eq!(res, Some("$hort, Too"));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "17e",
+    family: 17,
+    sql: include_str!("../queries/17e.sql"),
+    relations: &[
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q17e(db)?.into()),
+};
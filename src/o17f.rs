@@ -1,18 +1,23 @@
+use crate::aggregate::{Accumulate, Aggregate, Min};
+use crate::cancel;
 use crate::data::Data;
-use memchr::memmem::Finder;
+use crate::dense::DenseMap;
+use crate::hashtable::I32Set;
+use crate::like::LikePattern;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q17f(db: &Data) -> Result<Option<&str>, PolarsError> {
+    let mut timer = QueryTimer::start("17f");
     let ci = &db.ci;
     let k = &db.k;
     let mk = &db.mk;
     let n = &db.n;
 
-    let b = Finder::new("B");
-
-    let start = Instant::now();
+    let b = LikePattern::compile("%B%")?;
 
+    timer.phase(Phase::Lookup);
     let k_id = k
         .keyword
         .iter()
@@ -21,71 +26,47 @@ pub fn q17f(db: &Data) -> Result<Option<&str>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s: ahash::HashSet<i32> = mk
+    timer.phase(Phase::Build);
+    let mk_s: I32Set = mk
         .keyword_id
         .iter()
         .zip(mk.movie_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(keyword_id, movie_id)| (keyword_id == k_id).then_some(*movie_id))
         .collect();
 
-    let n_m: ahash::HashMap<&i32, &str> =
+    let n_m: DenseMap<&str> =
         n.id.iter()
             .zip(n.name.iter())
-            .filter_map(|(id, name)| {
-                b.find(name.as_bytes())
-                    .is_some()
-                    .then_some((id, name.as_str()))
-            })
+            .take_while(|_| !cancel::poll())
+            .filter_map(|(id, name)| b.matches(name.as_bytes()).then_some((id, name.as_str())))
             .collect();
 
-    let res = ci
-        .person_id
-        .iter()
-        .zip(ci.movie_id.iter())
-        .filter_map(|(person_id, movie_id)| {
-            // Check if this person has a "Bert" name and the movie is valid
-            n_m.get(&person_id)
-                .filter(|_| mk_s.contains(&movie_id))
-                .copied()
-        })
-        .min();
+    timer.phase(Phase::Probe);
+    let mut res = Min::<&str>::default();
+    let rows = (0..ci.person_id.len()).filter(|&row| n_m.contains_key(&ci.person_id[row]));
+    mk_s.probe_rows(&ci.movie_id, rows, |row| {
+        res.update(*n_m.get(&ci.person_id[row]).unwrap())
+    });
+    let res = res.finish();
 
-    println!("17f,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 17f
-// SELECT MIN(n.name) AS member_in_charnamed_movie
-// FROM cast_info AS ci,
-// company_name AS cn,
-// keyword AS k,
-// movie_companies AS mc,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE k.keyword = 'character-name-in-title'
-// AND n.name LIKE '%B%'
-// AND n.id = ci.person_id
-// AND ci.movie_id = t.id
-// AND t.id = mk.movie_id
-// AND mk.keyword_id = k.id
-// AND t.id = mc.movie_id
-// AND mc.company_id = cn.id
-// AND ci.movie_id = mc.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND mc.movie_id = mk.movie_id;
-
-#[cfg(test)]
-mod test_q17f {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q17f() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        assert_eq!(q17f(&data)?, Some("'El Galgo PornoStar', Blanquito"));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "17f",
+    family: 17,
+    sql: include_str!("../queries/17f.sql"),
+    relations: &[
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q17f(db)?.into()),
+};
@@ -1,9 +1,15 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
+use crate::dense::DenseMap;
+use crate::parallel::{self, PartitionedMap};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
+use ahash::HashSet;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q18b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("18b");
     let it = &db.it;
     let mi = &db.mi;
     let mi_idx = &db.mi_idx;
@@ -11,8 +17,19 @@ pub fn q18b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let t = &db.t;
     let ci = &db.ci;
 
-    let start = Instant::now();
+    let target_info: ahash::HashSet<&str> = ["Horror", "Thriller"].into_iter().collect();
 
+    let target_note: ahash::HashSet<&str> = [
+        "(writer)",
+        "(head writer)",
+        "(written by)",
+        "(story)",
+        "(story editor)",
+    ]
+    .into_iter()
+    .collect();
+
+    timer.phase(Phase::Lookup);
     let mut it1_id: &i32 = &0;
     let mut it2_id: &i32 = &0;
 
@@ -24,28 +41,29 @@ pub fn q18b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    let target_info: ahash::HashSet<&str> = ["Horror", "Thriller"].into_iter().collect();
-
-    let mi_m: HashMap<&i32, Vec<&str>> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info.iter())
-        .zip(mi.info_type_id.iter())
-        .zip(mi.note.iter())
-        .filter_map(|(((movie_id, info), info_type_id), note)| {
-            (it1_id == info_type_id && note.is_none() && target_info.contains(info.as_str()))
-                .then_some((movie_id, info))
-        })
-        .fold(HashMap::default(), |mut acc, (movie_id, info)| {
-            acc.entry(movie_id).or_default().push(info);
-            acc
-        });
+    timer.phase(Phase::Build);
+    let mi_m: PartitionedMap<&i32, Vec<&str>> = PartitionedMap::build(
+        mi.movie_id.len(),
+        |row| {
+            let movie_id = &mi.movie_id[row];
+            let info = &mi.info[row];
+            let info_type_id = &mi.info_type_id[row];
+            let note = &mi.note[row];
+            let entry: Option<(&i32, &str)> = {
+                (it1_id == info_type_id && note.is_none() && target_info.contains(info.as_str()))
+                    .then_some((movie_id, info))
+            };
+            entry.map(|(movie_id, info)| (movie_id, vec![info]))
+        },
+        |values, more| values.extend(more),
+    );
 
     let mi_idx_m: ahash::HashMap<&i32, Vec<&str>> = mi_idx
         .movie_id
         .iter()
         .zip(mi_idx.info.iter())
         .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, info), info_type_id)| {
             (it2_id == info_type_id && info.as_str() > "8.0").then_some((movie_id, info))
         })
@@ -54,10 +72,11 @@ pub fn q18b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((movie_id, title), production_year)| {
                 if let Some(production_year) = production_year
                     && (2008..=2014).contains(production_year)
@@ -74,6 +93,7 @@ pub fn q18b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let n_s: HashSet<&i32> =
         n.id.iter()
             .zip(n.gender.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(id, gender)| {
                 if let Some(gender) = gender
                     && gender == "f"
@@ -85,89 +105,46 @@ pub fn q18b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
-    let target_note: ahash::HashSet<&str> = [
-        "(writer)",
-        "(head writer)",
-        "(written by)",
-        "(story)",
-        "(story editor)",
-    ]
-    .into_iter()
-    .collect();
-
-    let mut res: Option<(&str, &str, &str)> = None;
-
-    for ((person_id, movie_id), note) in ci
-        .person_id
-        .iter()
-        .zip(ci.movie_id.iter())
-        .zip(ci.note.iter())
-    {
-        if let Some(note) = note
-            && target_note.contains(note.as_str())
-            && n_s.contains(&person_id)
-            && let Some(title) = t_m.get(&movie_id)
-            && let Some(info) = mi_m.get(&movie_id)
-            && let Some(xinfo) = mi_idx_m.get(&movie_id)
-        {
-            res = match res {
-                Some((old_info, old_xinfo, old_title)) => Some((
-                    info.iter().min().unwrap().min(&old_info),
-                    xinfo.iter().min().unwrap().min(&old_xinfo),
-                    title.min(&old_title),
-                )),
-                None => Some((
-                    info.iter().min().unwrap(),
-                    xinfo.iter().min().unwrap(),
-                    title,
-                )),
-            };
-        }
-    }
+    timer.phase(Phase::Probe);
+    let res =
+        parallel::probe::<(Min<&str>, Min<&str>, Min<&str>)>(ci.person_id.len(), |row, res| {
+            let person_id = &ci.person_id[row];
+            let movie_id = &ci.movie_id[row];
+            let note = &ci.note[row];
+            if let Some(note) = note
+                && target_note.contains(note.as_str())
+                && n_s.contains(&person_id)
+                && let Some(title) = t_m.get(&movie_id)
+                && let Some(info) = mi_m.get(&movie_id)
+                && let Some(xinfo) = mi_idx_m.get(&movie_id)
+            {
+                res.update((
+                    *info.iter().min().unwrap(),
+                    *xinfo.iter().min().unwrap(),
+                    *title,
+                ));
+            }
+        })
+        .finish()
+        .transpose();
 
-    println!("18b,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 18b
-// SELECT MIN(mi.info) AS movie_budget, MIN(mi_idx.info) AS movie_votes, MIN(t.title) AS movie_title
-// FROM cast_info AS ci,
-// info_type AS it1,
-// info_type AS it2,
-// movie_info AS mi,
-// movie_info_idx AS mi_idx,
-// name AS n,
-// title AS t
-// WHERE ci.note in ('(writer)', '(head writer)', '(written by)', '(story)', '(story editor)')
-// AND it1.info = 'genres'
-// AND it2.info = 'rating'
-// AND mi.info in ('Horror', 'Thriller')
-// and mi.note is NULL
-// AND mi_idx.info > '8.0'
-// AND n.gender is not null
-// and n.gender = 'f'
-// AND t.production_year between 2008 and 2014
-// AND t.id = mi.movie_id
-// AND t.id = mi_idx.movie_id
-// AND t.id = ci.movie_id
-// AND ci.movie_id = mi.movie_id
-// AND ci.movie_id = mi_idx.movie_id
-// AND mi.movie_id = mi_idx.movie_id
-// AND n.id = ci.person_id
-// AND it1.id = mi.info_type_id
-// AND it2.id = mi_idx.info_type_id;
-#[cfg(test)]
-mod test_q18b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q18b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q18b(&data)?;
-        assert_eq!(res, Some(("Horror", "8.1", "Agorable")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "18b",
+    family: 18,
+    sql: include_str!("../queries/18b.sql"),
+    relations: &[
+        ("ci", "cast_info"),
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("mi", "movie_info"),
+        ("mi_idx", "movie_info_idx"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q18b(db)?.into()),
+};
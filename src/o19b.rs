@@ -1,11 +1,16 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::HashMap;
+use crate::dense::{DenseMap, DenseSet};
+use crate::like::LikePattern;
+use crate::parallel::{self, PartitionedSet};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::HashSet;
-use memchr::memmem;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q19b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("19b");
     let an = &db.an;
     let chn = &db.chn;
     let ci = &db.ci;
@@ -17,32 +22,15 @@ pub fn q19b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let rt = &db.rt;
     let t = &db.t;
 
-    let angel = memmem::Finder::new("Angel");
-    let two_hundred = memmem::Finder::new("(200");
-    let worldwide = memmem::Finder::new("(worldwide)");
-    let japan = memmem::Finder::new("Japan:");
-    let usa = memmem::Finder::new("(USA)");
-    let usa2 = memmem::Finder::new("USA:");
-    let y2008 = memmem::Finder::new("2008");
-    let y2007 = memmem::Finder::new("2007");
-    let kung_fu_panda = memmem::Finder::new("Kung Fu Panda");
-
-    let start = Instant::now();
-
-    let chn_s: HashSet<i32> = chn.id.iter().map(|id| *id).collect();
-
-    let cn_s: HashSet<i32> = cn
-        .country_code
-        .iter()
-        .zip(cn.id.iter())
-        .filter_map(|(country_code, id)| {
-            country_code
-                .as_ref()
-                .filter(|country_code| country_code == &"[us]")
-                .map(|_| *id)
-        })
-        .collect();
+    let angel = LikePattern::compile("%Angel%")?;
+    let two_hundred = LikePattern::compile("%(200%)%")?;
+    let worldwide = LikePattern::compile("%(worldwide)%")?;
+    let japan = LikePattern::compile("Japan:%2007%")?;
+    let usa = LikePattern::compile("%(USA)%")?;
+    let usa2 = LikePattern::compile("USA:%2008%")?;
+    let kung_fu_panda = LikePattern::compile("%Kung%Fu%Panda%")?;
 
+    timer.phase(Phase::Lookup);
     let it_id = it
         .info
         .iter()
@@ -51,8 +39,6 @@ pub fn q19b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .map(|(_, id)| *id)
         .unwrap();
 
-    let an_s: HashSet<i32> = an.person_id.iter().map(|id| *id).collect();
-
     let rt_id = rt
         .role
         .iter()
@@ -61,61 +47,76 @@ pub fn q19b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .map(|(_, id)| *id)
         .unwrap();
 
-    let n_m: HashMap<&i32, &str> = n
-        .id
+    timer.phase(Phase::Build);
+    let chn_s: HashSet<i32> = chn.id.iter().map(|id| *id).collect();
+
+    let cn_s: DenseSet = cn
+        .country_code
         .iter()
-        .zip(n.gender.iter())
-        .zip(n.name.iter())
-        .filter_map(|((id, gender), name)| {
-            gender
+        .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|(country_code, id)| {
+            country_code
                 .as_ref()
-                .filter(|gender| {
-                    an_s.contains(&id) && gender == &"f" && angel.find(name.as_bytes()).is_some()
-                })
-                .map(|_| (id, name.as_str()))
+                .filter(|country_code| country_code == &"[us]")
+                .map(|_| *id)
         })
         .collect();
 
+    let an_s: HashSet<i32> = an.person_id.iter().map(|id| *id).collect();
+
+    let n_m: DenseMap<&str> =
+        n.id.iter()
+            .zip(n.gender.iter())
+            .zip(n.name.iter())
+            .take_while(|_| !cancel::poll())
+            .filter_map(|((id, gender), name)| {
+                gender
+                    .as_ref()
+                    .filter(|gender| {
+                        an_s.contains(&id) && gender == &"f" && angel.matches(name.as_bytes())
+                    })
+                    .map(|_| (id, name.as_str()))
+            })
+            .collect();
+
     let mc_s: HashSet<i32> = mc
         .note
         .iter()
         .zip(mc.movie_id.iter())
         .zip(mc.company_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((note, movie_id), company_id)| {
             note.as_ref()
                 .filter(|note| {
                     cn_s.contains(&company_id)
-                        && two_hundred.find(note.as_bytes()).is_some()
-                        && (usa.find(note.as_bytes()).is_some()
-                            || worldwide.find(note.as_bytes()).is_some())
+                        && two_hundred.matches(note.as_bytes())
+                        && (usa.matches(note.as_bytes()) || worldwide.matches(note.as_bytes()))
                 })
                 .map(|_| *movie_id)
         })
         .collect();
 
-    let mi_s: HashSet<i32> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((movie_id, info), info_type_id)| {
-            let info_bytes = info.as_bytes();
-            (it_id == *info_type_id
-                && ((japan.find(info_bytes).is_some() && y2007.find(info_bytes).is_some())
-                    || (usa2.find(info_bytes).is_some() && y2008.find(info_bytes).is_some()))
-                && mc_s.contains(movie_id))
-            .then_some(*movie_id)
-        })
-        .collect::<HashSet<_>>();
-
-    let t_m: HashMap<&i32, &str> =
+    let mi_s: PartitionedSet<i32> = PartitionedSet::build(mi.movie_id.len(), |row| {
+        let movie_id = &mi.movie_id[row];
+        let info = &mi.info[row];
+        let info_type_id = &mi.info_type_id[row];
+        let info_bytes = info.as_bytes();
+        (it_id == *info_type_id
+            && (japan.matches(info_bytes) || usa2.matches(info_bytes))
+            && mc_s.contains(movie_id))
+        .then_some(*movie_id)
+    });
+
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 production_year
                     .filter(|production_year| {
-                        kung_fu_panda.find(title.as_bytes()).is_some()
+                        kung_fu_panda.matches(title.as_bytes())
                             && (2007..=2008).contains(production_year)
                             && mi_s.contains(&id)
                     })
@@ -123,16 +124,13 @@ pub fn q19b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mut res: Option<(&str, &str)> = None;
-
-    for ((((mid, pid), rid), prid), note) in ci
-        .movie_id
-        .iter()
-        .zip(ci.person_id.iter())
-        .zip(ci.role_id.iter())
-        .zip(ci.person_role_id.iter())
-        .zip(ci.note.iter())
-    {
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.movie_id.len(), |row, res| {
+        let mid = &ci.movie_id[row];
+        let pid = &ci.person_id[row];
+        let rid = &ci.role_id[row];
+        let prid = &ci.person_role_id[row];
+        let note = &ci.note[row];
         if let Some(prid) = prid
             && let Some(note) = note
             && note == "(voice)"
@@ -141,66 +139,32 @@ pub fn q19b(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             && let Some(title) = t_m.get(&mid)
             && let Some(name) = n_m.get(&pid)
         {
-            res = match res {
-                Some((old_name, old_title)) => Some((name.min(&old_name), title.min(&old_title))),
-                None => Some((name, title)),
-            };
+            res.update((*name, *title));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    println!("19b,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 19b
-// SELECT MIN(n.name) AS voicing_actress, MIN(t.title) AS kung_fu_panda
-// FROM aka_name AS an,
-// char_name AS chn,
-// cast_info AS ci,
-// company_name AS cn,
-// info_type AS it,
-// movie_companies AS mc,
-// movie_info AS mi,
-// name AS n,
-// role_type AS rt,
-// title AS t
-// WHERE ci.note = '(voice)'
-// AND cn.country_code = '[us]'
-// AND it.info = 'release dates'
-// AND mc.note like '%(200%)%'
-// and (mc.note like '%(USA)%' or mc.note like '%(worldwide)%')
-// AND mi.info is not null
-// and (mi.info like 'Japan:%2007%' or mi.info like 'USA:%2008%')
-// AND n.gender = 'f'
-// and n.name like '%Angel%'
-// AND rt.role = 'actress'
-// AND t.production_year between 2007 and 2008
-// and t.title like '%Kung%Fu%Panda%'
-// AND t.id = mi.movie_id
-// AND t.id = mc.movie_id
-// AND t.id = ci.movie_id
-// AND mc.movie_id = ci.movie_id
-// AND mc.movie_id = mi.movie_id
-// AND mi.movie_id = ci.movie_id
-// AND cn.id = mc.company_id
-// AND it.id = mi.info_type_id
-// AND n.id = ci.person_id
-// AND rt.id = ci.role_id
-// AND n.id = an.person_id
-// AND ci.person_id = an.person_id
-// AND chn.id = ci.person_role_id;
-#[cfg(test)]
-mod test_19b {
-    use super::*;
-    use crate::data::{Data, ImdbData};
-
-    #[test]
-    fn test_q19b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q19b(&data)?;
-        assert_eq!(res, Some(("Jolie, Angelina", "Kung Fu Panda")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "19b",
+    family: 19,
+    sql: include_str!("../queries/19b.sql"),
+    relations: &[
+        ("an", "aka_name"),
+        ("chn", "char_name"),
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("it", "info_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("n", "name"),
+        ("rt", "role_type"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q19b(db)?.into()),
+};
@@ -1,10 +1,15 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::HashMap;
+use crate::dense::{DenseMap, DenseSet};
+use crate::parallel::{self, PartitionedSet};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::HashSet;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q19d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("19d");
     let an = &db.an;
     let chn = &db.chn;
     let ci = &db.ci;
@@ -16,24 +21,16 @@ pub fn q19d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let rt = &db.rt;
     let t = &db.t;
 
-    let start = Instant::now();
-
-    let chn_s: HashSet<i32> = chn.id.iter().map(|id| *id).collect();
-
-    let an_s: HashSet<&i32> = an.person_id.iter().collect();
-
-    let cn_s: HashSet<i32> = cn
-        .country_code
-        .iter()
-        .zip(cn.id.iter())
-        .filter_map(|(country_code, id)| {
-            country_code
-                .as_ref()
-                .filter(|country_code| *country_code == "[us]")
-                .map(|_| *id)
-        })
-        .collect();
+    let target_note: ahash::HashSet<&str> = [
+        "(voice)",
+        "(voice: Japanese version)",
+        "(voice) (uncredited)",
+        "(voice: English version)",
+    ]
+    .into_iter()
+    .collect();
 
+    timer.phase(Phase::Lookup);
     let it_id: &i32 = it
         .info
         .iter()
@@ -50,10 +47,29 @@ pub fn q19d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let n_m: HashMap<i32, &str> =
+    timer.phase(Phase::Build);
+    let chn_s: HashSet<i32> = chn.id.iter().map(|id| *id).collect();
+
+    let an_s: HashSet<&i32> = an.person_id.iter().collect();
+
+    let cn_s: DenseSet = cn
+        .country_code
+        .iter()
+        .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|(country_code, id)| {
+            country_code
+                .as_ref()
+                .filter(|country_code| *country_code == "[us]")
+                .map(|_| *id)
+        })
+        .collect();
+
+    let n_m: DenseMap<&str> =
         n.id.iter()
             .zip(n.name.iter())
             .zip(n.gender.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, name), gender)| {
                 if let Some(gender) = gender
                     && an_s.contains(&id)
@@ -70,22 +86,21 @@ pub fn q19d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .movie_id
         .iter()
         .zip(mc.company_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(movie_id, company_id)| cn_s.contains(&company_id).then_some(movie_id))
         .collect();
 
-    let mi_s: HashSet<&i32> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info_type_id.iter())
-        .filter_map(|(movie_id, info_type_id)| {
-            (it_id == info_type_id && mc_s.contains(movie_id)).then_some(movie_id)
-        })
-        .collect();
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.movie_id.len(), |row| {
+        let movie_id = &mi.movie_id[row];
+        let info_type_id = &mi.info_type_id[row];
+        (it_id == info_type_id && mc_s.contains(movie_id)).then_some(movie_id)
+    });
 
-    let t_m: HashMap<i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.production_year.iter())
             .zip(t.title.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((movie_id, production_year), title)| {
                 if let Some(production_year) = production_year
                     && *production_year > 2000
@@ -98,25 +113,13 @@ pub fn q19d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
-    let target_note: ahash::HashSet<&str> = [
-        "(voice)",
-        "(voice: Japanese version)",
-        "(voice) (uncredited)",
-        "(voice: English version)",
-    ]
-    .into_iter()
-    .collect();
-
-    let mut res: Option<(&str, &str)> = None;
-
-    for ((((mid, pid), rid), prid), note) in ci
-        .movie_id
-        .iter()
-        .zip(ci.person_id.iter())
-        .zip(ci.role_id.iter())
-        .zip(ci.person_role_id.iter())
-        .zip(ci.note.iter())
-    {
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.movie_id.len(), |row, res| {
+        let mid = &ci.movie_id[row];
+        let pid = &ci.person_id[row];
+        let rid = &ci.role_id[row];
+        let prid = &ci.person_role_id[row];
+        let note = &ci.note[row];
         if let Some(prid) = prid
             && let Some(note) = note
             && target_note.contains(note.as_str())
@@ -125,64 +128,32 @@ pub fn q19d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             && let Some(title) = t_m.get(&mid)
             && let Some(name) = n_m.get(&pid)
         {
-            res = match res {
-                Some((old_name, old_title)) => Some((name.min(&old_name), title.min(&old_title))),
-                None => Some((name, title)),
-            };
+            res.update((*name, *title));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    println!("19d,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// 19d.sql
-// SELECT MIN(n.name) AS voicing_actress,
-//        MIN(t.title) AS jap_engl_voiced_movie
-// FROM aka_name AS an,
-//      char_name AS chn,
-//      cast_info AS ci,
-//      company_name AS cn,
-//      info_type AS it,
-//      movie_companies AS mc,
-//      movie_info AS mi,
-//      name AS n,
-//      role_type AS rt,
-//      title AS t
-// WHERE ci.note IN ('(voice)',
-//                   '(voice: Japanese version)',
-//                   '(voice) (uncredited)',
-//                   '(voice: English version)')
-//   AND cn.country_code ='[us]'
-//   AND it.info = 'release dates'
-//   AND n.gender ='f'
-//   AND rt.role ='actress'
-//   AND t.production_year > 2000
-//   AND t.id = mi.movie_id
-//   AND t.id = mc.movie_id
-//   AND t.id = ci.movie_id
-//   AND mc.movie_id = ci.movie_id
-//   AND mc.movie_id = mi.movie_id
-//   AND mi.movie_id = ci.movie_id
-//   AND cn.id = mc.company_id
-//   AND it.id = mi.info_type_id
-//   AND n.id = ci.person_id
-//   AND rt.id = ci.role_id
-//   AND n.id = an.person_id
-//   AND ci.person_id = an.person_id
-//   AND chn.id = ci.person_role_id;
-
-#[cfg(test)]
-mod test_q19d {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q19d() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        assert_eq!(q19d(&data)?, Some(("Aaron, Caroline", "$9.99")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "19d",
+    family: 19,
+    sql: include_str!("../queries/19d.sql"),
+    relations: &[
+        ("an", "aka_name"),
+        ("chn", "char_name"),
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("it", "info_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("n", "name"),
+        ("rt", "role_type"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q19d(db)?.into()),
+};
@@ -1,8 +1,11 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
+use crate::like::LikePattern;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
 use polars::prelude::*;
-use std::time::Instant;
 
 // imdb.q1b_movie_companies(movie_id,company_id,company_type_id)
 // |imdb.q1b_title(movie_id,kind_id)
@@ -10,16 +13,16 @@ use std::time::Instant;
 // |||imdb.q1b_info_type(info_type_id)
 // |imdb.q1b_company_type(company_type_id)
 pub fn q1b(db: &Data) -> Result<Option<(&str, &str, &i32)>, PolarsError> {
+    let mut timer = QueryTimer::start("1b");
     let ct = &db.ct;
     let it = &db.it;
     let mc = &db.mc;
     let mi_idx = &db.mi_idx;
     let t = &db.t;
 
-    let pictures = Finder::new("(as Metro-Goldwyn-Mayer Pictures)");
-
-    let start = Instant::now();
+    let pictures = LikePattern::compile("%(as Metro-Goldwyn-Mayer Pictures)%")?;
 
+    timer.phase(Phase::Lookup);
     let it_id: &i32 = it
         .info
         .iter()
@@ -28,13 +31,6 @@ pub fn q1b(db: &Data) -> Result<Option<(&str, &str, &i32)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mi_idx_s: HashSet<i32> = mi_idx
-        .movie_id
-        .iter()
-        .zip(mi_idx.info_type_id.iter())
-        .filter_map(|(movie_id, info_type_id)| (it_id == info_type_id).then_some(*movie_id))
-        .collect();
-
     let ct_id = ct
         .kind
         .iter()
@@ -43,15 +39,25 @@ pub fn q1b(db: &Data) -> Result<Option<(&str, &str, &i32)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
+    timer.phase(Phase::Build);
+    let mi_idx_s: HashSet<i32> = mi_idx
+        .movie_id
+        .iter()
+        .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|(movie_id, info_type_id)| (it_id == info_type_id).then_some(*movie_id))
+        .collect();
+
     let mc_m: HashMap<i32, Vec<&str>> = mc
         .note
         .iter()
         .zip(mc.company_type_id.iter())
         .zip(mc.movie_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((note, company_type_id), movie_id)| {
             if let Some(note) = note {
                 if company_type_id == ct_id
-                    && pictures.find(note.as_bytes()).is_none()
+                    && !pictures.matches(note.as_bytes())
                     && mi_idx_s.contains(&movie_id)
                 {
                     Some((*movie_id, note.as_str()))
@@ -67,70 +73,40 @@ pub fn q1b(db: &Data) -> Result<Option<(&str, &str, &i32)>, PolarsError> {
             acc
         });
 
-    let mut res: Option<(&str, &str, &i32)> = None;
+    timer.phase(Phase::Probe);
+    let mut res = <(Min<&str>, Min<&str>, Min<&i32>)>::default();
 
     for ((id, title), production_year) in
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(note) = mc_m.get(&id)
             && let Some(production_year) = production_year
             && (2005..=2010).contains(production_year)
         {
-            res = match res {
-                Some((old_note, old_title, old_production_year)) => Some((
-                    note.iter().min().unwrap().min(&old_note),
-                    title.as_str().min(&old_title),
-                    production_year.min(&old_production_year),
-                )),
-                None => Some((note.iter().min().unwrap(), title, production_year)),
-            };
+            res.update((*note.iter().min().unwrap(), title.as_str(), production_year));
         }
     }
 
-    let duration = start.elapsed().as_secs_f32();
-    println!("1b,{duration:}");
+    timer.finish();
 
-    Ok(res)
+    Ok(res.finish().transpose())
 }
 
-// -- JOB 1b.sql
-// SELECT MIN(mc.note) AS production_note,
-// MIN(t.title) AS movie_title,
-// MIN(t.production_year) AS movie_year
-// FROM company_type AS ct,
-// info_type AS it,
-// movie_companies AS mc,
-// movie_info_idx AS mi_idx,
-// title AS t
-// WHERE ct.kind = 'production companies'
-// AND it.info = 'bottom 10 rank'
-// AND mc.note NOT LIKE '%(as Metro-Goldwyn-Mayer Pictures)%'
-// AND t.production_year BETWEEN 2005 AND 2010
-// AND ct.id = mc.company_type_id
-// AND t.id = mc.movie_id
-// AND t.id = mi_idx.movie_id
-// AND mc.movie_id = mi_idx.movie_id
-// AND it.id = mi_idx.info_type_id;
-#[cfg(test)]
-mod test_q1b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q1b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q1b(&data)?;
-        assert_eq!(
-            res,
-            Some((
-                "(Set Decoration Rentals) (uncredited)",
-                "Disaster Movie",
-                &2008,
-            ))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "1b",
+    family: 1,
+    sql: include_str!("../queries/1b.sql"),
+    relations: &[
+        ("ct", "company_type"),
+        ("it", "info_type"),
+        ("mc", "movie_companies"),
+        ("mi_idx", "movie_info_idx"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q1b(db)?.into()),
+};
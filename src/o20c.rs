@@ -1,11 +1,17 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::{DenseMap, DenseSet};
+use crate::like::LikePattern;
+use crate::parallel::{self, PartitionedSet};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::HashSet;
-use ahash::{HashMap, HashSetExt};
-use memchr::memmem;
+use ahash::HashSetExt;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q20c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("20c");
     let ci = &db.ci;
     let chn = &db.chn;
     let t = &db.t;
@@ -16,33 +22,59 @@ pub fn q20c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let mk = &db.mk;
     let k = &db.k;
 
-    let man = memmem::Finder::new(b"man");
-    let big_man = memmem::Finder::new(b"Man");
+    let man = LikePattern::compile("%man%")?;
+    let big_man = LikePattern::compile("%Man%")?;
+    let complete = LikePattern::compile("%complete%")?;
 
-    let start = Instant::now();
-
-    let n_m: HashMap<&i32, &str> =
-        n.id.iter()
-            .zip(n.name.iter())
-            .map(|(id, name)| (id, name.as_str()))
-            .collect();
+    let target_keywords: HashSet<&str> = [
+        "superhero",
+        "marvel-comics",
+        "based-on-comic",
+        "tv-special",
+        "fight",
+        "violence",
+        "magnet",
+        "web",
+        "claw",
+        "laser",
+    ]
+    .into_iter()
+    .collect();
 
+    timer.phase(Phase::Lookup);
     let mut cct1_id: i32 = 0;
     let mut cct2_s = HashSet::new();
 
     for (id, kind) in cct.id.iter().zip(cct.kind.iter()) {
         if kind == "cast" {
             cct1_id = *id;
-        } else if kind.contains("complete") {
+        } else if complete.matches(kind.as_bytes()) {
             cct2_s.insert(id);
         }
     }
 
+    let kt_id = kt
+        .kind
+        .iter()
+        .zip(kt.id.iter())
+        .find(|(kind, _)| *kind == "movie")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    timer.phase(Phase::Build);
+    let n_m: DenseMap<&str> =
+        n.id.iter()
+            .zip(n.name.iter())
+            .take_while(|_| !cancel::poll())
+            .map(|(id, name)| (id, name.as_str()))
+            .collect();
+
     let cc_s: HashSet<i32> = cc
         .subject_id
         .iter()
         .zip(cc.status_id.iter())
         .zip(cc.movie_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((subject_id, status_id), movie_id)| {
             (*subject_id == cct1_id && cct2_s.contains(status_id)).then_some(*movie_id)
         })
@@ -52,46 +84,25 @@ pub fn q20c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .name
         .iter()
         .zip(chn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(name, id)| {
-            (big_man.find(name.as_bytes()).is_some() || man.find(name.as_bytes()).is_some())
-                .then_some(id)
+            (big_man.matches(name.as_bytes()) || man.matches(name.as_bytes())).then_some(id)
         })
         .collect::<HashSet<_>>();
 
-    let target_keywords: HashSet<&str> = [
-        "superhero",
-        "marvel-comics",
-        "based-on-comic",
-        "tv-special",
-        "fight",
-        "violence",
-        "magnet",
-        "web",
-        "claw",
-        "laser",
-    ]
-    .into_iter()
-    .collect();
-
-    let k_s: HashSet<i32> =
+    let k_s: DenseSet =
         k.id.iter()
             .zip(k.keyword.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(id, keyword)| target_keywords.contains(keyword.as_str()).then_some(*id))
             .collect();
 
-    let kt_id = kt
-        .kind
-        .iter()
-        .zip(kt.id.iter())
-        .find(|(kind, _)| *kind == "movie")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let t_m: HashMap<i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
             .zip(t.kind_id.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(((id, title), production_year), kind_id)| {
                 production_year
                     .filter(|&year| year > 2000 && kt_id == kind_id && cc_s.contains(&id))
@@ -99,86 +110,49 @@ pub fn q20c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mk_s = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| {
-            (k_s.contains(&keyword_id) && t_m.contains_key(&movie_id)).then_some(movie_id)
-        })
-        .collect::<HashSet<_>>();
-
-    let mut res: Option<(&str, &str)> = None;
-
-    for ((movie_id, person_id), person_role_id) in ci
-        .movie_id
-        .iter()
-        .zip(ci.person_id.iter())
-        .zip(ci.person_role_id.iter())
-    {
+    let mk_s: PartitionedSet<_> = PartitionedSet::build(mk.keyword_id.len(), |row| {
+        let keyword_id = &mk.keyword_id[row];
+        let movie_id = &mk.movie_id[row];
+        (k_s.contains(&keyword_id) && t_m.contains_key(&movie_id)).then_some(movie_id)
+    });
+
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.movie_id.len(), |row, res| {
+        let movie_id = &ci.movie_id[row];
+        let person_id = &ci.person_id[row];
+        let person_role_id = &ci.person_role_id[row];
         if let Some(person_role_id) = person_role_id
             && chn_s.contains(person_role_id)
             && mk_s.contains(&movie_id)
             && let Some(name) = n_m.get(&person_id)
             && let Some(title) = t_m.get(&movie_id)
         {
-            res = match res {
-                Some((old_name, old_title)) => Some((name.min(&old_name), title.min(&old_title))),
-                None => Some((name, title)),
-            };
+            res.update((*name, *title));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    let duration = start.elapsed().as_secs_f32();
-    println!("20c,{duration:}");
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 20c
-// SELECT MIN(n.name) AS cast_member, MIN(t.title) AS complete_dynamic_hero_movie
-// FROM complete_cast AS cc,
-// comp_cast_type AS cct1,
-// comp_cast_type AS cct2,
-// char_name AS chn,
-// cast_info AS ci,
-// keyword AS k,
-// kind_type AS kt,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE cct1.kind = 'cast'
-// AND cct2.kind like '%complete%'
-// AND chn.name is not NULL
-// and (chn.name like '%man%' or chn.name like '%Man%')
-// AND k.keyword in
-// ('superhero', 'marvel-comics', 'based-on-comic', 'tv-special', 'fight', 'violence', 'magnet', 'web', 'claw',
-// 'laser')
-// AND kt.kind = 'movie'
-// AND t.production_year > 2000
-// AND kt.id = t.kind_id
-// AND t.id = mk.movie_id
-// AND t.id = ci.movie_id
-// AND t.id = cc.movie_id
-// AND mk.movie_id = ci.movie_id
-// AND mk.movie_id = cc.movie_id
-// AND ci.movie_id = cc.movie_id
-// AND chn.id = ci.person_role_id
-// AND n.id = ci.person_id
-// AND k.id = mk.keyword_id
-// AND cct1.id = cc.subject_id
-// AND cct2.id = cc.status_id;
-#[cfg(test)]
-mod test_q20c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q20c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q20c(&data)?;
-        assert_eq!(res, Some(("Abell, Alistair", "...And Then I...")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "20c",
+    family: 20,
+    sql: include_str!("../queries/20c.sql"),
+    relations: &[
+        ("cc", "complete_cast"),
+        ("cct1", "comp_cast_type"),
+        ("cct2", "comp_cast_type"),
+        ("chn", "char_name"),
+        ("ci", "cast_info"),
+        ("k", "keyword"),
+        ("kt", "kind_type"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q20c(db)?.into()),
+};
@@ -1,10 +1,15 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::DenseMap;
+use crate::like::LikePattern;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q21b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("21b");
     let cn = &db.cn;
     let ct = &db.ct;
     let k = &db.k;
@@ -15,28 +20,13 @@ pub fn q21b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let ml = &db.ml;
     let t = &db.t;
 
-    let film = Finder::new("Film");
-    let warner = Finder::new("Warner");
-    let follow = Finder::new("follow");
+    let film = LikePattern::compile("%Film%")?;
+    let warner = LikePattern::compile("%Warner%")?;
+    let follow = LikePattern::compile("%follow%")?;
 
-    let start = Instant::now();
-
-    let cn_m: HashMap<&i32, &str> = cn
-        .id
-        .iter()
-        .zip(cn.name.iter())
-        .zip(cn.country_code.iter())
-        .filter_map(|((id, name), country_code)| {
-            country_code
-                .as_deref()
-                .filter(|&code| {
-                    (film.find(name.as_bytes()).is_some() || warner.find(name.as_bytes()).is_some())
-                        && code != "[pl]"
-                })
-                .map(|_| (id, name.as_str()))
-        })
-        .collect();
+    let target_info: HashSet<&str> = ["Germany", "German"].into_iter().collect();
 
+    timer.phase(Phase::Lookup);
     let ct_id = ct
         .kind
         .iter()
@@ -53,29 +43,46 @@ pub fn q21b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s: HashSet<&i32> = mk
-        .movie_id
-        .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| (k_id == keyword_id).then_some(movie_id))
-        .collect();
-
-    let lt_m: HashMap<&i32, &str> = lt
+    let lt_m: DenseMap<&str> = lt
         .id
         .iter()
         .zip(lt.link.iter())
         .filter_map(|(id, link)| {
             follow
-                .find(link.as_bytes())
-                .is_some()
+                .matches(link.as_bytes())
                 .then_some((id, link.as_str()))
         })
         .collect();
 
+    timer.phase(Phase::Build);
+    let cn_m: DenseMap<&str> = cn
+        .id
+        .iter()
+        .zip(cn.name.iter())
+        .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|((id, name), country_code)| {
+            country_code
+                .as_deref()
+                .filter(|&code| {
+                    (film.matches(name.as_bytes()) || warner.matches(name.as_bytes()))
+                        && code != "[pl]"
+                })
+                .map(|_| (id, name.as_str()))
+        })
+        .collect();
+
+    let mk_s: PartitionedSet<&i32> = PartitionedSet::build(mk.movie_id.len(), |row| {
+        let movie_id = &mk.movie_id[row];
+        let keyword_id = &mk.keyword_id[row];
+        (k_id == keyword_id).then_some(movie_id)
+    });
+
     let ml_m: HashMap<&i32, Vec<&str>> = ml
         .movie_id
         .iter()
         .zip(ml.link_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(mid, lt_id)| {
             if mk_s.contains(&mid)
                 && let Some(link) = lt_m.get(&lt_id)
@@ -90,22 +97,17 @@ pub fn q21b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let target_info: HashSet<&str> = ["Germany", "German"].into_iter().collect();
-
-    let mi_s: HashSet<&i32> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info.iter())
-        .filter_map(|(movie_id, info)| {
-            (ml_m.contains_key(&movie_id) && target_info.contains(info.as_str()))
-                .then_some(movie_id)
-        })
-        .collect();
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.movie_id.len(), |row| {
+        let movie_id = &mi.movie_id[row];
+        let info = &mi.info[row];
+        (ml_m.contains_key(&movie_id) && target_info.contains(info.as_str())).then_some(movie_id)
+    });
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 if let Some(production_year) = production_year
                     && mi_s.contains(&id)
@@ -118,6 +120,7 @@ pub fn q21b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for (((mid, cid), company_type_id), note) in mc
@@ -127,6 +130,9 @@ pub fn q21b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(mc.company_type_id.iter())
         .zip(mc.note.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if ct_id == company_type_id
             && let Some(links) = ml_m.get(&mid)
             && let Some(name) = cn_m.get(&cid)
@@ -144,37 +150,25 @@ pub fn q21b(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    println!("21b,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 21b
-// SELECT MIN(cn.name) AS company_name, MIN(lt.link) AS link_type, MIN(t.title) AS german_follow_up
-// FROM company_name AS cn,
-// company_type AS ct,
-// keyword AS k,
-// link_type AS lt,
-// movie_companies AS mc,
-// movie_info AS mi,
-// movie_keyword AS mk,
-// movie_link AS ml,
-// title AS t
-// WHERE cn.country_code !='[pl]' AND (cn.name LIKE '%Film%' OR cn.name LIKE '%Warner%') AND ct.kind ='production companies' AND k.keyword ='sequel' AND lt.link LIKE '%follow%' AND mc.note IS NULL AND mi.info IN ('Germany', 'German') AND t.production_year BETWEEN 2000 AND 2010 AND lt.id = ml.link_type_id AND ml.movie_id = t.id AND t.id = mk.movie_id AND mk.keyword_id = k.id AND t.id = mc.movie_id AND mc.company_type_id = ct.id AND mc.company_id = cn.id AND mi.movie_id = t.id AND ml.movie_id = mk.movie_id AND ml.movie_id = mc.movie_id AND mk.movie_id = mc.movie_id AND ml.movie_id = mi.movie_id AND mk.movie_id = mi.movie_id AND mc.movie_id = mi.movie_id;
-#[cfg(test)]
-mod test_q21b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q21b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q21b(&data)?;
-        assert_eq!(
-            res,
-            Some(("Filmlance International AB", "followed by", "Hämndens pris"))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "21b",
+    family: 21,
+    sql: include_str!("../queries/21b.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("k", "keyword"),
+        ("lt", "link_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("mk", "movie_keyword"),
+        ("ml", "movie_link"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q21b(db)?.into()),
+};
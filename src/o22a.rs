@@ -1,8 +1,15 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
+use crate::dense::{DenseMap, DenseSet};
+use crate::like::LikePattern;
+use crate::parallel::PartitionedSet;
+use crate::predicate::{Pred, Selection};
+use crate::registry::Entry;
+use crate::roaring::RoaringSet;
+use crate::timer::{Phase, QueryTimer};
+use ahash::HashSet;
 use polars::prelude::*;
-use std::time::Instant;
 
 // imdb.q22a_movie_companies(movie_id,company_id,company_type_id)
 // |imdb.q22a_title(movie_id,kind_id)
@@ -16,6 +23,7 @@ use std::time::Instant;
 // |imdb_int.movie_keyword(movie_id,keyword_id)
 // ||imdb.q22a_keyword(keyword_id)
 pub fn q22a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("22a");
     let cn = &db.cn;
     // let ct = &db.ct;
     let it = &db.it;
@@ -27,25 +35,22 @@ pub fn q22a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let mk = &db.mk;
     let t = &db.t;
 
-    let two_hundred_p = Finder::new("(200");
-    let usa_p = Finder::new("(USA)");
+    let two_hundred_p = LikePattern::compile("%(200%)%")?;
+    let usa_p = LikePattern::compile("%(USA)%")?;
 
     // FK (ci) - PK (ct) optimization
-    let start = Instant::now();
 
-    let cn_m: HashMap<&i32, &str> = cn
-        .id
-        .iter()
-        .zip(cn.name.iter())
-        .zip(cn.country_code.iter())
-        .filter_map(|((id, name), country_code)| {
-            country_code
-                .as_deref()
-                .filter(|&code| code != "[us]")
-                .map(|_| (id, name.as_str()))
-        })
+    let target_keywords: HashSet<&str> = ["murder", "murder-in-title", "blood", "violence"]
+        .into_iter()
+        .collect();
+
+    let target_kind_type: ahash::HashSet<&str> = ["movie", "episode"].into_iter().collect();
+
+    let target_info: ahash::HashSet<&str> = ["Germany", "German", "USA", "American"]
+        .into_iter()
         .collect();
 
+    timer.phase(Phase::Lookup);
     let mut it1_id: &i32 = &0;
     let mut it2_id: &i32 = &0;
 
@@ -57,18 +62,6 @@ pub fn q22a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    let target_keywords: HashSet<&str> = ["murder", "murder-in-title", "blood", "violence"]
-        .into_iter()
-        .collect();
-
-    let k_s: HashSet<&i32> =
-        k.id.iter()
-            .zip(k.keyword.iter())
-            .filter_map(|(id, keyword)| target_keywords.contains(keyword.as_str()).then_some(id))
-            .collect();
-
-    let target_kind_type: ahash::HashSet<&str> = ["movie", "episode"].into_iter().collect();
-
     let kt_s: HashSet<&i32> = kt
         .kind
         .iter()
@@ -76,25 +69,41 @@ pub fn q22a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .filter_map(|(kind, id)| target_kind_type.contains(kind.as_str()).then_some(id))
         .collect();
 
-    let target_info: ahash::HashSet<&str> = ["Germany", "German", "USA", "American"]
-        .into_iter()
-        .collect();
-
-    let mi_s: HashSet<&i32> = mi
-        .info
+    timer.phase(Phase::Build);
+    let cn_m: DenseMap<&str> = cn
+        .id
         .iter()
-        .zip(mi.movie_id.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((info, movie_id), info_type_id)| {
-            (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
+        .zip(cn.name.iter())
+        .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|((id, name), country_code)| {
+            country_code
+                .as_deref()
+                .filter(|&code| code != "[us]")
+                .map(|_| (id, name.as_str()))
         })
         .collect();
 
-    let t_m: HashMap<&i32, &str> =
+    let k_s: DenseSet =
+        k.id.iter()
+            .zip(k.keyword.iter())
+            .take_while(|_| !cancel::poll())
+            .filter_map(|(id, keyword)| target_keywords.contains(keyword.as_str()).then_some(id))
+            .collect();
+
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.info.len(), |row| {
+        let info = &mi.info[row];
+        let movie_id = &mi.movie_id[row];
+        let info_type_id = &mi.info_type_id[row];
+        (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
+    });
+
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
             .zip(t.kind_id.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(((id, title), production_year), kind_id)| {
                 if let Some(production_year) = production_year
                     && kt_s.contains(&kind_id)
@@ -108,20 +117,18 @@ pub fn q22a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mk_s: HashSet<&i32> = mk
-        .movie_id
-        .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| {
-            (t_m.contains_key(&movie_id) && k_s.contains(&keyword_id)).then_some(movie_id)
-        })
-        .collect();
+    let mk_s: PartitionedSet<&i32> = PartitionedSet::build(mk.movie_id.len(), |row| {
+        let movie_id = &mk.movie_id[row];
+        let keyword_id = &mk.keyword_id[row];
+        (t_m.contains_key(&movie_id) && k_s.contains(&keyword_id)).then_some(movie_id)
+    });
 
     let mi_idx_m: ahash::HashMap<&i32, Vec<&str>> = mi_idx
         .movie_id
         .iter()
         .zip(mi_idx.info.iter())
         .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, info), info_type_id)| {
             (it2_id == info_type_id && mk_s.contains(&movie_id) && info.as_str() < "7.0")
                 .then_some((movie_id, info))
@@ -131,6 +138,7 @@ pub fn q22a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for ((movie_id, company_id), note) in mc
@@ -139,9 +147,12 @@ pub fn q22a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(mc.company_id.iter())
         .zip(mc.note.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(note) = note
-            && two_hundred_p.find(note.as_bytes()).is_some()
-            && usa_p.find(note.as_bytes()).is_none()
+            && two_hundred_p.matches(note.as_bytes())
+            && !usa_p.matches(note.as_bytes())
             && let Some(title) = t_m.get(&movie_id)
             && let Some(infos) = mi_idx_m.get(&movie_id)
             && let Some(name) = cn_m.get(&company_id)
@@ -157,71 +168,153 @@ pub fn q22a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    let duration = start.elapsed().as_secs_f32();
-    println!("22a,{duration:}");
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 22a
-// SELECT MIN(cn.name) AS movie_company,
-// MIN(mi_idx.info) AS rating,
-// MIN(t.title) AS western_violent_movie
-// FROM company_name AS cn,
-// company_type AS ct,
-// info_type AS it1,
-// info_type AS it2,
-// keyword AS k,
-// kind_type AS kt,
-// movie_companies AS mc,
-// movie_info AS mi,
-// movie_info_idx AS mi_idx,
-// movie_keyword AS mk,
-// title AS t
-// WHERE cn.country_code != '[us]'
-// AND it1.info = 'countries'
-// AND it2.info = 'rating'
-// AND k.keyword IN ('murder',
-// 'murder-in-title',
-// 'blood',
-// 'violence')
-// AND kt.kind IN ('movie',
-// 'episode')
-// AND mc.note NOT LIKE '%(USA)%'
-// AND mc.note LIKE '%(200%)%'
-// AND mi.info IN ('Germany',
-// 'German',
-// 'USA',
-// 'American')
-// AND mi_idx.info < '7.0'
-// AND t.production_year > 2008
-// AND kt.id = t.kind_id
-// AND t.id = mi.movie_id
-// AND t.id = mk.movie_id
-// AND t.id = mi_idx.movie_id
-// AND t.id = mc.movie_id
-// AND mk.movie_id = mi.movie_id
-// AND mk.movie_id = mi_idx.movie_id
-// AND mk.movie_id = mc.movie_id
-// AND mi.movie_id = mi_idx.movie_id
-// AND mi.movie_id = mc.movie_id
-// AND mc.movie_id = mi_idx.movie_id
-// AND k.id = mk.keyword_id
-// AND it1.id = mi.info_type_id
-// AND it2.id = mi_idx.info_type_id
-// AND ct.id = mc.company_type_id
-// AND cn.id = mc.company_id;
+/// q22a as a bitmap plan, like `q13a_bitmap`.
+pub fn q22a_bitmap(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("22a-bitmap");
+    let cn = &db.cn;
+    let it = &db.it;
+    let k = &db.k;
+    let kt = &db.kt;
+    let mc = &db.mc;
+    let mi = &db.mi;
+    let mi_idx = &db.mi_idx;
+    let mk = &db.mk;
+    let t = &db.t;
+
+    let two_hundred_p = LikePattern::compile("%(200%)%")?;
+    let usa_p = LikePattern::compile("%(USA)%")?;
+
+    timer.phase(Phase::Lookup);
+    let mut it1_id: i32 = 0;
+    let mut it2_id: i32 = 0;
+
+    for (id, info) in it.id.iter().zip(it.info.iter()) {
+        if info == "countries" {
+            it1_id = *id;
+        } else if info == "rating" {
+            it2_id = *id;
+        }
+    }
+
+    let kt_sel = Pred::is_in(&kt.kind, ["movie", "episode"]).eval();
+    let kt_s: DenseSet = kt_sel.iter().map(|row| kt.id[row]).collect();
+
+    timer.phase(Phase::Build);
+    let cn_m: DenseMap<&str> = cn
+        .id
+        .iter()
+        .zip(cn.name.iter())
+        .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|((id, name), country_code)| {
+            country_code
+                .as_deref()
+                .filter(|&code| code != "[us]")
+                .map(|_| (id, name.as_str()))
+        })
+        .collect();
+
+    let k_sel = Pred::is_in(
+        &k.keyword,
+        ["murder", "murder-in-title", "blood", "violence"],
+    )
+    .eval();
+    let k_s: DenseSet = k_sel.iter().map(|row| k.id[row]).collect();
+
+    let mut mc_sel = Pred::like(&mc.note, two_hundred_p)
+        .and(!Pred::like(&mc.note, usa_p))
+        .eval();
+    mc_sel.retain(|row| cn_m.contains_key(&mc.company_id[row]));
+    let mi_sel = Pred::eq(&mi.info_type_id, it1_id)
+        .and(Pred::is_in(
+            &mi.info,
+            ["Germany", "German", "USA", "American"],
+        ))
+        .eval();
+    let mut t_sel = Pred::gt(&t.production_year, 2008).eval();
+    t_sel.retain(|row| kt_s.contains(&t.kind_id[row]));
+    let mk_sel = Selection::from_values(&mk.keyword_id, |keyword_id| k_s.contains(keyword_id));
+    let mi_idx_sel = Pred::eq(&mi_idx.info_type_id, it2_id)
+        .and(Pred::lt(&mi_idx.info, "7.0"))
+        .eval();
+
+    timer.phase(Phase::Probe);
+    let movies = RoaringSet::intersect_all(&[
+        mc_sel.iter().map(|row| mc.movie_id[row]).collect(),
+        mi_sel.iter().map(|row| mi.movie_id[row]).collect(),
+        t_sel.iter().map(|row| t.id[row]).collect(),
+        mk_sel.iter().map(|row| mk.movie_id[row]).collect(),
+        mi_idx_sel.iter().map(|row| mi_idx.movie_id[row]).collect(),
+    ]);
+
+    timer.phase(Phase::Aggregate);
+    let mut res = <(Min<&str>, Min<&str>, Min<&str>)>::default();
+    for row in mc_sel
+        .iter()
+        .filter(|&row| movies.contains(mc.movie_id[row]))
+    {
+        if cancel::poll() {
+            break;
+        }
+        res.0.update(*cn_m.get(&mc.company_id[row]).unwrap());
+    }
+    for row in mi_idx_sel
+        .iter()
+        .filter(|&row| movies.contains(mi_idx.movie_id[row]))
+    {
+        if cancel::poll() {
+            break;
+        }
+        res.1.update(mi_idx.info[row].as_str());
+    }
+    for row in t_sel.iter().filter(|&row| movies.contains(t.id[row])) {
+        if cancel::poll() {
+            break;
+        }
+        res.2.update(t.title[row].as_str());
+    }
+
+    timer.finish();
+
+    Ok(res.finish().transpose())
+}
+
+pub const QUERY: Entry = Entry {
+    id: "22a",
+    family: 22,
+    sql: include_str!("../queries/22a.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("k", "keyword"),
+        ("kt", "kind_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("mi_idx", "movie_info_idx"),
+        ("mk", "movie_keyword"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q22a(db)?.into()),
+};
+
 #[cfg(test)]
 mod test_q22a {
     use super::*;
-    use crate::data::ImdbData;
+    use crate::test_support;
 
     #[test]
-    fn test_q22a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q22a(&data)?;
-        assert_eq!(res, Some(("01 Distribution", "2.1", "12 Rounds")));
+    fn test_q22a_bitmap() -> Result<(), PolarsError> {
+        let Some(data) = test_support::data() else {
+            return Ok(());
+        };
+        assert_eq!(q22a_bitmap(data)?, q22a(data)?);
         Ok(())
     }
 }
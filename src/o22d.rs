@@ -1,9 +1,14 @@
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
+use crate::dense::{DenseMap, DenseSet};
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
+use ahash::HashSet;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q22d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("22d");
     let cn = &db.cn;
     // let ct = &db.ct;
     let it = &db.it;
@@ -15,51 +20,12 @@ pub fn q22d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let mk = &db.mk;
     let t = &db.t;
 
-    let start = Instant::now();
-
-    let cn_m: HashMap<&i32, &str> = cn
-        .id
-        .iter()
-        .zip(cn.name.iter())
-        .zip(cn.country_code.iter())
-        .filter_map(|((id, name), country_code)| {
-            country_code
-                .as_deref()
-                .filter(|&code| code != "[us]")
-                .map(|_| (id, name.as_str()))
-        })
-        .collect();
-
-    let mut it1_id: &i32 = &0;
-    let mut it2_id: &i32 = &0;
-
-    for (id, info) in it.id.iter().zip(it.info.iter()) {
-        if info == "countries" {
-            it1_id = id;
-        } else if info == "rating" {
-            it2_id = id;
-        }
-    }
-
     let target_keywords: HashSet<&str> = ["murder", "murder-in-title", "blood", "violence"]
         .into_iter()
         .collect();
 
-    let k_s: HashSet<&i32> =
-        k.id.iter()
-            .zip(k.keyword.iter())
-            .filter_map(|(id, keyword)| target_keywords.contains(keyword.as_str()).then_some(id))
-            .collect();
-
     let target_kind_type: ahash::HashSet<&str> = ["movie", "episode"].into_iter().collect();
 
-    let kt_s: HashSet<&i32> = kt
-        .kind
-        .iter()
-        .zip(kt.id.iter())
-        .filter_map(|(kind, id)| target_kind_type.contains(kind.as_str()).then_some(id))
-        .collect();
-
     let target_info: ahash::HashSet<&str> = [
         "Sweden",
         "Germany",
@@ -75,21 +41,60 @@ pub fn q22d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     .into_iter()
     .collect();
 
-    let mi_s: HashSet<&i32> = mi
-        .info
+    timer.phase(Phase::Lookup);
+    let mut it1_id: &i32 = &0;
+    let mut it2_id: &i32 = &0;
+
+    for (id, info) in it.id.iter().zip(it.info.iter()) {
+        if info == "countries" {
+            it1_id = id;
+        } else if info == "rating" {
+            it2_id = id;
+        }
+    }
+
+    let kt_s: HashSet<&i32> = kt
+        .kind
         .iter()
-        .zip(mi.movie_id.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((info, movie_id), info_type_id)| {
-            (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
+        .zip(kt.id.iter())
+        .filter_map(|(kind, id)| target_kind_type.contains(kind.as_str()).then_some(id))
+        .collect();
+
+    timer.phase(Phase::Build);
+    let cn_m: DenseMap<&str> = cn
+        .id
+        .iter()
+        .zip(cn.name.iter())
+        .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|((id, name), country_code)| {
+            country_code
+                .as_deref()
+                .filter(|&code| code != "[us]")
+                .map(|_| (id, name.as_str()))
         })
         .collect();
 
-    let t_m: HashMap<&i32, &str> =
+    let k_s: DenseSet =
+        k.id.iter()
+            .zip(k.keyword.iter())
+            .take_while(|_| !cancel::poll())
+            .filter_map(|(id, keyword)| target_keywords.contains(keyword.as_str()).then_some(id))
+            .collect();
+
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.info.len(), |row| {
+        let info = &mi.info[row];
+        let movie_id = &mi.movie_id[row];
+        let info_type_id = &mi.info_type_id[row];
+        (target_info.contains(info.as_str()) && it1_id == info_type_id).then_some(movie_id)
+    });
+
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
             .zip(t.kind_id.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(((id, title), production_year), kind_id)| {
                 if let Some(production_year) = production_year
                     && kt_s.contains(&kind_id)
@@ -103,20 +108,18 @@ pub fn q22d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mk_s: HashSet<&i32> = mk
-        .movie_id
-        .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| {
-            (t_m.contains_key(&movie_id) && k_s.contains(&keyword_id)).then_some(movie_id)
-        })
-        .collect();
+    let mk_s: PartitionedSet<&i32> = PartitionedSet::build(mk.movie_id.len(), |row| {
+        let movie_id = &mk.movie_id[row];
+        let keyword_id = &mk.keyword_id[row];
+        (t_m.contains_key(&movie_id) && k_s.contains(&keyword_id)).then_some(movie_id)
+    });
 
     let mi_idx_m: ahash::HashMap<&i32, Vec<&str>> = mi_idx
         .movie_id
         .iter()
         .zip(mi_idx.info.iter())
         .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, info), info_type_id)| {
             (it2_id == info_type_id && mk_s.contains(&movie_id) && info.as_str() < "8.5")
                 .then_some((movie_id, info))
@@ -126,9 +129,13 @@ pub fn q22d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for (movie_id, company_id) in mc.movie_id.iter().zip(mc.company_id.iter()) {
+        if cancel::poll() {
+            break;
+        }
         if let Some(title) = t_m.get(&movie_id)
             && let Some(infos) = mi_idx_m.get(&movie_id)
             && let Some(name) = cn_m.get(&company_id)
@@ -144,37 +151,27 @@ pub fn q22d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    println!("22d,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 22d
-// SELECT MIN(cn.name) AS movie_company, MIN(mi_idx.info) AS rating, MIN(t.title) AS western_violent_movie
-// FROM company_name AS cn,
-// company_type AS ct,
-// info_type AS it1,
-// info_type AS it2,
-// keyword AS k,
-// kind_type AS kt,
-// movie_companies AS mc,
-// movie_info AS mi,
-// movie_info_idx AS mi_idx,
-// movie_keyword AS mk,
-// title AS t
-// WHERE cn.country_code != '[us]' AND it1.info  = 'countries' AND it2.info  = 'rating' AND k.keyword  in ('murder', 'murder-in-title', 'blood', 'violence') AND kt.kind  in ('movie', 'episode') AND mi.info IN ('Sweden', 'Norway', 'Germany', 'Denmark', 'Swedish', 'Danish', 'Norwegian', 'German', 'USA', 'American') AND mi_idx.info  < '8.5' AND t.production_year  > 2005 AND kt.id = t.kind_id AND t.id = mi.movie_id AND t.id = mk.movie_id AND t.id = mi_idx.movie_id AND t.id = mc.movie_id AND mk.movie_id = mi.movie_id AND mk.movie_id = mi_idx.movie_id AND mk.movie_id = mc.movie_id AND mi.movie_id = mi_idx.movie_id AND mi.movie_id = mc.movie_id AND mc.movie_id = mi_idx.movie_id AND k.id = mk.keyword_id AND it1.id = mi.info_type_id AND it2.id = mi_idx.info_type_id AND ct.id = mc.company_type_id AND cn.id = mc.company_id;
-#[cfg(test)]
-mod test_q22d {
-    use crate::data::{Data, ImdbData};
-    use crate::o22d::q22d;
-    use polars::error::PolarsError;
-
-    #[test]
-    fn test_q22d() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q22d(&data)?;
-        assert_eq!(res, Some(("01 Distribution", "1.6", "(#1.1)")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "22d",
+    family: 22,
+    sql: include_str!("../queries/22d.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("k", "keyword"),
+        ("kt", "kind_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("mi_idx", "movie_info_idx"),
+        ("mk", "movie_keyword"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q22d(db)?.into()),
+};
@@ -1,10 +1,15 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::{DenseMap, DenseSet};
+use crate::like::LikePattern;
+use crate::parallel::{PartitionedMap, PartitionedSet};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
-use memchr::memmem;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q25b(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("25b");
     let ci = &db.ci;
     let it = &db.it;
     let k = &db.k;
@@ -14,52 +19,59 @@ pub fn q25b(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError>
     let n = &db.n;
     let t = &db.t;
 
-    let vampire = memmem::Finder::new("Vampire");
+    let vampire = LikePattern::compile("Vampire%")?;
 
-    let start = Instant::now();
+    let target_keywords: HashSet<&str> = ["murder", "blood", "gore", "death", "female-nudity"]
+        .into_iter()
+        .collect();
+
+    timer.phase(Phase::Lookup);
+    let mut it_id: i32 = 0;
+    let mut it2_id: i32 = 0;
+    for (info, id) in it.info.iter().zip(it.id.iter()) {
+        if info == &"genres" {
+            it_id = *id;
+        } else if info == "votes" {
+            it2_id = *id;
+        }
+    }
 
-    let t_m: HashMap<&i32, &str> =
+    timer.phase(Phase::Build);
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 production_year
                     .filter(|production_year| {
-                        production_year > &2010 && vampire.find(title.as_bytes()).is_some()
+                        production_year > &2010 && vampire.matches(title.as_bytes())
                     })
                     .map(|_| (id, title.as_str()))
             })
             .collect();
 
-    let mut it_id: i32 = 0;
-    let mut it2_id: i32 = 0;
-    for (info, id) in it.info.iter().zip(it.id.iter()) {
-        if info == &"genres" {
-            it_id = *id;
-        } else if info == "votes" {
-            it2_id = *id;
-        }
-    }
-
-    let mi_m: HashMap<i32, Vec<&str>> = mi
-        .movie_id
-        .iter()
-        .zip(mi.info.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((movie_id, info), info_type_id)| {
-            (info == "Horror" && info_type_id == &it_id && t_m.contains_key(movie_id))
-                .then_some((*movie_id, info.as_str()))
-        })
-        .fold(HashMap::default(), |mut acc, (movie_id, info)| {
-            acc.entry(movie_id).or_default().push(info);
-            acc
-        });
+    let mi_m: PartitionedMap<i32, Vec<&str>> = PartitionedMap::build(
+        mi.movie_id.len(),
+        |row| {
+            let movie_id = &mi.movie_id[row];
+            let info = &mi.info[row];
+            let info_type_id = &mi.info_type_id[row];
+            let entry: Option<(i32, &str)> = {
+                (info == "Horror" && info_type_id == &it_id && t_m.contains_key(movie_id))
+                    .then_some((*movie_id, info.as_str()))
+            };
+            entry.map(|(movie_id, info)| (movie_id, vec![info]))
+        },
+        |values, more| values.extend(more),
+    );
 
     let mi_idx_m: HashMap<i32, Vec<&str>> = mi_idx
         .movie_id
         .iter()
         .zip(mi_idx.info.iter())
         .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, info), info_type_id)| {
             (it2_id == *info_type_id && mi_m.contains_key(movie_id))
                 .then_some((*movie_id, info.as_str()))
@@ -69,29 +81,24 @@ pub fn q25b(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError>
             acc
         });
 
-    let target_keywords: HashSet<&str> = ["murder", "blood", "gore", "death", "female-nudity"]
-        .into_iter()
-        .collect();
-
-    let k_s: HashSet<i32> =
+    let k_s: DenseSet =
         k.id.iter()
             .zip(k.keyword.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(id, keyword)| target_keywords.contains(keyword.as_str()).then_some(*id))
             .collect();
 
-    let mk_s: HashSet<i32> = mk
-        .movie_id
-        .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| {
-            (mi_idx_m.contains_key(movie_id) && k_s.contains(keyword_id)).then_some(*movie_id)
-        })
-        .collect();
+    let mk_s: PartitionedSet<i32> = PartitionedSet::build(mk.movie_id.len(), |row| {
+        let movie_id = &mk.movie_id[row];
+        let keyword_id = &mk.keyword_id[row];
+        (mi_idx_m.contains_key(movie_id) && k_s.contains(keyword_id)).then_some(*movie_id)
+    });
 
-    let n_m: HashMap<&i32, &str> =
+    let n_m: DenseMap<&str> =
         n.id.iter()
             .zip(n.name.iter())
             .zip(n.gender.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, name), gender)| {
                 gender
                     .as_ref()
@@ -100,6 +107,7 @@ pub fn q25b(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError>
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str, &str)> = None;
 
     let target_note: HashSet<&str> = [
@@ -118,6 +126,9 @@ pub fn q25b(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError>
         .zip(ci.movie_id.iter())
         .zip(ci.note.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(note) = note
             && target_note.contains(note.as_str())
             && let Some(mi_info) = mi_m.get(&movie_id)
@@ -143,61 +154,25 @@ pub fn q25b(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError>
         }
     }
 
-    println!("25b,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 25b
-// SELECT MIN(mi.info)     AS movie_budget,
-// MIN(mi_idx.info) AS movie_votes,
-// MIN(n.name)      AS male_writer,
-// MIN(t.title)     AS violent_movie_title
-// FROM cast_info AS ci,
-// info_type AS it1,
-// info_type AS it2,
-// keyword AS k,
-// movie_info AS mi,
-// movie_info_idx AS mi_idx,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE ci.note in ('(writer)', '(head writer)', '(written by)', '(story)', '(story editor)')
-// AND it1.info = 'genres'
-// AND it2.info = 'votes'
-// AND k.keyword in ('murder', 'blood', 'gore', 'death', 'female-nudity')
-// AND mi.info = 'Horror'
-// AND n.gender = 'm'
-// AND t.production_year > 2010
-// AND t.title like 'Vampire%'
-// AND t.id = mi.movie_id
-// AND t.id = mi_idx.movie_id
-// AND t.id = ci.movie_id
-// AND t.id = mk.movie_id
-// AND ci.movie_id = mi.movie_id
-// AND ci.movie_id = mi_idx.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND mi.movie_id = mi_idx.movie_id
-// AND mi.movie_id = mk.movie_id
-// AND mi_idx.movie_id = mk.movie_id
-// AND n.id = ci.person_id
-// AND it1.id = mi.info_type_id
-// AND it2.id = mi_idx.info_type_id
-// AND k.id = mk.keyword_id;
-#[cfg(test)]
-mod test_25b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q25b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q25b(&data)?;
-        assert_eq!(
-            res,
-            Some(("Horror", "138", "Campbell, Jeremiah", "Vampire Boys"))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "25b",
+    family: 25,
+    sql: include_str!("../queries/25b.sql"),
+    relations: &[
+        ("ci", "cast_info"),
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("k", "keyword"),
+        ("mi", "movie_info"),
+        ("mi_idx", "movie_info_idx"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q25b(db)?.into()),
+};
@@ -1,10 +1,15 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::DenseMap;
+use crate::like::LikePattern;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q27a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("27a");
     let cc = &db.cc;
     let cct = &db.cct;
     let cn = &db.cn;
@@ -17,12 +22,15 @@ pub fn q27a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let ml = &db.ml;
     let t = &db.t;
 
-    let film = Finder::new("Film");
-    let warner = Finder::new("Warner");
-    let follow = Finder::new("follow");
+    let film = LikePattern::compile("%Film%")?;
+    let warner = LikePattern::compile("%Warner%")?;
+    let follow = LikePattern::compile("%follow%")?;
 
-    let start = Instant::now();
+    let target_info: ahash::HashSet<&str> = ["Sweden", "Germany", "Swedish", "German"]
+        .into_iter()
+        .collect();
 
+    timer.phase(Phase::Lookup);
     let mut cct2_id: i32 = 0;
     let mut cct1_s = HashSet::default();
 
@@ -34,32 +42,6 @@ pub fn q27a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    let cc_s: HashSet<i32> = cc
-        .subject_id
-        .iter()
-        .zip(cc.status_id.iter())
-        .zip(cc.movie_id.iter())
-        .filter_map(|((subject_id, status_id), movie_id)| {
-            (cct1_s.contains(subject_id) && cct2_id == *status_id).then_some(*movie_id)
-        })
-        .collect();
-
-    let cn_m: HashMap<&i32, &str> = cn
-        .id
-        .iter()
-        .zip(cn.name.iter())
-        .zip(cn.country_code.iter())
-        .filter_map(|((id, name), country_code)| {
-            country_code
-                .as_deref()
-                .filter(|&code| {
-                    (film.find(name.as_bytes()).is_some() || warner.find(name.as_bytes()).is_some())
-                        && code != "[pl]"
-                })
-                .map(|_| (id, name.as_str()))
-        })
-        .collect();
-
     let ct_id = ct
         .kind
         .iter()
@@ -76,31 +58,57 @@ pub fn q27a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s: HashSet<i32> = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| {
-            (k_id == keyword_id && cc_s.contains(&movie_id)).then_some(*movie_id)
-        })
-        .collect();
-
-    let lt_m: HashMap<&i32, &str> = lt
+    let lt_m: DenseMap<&str> = lt
         .id
         .iter()
         .zip(lt.link.iter())
         .filter_map(|(id, link)| {
             follow
-                .find(link.as_bytes())
-                .is_some()
+                .matches(link.as_bytes())
                 .then_some((id, link.as_str()))
         })
         .collect();
 
+    timer.phase(Phase::Build);
+    let cc_s: HashSet<i32> = cc
+        .subject_id
+        .iter()
+        .zip(cc.status_id.iter())
+        .zip(cc.movie_id.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|((subject_id, status_id), movie_id)| {
+            (cct1_s.contains(subject_id) && cct2_id == *status_id).then_some(*movie_id)
+        })
+        .collect();
+
+    let cn_m: DenseMap<&str> = cn
+        .id
+        .iter()
+        .zip(cn.name.iter())
+        .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|((id, name), country_code)| {
+            country_code
+                .as_deref()
+                .filter(|&code| {
+                    (film.matches(name.as_bytes()) || warner.matches(name.as_bytes()))
+                        && code != "[pl]"
+                })
+                .map(|_| (id, name.as_str()))
+        })
+        .collect();
+
+    let mk_s: PartitionedSet<i32> = PartitionedSet::build(mk.keyword_id.len(), |row| {
+        let keyword_id = &mk.keyword_id[row];
+        let movie_id = &mk.movie_id[row];
+        (k_id == keyword_id && cc_s.contains(&movie_id)).then_some(*movie_id)
+    });
+
     let ml_m: HashMap<&i32, Vec<&str>> = ml
         .movie_id
         .iter()
         .zip(ml.link_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(mid, lt_id)| {
             if mk_s.contains(&mid)
                 && let Some(link) = lt_m.get(&lt_id)
@@ -115,24 +123,17 @@ pub fn q27a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let target_info: ahash::HashSet<&str> = ["Sweden", "Germany", "Swedish", "German"]
-        .into_iter()
-        .collect();
-
-    let mi_s: HashSet<&i32> = mi
-        .info
-        .iter()
-        .zip(mi.movie_id.iter())
-        .filter_map(|(info, movie_id)| {
-            (target_info.contains(info.as_str()) && ml_m.contains_key(&movie_id))
-                .then_some(movie_id)
-        })
-        .collect();
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.info.len(), |row| {
+        let info = &mi.info[row];
+        let movie_id = &mi.movie_id[row];
+        (target_info.contains(info.as_str()) && ml_m.contains_key(&movie_id)).then_some(movie_id)
+    });
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 if let Some(production_year) = production_year
                     && mi_s.contains(&id)
@@ -145,6 +146,7 @@ pub fn q27a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for (((company_id, company_type_id), movie_id), note) in mc
@@ -154,6 +156,9 @@ pub fn q27a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(mc.movie_id.iter())
         .zip(mc.note.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if ct_id == company_type_id
             && let Some(title) = t_m.get(&movie_id)
             && let Some(company) = cn_m.get(&company_id)
@@ -171,79 +176,28 @@ pub fn q27a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    let duration = start.elapsed().as_secs_f32();
-    println!("27a,{duration:}");
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 27a
-// SELECT MIN(cn.name) AS producing_company,
-//        MIN(lt.link) AS link_type,
-//        MIN(t.title) AS complete_western_sequel
-// FROM complete_cast AS cc,
-//      comp_cast_type AS cct1,
-//      comp_cast_type AS cct2,
-//      company_name AS cn,
-//      company_type AS ct,
-//      keyword AS k,
-//      link_type AS lt,
-//      movie_companies AS mc,
-//      movie_info AS mi,
-//      movie_keyword AS mk,
-//      movie_link AS ml,
-//      title AS t
-// WHERE cct1.kind IN ('cast',
-//                     'crew')
-//   AND cct2.kind = 'complete'
-//   AND cn.country_code !='[pl]'
-//   AND (cn.name LIKE '%Film%'
-//        OR cn.name LIKE '%Warner%')
-//   AND ct.kind ='production companies'
-//   AND k.keyword ='sequel'
-//   AND lt.link LIKE '%follow%'
-//   AND mc.note IS NULL
-//   AND mi.info IN ('Sweden',
-//                   'Germany',
-//                   'Swedish',
-//                   'German')
-//   AND t.production_year BETWEEN 1950 AND 2000
-//   AND lt.id = ml.link_type_id
-//   AND ml.movie_id = t.id
-//   AND t.id = mk.movie_id
-//   AND mk.keyword_id = k.id
-//   AND t.id = mc.movie_id
-//   AND mc.company_type_id = ct.id
-//   AND mc.company_id = cn.id
-//   AND mi.movie_id = t.id
-//   AND t.id = cc.movie_id
-//   AND cct1.id = cc.subject_id
-//   AND cct2.id = cc.status_id
-//   AND ml.movie_id = mk.movie_id
-//   AND ml.movie_id = mc.movie_id
-//   AND mk.movie_id = mc.movie_id
-//   AND ml.movie_id = mi.movie_id
-//   AND mk.movie_id = mi.movie_id
-//   AND mc.movie_id = mi.movie_id
-//   AND ml.movie_id = cc.movie_id
-//   AND mk.movie_id = cc.movie_id
-//   AND mc.movie_id = cc.movie_id
-//   AND mi.movie_id = cc.movie_id;
-
-#[cfg(test)]
-mod test_q27a {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q27a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q27a(&data)?;
-        assert_eq!(
-            res,
-            Some(("Det Danske Filminstitut", "followed by", "Spår i mörker"))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "27a",
+    family: 27,
+    sql: include_str!("../queries/27a.sql"),
+    relations: &[
+        ("cc", "complete_cast"),
+        ("cct1", "comp_cast_type"),
+        ("cct2", "comp_cast_type"),
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("k", "keyword"),
+        ("lt", "link_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("mk", "movie_keyword"),
+        ("ml", "movie_link"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q27a(db)?.into()),
+};
@@ -1,8 +1,15 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
+use crate::dense::{DenseMap, DenseSet};
+use crate::like::LikePattern;
+use crate::parallel::PartitionedSet;
+use crate::predicate::{Pred, Selection};
+use crate::registry::Entry;
+use crate::roaring::RoaringSet;
+use crate::timer::{Phase, QueryTimer};
+use ahash::HashSet;
 use polars::prelude::*;
-use std::time::Instant;
 
 // imdb.q28a_movie_companies(movie_id,company_id,company_type_id)
 // |imdb_int.complete_cast(movie_id,subject_id,status_id)
@@ -19,6 +26,7 @@ use std::time::Instant;
 // |imdb.q28a_company_name(company_id)
 // |imdb_int.company_type(company_type_id)
 pub fn q28a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("28a");
     let cc = &db.cc;
     let cct = &db.cct;
     let cn = &db.cn;
@@ -32,11 +40,31 @@ pub fn q28a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let mk = &db.mk;
     let t = &db.t;
 
-    let two_hundred_p = Finder::new("(200");
-    let usa_p = Finder::new("(USA)");
+    let two_hundred_p = LikePattern::compile("%(200%)%")?;
+    let usa_p = LikePattern::compile("%(USA)%")?;
 
-    let start = Instant::now();
+    let target_keywords: HashSet<&str> = ["murder", "murder-in-title", "blood", "violence"]
+        .into_iter()
+        .collect();
+
+    let target_kind_type: ahash::HashSet<&str> = ["movie", "episode"].into_iter().collect();
 
+    let target_info: ahash::HashSet<&str> = [
+        "Sweden",
+        "Norway",
+        "Germany",
+        "Denmark",
+        "Swedish",
+        "Denish",
+        "Norwegian",
+        "German",
+        "USA",
+        "American",
+    ]
+    .into_iter()
+    .collect();
+
+    timer.phase(Phase::Lookup);
     let mut cct1_id: i32 = 0;
     let mut cct2_s = HashSet::default();
 
@@ -49,21 +77,42 @@ pub fn q28a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
+    let mut it1_id: &i32 = &0;
+    let mut it2_id: &i32 = &0;
+
+    for (id, info) in it.id.iter().zip(it.info.iter()) {
+        if info == "countries" {
+            it1_id = id;
+        } else if info == "rating" {
+            it2_id = id;
+        }
+    }
+
+    let kt_s: HashSet<&i32> = kt
+        .kind
+        .iter()
+        .zip(kt.id.iter())
+        .filter_map(|(kind, id)| target_kind_type.contains(kind.as_str()).then_some(id))
+        .collect();
+
+    timer.phase(Phase::Build);
     let cc_s: HashSet<i32> = cc
         .subject_id
         .iter()
         .zip(cc.status_id.iter())
         .zip(cc.movie_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((subject_id, status_id), movie_id)| {
             (cct1_id == *subject_id && cct2_s.contains(status_id)).then_some(*movie_id)
         })
         .collect();
 
-    let cn_m: HashMap<&i32, &str> = cn
+    let cn_m: DenseMap<&str> = cn
         .id
         .iter()
         .zip(cn.name.iter())
         .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((id, name), country_code)| {
             country_code
                 .as_deref()
@@ -72,69 +121,27 @@ pub fn q28a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         })
         .collect();
 
-    let mut it1_id: &i32 = &0;
-    let mut it2_id: &i32 = &0;
-
-    for (id, info) in it.id.iter().zip(it.info.iter()) {
-        if info == "countries" {
-            it1_id = id;
-        } else if info == "rating" {
-            it2_id = id;
-        }
-    }
-
-    let target_keywords: HashSet<&str> = ["murder", "murder-in-title", "blood", "violence"]
-        .into_iter()
-        .collect();
-
-    let k_s: HashSet<&i32> =
+    let k_s: DenseSet =
         k.id.iter()
             .zip(k.keyword.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(id, keyword)| target_keywords.contains(keyword.as_str()).then_some(id))
             .collect();
 
-    let target_kind_type: ahash::HashSet<&str> = ["movie", "episode"].into_iter().collect();
-
-    let kt_s: HashSet<&i32> = kt
-        .kind
-        .iter()
-        .zip(kt.id.iter())
-        .filter_map(|(kind, id)| target_kind_type.contains(kind.as_str()).then_some(id))
-        .collect();
-
-    let target_info: ahash::HashSet<&str> = [
-        "Sweden",
-        "Norway",
-        "Germany",
-        "Denmark",
-        "Swedish",
-        "Denish",
-        "Norwegian",
-        "German",
-        "USA",
-        "American",
-    ]
-    .into_iter()
-    .collect();
-
-    let mi_s: HashSet<&i32> = mi
-        .info
-        .iter()
-        .zip(mi.movie_id.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((info, movie_id), info_type_id)| {
-            (target_info.contains(info.as_str())
-                && cc_s.contains(&movie_id)
-                && it1_id == info_type_id)
-                .then_some(movie_id)
-        })
-        .collect();
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.info.len(), |row| {
+        let info = &mi.info[row];
+        let movie_id = &mi.movie_id[row];
+        let info_type_id = &mi.info_type_id[row];
+        (target_info.contains(info.as_str()) && cc_s.contains(&movie_id) && it1_id == info_type_id)
+            .then_some(movie_id)
+    });
 
     let mi_idx_m: ahash::HashMap<&i32, Vec<&str>> = mi_idx
         .movie_id
         .iter()
         .zip(mi_idx.info.iter())
         .zip(mi_idx.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, info), info_type_id)| {
             (it2_id == info_type_id && info.as_str() < "8.5" && mi_s.contains(&movie_id))
                 .then_some((movie_id, info))
@@ -144,20 +151,18 @@ pub fn q28a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let mk_s: HashSet<&i32> = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| {
-            (k_s.contains(keyword_id) && mi_idx_m.contains_key(&movie_id)).then_some(movie_id)
-        })
-        .collect();
+    let mk_s: PartitionedSet<&i32> = PartitionedSet::build(mk.keyword_id.len(), |row| {
+        let keyword_id = &mk.keyword_id[row];
+        let movie_id = &mk.movie_id[row];
+        (k_s.contains(keyword_id) && mi_idx_m.contains_key(&movie_id)).then_some(movie_id)
+    });
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
             .zip(t.kind_id.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(((id, title), production_year), kind_id)| {
                 if let Some(production_year) = production_year
                     && *production_year > 2000
@@ -171,6 +176,7 @@ pub fn q28a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for ((movie_id, company_id), note) in mc
@@ -179,9 +185,12 @@ pub fn q28a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(mc.company_id.iter())
         .zip(mc.note.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(note) = note
-            && two_hundred_p.find(note.as_bytes()).is_some()
-            && usa_p.find(note.as_bytes()).is_none()
+            && two_hundred_p.matches(note.as_bytes())
+            && !usa_p.matches(note.as_bytes())
             && let Some(title) = t_m.get(&movie_id)
             && let Some(name) = cn_m.get(&company_id)
             && let Some(info) = mi_idx_m.get(&movie_id)
@@ -197,88 +206,184 @@ pub fn q28a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    println!("28a,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB 28a
-// SELECT MIN(cn.name) AS movie_company,
-//        MIN(mi_idx.info) AS rating,
-//        MIN(t.title) AS complete_euro_dark_movie
-// FROM complete_cast AS cc,
-//      comp_cast_type AS cct1,
-//      comp_cast_type AS cct2,
-//      company_name AS cn,
-//      company_type AS ct,
-//      info_type AS it1,
-//      info_type AS it2,
-//      keyword AS k,
-//      kind_type AS kt,
-//      movie_companies AS mc,
-//      movie_info AS mi,
-//      movie_info_idx AS mi_idx,
-//      movie_keyword AS mk,
-//      title AS t
-// WHERE cct1.kind = 'crew'
-//   AND cct2.kind != 'complete+verified'
-//   AND cn.country_code != '[us]'
-//   AND it1.info = 'countries'
-//   AND it2.info = 'rating'
-//   AND k.keyword IN ('murder',
-//                     'murder-in-title',
-//                     'blood',
-//                     'violence')
-//   AND kt.kind IN ('movie',
-//                   'episode')
-//   AND mc.note NOT LIKE '%(USA)%'
-//   AND mc.note LIKE '%(200%)%'
-//   AND mi.info IN ('Sweden',
-//                   'Norway',
-//                   'Germany',
-//                   'Denmark',
-//                   'Swedish',
-//                   'Danish',
-//                   'Norwegian',
-//                   'German',
-//                   'USA',
-//                   'American')
-//   AND mi_idx.info < '8.5'
-//   AND t.production_year > 2000
-//   AND kt.id = t.kind_id
-//   AND t.id = mi.movie_id
-//   AND t.id = mk.movie_id
-//   AND t.id = mi_idx.movie_id
-//   AND t.id = mc.movie_id
-//   AND t.id = cc.movie_id
-//   AND mk.movie_id = mi.movie_id
-//   AND mk.movie_id = mi_idx.movie_id
-//   AND mk.movie_id = mc.movie_id
-//   AND mk.movie_id = cc.movie_id
-//   AND mi.movie_id = mi_idx.movie_id
-//   AND mi.movie_id = mc.movie_id
-//   AND mi.movie_id = cc.movie_id
-//   AND mc.movie_id = mi_idx.movie_id
-//   AND mc.movie_id = cc.movie_id
-//   AND mi_idx.movie_id = cc.movie_id
-//   AND k.id = mk.keyword_id
-//   AND it1.id = mi.info_type_id
-//   AND it2.id = mi_idx.info_type_id
-//   AND ct.id = mc.company_type_id
-//   AND cn.id = mc.company_id
-//   AND cct1.id = cc.subject_id
-//   AND cct2.id = cc.status_id;
+/// q28a as a bitmap plan, like `q13a_bitmap`.
+pub fn q28a_bitmap(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("28a-bitmap");
+    let cc = &db.cc;
+    let cct = &db.cct;
+    let cn = &db.cn;
+    let it = &db.it;
+    let k = &db.k;
+    let kt = &db.kt;
+    let mc = &db.mc;
+    let mi = &db.mi;
+    let mi_idx = &db.mi_idx;
+    let mk = &db.mk;
+    let t = &db.t;
+
+    let two_hundred_p = LikePattern::compile("%(200%)%")?;
+    let usa_p = LikePattern::compile("%(USA)%")?;
+
+    timer.phase(Phase::Lookup);
+    let mut cct1_id: i32 = 0;
+    let mut cct2_s = HashSet::default();
+
+    for (id, kind) in cct.id.iter().zip(cct.kind.iter()) {
+        if kind != "complete+verified" {
+            cct2_s.insert(*id);
+        }
+        if kind == "crew" {
+            cct1_id = *id;
+        }
+    }
+
+    let mut it1_id: i32 = 0;
+    let mut it2_id: i32 = 0;
+
+    for (id, info) in it.id.iter().zip(it.info.iter()) {
+        if info == "countries" {
+            it1_id = *id;
+        } else if info == "rating" {
+            it2_id = *id;
+        }
+    }
+
+    let kt_sel = Pred::is_in(&kt.kind, ["movie", "episode"]).eval();
+    let kt_s: DenseSet = kt_sel.iter().map(|row| kt.id[row]).collect();
+
+    timer.phase(Phase::Build);
+    let cn_m: DenseMap<&str> = cn
+        .id
+        .iter()
+        .zip(cn.name.iter())
+        .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
+        .filter_map(|((id, name), country_code)| {
+            country_code
+                .as_deref()
+                .filter(|&code| code != "[us]")
+                .map(|_| (id, name.as_str()))
+        })
+        .collect();
+
+    let k_sel = Pred::is_in(
+        &k.keyword,
+        ["murder", "murder-in-title", "blood", "violence"],
+    )
+    .eval();
+    let k_s: DenseSet = k_sel.iter().map(|row| k.id[row]).collect();
+
+    let mut cc_sel = Pred::eq(&cc.subject_id, cct1_id).eval();
+    cc_sel.retain(|row| cct2_s.contains(&cc.status_id[row]));
+    let mut mc_sel = Pred::like(&mc.note, two_hundred_p)
+        .and(!Pred::like(&mc.note, usa_p))
+        .eval();
+    mc_sel.retain(|row| cn_m.contains_key(&mc.company_id[row]));
+    let mi_sel = Pred::eq(&mi.info_type_id, it1_id)
+        .and(Pred::is_in(
+            &mi.info,
+            [
+                "Sweden",
+                "Norway",
+                "Germany",
+                "Denmark",
+                "Swedish",
+                "Denish",
+                "Norwegian",
+                "German",
+                "USA",
+                "American",
+            ],
+        ))
+        .eval();
+    let mut t_sel = Pred::gt(&t.production_year, 2000).eval();
+    t_sel.retain(|row| kt_s.contains(&t.kind_id[row]));
+    let mk_sel = Selection::from_values(&mk.keyword_id, |keyword_id| k_s.contains(keyword_id));
+    let mi_idx_sel = Pred::eq(&mi_idx.info_type_id, it2_id)
+        .and(Pred::lt(&mi_idx.info, "8.5"))
+        .eval();
+
+    timer.phase(Phase::Probe);
+    let movies = RoaringSet::intersect_all(&[
+        cc_sel.iter().map(|row| cc.movie_id[row]).collect(),
+        mc_sel.iter().map(|row| mc.movie_id[row]).collect(),
+        mi_sel.iter().map(|row| mi.movie_id[row]).collect(),
+        t_sel.iter().map(|row| t.id[row]).collect(),
+        mk_sel.iter().map(|row| mk.movie_id[row]).collect(),
+        mi_idx_sel.iter().map(|row| mi_idx.movie_id[row]).collect(),
+    ]);
+
+    timer.phase(Phase::Aggregate);
+    let mut res = <(Min<&str>, Min<&str>, Min<&str>)>::default();
+    for row in mc_sel
+        .iter()
+        .filter(|&row| movies.contains(mc.movie_id[row]))
+    {
+        if cancel::poll() {
+            break;
+        }
+        res.0.update(*cn_m.get(&mc.company_id[row]).unwrap());
+    }
+    for row in mi_idx_sel
+        .iter()
+        .filter(|&row| movies.contains(mi_idx.movie_id[row]))
+    {
+        if cancel::poll() {
+            break;
+        }
+        res.1.update(mi_idx.info[row].as_str());
+    }
+    for row in t_sel.iter().filter(|&row| movies.contains(t.id[row])) {
+        if cancel::poll() {
+            break;
+        }
+        res.2.update(t.title[row].as_str());
+    }
+
+    timer.finish();
+
+    Ok(res.finish().transpose())
+}
+
+pub const QUERY: Entry = Entry {
+    id: "28a",
+    family: 28,
+    sql: include_str!("../queries/28a.sql"),
+    relations: &[
+        ("cc", "complete_cast"),
+        ("cct1", "comp_cast_type"),
+        ("cct2", "comp_cast_type"),
+        ("cn", "company_name"),
+        ("ct", "company_type"),
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("k", "keyword"),
+        ("kt", "kind_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("mi_idx", "movie_info_idx"),
+        ("mk", "movie_keyword"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q28a(db)?.into()),
+};
+
 #[cfg(test)]
 mod test_q28a {
     use super::*;
-    use crate::data::ImdbData;
+    use crate::test_support;
 
     #[test]
-    fn test_q28a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q28a(&data)?;
-        assert_eq!(res, Some(("01 Distribuzione", "2.9", "(#1.1)")));
+    fn test_q28a_bitmap() -> Result<(), PolarsError> {
+        let Some(data) = test_support::data() else {
+            return Ok(());
+        };
+        assert_eq!(q28a_bitmap(data)?, q28a(data)?);
         Ok(())
     }
 }
@@ -1,11 +1,16 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::{DenseMap, DenseSet};
+use crate::like::LikePattern;
+use crate::parallel::{self, PartitionedSet};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
-use memchr::memmem;
-use memchr::memmem::Finder;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q29c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("29c");
     let an = &db.an;
     let cc = &db.cc;
     let cct = &db.cct;
@@ -22,24 +27,20 @@ pub fn q29c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let rt = &db.rt;
     let t = &db.t;
 
-    let usa_colon = memmem::Finder::new("USA:");
-    let japan_colon = Finder::new("Japan:");
-    let two_hundred = memmem::Finder::new("200");
-    let an_finder = Finder::new("An");
+    let usa_colon = LikePattern::compile("USA:%200%")?;
+    let japan_colon = LikePattern::compile("Japan:%200%")?;
+    let an_finder = LikePattern::compile("%An%")?;
 
-    let chn_m: HashMap<&i32, Vec<&str>> =
-        chn.id
-            .iter()
-            .zip(chn.name.iter())
-            .fold(HashMap::default(), |mut acc, (id, name)| {
-                acc.entry(id).or_default().push(name);
-                acc
-            });
-
-    let start = Instant::now();
-
-    let an_s: HashSet<&i32> = an.person_id.iter().collect();
+    let target_note: ahash::HashSet<&str> = [
+        "(voice)",
+        "(voice) (uncredited)",
+        "(voice: English version)",
+        "(voice: Japanese version)",
+    ]
+    .into_iter()
+    .collect();
 
+    timer.phase(Phase::Lookup);
     let mut cct1_id: i32 = 0;
     let mut cct2_id: i32 = 0;
 
@@ -51,20 +52,67 @@ pub fn q29c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
+    let it_id: &i32 = it
+        .info
+        .iter()
+        .zip(it.id.iter())
+        .find(|(info, _)| *info == "release dates")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    let k_id = k
+        .keyword
+        .iter()
+        .zip(k.id.iter())
+        .find(|(keyword, _)| *keyword == "computer-animation")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    let it2_id = it
+        .info
+        .iter()
+        .zip(it.id.iter())
+        .find(|(info, _)| *info == "trivia")
+        .map(|(_, id)| *id)
+        .unwrap();
+
+    let rt_id = rt
+        .role
+        .iter()
+        .zip(rt.id.iter())
+        .find(|(role, _)| *role == "actress")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    timer.phase(Phase::Build);
+    let chn_m: HashMap<&i32, Vec<&str>> = chn
+        .id
+        .iter()
+        .zip(chn.name.iter())
+        .take_while(|_| !cancel::poll())
+        .fold(HashMap::default(), |mut acc, (id, name)| {
+            acc.entry(id).or_default().push(name);
+            acc
+        });
+
+    let an_s: HashSet<&i32> = an.person_id.iter().collect();
+
     let cc_s: HashSet<i32> = cc
         .subject_id
         .iter()
         .zip(cc.status_id.iter())
         .zip(cc.movie_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((subject_id, status_id), movie_id)| {
             (cct1_id == *subject_id && cct2_id == *status_id).then_some(*movie_id)
         })
         .collect();
 
-    let cn_s: HashSet<&i32> = cn
+    let cn_s: DenseSet = cn
         .id
         .iter()
         .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(id, country_code)| {
             country_code
                 .as_deref()
@@ -77,70 +125,41 @@ pub fn q29c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .movie_id
         .iter()
         .zip(mc.company_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(movie_id, company_id)| {
             (cc_s.contains(&movie_id) && cn_s.contains(company_id)).then_some(movie_id)
         })
         .collect();
 
-    let it_id: &i32 = it
-        .info
-        .iter()
-        .zip(it.id.iter())
-        .find(|(info, _)| *info == "release dates")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let mi_s: HashSet<&i32> = mi
-        .info
-        .iter()
-        .zip(mi.movie_id.iter())
-        .zip(mi.info_type_id.iter())
-        .filter_map(|((info, movie_id), info_type_id)| {
-            ((japan_colon.find(info.as_bytes()).is_some()
-                || usa_colon.find(info.as_bytes()).is_some())
-                && two_hundred.find(info.as_bytes()).is_some()
-                && it_id == info_type_id
-                && mc_s.contains(&movie_id))
-            .then_some(movie_id)
-        })
-        .collect();
-
-    let k_id = k
-        .keyword
-        .iter()
-        .zip(k.id.iter())
-        .find(|(keyword, _)| *keyword == "computer-animation")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let mk_s: HashSet<&i32> = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| {
-            (k_id == keyword_id && mi_s.contains(&movie_id)).then_some(movie_id)
-        })
-        .collect();
-
-    let it2_id = it
-        .info
-        .iter()
-        .zip(it.id.iter())
-        .find(|(info, _)| *info == "trivia")
-        .map(|(_, id)| *id)
-        .unwrap();
+    let mi_s: PartitionedSet<&i32> = PartitionedSet::build(mi.info.len(), |row| {
+        let info = &mi.info[row];
+        let movie_id = &mi.movie_id[row];
+        let info_type_id = &mi.info_type_id[row];
+        ((japan_colon.matches(info.as_bytes()) || usa_colon.matches(info.as_bytes()))
+            && it_id == info_type_id
+            && mc_s.contains(&movie_id))
+        .then_some(movie_id)
+    });
+
+    let mk_s: PartitionedSet<&i32> = PartitionedSet::build(mk.keyword_id.len(), |row| {
+        let keyword_id = &mk.keyword_id[row];
+        let movie_id = &mk.movie_id[row];
+        (k_id == keyword_id && mi_s.contains(&movie_id)).then_some(movie_id)
+    });
 
     let pi_s: HashSet<&i32> = pi
         .person_id
         .iter()
         .zip(pi.info_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(person_id, info_type_id)| (it2_id == *info_type_id).then_some(person_id))
         .collect();
 
-    let n_m: HashMap<&i32, &str> =
+    let n_m: DenseMap<&str> =
         n.id.iter()
             .zip(n.gender.iter())
             .zip(n.name.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, gender), name)| {
                 gender
                     .as_ref()
@@ -148,24 +167,17 @@ pub fn q29c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
                         an_s.contains(&id)
                             && pi_s.contains(id)
                             && gender == &"f"
-                            && an_finder.find(name.as_bytes()).is_some()
+                            && an_finder.matches(name.as_bytes())
                     })
                     .map(|_| (id, name.as_str()))
             })
             .collect();
 
-    let rt_id = rt
-        .role
-        .iter()
-        .zip(rt.id.iter())
-        .find(|(role, _)| *role == "actress")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 if let Some(production_year) = production_year
                     && (2000..=2010).contains(production_year)
@@ -178,120 +190,55 @@ pub fn q29c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
-    let target_note: ahash::HashSet<&str> = [
-        "(voice)",
-        "(voice) (uncredited)",
-        "(voice: English version)",
-        "(voice: Japanese version)",
-    ]
-    .into_iter()
-    .collect();
-
-    let mut res: Option<(&str, &str, &str)> = None;
-
-    for ((((movie_id, person_id), role_id), person_role_id), note) in ci
-        .movie_id
-        .iter()
-        .zip(ci.person_id.iter())
-        .zip(ci.role_id.iter())
-        .zip(ci.person_role_id.iter())
-        .zip(ci.note.iter())
-    {
-        if let Some(person_role_id) = person_role_id
-            && let Some(note) = note
-            && target_note.contains(note.as_str())
-            && rt_id == role_id
-            && let Some(title) = t_m.get(&movie_id)
-            && let Some(name) = n_m.get(&person_id)
-            && let Some(chn_names) = chn_m.get(&person_role_id)
-        {
-            res = match res {
-                Some((old_char_name, old_name, old_title)) => Some((
-                    chn_names.iter().min().unwrap().min(&old_char_name),
-                    name.min(&old_name),
-                    title.min(&old_title),
-                )),
-                None => Some((chn_names.iter().min().unwrap(), name, title)),
-            };
-        }
-    }
+    timer.phase(Phase::Probe);
+    let res =
+        parallel::probe::<(Min<&str>, Min<&str>, Min<&str>)>(ci.movie_id.len(), |row, res| {
+            let movie_id = &ci.movie_id[row];
+            let person_id = &ci.person_id[row];
+            let role_id = &ci.role_id[row];
+            let person_role_id = &ci.person_role_id[row];
+            let note = &ci.note[row];
+            if let Some(person_role_id) = person_role_id
+                && let Some(note) = note
+                && target_note.contains(note.as_str())
+                && rt_id == role_id
+                && let Some(title) = t_m.get(&movie_id)
+                && let Some(name) = n_m.get(&person_id)
+                && let Some(chn_names) = chn_m.get(&person_role_id)
+            {
+                res.update((*chn_names.iter().min().unwrap(), *name, *title));
+            }
+        })
+        .finish()
+        .transpose();
 
-    println!("29c,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 29c
-// SELECT MIN(chn.name) AS voiced_char, MIN(n.name) AS voicing_actress, MIN(t.title) AS voiced_animation
-// FROM aka_name AS an,
-// complete_cast AS cc,
-// comp_cast_type AS cct1,
-// comp_cast_type AS cct2,
-// char_name AS chn,
-// cast_info AS ci,
-// company_name AS cn,
-// info_type AS it,
-// info_type AS it3,
-// keyword AS k,
-// movie_companies AS mc,
-// movie_info AS mi,
-// movie_keyword AS mk,
-// name AS n,
-// person_info AS pi,
-// role_type AS rt,
-// title AS t
-// WHERE cct1.kind = 'cast'
-// AND cct2.kind = 'complete+verified'
-// AND ci.note in ('(voice)', '(voice: Japanese version)', '(voice) (uncredited)', '(voice: English version)')
-// AND cn.country_code = '[us]'
-// AND it.info = 'release dates'
-// AND it3.info = 'trivia'
-// AND k.keyword = 'computer-animation'
-// AND mi.info is not null
-// and (mi.info like 'Japan:%200%' or mi.info like 'USA:%200%')
-// AND n.gender = 'f'
-// and n.name like '%An%'
-// AND rt.role = 'actress'
-// AND t.production_year between 2000 and 2010
-// AND t.id = mi.movie_id
-// AND t.id = mc.movie_id
-// AND t.id = ci.movie_id
-// AND t.id = mk.movie_id
-// AND t.id = cc.movie_id
-// AND mc.movie_id = ci.movie_id
-// AND mc.movie_id = mi.movie_id
-// AND mc.movie_id = mk.movie_id
-// AND mc.movie_id = cc.movie_id
-// AND mi.movie_id = ci.movie_id
-// AND mi.movie_id = mk.movie_id
-// AND mi.movie_id = cc.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND ci.movie_id = cc.movie_id
-// AND mk.movie_id = cc.movie_id
-// AND cn.id = mc.company_id
-// AND it.id = mi.info_type_id
-// AND n.id = ci.person_id
-// AND rt.id = ci.role_id
-// AND n.id = an.person_id
-// AND ci.person_id = an.person_id
-// AND chn.id = ci.person_role_id
-// AND n.id = pi.person_id
-// AND ci.person_id = pi.person_id
-// AND it3.id = pi.info_type_id
-// AND k.id = mk.keyword_id
-// AND cct1.id = cc.subject_id
-// AND cct2.id = cc.status_id;
-#[cfg(test)]
-mod test_q29c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q29c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q29c(&data)?;
-        assert_eq!(res, Some(("Lola", "Andrews, Julie", "Hoodwinked!")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "29c",
+    family: 29,
+    sql: include_str!("../queries/29c.sql"),
+    relations: &[
+        ("an", "aka_name"),
+        ("cc", "complete_cast"),
+        ("cct1", "comp_cast_type"),
+        ("cct2", "comp_cast_type"),
+        ("chn", "char_name"),
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("it", "info_type"),
+        ("it3", "info_type"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("pi", "person_info"),
+        ("rt", "role_type"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q29c(db)?.into()),
+};
@@ -1,7 +1,10 @@
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
+use crate::dense::DenseSet;
+use crate::registry::Entry;
+use crate::spill::GraceTable;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 // imdb_int.movie_companies(movie_id,company_id,company_type_id)
 // |imdb.q2b_company_name(company_id)
@@ -9,18 +12,28 @@ use std::time::Instant;
 // |imdb_int.movie_keyword(movie_id,keyword_id)
 // ||imdb.q2b_keyword(keyword_id)
 pub fn q2a(db: &Data) -> Result<Option<&str>, PolarsError> {
+    let mut timer = QueryTimer::start("2a");
     let cn = &db.cn;
     let k = &db.k;
     let mc = &db.mc;
     let mk = &db.mk;
     let t = &db.t;
 
-    let start = Instant::now();
+    timer.phase(Phase::Lookup);
+    let k_id = k
+        .keyword
+        .iter()
+        .zip(k.id.iter())
+        .find(|(keyword, _)| *keyword == "character-name-in-title")
+        .map(|(_, id)| id)
+        .unwrap();
 
-    let cn_s: HashSet<i32> = cn
+    timer.phase(Phase::Build);
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -29,70 +42,82 @@ pub fn q2a(db: &Data) -> Result<Option<&str>, PolarsError> {
         })
         .collect();
 
-    let k_id = k
-        .keyword
-        .iter()
-        .zip(k.id.iter())
-        .find(|(keyword, _)| *keyword == "character-name-in-title")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let mk_s = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| (keyword_id == k_id).then_some(*movie_id))
-        .collect::<HashSet<_>>();
+    // The builds over movie_keyword and title are the ones that grow with the data, so they
+    // spill to disk past the memory budget.
+    let mk_t = GraceTable::build(
+        mk.keyword_id
+            .iter()
+            .zip(mk.movie_id.iter())
+            .enumerate()
+            .take_while(|_| !cancel::poll())
+            .filter(|(_, (keyword_id, _))| *keyword_id == k_id)
+            .map(|(row, (_, movie_id))| (*movie_id, row as u32)),
+    )?;
 
-    let t_m: HashMap<&i32, &str> =
-        t.id.iter()
-            .zip(t.title.iter())
-            .filter_map(|(id, title)| mk_s.contains(id).then_some((id, title.as_str())))
-            .collect();
+    let mut t_rows = Vec::new();
+    mk_t.semijoin(
+        t.id.iter().enumerate().map(|(row, id)| (*id, row as u32)),
+        |row| t_rows.push(row),
+    )?;
+    let t_t = GraceTable::build(t_rows.into_iter().map(|row| (t.id[row as usize], row)))?;
 
+    timer.phase(Phase::Probe);
     let mut res: Option<&str> = None;
 
-    for (movie_id, company_id) in mc.movie_id.iter().zip(mc.company_id.iter()) {
-        if cn_s.contains(&company_id)
-            && let Some(title) = t_m.get(&movie_id)
-        {
+    t_t.probe(
+        mc.movie_id
+            .iter()
+            .zip(mc.company_id.iter())
+            .enumerate()
+            .take_while(|_| !cancel::poll())
+            .filter(|(_, (_, company_id))| cn_s.contains(company_id))
+            .map(|(row, (movie_id, _))| (*movie_id, row as u32)),
+        |t_row, _| {
+            let title = t.title[t_row as usize].as_str();
             res = match res {
-                Some(old_title) => Some(title.min(&old_title)),
+                Some(old_title) => Some(title.min(old_title)),
                 None => Some(title),
             };
-        }
-    }
+        },
+    )?;
 
-    println!("2a,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// JOB 2a
-// SELECT MIN(t.title) AS movie_title
-// FROM company_name AS cn,
-// keyword AS k,
-// movie_companies AS mc,
-// movie_keyword AS mk,
-// title AS t
-// WHERE cn.country_code ='[de]'
-// AND k.keyword ='character-name-in-title'
-// AND cn.id = mc.company_id
-// AND mc.movie_id = t.id
-// AND t.id = mk.movie_id
-// AND mk.keyword_id = k.id
-// AND mc.movie_id = mk.movie_id;
+pub const QUERY: Entry = Entry {
+    id: "2a",
+    family: 2,
+    sql: include_str!("../queries/2a.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mk", "movie_keyword"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q2a(db)?.into()),
+};
+
 #[cfg(test)]
 mod test_q2a {
     use super::*;
-    use crate::data::ImdbData;
+    use crate::spill;
+    use crate::test_support;
 
     #[test]
-    fn test_q2a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q2a(&data)?;
-        assert_eq!(res, Some("'Doc'"));
+    fn test_q2a_spilled() -> Result<(), PolarsError> {
+        let Some(data) = test_support::data() else {
+            return Ok(());
+        };
+        let expected = q2a(data)?;
+        // Small enough for both builds to spill, for this call only.
+        spill::take_spilled();
+        let res = spill::with_scoped_budget(4096, || q2a(data));
+        let spilled = spill::take_spilled();
+        assert_eq!(res?, expected);
+        assert!(spilled > 0);
         Ok(())
     }
 }
@@ -1,7 +1,10 @@
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
+use crate::dense::{DenseMap, DenseSet};
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 // * imdb_int.movie_companies(movie_id,company_id,company_type_id)
 // * |imdb.q2c_company_name(company_id)
@@ -9,14 +12,14 @@ use std::time::Instant;
 // * |imdb_int.movie_keyword(movie_id,keyword_id)
 // * ||imdb.q2c_keyword(keyword_id)
 pub fn q2c(db: &Data) -> Result<Option<&str>, PolarsError> {
+    let mut timer = QueryTimer::start("2c");
     let cn = &db.cn;
     let k = &db.k;
     let mc = &db.mc;
     let mk = &db.mk;
     let t = &db.t;
 
-    let start = Instant::now();
-
+    timer.phase(Phase::Lookup);
     let k_id = k
         .keyword
         .iter()
@@ -25,23 +28,25 @@ pub fn q2c(db: &Data) -> Result<Option<&str>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| (keyword_id == k_id).then_some(*movie_id))
-        .collect::<HashSet<_>>();
+    timer.phase(Phase::Build);
+    let mk_s: PartitionedSet<_> = PartitionedSet::build(mk.keyword_id.len(), |row| {
+        let keyword_id = &mk.keyword_id[row];
+        let movie_id = &mk.movie_id[row];
+        (keyword_id == k_id).then_some(*movie_id)
+    });
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(id, title)| mk_s.contains(id).then_some((id, title.as_str())))
             .collect();
 
-    let cn_s: HashSet<i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -50,9 +55,13 @@ pub fn q2c(db: &Data) -> Result<Option<&str>, PolarsError> {
         })
         .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<&str> = None;
 
     for (movie_id, company_id) in mc.movie_id.iter().zip(mc.company_id.iter()) {
+        if cancel::poll() {
+            break;
+        }
         if cn_s.contains(&company_id)
             && let Some(title) = t_m.get(&movie_id)
         {
@@ -63,36 +72,21 @@ pub fn q2c(db: &Data) -> Result<Option<&str>, PolarsError> {
         }
     }
 
-    println!("2c,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 2c
-// SELECT MIN(t.title) AS movie_title
-// FROM company_name AS cn,
-// keyword AS k,
-// movie_companies AS mc,
-// movie_keyword AS mk,
-// title AS t
-// WHERE cn.country_code = '[sm]'
-// AND k.keyword = 'character-name-in-title'
-// AND cn.id = mc.company_id
-// AND mc.movie_id = t.id
-// AND t.id = mk.movie_id
-// AND mk.keyword_id = k.id
-// AND mc.movie_id = mk.movie_id;
-#[cfg(test)]
-mod test_q2c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q2c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q2c(&data)?;
-        assert_eq!(res, None);
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "2c",
+    family: 2,
+    sql: include_str!("../queries/2c.sql"),
+    relations: &[
+        ("cn", "company_name"),
+        ("k", "keyword"),
+        ("mc", "movie_companies"),
+        ("mk", "movie_keyword"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q2c(db)?.into()),
+};
@@ -1,7 +1,10 @@
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
+use crate::dense::DenseMap;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 // imdb_int.movie_link(movie_id,linked_movie_id,link_type_id)
 // |imdb_int.link_type(link_type_id)
@@ -10,15 +13,15 @@ use std::time::Instant;
 // |imdb_int.movie_keyword(movie_id,keyword_id)
 // ||imdb.q32a_keyword(keyword_id)
 pub fn q32a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("32a");
     let k = &db.k;
     let lt = &db.lt;
     let mk = &db.mk;
     let ml = &db.ml;
     let t = &db.t;
 
-    let elapsed = Instant::now();
-
-    let lt_m: HashMap<i32, &str> = lt
+    timer.phase(Phase::Lookup);
+    let lt_m: DenseMap<&str> = lt
         .id
         .iter()
         .zip(lt.link.iter())
@@ -33,19 +36,21 @@ pub fn q32a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .map(|(_, id)| *id)
         .unwrap();
 
-    let mk_s: HashSet<i32> = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| (k_id == *keyword_id).then_some(*movie_id))
-        .collect();
+    timer.phase(Phase::Build);
+    let mk_s: PartitionedSet<i32> = PartitionedSet::build(mk.keyword_id.len(), |row| {
+        let keyword_id = &mk.keyword_id[row];
+        let movie_id = &mk.movie_id[row];
+        (k_id == *keyword_id).then_some(*movie_id)
+    });
 
-    let t1_m: HashMap<i32, &str> =
+    let t1_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(id, title)| mk_s.contains(id).then_some((*id, title.as_str())))
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for ((movie_id, linked_movie_id), link_type_id) in ml
@@ -54,6 +59,9 @@ pub fn q32a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .zip(ml.linked_movie_id.iter())
         .zip(ml.link_type_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if mk_s.contains(&movie_id)
             && let Some(link) = lt_m.get(&link_type_id)
             && let Some(title1) = t1_m.get(&movie_id)
@@ -70,39 +78,22 @@ pub fn q32a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    println!("32a,{:}", elapsed.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 32a
-// SELECT MIN(lt.link) AS link_type,
-// MIN(t1.title) AS first_movie,
-// MIN(t2.title) AS second_movie
-// FROM keyword AS k,
-// link_type AS lt,
-// movie_keyword AS mk,
-// movie_link AS ml,
-// title AS t1,
-// title AS t2
-// WHERE k.keyword ='10,000-mile-club'
-// AND mk.keyword_id = k.id
-// AND t1.id = mk.movie_id
-// AND ml.movie_id = t1.id
-// AND ml.linked_movie_id = t2.id
-// AND lt.id = ml.link_type_id
-// AND mk.movie_id = t1.id;
-#[cfg(test)]
-mod test_q32a {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q32a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q32a(&data)?;
-        assert_eq!(res, None);
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "32a",
+    family: 32,
+    sql: include_str!("../queries/32a.sql"),
+    relations: &[
+        ("k", "keyword"),
+        ("lt", "link_type"),
+        ("mk", "movie_keyword"),
+        ("ml", "movie_link"),
+        ("t1", "title"),
+        ("t2", "title"),
+    ],
+    run: |db| Ok(q32a(db)?.into()),
+};
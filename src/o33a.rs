@@ -1,10 +1,15 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::DenseMap;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
 use polars::prelude::*;
-use std::time::Instant;
 
 #[allow(clippy::type_complexity)]
 pub fn q33a(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("33a");
     let cn = &db.cn;
     let it = &db.it;
     let kt = &db.kt;
@@ -14,13 +19,39 @@ pub fn q33a(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
     let ml = &db.ml;
     let t = &db.t;
 
-    let start = Instant::now();
+    let target_links: HashSet<&str> = ["sequel", "follows", "followed by"].into_iter().collect();
+
+    timer.phase(Phase::Lookup);
+    let it1_id: &i32 = it
+        .info
+        .iter()
+        .zip(it.id.iter())
+        .find(|(info, _)| *info == "rating")
+        .map(|(_, id)| id)
+        .unwrap();
 
-    let cn1_m: HashMap<&i32, &str> = cn
+    let kt_id = kt
+        .kind
+        .iter()
+        .zip(kt.id.iter())
+        .find(|(kind, _)| *kind == "tv series")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    let lt_s: HashSet<&i32> = lt
+        .id
+        .iter()
+        .zip(lt.link.iter())
+        .filter_map(|(id, link)| target_links.contains(link.as_str()).then_some(id))
+        .collect();
+
+    timer.phase(Phase::Build);
+    let cn1_m: DenseMap<&str> = cn
         .id
         .iter()
         .zip(cn.name.iter())
         .zip(cn.country_code.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((id, name), country_code)| {
             country_code
                 .as_deref()
@@ -29,14 +60,6 @@ pub fn q33a(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         })
         .collect();
 
-    let it1_id: &i32 = it
-        .info
-        .iter()
-        .zip(it.id.iter())
-        .find(|(info, _)| *info == "rating")
-        .map(|(_, id)| id)
-        .unwrap();
-
     let mut mi_idx1_m: HashMap<&i32, &str> = HashMap::default();
     let mut mi_idx2_m: HashMap<&i32, &str> = HashMap::default();
 
@@ -46,6 +69,9 @@ pub fn q33a(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         .zip(mi_idx.info.iter())
         .zip(mi_idx.movie_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if it1_id == it_id {
             mi_idx1_m.insert(movie_id, info);
             if info.as_str() < "3.0" {
@@ -54,14 +80,6 @@ pub fn q33a(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         }
     }
 
-    let kt_id = kt
-        .kind
-        .iter()
-        .zip(kt.id.iter())
-        .find(|(kind, _)| *kind == "tv series")
-        .map(|(_, id)| id)
-        .unwrap();
-
     let mut t1_m: HashMap<&i32, &str> = HashMap::default();
     let mut t2_m: HashMap<&i32, &str> = HashMap::default();
 
@@ -71,6 +89,9 @@ pub fn q33a(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
             .zip(t.title.iter())
             .zip(t.production_year.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if kt_id == kind_id {
             if mi_idx1_m.contains_key(id) {
                 t1_m.insert(id, title);
@@ -84,19 +105,11 @@ pub fn q33a(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         }
     }
 
-    let target_links: HashSet<&str> = ["sequel", "follows", "followed by"].into_iter().collect();
-
-    let lt_s: HashSet<&i32> = lt
-        .id
-        .iter()
-        .zip(lt.link.iter())
-        .filter_map(|(id, link)| target_links.contains(link.as_str()).then_some(id))
-        .collect();
-
     let mc1_m: HashMap<&i32, &str> = mc
         .company_id
         .iter()
         .zip(mc.movie_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(company_id, movie_id)| {
             if let Some(name) = cn1_m.get(&company_id)
                 && t1_m.contains_key(&movie_id)
@@ -115,6 +128,7 @@ pub fn q33a(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         .company_id
         .iter()
         .zip(mc.movie_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(company_id, movie_id)| {
             if let Some(name) = cn1_m.get(&company_id)
                 && t2_m.contains_key(&movie_id)
@@ -129,7 +143,15 @@ pub fn q33a(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
             acc
         });
 
-    let mut res: Option<(&str, &str, &str, &str, &str, &str)> = None;
+    timer.phase(Phase::Probe);
+    let mut res = <(
+        Min<&str>,
+        Min<&str>,
+        Min<&str>,
+        Min<&str>,
+        Min<&str>,
+        Min<&str>,
+    )>::default();
 
     for ((link_type_id, movie_id), linked_movie_id) in ml
         .link_type_id
@@ -137,6 +159,9 @@ pub fn q33a(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         .zip(ml.movie_id.iter())
         .zip(ml.linked_movie_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if lt_s.contains(&link_type_id) {
             if let Some(mi_idx1_info) = mi_idx1_m.get(&movie_id)
                 && let Some(mi_idx2_info) = mi_idx2_m.get(&linked_movie_id)
@@ -145,102 +170,42 @@ pub fn q33a(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
                 && let Some(mc1_min_name) = mc1_m.get(&movie_id)
                 && let Some(mc2_min_name) = mc2_m.get(&linked_movie_id)
             {
-                res = match res {
-                    Some((old_n1, old_n2, old_r1, old_r2, old_t1, old_t2)) => Some((
-                        old_n1.min(mc1_min_name),
-                        old_n2.min(mc2_min_name),
-                        old_r1.min(mi_idx1_info),
-                        old_r2.min(mi_idx2_info),
-                        old_t1.min(t1_title),
-                        old_t2.min(t2_title),
-                    )),
-                    None => Some((
-                        mc1_min_name,
-                        mc2_min_name,
-                        mi_idx1_info,
-                        mi_idx2_info,
-                        t1_title,
-                        t2_title,
-                    )),
-                };
+                res.update((
+                    *mc1_min_name,
+                    *mc2_min_name,
+                    *mi_idx1_info,
+                    *mi_idx2_info,
+                    *t1_title,
+                    *t2_title,
+                ));
             }
         }
     }
 
-    println!("33a,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
-    Ok(res)
+    Ok(res.finish().transpose())
 }
 
-// -- JOB Query 33a
-// SELECT MIN(cn1.name)     AS first_company,
-// MIN(cn2.name)     AS second_company,
-// MIN(mi_idx1.info) AS first_rating,
-// MIN(mi_idx2.info) AS second_rating,
-// MIN(t1.title)     AS first_movie,
-// MIN(t2.title)     AS second_movie
-// FROM company_name AS cn1,
-// company_name AS cn2,
-// info_type AS it1,
-// info_type AS it2,
-// kind_type AS kt1,
-// kind_type AS kt2,
-// link_type AS lt,
-// movie_companies AS mc1,
-// movie_companies AS mc2,
-// movie_info_idx AS mi_idx1,
-// movie_info_idx AS mi_idx2,
-// movie_link AS ml,
-// title AS t1,
-// title AS t2
-// WHERE cn1.country_code = '[us]'
-// AND it1.info = 'rating'
-// AND it2.info = 'rating'
-// AND kt1.kind in ('tv series')
-// AND kt2.kind in ('tv series')
-// AND lt.link in ('sequel', 'follows', 'followed by')
-// AND mi_idx2.info < '3.0'
-// AND t2.production_year between 2005 and 2008
-// AND lt.id = ml.link_type_id
-// AND t1.id = ml.movie_id
-// AND t2.id = ml.linked_movie_id
-// AND it1.id = mi_idx1.info_type_id
-// AND t1.id = mi_idx1.movie_id
-// AND kt1.id = t1.kind_id
-// AND cn1.id = mc1.company_id
-// AND t1.id = mc1.movie_id
-// AND ml.movie_id = mi_idx1.movie_id
-// AND ml.movie_id = mc1.movie_id
-// AND mi_idx1.movie_id = mc1.movie_id
-// AND it2.id = mi_idx2.info_type_id
-// AND t2.id = mi_idx2.movie_id
-// AND kt2.id = t2.kind_id
-// AND cn2.id = mc2.company_id
-// AND t2.id = mc2.movie_id
-// AND ml.linked_movie_id = mi_idx2.movie_id
-// AND ml.linked_movie_id = mc2.movie_id
-// AND mi_idx2.movie_id = mc2.movie_id;
-#[cfg(test)]
-mod test_q33a {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q33a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q33a(&data)?;
-        assert_eq!(
-            res,
-            Some((
-                "495 Productions",
-                "495 Productions",
-                "3.3",
-                "2.7",
-                "A Double Shot at Love",
-                "A Shot at Love with Tila Tequila",
-            ))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "33a",
+    family: 33,
+    sql: include_str!("../queries/33a.sql"),
+    relations: &[
+        ("cn1", "company_name"),
+        ("cn2", "company_name"),
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("kt1", "kind_type"),
+        ("kt2", "kind_type"),
+        ("lt", "link_type"),
+        ("mc1", "movie_companies"),
+        ("mc2", "movie_companies"),
+        ("mi_idx1", "movie_info_idx"),
+        ("mi_idx2", "movie_info_idx"),
+        ("ml", "movie_link"),
+        ("t1", "title"),
+        ("t2", "title"),
+    ],
+    run: |db| Ok(q33a(db)?.into()),
+};
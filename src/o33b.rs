@@ -1,11 +1,14 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::like::LikePattern;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
-use memchr::memmem;
 use polars::prelude::*;
-use std::time::Instant;
 
 #[allow(clippy::type_complexity)]
 pub fn q33b(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("33b");
     let cn = &db.cn;
     let it = &db.it;
     let kt = &db.kt;
@@ -15,14 +18,40 @@ pub fn q33b(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
     let ml = &db.ml;
     let t = &db.t;
 
-    let follow = memmem::Finder::new("follow");
+    let follow = LikePattern::compile("%follow%")?;
 
-    let start = Instant::now();
+    timer.phase(Phase::Lookup);
+    let it1_s: i32 = it
+        .id
+        .iter()
+        .zip(it.info.iter())
+        .find(|(_, info)| *info == "rating")
+        .map(|(id, _)| *id)
+        .unwrap();
 
+    let kt1_s: i32 = kt
+        .id
+        .iter()
+        .zip(kt.kind.iter())
+        .find(|(_, kind)| *kind == "tv series")
+        .map(|(id, _)| *id)
+        .unwrap();
+
+    let lt_s: HashSet<i32> = lt
+        .id
+        .iter()
+        .zip(lt.link.iter())
+        .filter_map(|(id, link)| follow.matches(link.as_bytes()).then_some(*id))
+        .collect();
+
+    timer.phase(Phase::Build);
     let mut cn1_m: HashMap<&i32, &str> = HashMap::default();
     let mut cn2_m: HashMap<&i32, &str> = HashMap::default();
 
     for ((id, name), country_code) in cn.id.iter().zip(cn.name.iter()).zip(cn.country_code.iter()) {
+        if cancel::poll() {
+            break;
+        }
         cn2_m.insert(id, name.as_str());
         if let Some(country_code) = country_code
             && country_code == &"[nl]"
@@ -31,14 +60,6 @@ pub fn q33b(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         }
     }
 
-    let it1_s: i32 = it
-        .id
-        .iter()
-        .zip(it.info.iter())
-        .find(|(_, info)| *info == "rating")
-        .map(|(id, _)| *id)
-        .unwrap();
-
     let mut mi_idx1_m: HashMap<i32, Vec<&str>> = HashMap::default();
     let mut mi_idx2_m: HashMap<i32, Vec<&str>> = HashMap::default();
 
@@ -48,6 +69,9 @@ pub fn q33b(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         .zip(mi_idx.info.iter())
         .zip(mi_idx.movie_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if it1_s == *it_id {
             mi_idx1_m.entry(*movie_id).or_default().push(info.as_str());
             if info.as_str() < "3.0" {
@@ -56,14 +80,6 @@ pub fn q33b(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         }
     }
 
-    let kt1_s: i32 = kt
-        .id
-        .iter()
-        .zip(kt.kind.iter())
-        .find(|(_, kind)| *kind == "tv series")
-        .map(|(id, _)| *id)
-        .unwrap();
-
     let mut t1_m: HashMap<&i32, &str> = HashMap::default();
     let mut t2_m: HashMap<&i32, &str> = HashMap::default();
 
@@ -73,6 +89,9 @@ pub fn q33b(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
             .zip(t.title.iter())
             .zip(t.production_year.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if kt1_s == *kind_id {
             if mi_idx1_m.contains_key(id) {
                 t1_m.insert(id, title.as_str());
@@ -86,17 +105,13 @@ pub fn q33b(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         }
     }
 
-    let lt_s: HashSet<i32> = lt
-        .id
-        .iter()
-        .zip(lt.link.iter())
-        .filter_map(|(id, link)| follow.find(link.as_bytes()).is_some().then_some(*id))
-        .collect();
-
     let mut mc1_m: HashMap<i32, Vec<&str>> = HashMap::default();
     let mut mc2_m: HashMap<i32, Vec<&str>> = HashMap::default();
 
     for (company_id, movie_id) in mc.company_id.iter().zip(mc.movie_id.iter()) {
+        if cancel::poll() {
+            break;
+        }
         if cn1_m.contains_key(&company_id) && t1_m.contains_key(movie_id) {
             mc1_m
                 .entry(*movie_id)
@@ -110,6 +125,7 @@ pub fn q33b(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         }
     }
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str, &str, &str, &str)> = None;
 
     for ((link_type_id, movie_id), linked_movie_id) in ml
@@ -118,6 +134,9 @@ pub fn q33b(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         .zip(ml.movie_id.iter())
         .zip(ml.linked_movie_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if lt_s.contains(&link_type_id) {
             if let Some(mi_idx1_info) = mi_idx1_m.get(&movie_id)
                 && let Some(mi_idx2_info) = mi_idx2_m.get(&linked_movie_id)
@@ -148,80 +167,30 @@ pub fn q33b(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         }
     }
 
-    println!("33b,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 33b
-// SELECT MIN(cn1.name)     AS first_company,
-// MIN(cn2.name)     AS second_company,
-// MIN(mi_idx1.info) AS first_rating,
-// MIN(mi_idx2.info) AS second_rating,
-// MIN(t1.title)     AS first_movie,
-// MIN(t2.title)     AS second_movie
-// FROM company_name AS cn1,
-// company_name AS cn2,
-// info_type AS it1,
-// info_type AS it2,
-// kind_type AS kt1,
-// kind_type AS kt2,
-// link_type AS lt,
-// movie_companies AS mc1,
-// movie_companies AS mc2,
-// movie_info_idx AS mi_idx1,
-// movie_info_idx AS mi_idx2,
-// movie_link AS ml,
-// title AS t1,
-// title AS t2
-// WHERE cn1.country_code = '[nl]'
-// AND it1.info = 'rating'
-// AND it2.info = 'rating'
-// AND kt1.kind in ('tv series')
-// AND kt2.kind in ('tv series')
-// AND lt.link LIKE '%follow%'
-// AND mi_idx2.info < '3.0'
-// AND t2.production_year = 2007
-// AND lt.id = ml.link_type_id
-// AND t1.id = ml.movie_id
-// AND t2.id = ml.linked_movie_id
-// AND it1.id = mi_idx1.info_type_id
-// AND t1.id = mi_idx1.movie_id
-// AND kt1.id = t1.kind_id
-// AND cn1.id = mc1.company_id
-// AND t1.id = mc1.movie_id
-// AND ml.movie_id = mi_idx1.movie_id
-// AND ml.movie_id = mc1.movie_id
-// AND mi_idx1.movie_id = mc1.movie_id
-// AND it2.id = mi_idx2.info_type_id
-// AND t2.id = mi_idx2.movie_id
-// AND kt2.id = t2.kind_id
-// AND cn2.id = mc2.company_id
-// AND t2.id = mc2.movie_id
-// AND ml.linked_movie_id = mi_idx2.movie_id
-// AND ml.linked_movie_id = mc2.movie_id
-// AND mi_idx2.movie_id = mc2.movie_id;
-#[cfg(test)]
-mod test_q33b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q33b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q33b(&data)?;
-        assert_eq!(
-            res,
-            Some((
-                "MTV Netherlands",
-                "495 Productions",
-                "3.3",
-                "2.7",
-                "A Double Shot at Love",
-                "A Shot at Love with Tila Tequila"
-            ))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "33b",
+    family: 33,
+    sql: include_str!("../queries/33b.sql"),
+    relations: &[
+        ("cn1", "company_name"),
+        ("cn2", "company_name"),
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("kt1", "kind_type"),
+        ("kt2", "kind_type"),
+        ("lt", "link_type"),
+        ("mc1", "movie_companies"),
+        ("mc2", "movie_companies"),
+        ("mi_idx1", "movie_info_idx"),
+        ("mi_idx2", "movie_info_idx"),
+        ("ml", "movie_link"),
+        ("t1", "title"),
+        ("t2", "title"),
+    ],
+    run: |db| Ok(q33b(db)?.into()),
+};
@@ -1,10 +1,13 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::HashMap;
 use polars::prelude::*;
-use std::time::Instant;
 
 #[allow(clippy::type_complexity)]
 pub fn q33c(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("33c");
     let cn = &db.cn;
     let it = &db.it;
     let kt = &db.kt;
@@ -14,12 +17,42 @@ pub fn q33c(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
     let ml = &db.ml;
     let t = &db.t;
 
-    let start = Instant::now();
+    let target_kind_type: ahash::HashSet<&str> = ["tv series", "episode"].into_iter().collect();
+
+    let target_link: ahash::HashSet<&str> =
+        ["sequel", "follows", "followed by"].into_iter().collect();
+
+    timer.phase(Phase::Lookup);
+    let it1_id: &i32 = it
+        .info
+        .iter()
+        .zip(it.id.iter())
+        .find(|(info, _)| *info == "rating")
+        .map(|(_, id)| id)
+        .unwrap();
+
+    let kt_s: ahash::HashSet<&i32> = kt
+        .kind
+        .iter()
+        .zip(kt.id.iter())
+        .filter_map(|(kind, id)| target_kind_type.contains(kind.as_str()).then_some(id))
+        .collect();
 
+    let lt_s: Vec<i32> = lt
+        .id
+        .iter()
+        .zip(lt.link.iter())
+        .filter_map(|(id, link)| target_link.contains(link.as_str()).then_some(*id))
+        .collect();
+
+    timer.phase(Phase::Build);
     let mut cn1_m: HashMap<&i32, &str> = HashMap::default();
     let mut cn2_m: HashMap<&i32, &str> = HashMap::default();
 
     for ((id, name), country_code) in cn.id.iter().zip(cn.name.iter()).zip(cn.country_code.iter()) {
+        if cancel::poll() {
+            break;
+        }
         cn2_m.insert(id, name.as_str());
         if let Some(country_code) = country_code
             && country_code != "[us]"
@@ -28,14 +61,6 @@ pub fn q33c(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         }
     }
 
-    let it1_id: &i32 = it
-        .info
-        .iter()
-        .zip(it.id.iter())
-        .find(|(info, _)| *info == "rating")
-        .map(|(_, id)| id)
-        .unwrap();
-
     let mut mi_idx1_m: ahash::HashMap<&i32, &str> = ahash::HashMap::default();
     let mut mi_idx2_m: ahash::HashMap<&i32, &str> = ahash::HashMap::default();
 
@@ -45,6 +70,9 @@ pub fn q33c(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         .zip(mi_idx.info.iter())
         .zip(mi_idx.movie_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if it1_id == it_id {
             mi_idx1_m.insert(movie_id, info);
             if info.as_str() < "3.5" {
@@ -53,19 +81,13 @@ pub fn q33c(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         }
     }
 
-    let target_kind_type: ahash::HashSet<&str> = ["tv series", "episode"].into_iter().collect();
-
-    let kt_s: ahash::HashSet<&i32> = kt
-        .kind
-        .iter()
-        .zip(kt.id.iter())
-        .filter_map(|(kind, id)| target_kind_type.contains(kind.as_str()).then_some(id))
-        .collect();
-
     let mut mc1_m: HashMap<i32, Vec<&str>> = HashMap::default();
     let mut mc2_m: HashMap<i32, Vec<&str>> = HashMap::default();
 
     for (company_id, movie_id) in mc.company_id.iter().zip(mc.movie_id.iter()) {
+        if cancel::poll() {
+            break;
+        }
         if let Some(name) = cn1_m.get(&company_id)
             && mi_idx1_m.contains_key(&movie_id)
         {
@@ -87,6 +109,9 @@ pub fn q33c(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
             .zip(t.title.iter())
             .zip(t.production_year.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if kt_s.contains(&kind_id) {
             if mc1_m.contains_key(&id) {
                 t1_m.insert(id, title.as_str());
@@ -100,16 +125,7 @@ pub fn q33c(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         }
     }
 
-    let target_link: ahash::HashSet<&str> =
-        ["sequel", "follows", "followed by"].into_iter().collect();
-
-    let lt_s: Vec<i32> = lt
-        .id
-        .iter()
-        .zip(lt.link.iter())
-        .filter_map(|(id, link)| target_link.contains(link.as_str()).then_some(*id))
-        .collect();
-
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str, &str, &str, &str)> = None;
 
     for ((link_type_id, movie_id), linked_movie_id) in ml
@@ -118,6 +134,9 @@ pub fn q33c(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         .zip(ml.movie_id.iter())
         .zip(ml.linked_movie_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if lt_s.contains(&link_type_id)
             && let Some(mi_idx1_info) = mi_idx1_m.get(&movie_id)
             && let Some(mi_idx2_info) = mi_idx2_m.get(&linked_movie_id)
@@ -147,54 +166,30 @@ pub fn q33c(db: &Data) -> Result<Option<(&str, &str, &str, &str, &str, &str)>, P
         }
     }
 
-    println!("33c,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 33c
-// SELECT MIN(cn1.name)     AS first_company,
-// MIN(cn2.name)     AS second_company,
-// MIN(mi_idx1.info) AS first_rating,
-// MIN(mi_idx2.info) AS second_rating,
-// MIN(t1.title)     AS first_movie,
-// MIN(t2.title)     AS second_movie
-// FROM company_name AS cn1,
-// company_name AS cn2,
-// info_type AS it1,
-// info_type AS it2,
-// kind_type AS kt1,
-// kind_type AS kt2,
-// link_type AS lt,
-// movie_companies AS mc1,
-// movie_companies AS mc2,
-// movie_info_idx AS mi_idx1,
-// movie_info_idx AS mi_idx2,
-// movie_link AS ml,
-// title AS t1,
-// title AS t2
-// WHERE cn1.country_code != '[us]' AND it1.info  = 'rating' AND it2.info  = 'rating' AND kt1.kind  in ('tv series', 'episode') AND kt2.kind  in ('tv series', 'episode') AND lt.link  in ('sequel', 'follows', 'followed by') AND mi_idx2.info  < '3.5' AND t2.production_year  between 2000 and 2010 AND lt.id = ml.link_type_id AND t1.id = ml.movie_id AND t2.id = ml.linked_movie_id AND it1.id = mi_idx1.info_type_id AND t1.id = mi_idx1.movie_id AND kt1.id = t1.kind_id AND cn1.id = mc1.company_id AND t1.id = mc1.movie_id AND ml.movie_id = mi_idx1.movie_id AND ml.movie_id = mc1.movie_id AND mi_idx1.movie_id = mc1.movie_id AND it2.id = mi_idx2.info_type_id AND t2.id = mi_idx2.movie_id AND kt2.id = t2.kind_id AND cn2.id = mc2.company_id AND t2.id = mc2.movie_id AND ml.linked_movie_id = mi_idx2.movie_id AND ml.linked_movie_id = mc2.movie_id AND mi_idx2.movie_id = mc2.movie_id;
-#[cfg(test)]
-mod test_q33c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q33c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q33c(&data)?;
-        assert_eq!(
-            res,
-            Some((
-                "2BE",
-                "495 Productions",
-                "1.3",
-                "1.0",
-                "A Double Shot at Love",
-                "A Double Shot at Love"
-            ))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "33c",
+    family: 33,
+    sql: include_str!("../queries/33c.sql"),
+    relations: &[
+        ("cn1", "company_name"),
+        ("cn2", "company_name"),
+        ("it1", "info_type"),
+        ("it2", "info_type"),
+        ("kt1", "kind_type"),
+        ("kt2", "kind_type"),
+        ("lt", "link_type"),
+        ("mc1", "movie_companies"),
+        ("mc2", "movie_companies"),
+        ("mi_idx1", "movie_info_idx"),
+        ("mi_idx2", "movie_info_idx"),
+        ("ml", "movie_link"),
+        ("t1", "title"),
+        ("t2", "title"),
+    ],
+    run: |db| Ok(q33c(db)?.into()),
+};
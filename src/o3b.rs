@@ -1,20 +1,22 @@
+use crate::cancel;
 use crate::data::Data;
-use ahash::HashSet;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 // * imdb_int.movie_keyword(movie_id,keyword_id)
 // * |imdb.q3b_title(movie_id,kind_id)
 // * |imdb.q3b_keyword(keyword_id)
 // * |imdb.q3b_movie_info(movie_id,info_type_id)
 pub fn q3b(db: &Data) -> Result<Option<&str>, PolarsError> {
+    let mut timer = QueryTimer::start("3b");
     let k = &db.k;
     let mi = &db.mi;
     let mk = &db.mk;
     let t = &db.t;
 
-    let start = Instant::now();
-
+    timer.phase(Phase::Lookup);
     let k_id = k
         .keyword
         .iter()
@@ -23,20 +25,20 @@ pub fn q3b(db: &Data) -> Result<Option<&str>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| (keyword_id == k_id).then_some(*movie_id))
-        .collect::<HashSet<_>>();
+    timer.phase(Phase::Build);
+    let mk_s: PartitionedSet<_> = PartitionedSet::build(mk.keyword_id.len(), |row| {
+        let keyword_id = &mk.keyword_id[row];
+        let movie_id = &mk.movie_id[row];
+        (keyword_id == k_id).then_some(*movie_id)
+    });
 
-    let mi_s: HashSet<i32> = mi
-        .info
-        .iter()
-        .zip(mi.movie_id.iter())
-        .filter_map(|(info, movie_id)| (info == "Bulgaria").then_some(*movie_id))
-        .collect();
+    let mi_s: PartitionedSet<i32> = PartitionedSet::build(mi.info.len(), |row| {
+        let info = &mi.info[row];
+        let movie_id = &mi.movie_id[row];
+        (info == "Bulgaria").then_some(*movie_id)
+    });
 
+    timer.phase(Phase::Probe);
     let mut res: Option<&str> = None;
 
     for ((movie_id, production_year), title) in
@@ -44,6 +46,9 @@ pub fn q3b(db: &Data) -> Result<Option<&str>, PolarsError> {
             .zip(t.production_year.iter())
             .zip(t.title.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(production_year) = production_year
             && production_year > &2010
             && mk_s.contains(&movie_id)
@@ -56,36 +61,20 @@ pub fn q3b(db: &Data) -> Result<Option<&str>, PolarsError> {
         }
     }
 
-    let duration = start.elapsed().as_secs_f32();
-    println!("3b,{duration:}");
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 3b
-// SELECT MIN(t.title) AS movie_title
-// FROM keyword AS k,
-// movie_info AS mi,
-// movie_keyword AS mk,
-// title AS t
-// WHERE k.keyword like '%sequel%'
-// AND mi.info IN ('Bulgaria')
-// AND t.production_year > 2010
-// AND t.id = mi.movie_id
-// AND t.id = mk.movie_id
-// AND mk.movie_id = mi.movie_id
-// AND k.id = mk.keyword_id;
-#[cfg(test)]
-mod test_q3b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q3b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q3b(&data)?;
-        assert_eq!(res, Some("300: Rise of an Empire"));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "3b",
+    family: 3,
+    sql: include_str!("../queries/3b.sql"),
+    relations: &[
+        ("k", "keyword"),
+        ("mi", "movie_info"),
+        ("mk", "movie_keyword"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q3b(db)?.into()),
+};
@@ -1,7 +1,10 @@
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
+use crate::dense::DenseMap;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 // * imdb.q4c_movie_info_idx(movie_id,info_type_id)
 // * |imdb.q4c_title(movie_id,kind_id)
@@ -9,14 +12,14 @@ use std::time::Instant;
 // * |imdb_int.movie_keyword(movie_id,keyword_id)
 // * ||imdb.q4c_keyword(keyword_id)
 pub fn q4c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("4c");
     let it = &db.it;
     let k = &db.k;
     let mi_idx = &db.mi_idx;
     let mk = &db.mk;
     let t = &db.t;
 
-    let start = Instant::now();
-
+    timer.phase(Phase::Lookup);
     let it_id: &i32 = it
         .info
         .iter()
@@ -33,17 +36,18 @@ pub fn q4c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let mk_s = mk
-        .keyword_id
-        .iter()
-        .zip(mk.movie_id.iter())
-        .filter_map(|(keyword_id, movie_id)| (keyword_id == k_id).then_some(*movie_id))
-        .collect::<HashSet<_>>();
+    timer.phase(Phase::Build);
+    let mk_s: PartitionedSet<_> = PartitionedSet::build(mk.keyword_id.len(), |row| {
+        let keyword_id = &mk.keyword_id[row];
+        let movie_id = &mk.movie_id[row];
+        (keyword_id == k_id).then_some(*movie_id)
+    });
 
-    let t_m: HashMap<i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 production_year
                     .filter(|production_year| *production_year > 1990 && mk_s.contains(&id))
@@ -51,6 +55,7 @@ pub fn q4c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str)> = None;
 
     for ((movie_id, info), info_type_id) in mi_idx
@@ -59,6 +64,9 @@ pub fn q4c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .zip(mi_idx.info.iter())
         .zip(mi_idx.info_type_id.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if it_id == info_type_id
             && info.as_str() > "2.0"
             && let Some(title) = t_m.get(&movie_id)
@@ -71,39 +79,21 @@ pub fn q4c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             };
         }
     }
-    let duration = start.elapsed().as_secs_f32();
-    println!("4c,{duration:}");
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 4c
-// SELECT MIN(mi_idx.info) AS rating, MIN(t.title) AS movie_title
-// FROM info_type AS it,
-// keyword AS k,
-// movie_info_idx AS mi_idx,
-// movie_keyword AS mk,
-// title AS t
-// WHERE it.info = 'rating'
-// AND k.keyword like '%sequel%'
-// AND mi_idx.info > '2.0'
-// AND t.production_year > 1990
-// AND t.id = mi_idx.movie_id
-// AND t.id = mk.movie_id
-// AND mk.movie_id = mi_idx.movie_id
-// AND k.id = mk.keyword_id
-// AND it.id = mi_idx.info_type_id;
-#[cfg(test)]
-mod test_q4c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q4c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q4c(&data)?;
-        assert_eq!(res, Some(("2.1", "& Teller 2")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "4c",
+    family: 4,
+    sql: include_str!("../queries/4c.sql"),
+    relations: &[
+        ("it", "info_type"),
+        ("k", "keyword"),
+        ("mi_idx", "movie_info_idx"),
+        ("mk", "movie_keyword"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q4c(db)?.into()),
+};
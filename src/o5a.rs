@@ -1,21 +1,37 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::like::LikePattern;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::HashSet;
-use memchr::memmem::Finder;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q5a(db: &Data) -> Result<Option<&str>, PolarsError> {
+    let mut timer = QueryTimer::start("5a");
     let ct = &db.ct;
     // let it = &db.it;
     let mc = &db.mc;
     let mi = &db.mi;
     let t = &db.t;
 
-    let theatrical = Finder::new("(theatrical)");
-    let france = Finder::new("(France)");
+    let theatrical = LikePattern::compile("%(theatrical)%")?;
+    let france = LikePattern::compile("%(France)%")?;
 
-    let start = Instant::now();
+    let target_info: HashSet<&str> = [
+        "Sweden",
+        "Norway",
+        "Germany",
+        "Denmark",
+        "Swedish",
+        "Denish",
+        "Norwegian",
+        "German",
+    ]
+    .into_iter()
+    .collect();
 
+    timer.phase(Phase::Lookup);
     let ct_id = ct
         .kind
         .iter()
@@ -24,15 +40,17 @@ pub fn q5a(db: &Data) -> Result<Option<&str>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
+    timer.phase(Phase::Build);
     let mc_s: HashSet<i32> = mc
         .note
         .iter()
         .zip(mc.movie_id.iter())
         .zip(mc.company_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((note, movie_id), company_type_id)| {
             if let Some(note) = note
-                && theatrical.find(note.as_bytes()).is_some()
-                && france.find(note.as_bytes()).is_some()
+                && theatrical.matches(note.as_bytes())
+                && france.matches(note.as_bytes())
                 && ct_id == company_type_id
             {
                 Some(*movie_id)
@@ -42,26 +60,13 @@ pub fn q5a(db: &Data) -> Result<Option<&str>, PolarsError> {
         })
         .collect();
 
-    let target_info: HashSet<&str> = [
-        "Sweden",
-        "Norway",
-        "Germany",
-        "Denmark",
-        "Swedish",
-        "Denish",
-        "Norwegian",
-        "German",
-    ]
-    .into_iter()
-    .collect();
-
-    let mi_s: HashSet<i32> = mi
-        .info
-        .iter()
-        .zip(mi.movie_id.iter())
-        .filter_map(|(info, movie_id)| target_info.contains(info.as_str()).then_some(*movie_id))
-        .collect();
+    let mi_s: PartitionedSet<i32> = PartitionedSet::build(mi.info.len(), |row| {
+        let info = &mi.info[row];
+        let movie_id = &mi.movie_id[row];
+        target_info.contains(info.as_str()).then_some(*movie_id)
+    });
 
+    timer.phase(Phase::Probe);
     let mut res: Option<&str> = None;
 
     for ((movie_id, production_year), title) in
@@ -69,6 +74,9 @@ pub fn q5a(db: &Data) -> Result<Option<&str>, PolarsError> {
             .zip(t.production_year.iter())
             .zip(t.title.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(production_year) = production_year
             && production_year > &2005
             && mc_s.contains(&movie_id)
@@ -81,47 +89,21 @@ pub fn q5a(db: &Data) -> Result<Option<&str>, PolarsError> {
         }
     }
 
-    let duration = start.elapsed().as_secs_f32();
-    println!("5a,{duration:}");
+    timer.finish();
 
     Ok(res)
 }
 
-// SELECT MIN(t.title) AS typical_european_movie
-// FROM company_type AS ct,
-//      info_type AS it,
-//      movie_companies AS mc,
-//      movie_info AS mi,
-//      title AS t
-// WHERE ct.kind = 'production companies'
-//   AND mc.note LIKE '%(theatrical)%'
-//   AND mc.note LIKE '%(France)%'
-//   AND mi.info IN ('Sweden',
-//                   'Norway',
-//                   'Germany',
-//                   'Denmark',
-//                   'Swedish',
-//                   'Denish',
-//                   'Norwegian',
-//                   'German')
-//   AND t.production_year > 2005
-//   AND t.id = mi.movie_id
-//   AND t.id = mc.movie_id
-//   AND mc.movie_id = mi.movie_id
-//   AND ct.id = mc.company_type_id
-//   AND it.id = mi.info_type_id;
-
-#[cfg(test)]
-mod test_q5a {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q5a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q5a(&data)?;
-        assert_eq!(res, None);
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "5a",
+    family: 5,
+    sql: include_str!("../queries/5a.sql"),
+    relations: &[
+        ("ct", "company_type"),
+        ("it", "info_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q5a(db)?.into()),
+};
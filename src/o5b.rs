@@ -1,22 +1,27 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::like::LikePattern;
+use crate::parallel::PartitionedSet;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::HashSet;
-use memchr::memmem::Finder;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q5b(db: &Data) -> Result<Option<&str>, PolarsError> {
+    let mut timer = QueryTimer::start("5b");
     let ct = &db.ct;
     // let it = &db.it;
     let mc = &db.mc;
     let mi = &db.mi;
     let t = &db.t;
 
-    let vhs = Finder::new("(VHS)");
-    let usa = Finder::new("(USA)");
-    let nine_four = Finder::new("(1994)");
+    let vhs = LikePattern::compile("%(VHS)%")?;
+    let usa = LikePattern::compile("%(USA)%")?;
+    let nine_four = LikePattern::compile("%(1994)%")?;
 
-    let start = Instant::now();
+    let target_info: HashSet<&str> = ["USA", "America"].into_iter().collect();
 
+    timer.phase(Phase::Lookup);
     let ct_id = ct
         .kind
         .iter()
@@ -25,16 +30,18 @@ pub fn q5b(db: &Data) -> Result<Option<&str>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
+    timer.phase(Phase::Build);
     let mc_s: HashSet<i32> = mc
         .note
         .iter()
         .zip(mc.movie_id.iter())
         .zip(mc.company_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((note, movie_id), company_type_id)| {
             if let Some(note) = note
-                && vhs.find(note.as_bytes()).is_some()
-                && usa.find(note.as_bytes()).is_some()
-                && nine_four.find(note.as_bytes()).is_some()
+                && vhs.matches(note.as_bytes())
+                && usa.matches(note.as_bytes())
+                && nine_four.matches(note.as_bytes())
                 && ct_id == company_type_id
             {
                 Some(*movie_id)
@@ -44,15 +51,13 @@ pub fn q5b(db: &Data) -> Result<Option<&str>, PolarsError> {
         })
         .collect();
 
-    let target_info: HashSet<&str> = ["USA", "America"].into_iter().collect();
-
-    let mi_s: HashSet<i32> = mi
-        .info
-        .iter()
-        .zip(mi.movie_id.iter())
-        .filter_map(|(info, movie_id)| target_info.contains(info.as_str()).then_some(*movie_id))
-        .collect();
+    let mi_s: PartitionedSet<i32> = PartitionedSet::build(mi.info.len(), |row| {
+        let info = &mi.info[row];
+        let movie_id = &mi.movie_id[row];
+        target_info.contains(info.as_str()).then_some(*movie_id)
+    });
 
+    timer.phase(Phase::Probe);
     let mut res: Option<&str> = None;
 
     for ((movie_id, production_year), title) in
@@ -60,6 +65,9 @@ pub fn q5b(db: &Data) -> Result<Option<&str>, PolarsError> {
             .zip(t.production_year.iter())
             .zip(t.title.iter())
     {
+        if cancel::poll() {
+            break;
+        }
         if let Some(production_year) = production_year
             && production_year > &2010
             && mc_s.contains(&movie_id)
@@ -72,40 +80,21 @@ pub fn q5b(db: &Data) -> Result<Option<&str>, PolarsError> {
         }
     }
 
-    println!("5b,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 5b
-// SELECT MIN(t.title) AS american_vhs_movie
-// FROM company_type AS ct,
-// info_type AS it,
-// movie_companies AS mc,
-// movie_info AS mi,
-// title AS t
-// WHERE ct.kind = 'production companies'
-// AND mc.note like '%(VHS)%'
-// and mc.note like '%(USA)%'
-// and mc.note like '%(1994)%'
-// AND mi.info IN ('USA', 'America')
-// AND t.production_year > 2010
-// AND t.id = mi.movie_id
-// AND t.id = mc.movie_id
-// AND mc.movie_id = mi.movie_id
-// AND ct.id = mc.company_type_id
-// AND it.id = mi.info_type_id;
-#[cfg(test)]
-mod test_q5b {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q5b() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q5b(&data)?;
-        assert_eq!(res, None);
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "5b",
+    family: 5,
+    sql: include_str!("../queries/5b.sql"),
+    relations: &[
+        ("ct", "company_type"),
+        ("it", "info_type"),
+        ("mc", "movie_companies"),
+        ("mi", "movie_info"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q5b(db)?.into()),
+};
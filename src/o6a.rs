@@ -1,30 +1,24 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashMapExt};
-use memchr::memmem::Finder;
+use crate::dense::DenseMap;
+use crate::like::LikePattern;
+use crate::parallel::{self, PartitionedMap};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q6a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("6a");
     let ci = &db.ci;
     let k = &db.k;
     let mk = &db.mk;
     let n = &db.n;
     let t = &db.t;
 
-    let downey = Finder::new("Downey");
-    let robert = Finder::new("Robert");
-
-    let start = Instant::now();
-
-    let n_m: HashMap<i32, &str> =
-        n.id.iter()
-            .zip(n.name.iter())
-            .filter_map(|(id, name)| {
-                (downey.find(name.as_bytes()).is_some() && robert.find(name.as_bytes()).is_some())
-                    .then_some((*id, name.as_str()))
-            })
-            .collect();
+    let downey_robert = LikePattern::compile("%Downey%Robert%")?;
 
+    timer.phase(Phase::Lookup);
     let k_id = k
         .keyword
         .iter()
@@ -33,10 +27,23 @@ pub fn q6a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let t_m: HashMap<i32, &str> =
+    timer.phase(Phase::Build);
+    let n_m: DenseMap<&str> =
+        n.id.iter()
+            .zip(n.name.iter())
+            .take_while(|_| !cancel::poll())
+            .filter_map(|(id, name)| {
+                downey_robert
+                    .matches(name.as_bytes())
+                    .then_some((*id, name.as_str()))
+            })
+            .collect();
+
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 production_year
                     .filter(|&year| year > 2010)
@@ -44,41 +51,39 @@ pub fn q6a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mk_m: HashMap<&i32, Vec<&str>> = mk
-        .movie_id
-        .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| {
-            if k_id == keyword_id
-                && let Some(title) = t_m.get(movie_id)
-            {
-                Some((movie_id, title))
-            } else {
-                None
-            }
-        })
-        .fold(HashMap::new(), |mut acc, (movie_id, title)| {
-            acc.entry(movie_id).or_default().push(title);
-            acc
-        });
-
-    let mut res: Option<(&str, &str)> = None;
+    let mk_m: PartitionedMap<&i32, Vec<&str>> = PartitionedMap::build(
+        mk.movie_id.len(),
+        |row| {
+            let movie_id = &mk.movie_id[row];
+            let keyword_id = &mk.keyword_id[row];
+            let entry: Option<(&i32, &str)> = {
+                if k_id == keyword_id
+                    && let Some(title) = t_m.get(movie_id)
+                {
+                    Some((movie_id, title))
+                } else {
+                    None
+                }
+            };
+            entry.map(|(movie_id, title)| (movie_id, vec![title]))
+        },
+        |values, more| values.extend(more),
+    );
 
-    for (pid, mid) in ci.person_id.iter().zip(ci.movie_id.iter()) {
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.person_id.len(), |row, res| {
+        let pid = &ci.person_id[row];
+        let mid = &ci.movie_id[row];
         if let Some(name) = n_m.get(&pid)
             && let Some(title) = mk_m.get(&mid)
         {
-            res = match res {
-                Some((old_name, old_title)) => Some((
-                    name.min(&old_name),
-                    title.iter().min().unwrap().min(&old_title),
-                )),
-                None => Some((name, title.iter().min().unwrap())),
-            };
+            res.update((*name, *title.iter().min().unwrap()));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    println!("6a,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(match res {
         Some((name, title)) => Some(("marvel-cinematic-universe", name, title)),
@@ -86,39 +91,16 @@ pub fn q6a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     })
 }
 
-// -- JOB Query 6a
-// SELECT MIN(k.keyword) AS movie_keyword, MIN(n.name) AS actor_name, MIN(t.title) AS marvel_movie
-// FROM cast_info AS ci,
-// keyword AS k,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE k.keyword = 'marvel-cinematic-universe'
-// AND n.name LIKE '%Downey%Robert%'
-// AND t.production_year > 2010
-// AND k.id = mk.keyword_id
-// AND t.id = mk.movie_id
-// AND t.id = ci.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND n.id = ci.person_id;
-#[cfg(test)]
-mod test_q6a {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q6a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q6a(&data)?;
-        assert_eq!(
-            res,
-            Some((
-                "marvel-cinematic-universe",
-                "Downey Jr., Robert",
-                "Iron Man 3"
-            ))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "6a",
+    family: 6,
+    sql: include_str!("../queries/6a.sql"),
+    relations: &[
+        ("ci", "cast_info"),
+        ("k", "keyword"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q6a(db)?.into()),
+};
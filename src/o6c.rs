@@ -1,34 +1,24 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
-use memchr::memmem;
+use crate::dense::DenseMap;
+use crate::like::LikePattern;
+use crate::parallel::{self, PartitionedSet};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q6c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("6c");
     let ci = &db.ci;
     let k = &db.k;
     let mk = &db.mk;
     let n = &db.n;
     let t = &db.t;
 
-    let downey = memmem::Finder::new(b"Downey");
-    let robert = memmem::Finder::new(b"Robert");
-
-    let start = Instant::now();
-
-    let n_m: HashMap<i32, &str> = n
-        .id
-        .iter()
-        .zip(n.name.iter())
-        .filter_map(|(id, name)| {
-            if downey.find(name.as_bytes()).is_some() && robert.find(name.as_bytes()).is_some() {
-                Some((*id, name.as_str()))
-            } else {
-                None
-            }
-        })
-        .collect();
+    let downey_robert = LikePattern::compile("%Downey%Robert%")?;
 
+    timer.phase(Phase::Lookup);
     // 6c has WHERE k.keyword = 'marvel-cinematic-universe'
     // then it's guaranteed that MIN(k.keyword) in SELECT is 'marvel-cinematic-universe'.
     // Therefore, we only need the id that corresponds to 'marvel-cinematic-universe'.
@@ -40,10 +30,25 @@ pub fn q6c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .map(|(_, id)| id)
         .unwrap();
 
-    let t_m: HashMap<i32, &str> =
+    timer.phase(Phase::Build);
+    let n_m: DenseMap<&str> =
+        n.id.iter()
+            .zip(n.name.iter())
+            .take_while(|_| !cancel::poll())
+            .filter_map(|(id, name)| {
+                if downey_robert.matches(name.as_bytes()) {
+                    Some((*id, name.as_str()))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 production_year
                     .filter(|&year| year > 2014)
@@ -53,30 +58,27 @@ pub fn q6c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
 
     // Because k.id = mk.keyword_id and k.id is only one,
     // we just need a hashset.
-    let mk_s: HashSet<i32> = mk
-        .movie_id
-        .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| {
-            (k_id == keyword_id && t_m.contains_key(&movie_id)).then_some(*movie_id)
-        })
-        .collect();
-
-    let mut res: Option<(&str, &str)> = None;
+    let mk_s: PartitionedSet<i32> = PartitionedSet::build(mk.movie_id.len(), |row| {
+        let movie_id = &mk.movie_id[row];
+        let keyword_id = &mk.keyword_id[row];
+        (k_id == keyword_id && t_m.contains_key(&movie_id)).then_some(*movie_id)
+    });
 
-    for (pid, mid) in ci.person_id.iter().zip(ci.movie_id.iter()) {
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.person_id.len(), |row, res| {
+        let pid = &ci.person_id[row];
+        let mid = &ci.movie_id[row];
         if mk_s.contains(&mid)
             && let Some(name) = n_m.get(&pid)
             && let Some(title) = t_m.get(&mid)
         {
-            res = match res {
-                Some((old_name, old_title)) => Some((name.min(&old_name), title.min(&old_title))),
-                None => Some((name, title)),
-            };
+            res.update((*name, *title));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    println!("6c,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(match res {
         Some((name, title)) => Some(("marvel-cinematic-universe", name, title)),
@@ -84,39 +86,16 @@ pub fn q6c(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     })
 }
 
-// -- JOB Query 6c
-// SELECT MIN(k.keyword) AS movie_keyword, MIN(n.name) AS actor_name, MIN(t.title) AS marvel_movie
-// FROM cast_info AS ci,
-// keyword AS k,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE k.keyword = 'marvel-cinematic-universe'
-// AND n.name LIKE '%Downey%Robert%'
-// AND t.production_year > 2014
-// AND k.id = mk.keyword_id
-// AND t.id = mk.movie_id
-// AND t.id = ci.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND n.id = ci.person_id;
-#[cfg(test)]
-mod test_6c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q6c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q6c(&data)?;
-        assert_eq!(
-            res,
-            Some((
-                "marvel-cinematic-universe",
-                "Downey Jr., Robert",
-                "The Avengers 2"
-            ))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "6c",
+    family: 6,
+    sql: include_str!("../queries/6c.sql"),
+    relations: &[
+        ("ci", "cast_info"),
+        ("k", "keyword"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q6c(db)?.into()),
+};
@@ -1,29 +1,23 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
+use crate::dense::DenseMap;
+use crate::like::LikePattern;
+use crate::parallel::{self, PartitionedMap};
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
+use ahash::HashSet;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q6d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("6d");
     let ci = &db.ci;
     let k = &db.k;
     let mk = &db.mk;
     let n = &db.n;
     let t = &db.t;
 
-    let downey = Finder::new("Downey");
-    let robert = Finder::new("Robert");
-
-    let start = Instant::now();
-
-    let n_m: HashMap<i32, &str> =
-        n.id.iter()
-            .zip(n.name.iter())
-            .filter_map(|(id, name)| {
-                (downey.find(name.as_bytes()).is_some() && robert.find(name.as_bytes()).is_some())
-                    .then_some((*id, name.as_str()))
-            })
-            .collect();
+    let downey_robert = LikePattern::compile("%Downey%Robert%")?;
 
     let target_keywords: HashSet<&str> = [
         "superhero",
@@ -38,9 +32,22 @@ pub fn q6d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     .into_iter()
     .collect();
 
-    let k_m: HashMap<i32, &str> =
+    timer.phase(Phase::Build);
+    let n_m: DenseMap<&str> =
+        n.id.iter()
+            .zip(n.name.iter())
+            .take_while(|_| !cancel::poll())
+            .filter_map(|(id, name)| {
+                downey_robert
+                    .matches(name.as_bytes())
+                    .then_some((*id, name.as_str()))
+            })
+            .collect();
+
+    let k_m: DenseMap<&str> =
         k.id.iter()
             .zip(k.keyword.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(id, keyword)| {
                 target_keywords
                     .contains(keyword.as_str())
@@ -48,26 +55,28 @@ pub fn q6d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mk_m: HashMap<i32, Vec<&str>> = mk
-        .movie_id
-        .iter()
-        .zip(mk.keyword_id.iter())
-        .filter_map(|(movie_id, keyword_id)| {
-            if let Some(keyword) = k_m.get(keyword_id) {
-                Some((*movie_id, *keyword))
-            } else {
-                None
-            }
-        })
-        .fold(HashMap::default(), |mut acc, (movie_id, keyword)| {
-            acc.entry(movie_id).or_default().push(keyword);
-            acc
-        });
+    let mk_m: PartitionedMap<i32, Vec<&str>> = PartitionedMap::build(
+        mk.movie_id.len(),
+        |row| {
+            let movie_id = &mk.movie_id[row];
+            let keyword_id = &mk.keyword_id[row];
+            let entry: Option<(i32, &str)> = {
+                if let Some(keyword) = k_m.get(keyword_id) {
+                    Some((*movie_id, *keyword))
+                } else {
+                    None
+                }
+            };
+            entry.map(|(movie_id, keyword)| (movie_id, vec![keyword]))
+        },
+        |values, more| values.extend(more),
+    );
 
-    let t_m: HashMap<i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 if let Some(production_year) = production_year
                     && production_year > &2000
@@ -80,63 +89,36 @@ pub fn q6d(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mut res: Option<(&str, &str, &str)> = None;
-
-    for (pid, mid) in ci.person_id.iter().zip(ci.movie_id.iter()) {
-        if let Some(title) = t_m.get(&mid)
-            && let Some(name) = n_m.get(&pid)
-            && let Some(keywords) = mk_m.get(&mid)
-        {
-            res = match res {
-                Some((old_keyword, old_name, old_title)) => Some((
-                    keywords.iter().min().unwrap().min(&old_keyword),
-                    name.min(&old_name),
-                    title.min(&old_title),
-                )),
-                None => Some((keywords.iter().min().unwrap(), name, title)),
-            };
-        }
-    }
+    timer.phase(Phase::Probe);
+    let res =
+        parallel::probe::<(Min<&str>, Min<&str>, Min<&str>)>(ci.person_id.len(), |row, res| {
+            let pid = &ci.person_id[row];
+            let mid = &ci.movie_id[row];
+            if let Some(title) = t_m.get(&mid)
+                && let Some(name) = n_m.get(&pid)
+                && let Some(keywords) = mk_m.get(&mid)
+            {
+                res.update((*keywords.iter().min().unwrap(), *name, *title));
+            }
+        })
+        .finish()
+        .transpose();
 
-    println!("6d,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 6d
-// SELECT MIN(k.keyword) AS movie_keyword, MIN(n.name) AS actor_name, MIN(t.title) AS hero_movie
-// FROM cast_info AS ci,
-// keyword AS k,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE k.keyword in
-// ('superhero', 'sequel', 'second-part', 'marvel-comics', 'based-on-comic', 'tv-special', 'fight', 'violence')
-// AND n.name LIKE '%Downey%Robert%'
-// AND t.production_year > 2000
-// AND k.id = mk.keyword_id
-// AND t.id = mk.movie_id
-// AND t.id = ci.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND n.id = ci.person_id;
-#[cfg(test)]
-mod test_q6d {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q6d() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q6d(&data)?;
-        assert_eq!(
-            res,
-            Some((
-                "based-on-comic",
-                "Downey Jr., Robert",
-                "2008 MTV Movie Awards"
-            ))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "6d",
+    family: 6,
+    sql: include_str!("../queries/6d.sql"),
+    relations: &[
+        ("ci", "cast_info"),
+        ("k", "keyword"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q6d(db)?.into()),
+};
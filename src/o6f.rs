@@ -1,23 +1,19 @@
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::DenseMap;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashMapExt};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q6f(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("6f");
     let ci = &db.ci;
     let k = &db.k;
     let mk = &db.mk;
     let n = &db.n;
     let t = &db.t;
 
-    let start = Instant::now();
-
-    let n_m: HashMap<&i32, &str> =
-        n.id.iter()
-            .zip(n.name.iter())
-            .map(|(id, name)| (id, name.as_str()))
-            .collect();
-
     let target_keywords: ahash::HashSet<&str> = [
         "superhero",
         "sequel",
@@ -31,9 +27,18 @@ pub fn q6f(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     .into_iter()
     .collect();
 
-    let k_m: HashMap<i32, &str> =
+    timer.phase(Phase::Build);
+    let n_m: DenseMap<&str> =
+        n.id.iter()
+            .zip(n.name.iter())
+            .take_while(|_| !cancel::poll())
+            .map(|(id, name)| (id, name.as_str()))
+            .collect();
+
+    let k_m: DenseMap<&str> =
         k.id.iter()
             .zip(k.keyword.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(id, keyword)| {
                 target_keywords
                     .contains(keyword.as_str())
@@ -45,6 +50,7 @@ pub fn q6f(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .movie_id
         .iter()
         .zip(mk.keyword_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(movie_id, keyword_id)| {
             (k_m.contains_key(&keyword_id)).then_some((*movie_id, *keyword_id))
         })
@@ -55,10 +61,11 @@ pub fn q6f(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             acc
         });
 
-    let t_m: HashMap<i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, title), production_year)| {
                 production_year
                     .filter(|&year| year > 2000 && mk_m.contains_key(&id))
@@ -66,14 +73,21 @@ pub fn q6f(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
+    timer.phase(Phase::Probe);
     let mut res: Option<(&str, &str, &str)> = None;
 
     for (pid, mid) in ci.person_id.iter().zip(ci.movie_id.iter()) {
+        if cancel::poll() {
+            break;
+        }
         if let Some(title) = t_m.get(&mid)
             && let Some(kids) = mk_m.get(&mid)
             && let Some(name) = n_m.get(&pid)
         {
             for kid in kids {
+                if cancel::poll() {
+                    break;
+                }
                 if let Some(keyword) = k_m.get(kid) {
                     res = match res {
                         Some((old_name, old_keyword, old_title)) => Some((
@@ -88,46 +102,21 @@ pub fn q6f(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         }
     }
 
-    let duration = start.elapsed().as_secs_f32();
-    println!("6f,{duration:}");
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 6f
-// SELECT MIN(k.keyword) AS movie_keyword, MIN(n.name) AS actor_name, MIN(t.title) AS hero_movie
-// FROM cast_info AS ci,
-// keyword AS k,
-// movie_keyword AS mk,
-// name AS n,
-// title AS t
-// WHERE k.keyword in
-// ('superhero', 'sequel', 'second-part', 'marvel-comics', 'based-on-comic', 'tv-special', 'fight', 'violence')
-// AND t.production_year > 2000
-// AND k.id = mk.keyword_id
-// AND t.id = mk.movie_id
-// AND t.id = ci.movie_id
-// AND ci.movie_id = mk.movie_id
-// AND n.id = ci.person_id;
-
-#[cfg(test)]
-mod test_q6f {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q6f() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q6f(&data)?;
-        assert_eq!(
-            res,
-            Some((
-                "\"Steff\", Stefanie Oxmann Mcgaha",
-                "based-on-comic",
-                "& Teller 2",
-            ))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "6f",
+    family: 6,
+    sql: include_str!("../queries/6f.sql"),
+    relations: &[
+        ("ci", "cast_info"),
+        ("k", "keyword"),
+        ("mk", "movie_keyword"),
+        ("n", "name"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q6f(db)?.into()),
+};
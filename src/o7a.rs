@@ -1,11 +1,16 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
-use ahash::HashMap;
+use crate::dense::DenseMap;
+use crate::like::LikePattern;
+use crate::parallel;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::HashSet;
-use memchr::memmem::Finder;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q7a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("7a");
     let an = &db.an;
     let ci = &db.ci;
     let it = &db.it;
@@ -15,11 +20,10 @@ pub fn q7a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let pi = &db.pi;
     let t = &db.t;
 
-    let b = Finder::new("B");
-    let a = Finder::new("a");
-
-    let start = Instant::now();
+    let b = LikePattern::compile("B%")?;
+    let a = LikePattern::compile("%a%")?;
 
+    timer.phase(Phase::Lookup);
     let lt_id = lt
         .id
         .iter()
@@ -36,10 +40,12 @@ pub fn q7a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .map(|(id, _)| id)
         .unwrap();
 
+    timer.phase(Phase::Build);
     let ml_s: HashSet<i32> = ml
         .linked_movie_id
         .iter()
         .zip(ml.link_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(id, link_type_id)| (lt_id == link_type_id).then_some(*id))
         .collect();
 
@@ -48,6 +54,7 @@ pub fn q7a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .iter()
         .zip(pi.info_type_id.iter())
         .zip(pi.note.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((person_id, info_type_id), note)| {
             note.as_ref()
                 .filter(|note| it_id == info_type_id && *note == "Volker Boehm")
@@ -55,10 +62,11 @@ pub fn q7a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         })
         .collect();
 
-    let t_m: HashMap<i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.production_year.iter())
             .zip(t.title.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, production_year), title)| {
                 production_year
                     .filter(|production_year| {
@@ -72,19 +80,21 @@ pub fn q7a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .person_id
         .iter()
         .zip(db.an.name.iter())
-        .filter_map(|(id, name)| a.find(name.as_bytes()).is_some().then_some(*id))
+        .take_while(|_| !cancel::poll())
+        .filter_map(|(id, name)| a.matches(name.as_bytes()).then_some(*id))
         .collect();
 
-    let n_m: HashMap<i32, &str> =
+    let n_m: DenseMap<&str> =
         n.id.iter()
             .zip(n.name.iter())
             .zip(n.name_pcode_cf.iter())
             .zip(n.gender.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(((id, name), name_pcode), gender)| {
                 if let Some(name_pcode) = name_pcode.as_deref()
                     && let Some(gender) = gender.as_deref()
                     && ((("A"..="F").contains(&name_pcode) && gender == "m")
-                        || (gender == "f" && b.find(name.as_bytes()) == Some(0)))
+                        || (gender == "f" && b.matches(name.as_bytes())))
                     && pi_s.contains(id)
                     && an_s.contains(id)
                 {
@@ -95,63 +105,37 @@ pub fn q7a(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mut res: Option<(&str, &str)> = None;
-
-    for (pid, mid) in ci.person_id.iter().zip(ci.movie_id.iter()) {
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.person_id.len(), |row, res| {
+        let pid = &ci.person_id[row];
+        let mid = &ci.movie_id[row];
         if let Some(name) = n_m.get(&pid)
             && let Some(title) = t_m.get(&mid)
         {
-            res = match res {
-                Some((old_name, old_title)) => Some((name.min(&old_name), title.min(&old_title))),
-                None => Some((name, title)),
-            };
+            res.update((*name, *title));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    println!("7a,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 7a
-// SELECT MIN(n.name) AS of_person, MIN(t.title) AS biography_movie
-// FROM aka_name AS an,
-// cast_info AS ci,
-// info_type AS it,
-// link_type AS lt,
-// movie_link AS ml,
-// name AS n,
-// person_info AS pi,
-// title AS t
-// WHERE an.name LIKE '%a%'
-// AND it.info = 'mini biography'
-// AND lt.link = 'features'
-// AND n.name_pcode_cf BETWEEN 'A' AND 'F'
-// AND (n.gender = 'm' OR (n.gender = 'f' AND n.name LIKE 'B%'))
-// AND pi.note = 'Volker Boehm'
-// AND t.production_year BETWEEN 1980 AND 1995
-// AND n.id = an.person_id
-// AND n.id = pi.person_id
-// AND ci.person_id = n.id
-// AND t.id = ci.movie_id
-// AND ml.linked_movie_id = t.id
-// AND lt.id = ml.link_type_id
-// AND it.id = pi.info_type_id
-// AND pi.person_id = an.person_id
-// AND pi.person_id = ci.person_id
-// AND an.person_id = ci.person_id
-// AND ci.movie_id = ml.linked_movie_id;
-#[cfg(test)]
-mod test_q7a {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q7a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q7a(&data)?;
-        assert_eq!(res, Some(("Antonioni, Michelangelo", "Dressed to Kill")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "7a",
+    family: 7,
+    sql: include_str!("../queries/7a.sql"),
+    relations: &[
+        ("an", "aka_name"),
+        ("ci", "cast_info"),
+        ("it", "info_type"),
+        ("lt", "link_type"),
+        ("ml", "movie_link"),
+        ("n", "name"),
+        ("pi", "person_info"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q7a(db)?.into()),
+};
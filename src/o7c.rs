@@ -1,11 +1,17 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::DenseMap;
+use crate::like::LikePattern;
+use crate::parallel;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::HashMap;
 use ahash::HashSet;
-use memchr::memmem::Finder;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q7c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("7c");
     let an = &db.an;
     let ci = &db.ci;
     let it = &db.it;
@@ -15,15 +21,15 @@ pub fn q7c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let pi = &db.pi;
     let t = &db.t;
 
-    let a = Finder::new("A");
-
-    let start = Instant::now();
+    let a = LikePattern::compile("%a%")?;
+    let big_a = LikePattern::compile("A%")?;
 
     let target_link: ahash::HashSet<&str> =
         ["references", "referenced in", "features", "featured in"]
             .into_iter()
             .collect();
 
+    timer.phase(Phase::Lookup);
     let lt_s: Vec<i32> = lt
         .id
         .iter()
@@ -39,10 +45,12 @@ pub fn q7c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .map(|(id, _)| id)
         .unwrap();
 
+    timer.phase(Phase::Build);
     let ml_s: HashSet<&i32> = ml
         .linked_movie_id
         .iter()
         .zip(ml.link_type_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(id, link_type_id)| (lt_s.contains(link_type_id)).then_some(id))
         .collect();
 
@@ -51,6 +59,7 @@ pub fn q7c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .iter()
         .zip(pi.info_type_id.iter())
         .zip(pi.info.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((id, info_type_id), info)| (it_id == info_type_id).then_some((id, info)))
         .fold(HashMap::default(), |mut acc, (id, info)| {
             acc.entry(id).or_default().push(info);
@@ -60,6 +69,7 @@ pub fn q7c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let t_s: HashSet<&i32> =
         t.id.iter()
             .zip(t.production_year.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(id, production_year)| {
                 production_year
                     .as_ref()
@@ -74,23 +84,25 @@ pub fn q7c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .person_id
         .iter()
         .zip(an.name.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(id, name)| {
-            (name.contains('a') || a.find(name.as_bytes()) == Some(0)).then_some(id)
+            (a.matches(name.as_bytes()) || big_a.matches(name.as_bytes())).then_some(id)
         })
         .collect();
 
-    let n_m: HashMap<&i32, &str> =
+    let n_m: DenseMap<&str> =
         n.id.iter()
             .zip(n.name.iter())
             .zip(n.name_pcode_cf.iter())
             .zip(n.gender.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|(((id, name), name_pcode_cf), gender)| {
                 if let Some(gender) = gender
                     && let Some(name_pcode_cf) = name_pcode_cf.as_deref()
                     && pi_m.contains_key(&id)
                     && an_s.contains(id)
                     && (("A"..="F").contains(&name_pcode_cf) && gender == "m"
-                        || gender == "f" && a.find(name.as_bytes()).is_some())
+                        || gender == "f" && big_a.matches(name.as_bytes()))
                 {
                     Some((id, name.as_str()))
                 } else {
@@ -99,76 +111,38 @@ pub fn q7c(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
             })
             .collect();
 
-    let mut res: Option<(&str, &str)> = None;
-
-    for (pid, mid) in ci.person_id.iter().zip(ci.movie_id.iter()) {
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.person_id.len(), |row, res| {
+        let pid = &ci.person_id[row];
+        let mid = &ci.movie_id[row];
         if let Some(info) = pi_m.get(&pid)
             && let Some(name) = n_m.get(&pid)
             && t_s.contains(&mid)
         {
-            res = match res {
-                Some((old_name, old_info)) => Some((
-                    name.min(&old_name),
-                    info.iter().min().unwrap().min(&old_info),
-                )),
-                None => Some((name, info.iter().min().unwrap())),
-            };
+            res.update((*name, *info.iter().min().unwrap()));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    let duration = start.elapsed().as_secs_f32();
-    println!("7c,{duration:}");
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 7c
-// SELECT MIN(n.name) AS cast_member_name, MIN(pi.info) AS cast_member_info
-// FROM aka_name AS an,
-// cast_info AS ci,
-// info_type AS it,
-// link_type AS lt,
-// movie_link AS ml,
-// name AS n,
-// person_info AS pi,
-// title AS t
-// WHERE an.name is not NULL
-// and (an.name LIKE '%a%' or an.name LIKE 'A%')
-// AND it.info = 'mini biography'
-// AND lt.link in ('references', 'referenced in', 'features', 'featured in')
-// AND n.name_pcode_cf BETWEEN 'A' AND 'F'
-// AND (n.gender = 'm' OR (n.gender = 'f' AND n.name LIKE 'A%'))
-// AND pi.note is not NULL
-// AND t.production_year BETWEEN 1980 AND 2010
-// AND n.id = an.person_id
-// AND n.id = pi.person_id
-// AND ci.person_id = n.id
-// AND t.id = ci.movie_id
-// AND ml.linked_movie_id = t.id
-// AND lt.id = ml.link_type_id
-// AND it.id = pi.info_type_id
-// AND pi.person_id = an.person_id
-// AND pi.person_id = ci.person_id
-// AND an.person_id = ci.person_id
-// AND ci.movie_id = ml.linked_movie_id;
-
-#[cfg(test)]
-mod test_q7c {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q7c() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q7c(&data)?;
-        assert_eq!(
-            res,
-            Some((
-                "50 Cent",
-                "\"Boo\" Arnold was born Earl Arnold in Hattiesburg, Mississippi in 1966. His father gave him the nickname 'Boo' early in life and it stuck through grade school, high school, and college. He is still known as \"Boo\" to family and friends.  Raised in central Texas, Arnold played baseball at Texas Tech University where he graduated with a BA in Advertising and Marketing. While at Texas Tech he was also a member of the Texas Epsilon chapter of Phi Delta Theta fraternity. After college he worked with Young Life, an outreach to high school students, in San Antonio, Texas.  While with Young Life Arnold began taking extension courses through Fuller Theological Seminary and ultimately went full-time to Gordon-Conwell Theological Seminary in Boston, Massachusetts. At Gordon-Conwell he completed a Master's Degree in Divinity studying Theology, Philosophy, Church History, Biblical Languages (Hebrew & Greek), and Exegetical Methods. Following seminary he was involved with reconciliation efforts in the former Yugoslavia shortly after the war ended there in1995.  Arnold started acting in his early thirties in Texas. After an encouraging visit to Los Angeles where he spent time with childhood friend George Eads (of CSI Las Vegas) he decided to move to Los Angeles in 2001 to pursue acting full-time. While in Los Angeles he has studied acting with Judith Weston at Judith Weston Studio for Actors and Directors.  Arnold's acting career has been one of steady development, booking co-star and guest-star roles in nighttime television. He guest-starred opposite of Jane Seymour on the night time television drama Justice. He played the lead, Michael Hollister, in the film The Seer, written and directed by Patrick Masset (Friday Night Lights).  He was nominated Best Actor in the168 Film Festival for the role of Phil Stevens in the short-film Useless. In Useless he played a US Marshal who must choose between mercy and justice as he confronts the man who murdered his father. Arnold's performance in Useless confirmed his ability to carry lead roles, and he continues to work toward solidifying himself as a male lead in film and television.  Arnold married fellow Texan Stacy Rudd of San Antonio in 2003 and they are now raising their three children in the Los Angeles area.",
-            ))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "7c",
+    family: 7,
+    sql: include_str!("../queries/7c.sql"),
+    relations: &[
+        ("an", "aka_name"),
+        ("ci", "cast_info"),
+        ("it", "info_type"),
+        ("lt", "link_type"),
+        ("ml", "movie_link"),
+        ("n", "name"),
+        ("pi", "person_info"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q7c(db)?.into()),
+};
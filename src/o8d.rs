@@ -1,10 +1,16 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::{DenseMap, DenseSet};
+use crate::parallel;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::HashMap;
 use ahash::HashSet;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q8d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("8d");
     let t = &db.t;
     let an = &db.an;
     // let n = &db.n;
@@ -13,8 +19,16 @@ pub fn q8d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
     let mc = &db.mc;
     let cn = &db.cn;
 
-    let start = Instant::now();
+    timer.phase(Phase::Lookup);
+    let rt_id = rt
+        .role
+        .iter()
+        .zip(rt.id.iter())
+        .find(|(role, _)| *role == "costume designer")
+        .map(|(_, id)| id)
+        .unwrap();
 
+    timer.phase(Phase::Build);
     let an_m: HashMap<&i32, Vec<&str>> = an.person_id.iter().zip(an.name.iter()).fold(
         HashMap::default(),
         |mut acc, (person_id, name)| {
@@ -23,24 +37,18 @@ pub fn q8d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         },
     );
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
+            .take_while(|_| !cancel::poll())
             .map(|(id, title)| (id, title.as_str()))
             .collect();
 
-    let rt_id = rt
-        .role
-        .iter()
-        .zip(rt.id.iter())
-        .find(|(role, _)| *role == "costume designer")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let cn_s: HashSet<&i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -53,70 +61,43 @@ pub fn q8d(db: &Data) -> Result<Option<(&str, &str)>, PolarsError> {
         .movie_id
         .iter()
         .zip(mc.company_id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(movie_id, company_id)| cn_s.contains(&company_id).then_some(movie_id))
         .collect();
 
-    let mut res: Option<(&str, &str)> = None;
-
-    for ((movie_id, person_id), role_id) in ci
-        .movie_id
-        .iter()
-        .zip(ci.person_id.iter())
-        .zip(ci.role_id.iter())
-    {
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>)>(ci.movie_id.len(), |row, res| {
+        let movie_id = &ci.movie_id[row];
+        let person_id = &ci.person_id[row];
+        let role_id = &ci.role_id[row];
         if rt_id == role_id
             && mc_s.contains(&movie_id)
             && let Some(name) = an_m.get(&person_id)
             && let Some(title) = t_m.get(&movie_id)
         {
-            res = match res {
-                Some((old_name, old_title)) => Some((
-                    name.iter().min().unwrap().min(&old_name),
-                    title.min(&old_title),
-                )),
-                None => Some((name.iter().min().unwrap(), title)),
-            };
+            res.update((*name.iter().min().unwrap(), *title));
         }
-    }
+    })
+    .finish()
+    .transpose();
 
-    println!("8d,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
 
     Ok(res)
 }
 
-// -- JOB Query 8d
-// SELECT MIN(an1.name) AS costume_designer_pseudo, MIN(t.title) AS movie_with_costumes
-// FROM aka_name AS an1,
-// cast_info AS ci,
-// company_name AS cn,
-// movie_companies AS mc,
-// name AS n1,
-// role_type AS rt,
-// title AS t
-// WHERE cn.country_code = '[us]'
-// AND rt.role = 'costume designer'
-// AND an1.person_id = n1.id
-// AND n1.id = ci.person_id
-// AND ci.movie_id = t.id
-// AND t.id = mc.movie_id
-// AND mc.company_id = cn.id
-// AND ci.role_id = rt.id
-// AND an1.person_id = ci.person_id
-// AND ci.movie_id = mc.movie_id;
-#[cfg(test)]
-mod test_q8d {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q8d() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q8d(&data)?;
-        assert_eq!(
-            res,
-            Some(("\"Jenny from the Block\"", "#1 Cheerleader Camp"))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "8d",
+    family: 8,
+    sql: include_str!("../queries/8d.sql"),
+    relations: &[
+        ("an1", "aka_name"),
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("mc", "movie_companies"),
+        ("n1", "name"),
+        ("rt", "role_type"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q8d(db)?.into()),
+};
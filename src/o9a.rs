@@ -1,10 +1,16 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::{DenseMap, DenseSet};
+use crate::like::LikePattern;
+use crate::parallel;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
-use memchr::memmem::Finder;
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q9a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("9a");
     let an = &db.an;
     let chn = &db.chn;
     let ci = &db.ci;
@@ -14,12 +20,29 @@ pub fn q9a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
     let rt = &db.rt;
     let t = &db.t;
 
-    let usa = Finder::new("(USA)");
-    let worldwide = Finder::new("(worldwide)");
-    let ang = Finder::new("Ang");
+    let usa = LikePattern::compile("%(USA)%")?;
+    let worldwide = LikePattern::compile("%(worldwide)%")?;
+    let ang = LikePattern::compile("%Ang%")?;
 
-    let start = Instant::now();
+    let target_note: HashSet<&str> = [
+        "(voice)",
+        "(voice: Japanese version)",
+        "(voice) (uncredited)",
+        "(voice: English version)",
+    ]
+    .into_iter()
+    .collect();
+
+    timer.phase(Phase::Lookup);
+    let rt_id = rt
+        .role
+        .iter()
+        .zip(rt.id.iter())
+        .find(|(role, _)| *role == "actress")
+        .map(|(_, id)| id)
+        .unwrap();
 
+    timer.phase(Phase::Build);
     let an_m: HashMap<&i32, Vec<&str>> = an.person_id.iter().zip(an.name.iter()).fold(
         HashMap::default(),
         |mut acc, (person_id, name)| {
@@ -28,17 +51,19 @@ pub fn q9a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         },
     );
 
-    let chn_m: HashMap<&i32, &str> = chn
+    let chn_m: DenseMap<&str> = chn
         .id
         .iter()
         .zip(chn.name.iter())
+        .take_while(|_| !cancel::poll())
         .map(|(id, title)| (id, title.as_str()))
         .collect();
 
-    let cn_s: HashSet<&i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -52,12 +77,12 @@ pub fn q9a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         .iter()
         .zip(mc.company_id.iter())
         .zip(mc.note.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, company_id), note)| {
             note.as_deref()
                 .filter(|note| {
                     cn_s.contains(&company_id)
-                        && (usa.find(note.as_bytes()).is_some()
-                            || worldwide.find(note.as_bytes()).is_some())
+                        && (usa.matches(note.as_bytes()) || worldwide.matches(note.as_bytes()))
                 })
                 .map(|_| movie_id)
         })
@@ -67,26 +92,20 @@ pub fn q9a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
         n.id.iter()
             .zip(n.gender.iter())
             .zip(n.name.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, gender), name)| {
                 gender
                     .as_deref()
-                    .filter(|&gender| gender == "f" && ang.find(name.as_bytes()).is_some())
+                    .filter(|&gender| gender == "f" && ang.matches(name.as_bytes()))
                     .map(|_| id)
             })
             .collect();
 
-    let rt_id = rt
-        .role
-        .iter()
-        .zip(rt.id.iter())
-        .find(|(role, _)| *role == "actress")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.production_year.iter())
             .zip(t.title.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((movie_id, production_year), title)| {
                 if let Some(production_year) = production_year
                     && (2005..=2015).contains(production_year)
@@ -98,104 +117,52 @@ pub fn q9a(db: &Data) -> Result<Option<(&str, &str, &str)>, PolarsError> {
             })
             .collect();
 
-    let target_note: HashSet<&str> = [
-        "(voice)",
-        "(voice: Japanese version)",
-        "(voice) (uncredited)",
-        "(voice: English version)",
-    ]
-    .into_iter()
-    .collect();
-
-    let mut res: Option<(&str, &str, &str)> = None;
-
-    for ((((movie_id, person_id), role_id), note), person_role_id) in ci
-        .movie_id
-        .iter()
-        .zip(ci.person_id.iter())
-        .zip(ci.role_id.iter())
-        .zip(ci.note.iter())
-        .zip(ci.person_role_id.iter())
-    {
-        if let Some(note) = note
-            && let Some(person_role_id) = person_role_id
-            && mc_s.contains(&movie_id)
-            && n_s.contains(&person_id)
-            && rt_id == role_id
-            && target_note.contains(note.as_str())
-            && let Some(title) = t_m.get(&movie_id)
-            && let Some(alternative_names) = an_m.get(&person_id)
-            && let Some(character_name) = chn_m.get(&person_role_id)
-        {
-            res = match res {
-                Some((old_alternative_name, old_character_name, old_title)) => Some((
-                    alternative_names
-                        .iter()
-                        .min()
-                        .unwrap()
-                        .min(&old_alternative_name),
-                    character_name.min(&old_character_name),
-                    title.min(&old_title),
-                )),
-                None => Some((
-                    alternative_names.iter().min().unwrap(),
-                    character_name,
-                    title,
-                )),
-            };
-        }
-    }
+    timer.phase(Phase::Probe);
+    let res =
+        parallel::probe::<(Min<&str>, Min<&str>, Min<&str>)>(ci.movie_id.len(), |row, res| {
+            let movie_id = &ci.movie_id[row];
+            let person_id = &ci.person_id[row];
+            let role_id = &ci.role_id[row];
+            let note = &ci.note[row];
+            let person_role_id = &ci.person_role_id[row];
+            if let Some(note) = note
+                && let Some(person_role_id) = person_role_id
+                && mc_s.contains(&movie_id)
+                && n_s.contains(&person_id)
+                && rt_id == role_id
+                && target_note.contains(note.as_str())
+                && let Some(title) = t_m.get(&movie_id)
+                && let Some(alternative_names) = an_m.get(&person_id)
+                && let Some(character_name) = chn_m.get(&person_role_id)
+            {
+                res.update((
+                    *alternative_names.iter().min().unwrap(),
+                    *character_name,
+                    *title,
+                ));
+            }
+        })
+        .finish()
+        .transpose();
 
-    let duration = start.elapsed().as_secs_f32();
-    println!("9a,{duration:}");
+    timer.finish();
 
     Ok(res)
 }
 
-// SELECT MIN(an.name) AS alternative_name,
-//        MIN(chn.name) AS character_name,
-//        MIN(t.title) AS movie
-// FROM aka_name AS an,
-//      char_name AS chn,
-//      cast_info AS ci,
-//      company_name AS cn,
-//      movie_companies AS mc,
-//      name AS n,
-//      role_type AS rt,
-//      title AS t
-// WHERE ci.note IN ('(voice)',
-//                   '(voice: Japanese version)',
-//                   '(voice) (uncredited)',
-//                   '(voice: English version)')
-//   AND cn.country_code ='[us]'
-//   AND mc.note IS NOT NULL
-//   AND (mc.note LIKE '%(USA)%'
-//        OR mc.note LIKE '%(worldwide)%')
-//   AND n.gender ='f'
-//   AND n.name LIKE '%Ang%'
-//   AND rt.role ='actress'
-//   AND t.production_year BETWEEN 2005 AND 2015
-//   AND ci.movie_id = t.id
-//   AND t.id = mc.movie_id
-//   AND ci.movie_id = mc.movie_id
-//   AND mc.company_id = cn.id
-//   AND ci.role_id = rt.id
-//   AND n.id = ci.person_id
-//   AND chn.id = ci.person_role_id
-//   AND an.person_id = n.id
-//   AND an.person_id = ci.person_id;
-
-#[cfg(test)]
-mod test_q9a {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q9a() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q9a(&data)?;
-        assert_eq!(res, Some(("AJ", "Airport Announcer", "Blue Harvest")));
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "9a",
+    family: 9,
+    sql: include_str!("../queries/9a.sql"),
+    relations: &[
+        ("an", "aka_name"),
+        ("chn", "char_name"),
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("mc", "movie_companies"),
+        ("n", "name"),
+        ("rt", "role_type"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q9a(db)?.into()),
+};
@@ -1,9 +1,15 @@
+use crate::aggregate::{Accumulate, Aggregate, Min, Transpose};
+use crate::cancel;
 use crate::data::Data;
+use crate::dense::{DenseMap, DenseSet};
+use crate::parallel;
+use crate::registry::Entry;
+use crate::timer::{Phase, QueryTimer};
 use ahash::{HashMap, HashSet};
 use polars::prelude::*;
-use std::time::Instant;
 
 pub fn q9d(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError> {
+    let mut timer = QueryTimer::start("9d");
     let an = &db.an;
     let chn = &db.chn;
     let ci = &db.ci;
@@ -13,8 +19,25 @@ pub fn q9d(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError> {
     let rt = &db.rt;
     let t = &db.t;
 
-    let start = Instant::now();
+    let target_note: HashSet<&str> = [
+        "(voice)",
+        "(voice: Japanese version)",
+        "(voice) (uncredited)",
+        "(voice: English version)",
+    ]
+    .into_iter()
+    .collect();
+
+    timer.phase(Phase::Lookup);
+    let rt_id = rt
+        .role
+        .iter()
+        .zip(rt.id.iter())
+        .find(|(role, _)| *role == "actress")
+        .map(|(_, id)| id)
+        .unwrap();
 
+    timer.phase(Phase::Build);
     let an_m: HashMap<&i32, Vec<&str>> = an.person_id.iter().zip(an.name.iter()).fold(
         HashMap::default(),
         |mut acc, (person_id, name)| {
@@ -23,23 +46,26 @@ pub fn q9d(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError> {
         },
     );
 
-    let chn_m: HashMap<&i32, &str> = chn
+    let chn_m: DenseMap<&str> = chn
         .id
         .iter()
         .zip(chn.name.iter())
+        .take_while(|_| !cancel::poll())
         .map(|(id, title)| (id, title.as_str()))
         .collect();
 
-    let t_m: HashMap<&i32, &str> =
+    let t_m: DenseMap<&str> =
         t.id.iter()
             .zip(t.title.iter())
+            .take_while(|_| !cancel::poll())
             .map(|(id, title)| (id, title.as_str()))
             .collect();
 
-    let cn_s: HashSet<&i32> = cn
+    let cn_s: DenseSet = cn
         .country_code
         .iter()
         .zip(cn.id.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|(country_code, id)| {
             country_code
                 .as_ref()
@@ -53,6 +79,7 @@ pub fn q9d(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError> {
         .iter()
         .zip(mc.company_id.iter())
         .zip(mc.note.iter())
+        .take_while(|_| !cancel::poll())
         .filter_map(|((movie_id, company_id), note)| {
             note.as_deref()
                 .filter(|_| cn_s.contains(&company_id))
@@ -60,10 +87,11 @@ pub fn q9d(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError> {
         })
         .collect();
 
-    let n_m: HashMap<&i32, &str> =
+    let n_m: DenseMap<&str> =
         n.id.iter()
             .zip(n.gender.iter())
             .zip(n.name.iter())
+            .take_while(|_| !cancel::poll())
             .filter_map(|((id, gender), name)| {
                 if let Some(gender) = gender
                     && gender == "f"
@@ -75,113 +103,54 @@ pub fn q9d(db: &Data) -> Result<Option<(&str, &str, &str, &str)>, PolarsError> {
             })
             .collect();
 
-    let rt_id = rt
-        .role
-        .iter()
-        .zip(rt.id.iter())
-        .find(|(role, _)| *role == "actress")
-        .map(|(_, id)| id)
-        .unwrap();
-
-    let target_note: HashSet<&str> = [
-        "(voice)",
-        "(voice: Japanese version)",
-        "(voice) (uncredited)",
-        "(voice: English version)",
-    ]
-    .into_iter()
-    .collect();
-
-    let mut res: Option<(&str, &str, &str, &str)> = None;
-
-    for ((((movie_id, person_id), role_id), note), person_role_id) in ci
-        .movie_id
-        .iter()
-        .zip(ci.person_id.iter())
-        .zip(ci.role_id.iter())
-        .zip(ci.note.iter())
-        .zip(ci.person_role_id.iter())
-    {
-        if let Some(note) = note
-            && let Some(person_role_id) = person_role_id
-            && mc_s.contains(&movie_id)
-            && rt_id == role_id
-            && target_note.contains(note.as_str())
-            && let Some(alternative_names) = an_m.get(&person_id)
-            && let Some(character_name) = chn_m.get(&person_role_id)
-            && let Some(name) = n_m.get(&person_id)
-            && let Some(title) = t_m.get(&movie_id)
-        {
-            res = match res {
-                Some((old_alternative_name, old_character_name, old_name, old_title)) => Some((
-                    alternative_names
-                        .iter()
-                        .min()
-                        .unwrap()
-                        .min(&old_alternative_name),
-                    character_name.min(&old_character_name),
-                    name.min(&old_name),
-                    title.min(&old_title),
-                )),
-                None => Some((
-                    alternative_names.iter().min().unwrap(),
-                    character_name,
-                    name,
-                    title,
-                )),
-            };
-        }
-    }
+    timer.phase(Phase::Probe);
+    let res = parallel::probe::<(Min<&str>, Min<&str>, Min<&str>, Min<&str>)>(
+        ci.movie_id.len(),
+        |row, res| {
+            let movie_id = &ci.movie_id[row];
+            let person_id = &ci.person_id[row];
+            let role_id = &ci.role_id[row];
+            let note = &ci.note[row];
+            let person_role_id = &ci.person_role_id[row];
+            if let Some(note) = note
+                && let Some(person_role_id) = person_role_id
+                && mc_s.contains(&movie_id)
+                && rt_id == role_id
+                && target_note.contains(note.as_str())
+                && let Some(alternative_names) = an_m.get(&person_id)
+                && let Some(character_name) = chn_m.get(&person_role_id)
+                && let Some(name) = n_m.get(&person_id)
+                && let Some(title) = t_m.get(&movie_id)
+            {
+                res.update((
+                    *alternative_names.iter().min().unwrap(),
+                    *character_name,
+                    *name,
+                    *title,
+                ));
+            }
+        },
+    )
+    .finish()
+    .transpose();
 
-    println!("9d,{:}", start.elapsed().as_secs_f32());
+    timer.finish();
     Ok(res)
 }
 
-// -- JOB Query 9d
-// SELECT MIN(an.name)  AS alternative_name,
-// MIN(chn.name) AS voiced_char_name,
-// MIN(n.name)   AS voicing_actress,
-// MIN(t.title)  AS american_movie
-// FROM aka_name AS an,
-// char_name AS chn,
-// cast_info AS ci,
-// company_name AS cn,
-// movie_companies AS mc,
-// name AS n,
-// role_type AS rt,
-// title AS t
-// WHERE ci.note in ('(voice)', '(voice: Japanese version)', '(voice) (uncredited)', '(voice: English version)')
-// AND cn.country_code = '[us]'
-// AND n.gender = 'f'
-// AND rt.role = 'actress'
-// AND ci.movie_id = t.id
-// AND t.id = mc.movie_id
-// AND ci.movie_id = mc.movie_id
-// AND mc.company_id = cn.id
-// AND ci.role_id = rt.id
-// AND n.id = ci.person_id
-// AND chn.id = ci.person_role_id
-// AND an.person_id = n.id
-// AND an.person_id = ci.person_id;
-#[cfg(test)]
-mod test_q9d {
-    use super::*;
-    use crate::data::ImdbData;
-
-    #[test]
-    fn test_q9d() -> Result<(), PolarsError> {
-        let db = ImdbData::new();
-        let data = Data::new(&db);
-        let res = q9d(&data)?;
-        assert_eq!(
-            res,
-            Some((
-                "!!!, Toy",
-                "\"Cockamamie's\" Salesgirl",
-                "Aaron, Caroline",
-                "$15,000.00 Error"
-            ))
-        );
-        Ok(())
-    }
-}
+pub const QUERY: Entry = Entry {
+    id: "9d",
+    family: 9,
+    sql: include_str!("../queries/9d.sql"),
+    relations: &[
+        ("an", "aka_name"),
+        ("chn", "char_name"),
+        ("ci", "cast_info"),
+        ("cn", "company_name"),
+        ("mc", "movie_companies"),
+        ("n", "name"),
+        ("rt", "role_type"),
+        ("t", "title"),
+    ],
+    run: |db| Ok(q9d(db)?.into()),
+};
@@ -0,0 +1,259 @@
+use crate::bench::csv_field;
+use crate::data::{Data, ImdbData};
+use crate::predicate::{CmpOp, Scalar};
+use crate::registry::{Answer, Entry};
+use crate::sql::{self, ColumnRef, Query};
+use crate::timer;
+use polars::prelude::*;
+
+/// The name of `alias.column` in the joined frame.
+fn name(column: &ColumnRef) -> String {
+    format!("{}.{}", column.alias, column.column)
+}
+
+fn scalar(value: &Scalar) -> Expr {
+    match value {
+        Scalar::Int(value) => lit(*value),
+        Scalar::Str(value) => lit(*value),
+    }
+}
+
+/// `filter` over the columns of the joined frame. Comparisons with NULL are NULL, which `filter`
+/// drops, as SQL does.
+fn expr(filter: &sql::Expr) -> PolarsResult<Expr> {
+    let column = |column: &ColumnRef| col(name(column));
+    let all = |exprs: &[sql::Expr]| exprs.iter().map(expr).collect::<PolarsResult<Vec<_>>>();
+    Ok(match filter {
+        sql::Expr::Cmp(c, op, value) => {
+            let (c, value) = (column(c), scalar(value));
+            match op {
+                CmpOp::Eq => c.eq(value),
+                CmpOp::Ne => c.neq(value),
+                CmpOp::Lt => c.lt(value),
+                CmpOp::Le => c.lt_eq(value),
+                CmpOp::Gt => c.gt(value),
+                CmpOp::Ge => c.gt_eq(value),
+            }
+        }
+        sql::Expr::Join(left, right) => column(left).eq(column(right)),
+        sql::Expr::Between(c, low, high) => column(c)
+            .gt_eq(scalar(low))
+            .and(column(c).lt_eq(scalar(high))),
+        sql::Expr::In(c, values) => values
+            .iter()
+            .map(|value| column(c).eq(scalar(value)))
+            .reduce(Expr::or)
+            .unwrap_or(lit(false)),
+        sql::Expr::Like(c, pattern) => column(c).str().contains(lit(like_regex(pattern)?), true),
+        sql::Expr::IsNull(c) => column(c).is_null(),
+        sql::Expr::And(exprs) => all(exprs)?.into_iter().reduce(Expr::and).unwrap(),
+        sql::Expr::Or(exprs) => all(exprs)?.into_iter().reduce(Expr::or).unwrap(),
+        sql::Expr::Not(inner) => expr(inner)?.not(),
+    })
+}
+
+/// The regular expression matching what the `LIKE` pattern `pattern` matches, with `\` as the
+/// escape character as for `LikePattern::compile`.
+fn like_regex(pattern: &str) -> PolarsResult<String> {
+    let mut regex = String::from("(?s)^");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '%' => {
+                regex.push_str(".*");
+                continue;
+            }
+            '_' => {
+                regex.push('.');
+                continue;
+            }
+            '\\' => chars.next().ok_or_else(|| {
+                polars_err!(InvalidOperation: "LIKE pattern {pattern:?} ends with the escape character")
+            })?,
+            c => c,
+        };
+        if r"\.+*?()|[]{}^$#&-~".contains(c) {
+            regex.push('\\');
+        }
+        regex.push(c);
+    }
+    regex.push('$');
+    Ok(regex)
+}
+
+/// Runs the JOB query `sql` as a Polars `LazyFrame` plan over the tables of `db`: it filters
+/// every relation, inner joins them on the query's equi-joins, each with the first relation in
+/// the `FROM` clause that joins with the ones before, and takes the `min` of the selected
+/// columns. It shares nothing with the hand-written plans but the SQL parser, which makes it the
+/// reference they are checked against.
+pub fn run(db: &ImdbData, sql: &str) -> PolarsResult<Answer> {
+    let query = Query::parse(sql)?;
+    polars_ensure!(
+        !query.select.is_empty(),
+        SQLInterface: "the oracle needs MIN aggregates to compute"
+    );
+    let mut frames = Vec::with_capacity(query.relations.len());
+    for relation in &query.relations {
+        let df = db
+            .table(relation.table)
+            .ok_or_else(|| polars_err!(SQLInterface: "unknown table {}", relation.table))?;
+        let columns: Vec<Expr> = df
+            .get_column_names()
+            .into_iter()
+            .map(|column| col(column.as_str()).alias(format!("{}.{column}", relation.alias)))
+            .collect();
+        let mut frame = df.clone().lazy().select(columns);
+        for filter in &query.filters {
+            if filter.alias() == Some(relation.alias) {
+                frame = frame.filter(expr(filter)?);
+            }
+        }
+        frames.push(Some(frame));
+    }
+
+    let mut joined = vec![false; frames.len()];
+    joined[0] = true;
+    let mut plan = frames[0].take().unwrap();
+    for _ in 1..frames.len() {
+        // The keys joining relation `r` with the joined ones, the joined side first.
+        let keys = |r: usize| {
+            let keys: Vec<(Expr, Expr)> = query
+                .joins
+                .iter()
+                .filter_map(|(left, right)| {
+                    let (left_r, right_r) = (
+                        query.relation(left.alias).ok()?,
+                        query.relation(right.alias).ok()?,
+                    );
+                    if left_r == r && joined[right_r] {
+                        Some((col(name(right)), col(name(left))))
+                    } else if right_r == r && joined[left_r] {
+                        Some((col(name(left)), col(name(right))))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            keys
+        };
+        let Some(next) = (0..frames.len()).find(|&r| !joined[r] && !keys(r).is_empty()) else {
+            polars_bail!(SQLInterface: "the oracle does not compute cross products");
+        };
+        let (left_on, right_on): (Vec<Expr>, Vec<Expr>) = keys(next).into_iter().unzip();
+        let args = JoinArgs::new(JoinType::Inner).with_coalesce(JoinCoalesce::KeepColumns);
+        plan = plan.join(frames[next].take().unwrap(), left_on, right_on, args);
+        joined[next] = true;
+    }
+
+    let mut aggregates = vec![len().alias("rows")];
+    aggregates.extend(query.select.iter().map(|column| col(name(column)).min()));
+    let result = plan.select(aggregates).collect()?;
+    let columns = result.get_columns();
+    if columns[0].get(0)?.extract::<u64>() == Some(0) {
+        return Ok(Answer(None));
+    }
+    let values = columns[1..]
+        .iter()
+        .map(|column| {
+            Ok(match column.get(0)? {
+                AnyValue::Null => "NULL".to_string(),
+                AnyValue::String(value) => value.to_string(),
+                AnyValue::StringOwned(value) => value.to_string(),
+                value => value.to_string(),
+            })
+        })
+        .collect::<PolarsResult<_>>()?;
+    Ok(Answer(Some(values)))
+}
+
+/// Runs `queries` with their hand-written plans over `data` and with the oracle over `db`, and
+/// prints `query,status,result,oracle` for every query, where the status is `same`, `differ`, or
+/// `failed` if either plan failed, with the error in the place of its result. Returns whether
+/// every query matched.
+pub fn verify(db: &ImdbData, data: &Data, queries: &[&Entry]) -> PolarsResult<bool> {
+    println!("query,status,result,oracle");
+    let mut differences = 0;
+    for query in queries {
+        let (answer, _) = timer::capture(|| (query.run)(data));
+        let reference = run(db, query.sql);
+        let show = |answer: &PolarsResult<Answer>| match answer {
+            Ok(answer) => answer.to_string(),
+            Err(e) => e.to_string(),
+        };
+        let status = match (&answer, &reference) {
+            (Ok(answer), Ok(reference)) if answer == reference => "same",
+            (Ok(_), Ok(_)) => "differ",
+            _ => "failed",
+        };
+        if status != "same" {
+            differences += 1;
+        }
+        println!(
+            "{},{status},{},{}",
+            query.id,
+            csv_field(&show(&answer)),
+            csv_field(&show(&reference))
+        );
+    }
+    eprintln!(
+        "{differences} of {} queries differ from the oracle",
+        queries.len()
+    );
+    Ok(differences == 0)
+}
+
+#[cfg(test)]
+mod test_oracle {
+    use super::*;
+
+    #[test]
+    fn test_like_regex() {
+        assert_eq!(
+            like_regex("%(co-production)%").unwrap(),
+            r"(?s)^.*\(co\-production\).*$"
+        );
+        assert_eq!(like_regex("_a.b").unwrap(), r"(?s)^.a\.b$");
+        assert_eq!(like_regex(r"100\%\_\\%").unwrap(), r"(?s)^100%_\\.*$");
+        assert!(like_regex(r"100\").is_err());
+    }
+
+    #[test]
+    fn test_oracle() -> PolarsResult<()> {
+        let mut db = ImdbData::empty();
+        db.t = df!(
+            "id" => [1, 2, 3, 4],
+            "title" => ["d", "c", "b", "a"],
+            "kind_id" => [Some(1), Some(1), Some(2), None],
+            "production_year" => [Some(2000), None, Some(2010), Some(1990)],
+        )?;
+        db.kt = df!("id" => [1, 2], "kind" => ["movie", "episode"])?;
+        let sql = |condition: &str| {
+            format!(
+                "SELECT MIN(t.title) AS title, MIN(t.production_year) AS year \
+                 FROM title AS t, kind_type AS kt WHERE t.kind_id = kt.id AND {condition}"
+            )
+        };
+        let answer = |values: &[&str]| Answer(Some(values.iter().map(|v| v.to_string()).collect()));
+
+        assert_eq!(run(&db, &sql("kt.kind = 'movie'"))?, answer(&["c", "2000"]));
+        assert_eq!(
+            run(&db, &sql("kt.kind LIKE '%o%' AND NOT t.title IN ('b')"))?,
+            answer(&["c", "2000"])
+        );
+        assert_eq!(
+            run(&db, &sql("t.production_year BETWEEN 1995 AND 2005"))?,
+            answer(&["d", "2000"])
+        );
+        // NULL years neither equal nor differ from anything
+        assert_eq!(
+            run(&db, &sql("t.production_year != 2000"))?,
+            answer(&["b", "2010"])
+        );
+        assert_eq!(
+            run(&db, &sql("t.production_year IS NULL"))?,
+            answer(&["c", "NULL"])
+        );
+        assert_eq!(run(&db, &sql("kt.kind = 'short'"))?, Answer(None));
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use crate::bench::{self, Format};
+use crate::cancel::{self, Outcome, Token};
+use crate::data::Data;
+use crate::enumerate::{Join, bind};
+use crate::registry::QUERIES;
+use crate::sql::Query;
+use ahash::HashSet;
+use polars::prelude::*;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Time allowed to each order unless `--timeout` says otherwise, so that orders with huge
+/// intermediate results cannot stall the exploration.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Seed of the order sampling, fixed so that repeated explorations try the same orders.
+const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Left-deep join orders of `n` relations, as permutations of `0..n`, in which every relation
+/// after the first joins with an earlier one through `edges`, unless none can. Orders with
+/// cross products are left out, as join order studies do: they are never competitive, and
+/// would make up nearly all orders of the larger queries.
+///
+/// Returns all the orders if there are at most `limit`, and otherwise `limit` distinct ones
+/// sampled by repeatedly binding a random relation connected to the bound ones.
+pub fn connected_orders(
+    n: usize,
+    edges: &[(usize, usize)],
+    limit: usize,
+    seed: u64,
+) -> Vec<Vec<usize>> {
+    assert!(n <= 64, "too many relations: {n}");
+    let mut neighbours = vec![0u64; n];
+    for &(a, b) in edges {
+        neighbours[a] |= 1 << b;
+        neighbours[b] |= 1 << a;
+    }
+    // The relations that may be bound next, given the bound ones.
+    let all = u64::MAX.checked_shr(64 - n as u32).unwrap_or(0);
+    let candidates = |bound: u64| {
+        let all = all & !bound;
+        let connected = members(bound).fold(0, |mask, r| mask | neighbours[r]) & !bound;
+        if connected == 0 { all } else { connected }
+    };
+
+    fn extend(
+        order: &mut Vec<usize>,
+        bound: u64,
+        n: usize,
+        limit: usize,
+        candidates: &impl Fn(u64) -> u64,
+        orders: &mut Vec<Vec<usize>>,
+    ) -> bool {
+        if order.len() == n {
+            orders.push(order.clone());
+            return orders.len() <= limit;
+        }
+        for r in members(candidates(bound)) {
+            order.push(r);
+            let complete = extend(order, bound | 1 << r, n, limit, candidates, orders);
+            order.pop();
+            if !complete {
+                return false;
+            }
+        }
+        true
+    }
+
+    let mut orders = Vec::new();
+    if extend(&mut Vec::new(), 0, n, limit, &candidates, &mut orders) {
+        return orders;
+    }
+
+    let mut state = seed | 1;
+    let mut random = |bound: usize| {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % bound as u64) as usize
+    };
+    let mut seen = HashSet::default();
+    orders.clear();
+    for _ in 0..limit.saturating_mul(100) {
+        if orders.len() == limit {
+            break;
+        }
+        let mut order = Vec::with_capacity(n);
+        let mut bound = 0u64;
+        while order.len() < n {
+            let options: Vec<usize> = members(candidates(bound)).collect();
+            let r = options[random(options.len())];
+            order.push(r);
+            bound |= 1 << r;
+        }
+        if seen.insert(order.clone()) {
+            orders.push(order);
+        }
+    }
+    orders
+}
+
+/// The relations in `mask`, in ascending order.
+fn members(mask: u64) -> impl Iterator<Item = usize> {
+    (0..64).filter(move |r| mask & 1 << r != 0)
+}
+
+/// The run of one join order.
+pub struct Run {
+    pub order: Vec<usize>,
+    pub seconds: f32,
+    /// `COUNT(*)` of the join result.
+    pub count: Outcome<u64>,
+}
+
+/// Runs the join of `sql` with the generic enumerator in every order of `connected_orders`,
+/// each under the configured timeout, or `DEFAULT_TIMEOUT` if none is. Returns the aliases of
+/// the relations, which the orders index, and the runs.
+pub fn explore<'a>(
+    db: &Data,
+    sql: &'a str,
+    limit: usize,
+) -> PolarsResult<(Vec<&'a str>, Vec<Run>)> {
+    let query = Query::parse(sql)?;
+    let (selections, edges) = bind(db, &query)?;
+    let pairs: Vec<(usize, usize)> = edges.iter().map(|&(a, _, b, _)| (a, b)).collect();
+    let timeout = cancel::timeout().unwrap_or(DEFAULT_TIMEOUT);
+
+    let mut runs = Vec::new();
+    for order in connected_orders(selections.len(), &pairs, limit, SEED) {
+        let start = Instant::now();
+        let count = cancel::run(Token::with_timeout(timeout), || {
+            let join = Join::with_order(selections.clone(), edges.clone(), &order);
+            let mut count = 0;
+            join.for_each(|_| count += 1);
+            Ok(count)
+        })?;
+        let seconds = start.elapsed().as_secs_f32();
+        runs.push(Run {
+            order,
+            seconds,
+            count,
+        });
+    }
+    let aliases = query.relations.iter().map(|r| r.alias).collect();
+    Ok((aliases, runs))
+}
+
+/// Explores up to `limit` join orders of every query in `QUERIES` with `explore`, and writes
+/// `query,order,seconds,count` rows for every run to `out` in `format` and the distribution of
+/// the runtimes per query, with its best and worst orders, to standard error. Orders are written
+/// as their aliases separated by spaces, and timed out runs have `timeout` for their count.
+/// Fails if two orders of a query count different results.
+pub fn report(db: &Data, limit: usize, out: &mut impl Write, format: Format) -> PolarsResult<()> {
+    let mut rows = Vec::new();
+    let mut summary = Vec::new();
+    for query in QUERIES {
+        let name = query.id;
+        let (aliases, runs) = explore(db, query.sql, limit)?;
+        let show = |order: &[usize]| {
+            let names: Vec<&str> = order.iter().map(|&r| aliases[r]).collect();
+            names.join(" ")
+        };
+        for run in &runs {
+            rows.push([
+                name.to_string(),
+                show(&run.order),
+                run.seconds.to_string(),
+                run.count.to_string(),
+            ]);
+        }
+
+        let mut done: Vec<&Run> = runs
+            .iter()
+            .filter(|run| matches!(run.count, Outcome::Done(_)))
+            .collect();
+        if let Some(first) = done.first()
+            && let Some(other) = done.iter().find(|run| run.count != first.count)
+        {
+            polars_bail!(
+                ComputeError: "{name}: order {} counts {}, order {} counts {}",
+                show(&first.order), first.count, show(&other.order), other.count
+            );
+        }
+        done.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+        let quantile = |q: f64| {
+            let i = ((done.len() - 1) as f64 * q).round() as usize;
+            done[i].seconds.to_string()
+        };
+        let timeouts = runs.len() - done.len();
+        let mut row = vec![
+            name.to_string(),
+            runs.len().to_string(),
+            timeouts.to_string(),
+        ];
+        match done.first() {
+            Some(best) => {
+                row.extend([0.0, 0.25, 0.5, 0.75, 1.0].map(quantile));
+                row.extend([show(&best.order), best.seconds.to_string()]);
+            }
+            None => row.extend(std::iter::repeat_n(String::new(), 7)),
+        }
+        match runs.iter().find(|run| run.count == Outcome::Timeout) {
+            Some(worst) => row.extend([show(&worst.order), "timeout".to_string()]),
+            None => match done.last() {
+                Some(worst) => row.extend([show(&worst.order), worst.seconds.to_string()]),
+                None => row.extend([String::new(), String::new()]),
+            },
+        }
+        summary.push(row);
+        eprintln!("{name}: {} orders, {timeouts} timed out", runs.len());
+    }
+
+    bench::write_rows(out, format, &["query", "order", "seconds", "count"], &rows)?;
+    let header = [
+        "query",
+        "orders",
+        "timeouts",
+        "min_seconds",
+        "p25_seconds",
+        "median_seconds",
+        "p75_seconds",
+        "max_seconds",
+        "best_order",
+        "best_seconds",
+        "worst_order",
+        "worst_seconds",
+    ];
+    bench::table(&mut std::io::stderr().lock(), &header, &summary)
+}
+
+#[cfg(test)]
+mod test_orders {
+    use super::*;
+
+    #[test]
+    fn test_connected_orders() {
+        // a path 0 - 1 - 2
+        let mut orders = connected_orders(3, &[(0, 1), (1, 2)], 100, SEED);
+        orders.sort();
+        assert_eq!(
+            orders,
+            vec![vec![0, 1, 2], vec![1, 0, 2], vec![1, 2, 0], vec![2, 1, 0]]
+        );
+
+        // two components are joined by a cross product only once one is exhausted
+        let orders = connected_orders(4, &[(0, 1), (2, 3)], 100, SEED);
+        assert_eq!(orders.len(), 8);
+
+        // 64 relations fill the masks; a path has 2^63 orders, of which a sample of 5
+        let path: Vec<(usize, usize)> = (1..64).map(|r| (r - 1, r)).collect();
+        let orders = connected_orders(64, &path, 5, SEED);
+        assert_eq!(orders.len(), 5);
+        for order in &orders {
+            let mut sorted = order.clone();
+            sorted.sort();
+            assert_eq!(sorted, (0..64).collect::<Vec<_>>());
+        }
+
+        // a star with 8 leaves has 8! orders starting at the centre and 8 * 7! starting at a
+        // leaf, too many to enumerate with a limit of 50
+        let edges: Vec<(usize, usize)> = (1..9).map(|leaf| (0, leaf)).collect();
+        let orders = connected_orders(9, &edges, 50, SEED);
+        assert_eq!(orders.len(), 50);
+        assert_eq!(orders.iter().collect::<HashSet<_>>().len(), 50);
+        for order in &orders {
+            let mut sorted = order.clone();
+            sorted.sort();
+            assert_eq!(sorted, (0..9).collect::<Vec<_>>());
+            assert!(order[0] == 0 || order[1] == 0, "{order:?}");
+        }
+        assert_eq!(orders, connected_orders(9, &edges, 50, SEED));
+    }
+}
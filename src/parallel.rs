@@ -0,0 +1,366 @@
+use crate::aggregate::Aggregate;
+use crate::bench::{self, Format, Status};
+use crate::cancel::{self, Token};
+use crate::data::Data;
+use crate::registry::Entry;
+use crate::timer::Phase;
+use ahash::{HashMap, HashSet, RandomState};
+use polars::prelude::*;
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Number of threads the parallel operators use, 0 until configured.
+static THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Rows a probing thread claims at a time.
+pub const MORSEL: usize = 16 * 1024;
+
+/// The number of threads the parallel operators use: the value given to `set_threads`, else the
+/// `LFJ_THREADS` environment variable, else the available parallelism.
+pub fn threads() -> usize {
+    match THREADS.load(Ordering::Relaxed) {
+        0 => std::env::var("LFJ_THREADS")
+            .ok()
+            .and_then(|threads| threads.parse().ok())
+            .filter(|&threads| threads > 0)
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get())),
+        threads => threads,
+    }
+}
+
+pub fn set_threads(threads: usize) {
+    assert!(threads > 0, "need at least one thread");
+    THREADS.store(threads, Ordering::Relaxed);
+}
+
+/// The hasher that assigns keys to partitions. It is seeded differently from the `ahash`
+/// defaults the partitions use internally, so keys within a partition still spread evenly.
+fn partitioner() -> RandomState {
+    RandomState::with_seeds(0x243f_6a88, 0x85a3_08d3, 0x1319_8a2e, 0x0370_7344)
+}
+
+/// The partition of `key` among `partitions`, skipping the hash when there is only one.
+#[inline]
+fn partition<Q: Hash + ?Sized>(partitioner: &RandomState, partitions: usize, key: &Q) -> usize {
+    if partitions == 1 {
+        0
+    } else {
+        (partitioner.hash_one(key) % partitions as u64) as usize
+    }
+}
+
+/// Splits `0..len` into `parts` contiguous chunks of nearly equal size.
+fn chunks(len: usize, parts: usize) -> impl Iterator<Item = Range<usize>> {
+    (0..parts).map(move |i| len * i / parts..len * (i + 1) / parts)
+}
+
+/// Runs `scatter` over `0..len` in one chunk per thread, each producing one bucket of items per
+/// partition, then `gather` on each partition's buckets from all threads, one thread per
+/// partition. Stops scattering once the query is cancelled, leaving the partitions partial.
+fn partitioned<T: Send, P: Send>(
+    len: usize,
+    scatter: impl Fn(Range<usize>, &mut [Vec<T>]) + Sync,
+    gather: impl Fn(Vec<Vec<T>>) -> P + Sync,
+) -> Vec<P> {
+    let threads = threads();
+    let token = cancel::current();
+    // The chunk `rows` in morsels, checking the token between them.
+    let scatter_chunk = |rows: Range<usize>, buckets: &mut [Vec<T>]| {
+        for start in rows.clone().step_by(MORSEL) {
+            if token.as_ref().is_some_and(Token::is_cancelled) {
+                break;
+            }
+            scatter(start..(start + MORSEL).min(rows.end), buckets);
+        }
+    };
+    if threads == 1 {
+        let mut bucket = vec![Vec::new()];
+        scatter_chunk(0..len, &mut bucket);
+        return vec![gather(bucket)];
+    }
+
+    let scattered: Vec<Vec<Vec<T>>> = thread::scope(|s| {
+        let handles: Vec<_> = chunks(len, threads)
+            .map(|rows| {
+                let scatter_chunk = &scatter_chunk;
+                s.spawn(move || {
+                    let mut buckets: Vec<Vec<T>> = (0..threads).map(|_| Vec::new()).collect();
+                    scatter_chunk(rows, &mut buckets);
+                    buckets
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let mut partitions: Vec<Vec<Vec<T>>> = (0..threads).map(|_| Vec::new()).collect();
+    for buckets in scattered {
+        for (partition, bucket) in partitions.iter_mut().zip(buckets) {
+            partition.push(bucket);
+        }
+    }
+    thread::scope(|s| {
+        let handles: Vec<_> = partitions
+            .into_iter()
+            .map(|buckets| {
+                let gather = &gather;
+                s.spawn(move || gather(buckets))
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+}
+
+/// A hash set built in parallel, split into partitions by key hash so that each partition is
+/// built by one thread without locking.
+pub struct PartitionedSet<K> {
+    partitions: Vec<HashSet<K>>,
+    partitioner: RandomState,
+}
+
+impl<K: Hash + Eq + Send> PartitionedSet<K> {
+    /// Collects `key(row)` for every row in `0..len` where it is `Some`.
+    pub fn build(len: usize, key: impl Fn(usize) -> Option<K> + Sync) -> Self {
+        let partitioner = partitioner();
+        let partitions = partitioned(
+            len,
+            |rows, buckets| {
+                for row in rows {
+                    if let Some(key) = key(row) {
+                        buckets[partition(&partitioner, buckets.len(), &key)].push(key);
+                    }
+                }
+            },
+            |buckets| buckets.into_iter().flatten().collect(),
+        );
+        PartitionedSet {
+            partitions,
+            partitioner,
+        }
+    }
+
+    /// Whether the set holds `key`, which may be any borrowed form of `K` as for `HashSet`.
+    pub fn contains<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.partitions[partition(&self.partitioner, self.partitions.len(), key)].contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.partitions.iter().map(HashSet::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A hash map built in parallel, partitioned like `PartitionedSet`.
+pub struct PartitionedMap<K, V> {
+    partitions: Vec<HashMap<K, V>>,
+    partitioner: RandomState,
+}
+
+impl<K: Hash + Eq + Send, V: Send> PartitionedMap<K, V> {
+    /// Collects `entry(row)` for every row in `0..len` where it is `Some`, combining the values
+    /// of equal keys with `merge`, in no particular order.
+    pub fn build(
+        len: usize,
+        entry: impl Fn(usize) -> Option<(K, V)> + Sync,
+        merge: impl Fn(&mut V, V) + Sync,
+    ) -> Self {
+        let partitioner = partitioner();
+        let partitions = partitioned(
+            len,
+            |rows, buckets| {
+                for row in rows {
+                    if let Some((key, value)) = entry(row) {
+                        buckets[partition(&partitioner, buckets.len(), &key)].push((key, value));
+                    }
+                }
+            },
+            |buckets| {
+                let mut map: HashMap<K, V> = HashMap::default();
+                for (key, value) in buckets.into_iter().flatten() {
+                    match map.get_mut(&key) {
+                        Some(existing) => merge(existing, value),
+                        None => {
+                            map.insert(key, value);
+                        }
+                    }
+                }
+                map
+            },
+        );
+        PartitionedMap {
+            partitions,
+            partitioner,
+        }
+    }
+
+    /// The value of `key`, which may be any borrowed form of `K` as for `HashMap`.
+    pub fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.partitions[partition(&self.partitioner, self.partitions.len(), key)].get(key)
+    }
+
+    pub fn contains_key<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.partitions.iter().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Runs `probe` on every row in `0..len`, with threads claiming `MORSEL` rows at a time and
+/// accumulating into their own aggregate state, which are merged at the end. Stops claiming
+/// morsels once the query is cancelled, leaving the result partial.
+pub fn probe<A: Aggregate + Send>(len: usize, probe: impl Fn(usize, &mut A) + Sync) -> A {
+    let threads = threads();
+    let mut result = A::default();
+    if threads == 1 {
+        for start in (0..len).step_by(MORSEL) {
+            if cancel::is_cancelled() {
+                break;
+            }
+            for row in start..(start + MORSEL).min(len) {
+                probe(row, &mut result);
+            }
+        }
+        return result;
+    }
+
+    let next = AtomicUsize::new(0);
+    let token = cancel::current();
+    let partials: Vec<A> = thread::scope(|s| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let (next, probe, token) = (&next, &probe, &token);
+                s.spawn(move || {
+                    let mut partial = A::default();
+                    loop {
+                        let start = next.fetch_add(MORSEL, Ordering::Relaxed);
+                        if start >= len || token.as_ref().is_some_and(Token::is_cancelled) {
+                            break partial;
+                        }
+                        for row in start..(start + MORSEL).min(len) {
+                            probe(row, &mut partial);
+                        }
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    for partial in partials {
+        result.merge(partial);
+    }
+    result
+}
+
+/// Runs `queries` with 1 to `max_threads` threads and writes `query,threads,status,seconds,
+/// <phases>` rows to `out` in `format`, with the measured time and the time of every phase in
+/// seconds.
+pub fn sweep(
+    db: &Data,
+    queries: &[&'static Entry],
+    max_threads: usize,
+    out: &mut impl Write,
+    format: Format,
+) -> PolarsResult<()> {
+    let phases: Vec<String> = Phase::ALL.iter().map(Phase::to_string).collect();
+    let mut header = vec!["query", "threads", "status", "seconds"];
+    header.extend(phases.iter().map(String::as_str));
+    let mut rows = Vec::new();
+    for threads in 1..=max_threads {
+        set_threads(threads);
+        for &query in queries {
+            let sample = bench::run_once(db, query, 0);
+            let status = match sample.status {
+                Status::Done(_) => "ok",
+                Status::Timeout => "timeout",
+                Status::Failed(_) => "failed",
+            };
+            let mut row = vec![
+                query.id.to_string(),
+                threads.to_string(),
+                status.to_string(),
+                sample.seconds.map_or(String::new(), |s| s.to_string()),
+            ];
+            row.extend(Phase::ALL.map(|phase| {
+                sample.phases.map_or(String::new(), |phases| {
+                    phases.get(phase).as_secs_f64().to_string()
+                })
+            }));
+            rows.push(row);
+        }
+        eprintln!("{threads} threads done");
+    }
+    bench::write_rows(out, format, &header, &rows)
+}
+
+#[cfg(test)]
+mod test_parallel {
+    use super::*;
+    use crate::aggregate::{Accumulate, CountStar, Min};
+
+    // All tests set the same thread count, since it is process-wide and tests run concurrently.
+    const THREADS: usize = 4;
+
+    #[test]
+    fn test_partitioned_set() {
+        set_threads(THREADS);
+        let values: Vec<i32> = (0..100_000).map(|i| (i * 7919) % 1000).collect();
+        let set = PartitionedSet::build(values.len(), |row| {
+            (values[row] % 3 == 0).then_some(values[row])
+        });
+        assert_eq!(set.len(), 334);
+        for value in 0..1000 {
+            assert_eq!(set.contains(&value), value % 3 == 0, "{value}");
+        }
+    }
+
+    #[test]
+    fn test_partitioned_map() {
+        set_threads(THREADS);
+        let keys: Vec<i32> = (0..50_000).map(|i| i % 100).collect();
+        let map = PartitionedMap::build(
+            keys.len(),
+            |row| Some((keys[row], row)),
+            |min, row| *min = (*min).min(row),
+        );
+        assert_eq!(map.len(), 100);
+        for key in 0..100 {
+            assert_eq!(map.get(&key), Some(&(key as usize)));
+        }
+        assert_eq!(map.get(&100), None);
+    }
+
+    #[test]
+    fn test_probe() {
+        set_threads(THREADS);
+        let values: Vec<i32> = (0..(3 * MORSEL as i32 + 17)).rev().collect();
+        let (min, count) = probe::<(Min<i32>, CountStar)>(values.len(), |row, acc| {
+            if values[row] % 2 == 1 {
+                acc.update((values[row], ()));
+            }
+        });
+        assert_eq!(min.finish(), Some(1));
+        assert_eq!(count.finish(), values.len() as u64 / 2);
+    }
+}
@@ -0,0 +1,749 @@
+use crate::like::LikePattern;
+use std::ops::{BitAndAssign, BitOrAssign, Not};
+
+/// A set of row positions in a column, stored as one bit per row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Selection {
+    /// Selects none of `len` rows.
+    pub fn empty(len: usize) -> Self {
+        Selection {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    /// Selects all of `len` rows.
+    pub fn full(len: usize) -> Self {
+        !Selection::empty(len)
+    }
+
+    /// Selects every row `i` of `values` for which `f(&values[i])` holds.
+    ///
+    /// Rows are processed 64 at a time into one word so that simple predicates over integer
+    /// columns compile to branch-free, vectorizable loops.
+    pub fn from_values<T>(values: &[T], f: impl Fn(&T) -> bool) -> Self {
+        let mut words = Vec::with_capacity(values.len().div_ceil(64));
+        let mut chunks = values.chunks_exact(64);
+        for chunk in &mut chunks {
+            let mut word = 0;
+            for (j, v) in chunk.iter().enumerate() {
+                word |= (f(v) as u64) << j;
+            }
+            words.push(word);
+        }
+        let remainder = chunks.remainder();
+        if !remainder.is_empty() {
+            let mut word = 0;
+            for (j, v) in remainder.iter().enumerate() {
+                word |= (f(v) as u64) << j;
+            }
+            words.push(word);
+        }
+        Selection {
+            words,
+            len: values.len(),
+        }
+    }
+
+    /// Number of rows the selection ranges over, selected or not.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of selected rows.
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn contains(&self, row: usize) -> bool {
+        row < self.len && self.words[row / 64] & (1 << (row % 64)) != 0
+    }
+
+    pub fn insert(&mut self, row: usize) {
+        assert!(
+            row < self.len,
+            "row {row} out of range for {} rows",
+            self.len
+        );
+        self.words[row / 64] |= 1 << (row % 64);
+    }
+
+    /// Iterates over the selected rows in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                (word != 0).then(|| {
+                    let j = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    i * 64 + j
+                })
+            })
+        })
+    }
+
+    /// Deselects the selected rows for which `f` does not hold.
+    pub fn retain(&mut self, mut f: impl FnMut(usize) -> bool) {
+        for (i, word) in self.words.iter_mut().enumerate() {
+            let mut rest = *word;
+            while rest != 0 {
+                let j = rest.trailing_zeros() as usize;
+                rest &= rest - 1;
+                if !f(i * 64 + j) {
+                    *word &= !(1 << j);
+                }
+            }
+        }
+    }
+
+    /// The selected rows as a selection vector.
+    pub fn to_indices(&self) -> Vec<u32> {
+        let mut indices = Vec::with_capacity(self.count());
+        indices.extend(self.iter().map(|row| row as u32));
+        indices
+    }
+}
+
+impl BitAndAssign<&Selection> for Selection {
+    fn bitand_assign(&mut self, rhs: &Selection) {
+        assert_eq!(self.len, rhs.len, "selections over different row counts");
+        for (a, b) in self.words.iter_mut().zip(rhs.words.iter()) {
+            *a &= b;
+        }
+    }
+}
+
+impl BitOrAssign<&Selection> for Selection {
+    fn bitor_assign(&mut self, rhs: &Selection) {
+        assert_eq!(self.len, rhs.len, "selections over different row counts");
+        for (a, b) in self.words.iter_mut().zip(rhs.words.iter()) {
+            *a |= b;
+        }
+    }
+}
+
+impl Not for Selection {
+    type Output = Selection;
+
+    fn not(mut self) -> Selection {
+        for word in self.words.iter_mut() {
+            *word = !*word;
+        }
+        if !self.len.is_multiple_of(64)
+            && let Some(last) = self.words.last_mut()
+        {
+            *last &= (1 << (self.len % 64)) - 1;
+        }
+        self
+    }
+}
+
+/// A column of a [`Data`](crate::data::Data) relation that predicates range over.
+#[derive(Clone, Copy, Debug)]
+pub enum Column<'a> {
+    Int(&'a [i32]),
+    NullableInt(&'a [Option<i32>]),
+    Str(&'a [String]),
+    NullableStr(&'a [Option<String>]),
+}
+
+impl Column<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            Column::Int(values) => values.len(),
+            Column::NullableInt(values) => values.len(),
+            Column::Str(values) => values.len(),
+            Column::NullableStr(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Rows that are not NULL.
+    fn valid(&self) -> Selection {
+        match self {
+            Column::NullableInt(values) => Selection::from_values(values, Option::is_some),
+            Column::NullableStr(values) => Selection::from_values(values, Option::is_some),
+            _ => Selection::full(self.len()),
+        }
+    }
+}
+
+impl<'a> From<&'a Vec<i32>> for Column<'a> {
+    fn from(values: &'a Vec<i32>) -> Self {
+        Column::Int(values)
+    }
+}
+
+impl<'a> From<&'a Vec<Option<i32>>> for Column<'a> {
+    fn from(values: &'a Vec<Option<i32>>) -> Self {
+        Column::NullableInt(values)
+    }
+}
+
+impl<'a> From<&'a Vec<String>> for Column<'a> {
+    fn from(values: &'a Vec<String>) -> Self {
+        Column::Str(values)
+    }
+}
+
+impl<'a> From<&'a Vec<Option<String>>> for Column<'a> {
+    fn from(values: &'a Vec<Option<String>>) -> Self {
+        Column::NullableStr(values)
+    }
+}
+
+/// A constant that a column is compared against.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scalar<'a> {
+    Int(i32),
+    Str(&'a str),
+}
+
+impl From<i32> for Scalar<'_> {
+    fn from(value: i32) -> Self {
+        Scalar::Int(value)
+    }
+}
+
+impl<'a> From<&'a str> for Scalar<'a> {
+    fn from(value: &'a str) -> Self {
+        Scalar::Str(value)
+    }
+}
+
+impl Scalar<'_> {
+    fn int(&self) -> i32 {
+        match self {
+            Scalar::Int(value) => *value,
+            Scalar::Str(value) => panic!("comparing an integer column with {value:?}"),
+        }
+    }
+
+    fn str(&self) -> &str {
+        match self {
+            Scalar::Str(value) => value,
+            Scalar::Int(value) => panic!("comparing a string column with {value}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn apply<T: PartialOrd + ?Sized>(self, a: &T, b: &T) -> bool {
+        match self {
+            CmpOp::Eq => a == b,
+            CmpOp::Ne => a != b,
+            CmpOp::Lt => a < b,
+            CmpOp::Le => a <= b,
+            CmpOp::Gt => a > b,
+            CmpOp::Ge => a >= b,
+        }
+    }
+}
+
+/// A filter over the columns of one relation, evaluated a whole column at a time.
+///
+/// Comparisons follow SQL semantics: a comparison with a NULL is unknown, which neither `eval`
+/// nor its negation selects, so `NOT (t.production_year > 2000)` does not select rows whose
+/// `production_year` is NULL.
+#[derive(Clone, Debug)]
+pub enum Pred<'a> {
+    Cmp(Column<'a>, CmpOp, Scalar<'a>),
+    /// `BETWEEN lo AND hi`, inclusive on both ends.
+    Between(Column<'a>, Scalar<'a>, Scalar<'a>),
+    In(Column<'a>, Vec<Scalar<'a>>),
+    Like(Column<'a>, LikePattern),
+    IsNull(Column<'a>),
+    And(Vec<Pred<'a>>),
+    Or(Vec<Pred<'a>>),
+    Not(Box<Pred<'a>>),
+}
+
+impl<'a> Pred<'a> {
+    pub fn cmp(column: impl Into<Column<'a>>, op: CmpOp, value: impl Into<Scalar<'a>>) -> Self {
+        Pred::Cmp(column.into(), op, value.into())
+    }
+
+    pub fn eq(column: impl Into<Column<'a>>, value: impl Into<Scalar<'a>>) -> Self {
+        Pred::cmp(column, CmpOp::Eq, value)
+    }
+
+    pub fn ne(column: impl Into<Column<'a>>, value: impl Into<Scalar<'a>>) -> Self {
+        Pred::cmp(column, CmpOp::Ne, value)
+    }
+
+    pub fn lt(column: impl Into<Column<'a>>, value: impl Into<Scalar<'a>>) -> Self {
+        Pred::cmp(column, CmpOp::Lt, value)
+    }
+
+    pub fn le(column: impl Into<Column<'a>>, value: impl Into<Scalar<'a>>) -> Self {
+        Pred::cmp(column, CmpOp::Le, value)
+    }
+
+    pub fn gt(column: impl Into<Column<'a>>, value: impl Into<Scalar<'a>>) -> Self {
+        Pred::cmp(column, CmpOp::Gt, value)
+    }
+
+    pub fn ge(column: impl Into<Column<'a>>, value: impl Into<Scalar<'a>>) -> Self {
+        Pred::cmp(column, CmpOp::Ge, value)
+    }
+
+    pub fn between(
+        column: impl Into<Column<'a>>,
+        lo: impl Into<Scalar<'a>>,
+        hi: impl Into<Scalar<'a>>,
+    ) -> Self {
+        Pred::Between(column.into(), lo.into(), hi.into())
+    }
+
+    pub fn is_in<S: Into<Scalar<'a>>>(
+        column: impl Into<Column<'a>>,
+        values: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Pred::In(column.into(), values.into_iter().map(Into::into).collect())
+    }
+
+    pub fn like(column: impl Into<Column<'a>>, pattern: LikePattern) -> Self {
+        Pred::Like(column.into(), pattern)
+    }
+
+    pub fn is_null(column: impl Into<Column<'a>>) -> Self {
+        Pred::IsNull(column.into())
+    }
+
+    pub fn is_not_null(column: impl Into<Column<'a>>) -> Self {
+        !Pred::is_null(column)
+    }
+
+    pub fn and(self, other: Pred<'a>) -> Self {
+        match self {
+            Pred::And(mut preds) => {
+                preds.push(other);
+                Pred::And(preds)
+            }
+            pred => Pred::And(vec![pred, other]),
+        }
+    }
+
+    pub fn or(self, other: Pred<'a>) -> Self {
+        match self {
+            Pred::Or(mut preds) => {
+                preds.push(other);
+                Pred::Or(preds)
+            }
+            pred => Pred::Or(vec![pred, other]),
+        }
+    }
+
+    /// Number of rows the predicate ranges over.
+    ///
+    /// # Panics
+    ///
+    /// Panics on an empty `And` or `Or`, which have no columns to take the length from.
+    pub fn len(&self) -> usize {
+        match self {
+            Pred::Cmp(column, _, _)
+            | Pred::Between(column, _, _)
+            | Pred::In(column, _)
+            | Pred::Like(column, _)
+            | Pred::IsNull(column) => column.len(),
+            Pred::And(preds) | Pred::Or(preds) => preds[0].len(),
+            Pred::Not(pred) => pred.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Selects the rows for which the predicate is true.
+    pub fn eval(&self) -> Selection {
+        match self {
+            Pred::Cmp(column, op, value) => eval_cmp(column, *op, value),
+            Pred::Between(column, lo, hi) => eval_between(column, lo, hi),
+            Pred::In(column, values) => eval_in(column, values),
+            Pred::Like(column, pattern) => eval_like(column, pattern),
+            Pred::IsNull(column) => match column {
+                Column::NullableInt(values) => Selection::from_values(values, Option::is_none),
+                Column::NullableStr(values) => Selection::from_values(values, Option::is_none),
+                _ => Selection::empty(column.len()),
+            },
+            Pred::And(preds) => {
+                // Intersect the cheap integer predicates a column at a time, then test the string
+                // predicates only on the rows that are still selected.
+                let (batch, row_wise): (Vec<_>, Vec<_>) =
+                    preds.iter().partition(|pred| pred.is_int_only());
+                let mut row_wise = row_wise.into_iter();
+                let mut sel = match batch.split_first() {
+                    Some((first, _)) => first.eval(),
+                    None => row_wise.next().expect("empty AND").eval(),
+                };
+                for pred in batch.iter().skip(1) {
+                    sel &= &pred.eval();
+                }
+                for pred in row_wise {
+                    sel.retain(|row| pred.eval_row(row) == Some(true));
+                }
+                sel
+            }
+            Pred::Or(preds) => {
+                let mut preds = preds.iter();
+                let mut sel = preds.next().expect("empty OR").eval();
+                for pred in preds {
+                    sel |= &pred.eval();
+                }
+                sel
+            }
+            Pred::Not(pred) => pred.eval_false(),
+        }
+    }
+
+    /// Whether every column the predicate reads is an integer column.
+    fn is_int_only(&self) -> bool {
+        match self {
+            Pred::Cmp(column, _, _)
+            | Pred::Between(column, _, _)
+            | Pred::In(column, _)
+            | Pred::Like(column, _)
+            | Pred::IsNull(column) => matches!(column, Column::Int(_) | Column::NullableInt(_)),
+            Pred::And(preds) | Pred::Or(preds) => preds.iter().all(Pred::is_int_only),
+            Pred::Not(pred) => pred.is_int_only(),
+        }
+    }
+
+    /// Evaluates the predicate on a single row, with `None` for unknown.
+    fn eval_row(&self, row: usize) -> Option<bool> {
+        match self {
+            Pred::Cmp(column, op, value) => match (column, value) {
+                (Column::Int(values), Scalar::Int(x)) => Some(op.apply(&values[row], x)),
+                (Column::NullableInt(values), Scalar::Int(x)) => {
+                    values[row].map(|v| op.apply(&v, x))
+                }
+                (Column::Str(values), Scalar::Str(x)) => Some(op.apply(values[row].as_str(), x)),
+                (Column::NullableStr(values), Scalar::Str(x)) => {
+                    values[row].as_deref().map(|v| op.apply(v, x))
+                }
+                _ => panic!("comparing {column:?} with {value:?}"),
+            },
+            Pred::Between(column, lo, hi) => match (column, lo, hi) {
+                (Column::Int(values), Scalar::Int(lo), Scalar::Int(hi)) => {
+                    Some((lo..=hi).contains(&&values[row]))
+                }
+                (Column::NullableInt(values), Scalar::Int(lo), Scalar::Int(hi)) => {
+                    values[row].map(|v| (*lo..=*hi).contains(&v))
+                }
+                (Column::Str(values), Scalar::Str(lo), Scalar::Str(hi)) => {
+                    Some((*lo..=*hi).contains(&values[row].as_str()))
+                }
+                (Column::NullableStr(values), Scalar::Str(lo), Scalar::Str(hi)) => {
+                    values[row].as_deref().map(|v| (*lo..=*hi).contains(&v))
+                }
+                _ => panic!("comparing {column:?} with {lo:?} and {hi:?}"),
+            },
+            Pred::In(column, values) => match column {
+                Column::Int(column) => Some(values.contains(&Scalar::Int(column[row]))),
+                Column::NullableInt(column) => {
+                    column[row].map(|v| values.contains(&Scalar::Int(v)))
+                }
+                Column::Str(column) => Some(values.contains(&Scalar::Str(&column[row]))),
+                Column::NullableStr(column) => column[row]
+                    .as_deref()
+                    .map(|v| values.contains(&Scalar::Str(v))),
+            },
+            Pred::Like(column, pattern) => match column {
+                Column::Str(values) => Some(pattern.matches(values[row].as_bytes())),
+                Column::NullableStr(values) => values[row]
+                    .as_deref()
+                    .map(|v| pattern.matches(v.as_bytes())),
+                _ => panic!("LIKE on an integer column"),
+            },
+            Pred::IsNull(column) => Some(match column {
+                Column::NullableInt(values) => values[row].is_none(),
+                Column::NullableStr(values) => values[row].is_none(),
+                _ => false,
+            }),
+            Pred::And(preds) => {
+                let mut result = Some(true);
+                for pred in preds {
+                    match pred.eval_row(row) {
+                        Some(false) => return Some(false),
+                        None => result = None,
+                        Some(true) => {}
+                    }
+                }
+                result
+            }
+            Pred::Or(preds) => {
+                let mut result = Some(false);
+                for pred in preds {
+                    match pred.eval_row(row) {
+                        Some(true) => return Some(true),
+                        None => result = None,
+                        Some(false) => {}
+                    }
+                }
+                result
+            }
+            Pred::Not(pred) => pred.eval_row(row).map(|v| !v),
+        }
+    }
+
+    /// Selects the rows for which the predicate is false, as opposed to true or unknown.
+    fn eval_false(&self) -> Selection {
+        match self {
+            Pred::Cmp(column, op, value) => {
+                let negated = match op {
+                    CmpOp::Eq => CmpOp::Ne,
+                    CmpOp::Ne => CmpOp::Eq,
+                    CmpOp::Lt => CmpOp::Ge,
+                    CmpOp::Le => CmpOp::Gt,
+                    CmpOp::Gt => CmpOp::Le,
+                    CmpOp::Ge => CmpOp::Lt,
+                };
+                eval_cmp(column, negated, value)
+            }
+            Pred::Between(column, _, _) | Pred::In(column, _) | Pred::Like(column, _) => {
+                let mut sel = !self.eval();
+                sel &= &column.valid();
+                sel
+            }
+            Pred::IsNull(column) => column.valid(),
+            Pred::And(preds) => Pred::Or(preds.iter().map(|p| !p.clone()).collect()).eval(),
+            Pred::Or(preds) => Pred::And(preds.iter().map(|p| !p.clone()).collect()).eval(),
+            Pred::Not(pred) => pred.eval(),
+        }
+    }
+}
+
+impl<'a> Not for Pred<'a> {
+    type Output = Pred<'a>;
+
+    fn not(self) -> Pred<'a> {
+        match self {
+            Pred::Not(pred) => *pred,
+            pred => Pred::Not(Box::new(pred)),
+        }
+    }
+}
+
+fn eval_cmp(column: &Column, op: CmpOp, value: &Scalar) -> Selection {
+    match column {
+        Column::Int(values) => {
+            let x = value.int();
+            // One loop per operator, so that each compiles to a tight vectorized comparison.
+            match op {
+                CmpOp::Eq => Selection::from_values(values, |&v| v == x),
+                CmpOp::Ne => Selection::from_values(values, |&v| v != x),
+                CmpOp::Lt => Selection::from_values(values, |&v| v < x),
+                CmpOp::Le => Selection::from_values(values, |&v| v <= x),
+                CmpOp::Gt => Selection::from_values(values, |&v| v > x),
+                CmpOp::Ge => Selection::from_values(values, |&v| v >= x),
+            }
+        }
+        Column::NullableInt(values) => {
+            let x = value.int();
+            match op {
+                CmpOp::Eq => Selection::from_values(values, |v| v.is_some_and(|v| v == x)),
+                CmpOp::Ne => Selection::from_values(values, |v| v.is_some_and(|v| v != x)),
+                CmpOp::Lt => Selection::from_values(values, |v| v.is_some_and(|v| v < x)),
+                CmpOp::Le => Selection::from_values(values, |v| v.is_some_and(|v| v <= x)),
+                CmpOp::Gt => Selection::from_values(values, |v| v.is_some_and(|v| v > x)),
+                CmpOp::Ge => Selection::from_values(values, |v| v.is_some_and(|v| v >= x)),
+            }
+        }
+        Column::Str(values) => {
+            let x = value.str();
+            Selection::from_values(values, |v| op.apply(v.as_str(), x))
+        }
+        Column::NullableStr(values) => {
+            let x = value.str();
+            Selection::from_values(values, |v| v.as_deref().is_some_and(|v| op.apply(v, x)))
+        }
+    }
+}
+
+fn eval_between(column: &Column, lo: &Scalar, hi: &Scalar) -> Selection {
+    match column {
+        Column::Int(values) => {
+            let (lo, hi) = (lo.int(), hi.int());
+            Selection::from_values(values, |&v| v >= lo && v <= hi)
+        }
+        Column::NullableInt(values) => {
+            let (lo, hi) = (lo.int(), hi.int());
+            Selection::from_values(values, |v| v.is_some_and(|v| v >= lo && v <= hi))
+        }
+        Column::Str(values) => {
+            let (lo, hi) = (lo.str(), hi.str());
+            Selection::from_values(values, |v| lo <= v.as_str() && v.as_str() <= hi)
+        }
+        Column::NullableStr(values) => {
+            let (lo, hi) = (lo.str(), hi.str());
+            Selection::from_values(values, |v| v.as_deref().is_some_and(|v| lo <= v && v <= hi))
+        }
+    }
+}
+
+fn eval_in(column: &Column, values: &[Scalar]) -> Selection {
+    match column {
+        Column::Int(column) => {
+            let list: Vec<i32> = values.iter().map(Scalar::int).collect();
+            Selection::from_values(column, |v| list.contains(v))
+        }
+        Column::NullableInt(column) => {
+            let list: Vec<i32> = values.iter().map(Scalar::int).collect();
+            Selection::from_values(column, |v| v.is_some_and(|v| list.contains(&v)))
+        }
+        Column::Str(column) => {
+            let list: Vec<&str> = values.iter().map(Scalar::str).collect();
+            Selection::from_values(column, |v| list.contains(&v.as_str()))
+        }
+        Column::NullableStr(column) => {
+            let list: Vec<&str> = values.iter().map(Scalar::str).collect();
+            Selection::from_values(column, |v| v.as_deref().is_some_and(|v| list.contains(&v)))
+        }
+    }
+}
+
+fn eval_like(column: &Column, pattern: &LikePattern) -> Selection {
+    match column {
+        Column::Str(values) => Selection::from_values(values, |v| pattern.matches(v.as_bytes())),
+        Column::NullableStr(values) => Selection::from_values(values, |v| {
+            v.as_deref().is_some_and(|v| pattern.matches(v.as_bytes()))
+        }),
+        _ => panic!("LIKE on an integer column"),
+    }
+}
+
+#[cfg(test)]
+mod test_predicate {
+    use super::*;
+
+    fn rows(sel: &Selection) -> Vec<usize> {
+        sel.iter().collect()
+    }
+
+    #[test]
+    fn test_selection() {
+        let sel = Selection::from_values(&(0..130).collect::<Vec<i32>>(), |v| v % 3 == 0);
+        assert_eq!(sel.len(), 130);
+        assert_eq!(sel.count(), 44);
+        assert!(sel.contains(129));
+        assert!(!sel.contains(128));
+        assert_eq!(sel.to_indices()[..4], [0, 3, 6, 9]);
+
+        let inverted = !sel.clone();
+        assert_eq!(inverted.count(), 130 - 44);
+        assert_eq!(Selection::full(130).count(), 130);
+
+        let mut both = sel.clone();
+        both &= &inverted;
+        assert_eq!(both, Selection::empty(130));
+        let mut either = sel;
+        either |= &inverted;
+        assert_eq!(either, Selection::full(130));
+    }
+
+    #[test]
+    fn test_pred_int() {
+        let kind_id = vec![1, 2, 7, 1, 3];
+        let production_year = vec![Some(2004), Some(2005), None, Some(2010), Some(2011)];
+
+        let pred = Pred::between(&production_year, 2005, 2010).and(Pred::eq(&kind_id, 1));
+        assert_eq!(rows(&pred.eval()), [3]);
+        assert_eq!(rows(&Pred::is_in(&kind_id, [1, 7]).eval()), [0, 2, 3]);
+        assert_eq!(rows(&Pred::gt(&production_year, 2005).eval()), [3, 4]);
+        assert_eq!(rows(&Pred::is_null(&production_year).eval()), [2]);
+        assert_eq!(
+            rows(&Pred::is_not_null(&production_year).eval()),
+            [0, 1, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_pred_null_semantics() {
+        let production_year = vec![Some(1999), None, Some(2001)];
+        let kind_id = vec![1, 1, 2];
+
+        let gt = Pred::gt(&production_year, 2000);
+        assert_eq!(rows(&gt.eval()), [2]);
+        assert_eq!(rows(&(!gt.clone()).eval()), [0]);
+        assert_eq!(rows(&(!!gt.clone()).eval()), [2]);
+
+        // NOT (year > 2000 AND kind_id = 1): row 1 is unknown AND true = unknown
+        let and = gt.clone().and(Pred::eq(&kind_id, 1));
+        assert_eq!(rows(&(!and).eval()), [0, 2]);
+        // NOT (year > 2000 OR kind_id = 2): row 1 is unknown OR false = unknown
+        let or = gt.or(Pred::eq(&kind_id, 2));
+        assert_eq!(rows(&(!or).eval()), [0]);
+    }
+
+    #[test]
+    fn test_pred_row_wise() {
+        let kind_id = vec![1, 1, 2, 1];
+        let production_year = vec![Some(2001), None, Some(2005), Some(1999)];
+        let title = vec![
+            "Murder".to_string(),
+            "Murder 2".to_string(),
+            "Mord".to_string(),
+            "Sky".to_string(),
+        ];
+        let preds = [
+            Pred::like(&title, LikePattern::compile("%Murder%").unwrap())
+                .and(Pred::eq(&kind_id, 1)),
+            Pred::like(&title, LikePattern::compile("%Mord%").unwrap())
+                .or(Pred::lt(&production_year, 2000))
+                .and(Pred::is_in(&kind_id, [1, 2])),
+            !(Pred::ge(&title, "Murder 2").or(Pred::gt(&production_year, 2000)))
+                .and(Pred::ne(&kind_id, 2)),
+        ];
+        for pred in preds {
+            let row_wise: Vec<usize> = (0..pred.len())
+                .filter(|&row| pred.eval_row(row) == Some(true))
+                .collect();
+            assert_eq!(rows(&pred.eval()), row_wise, "{pred:?}");
+        }
+    }
+
+    #[test]
+    fn test_pred_str() {
+        let info = vec!["2.5".to_string(), "8.7".to_string(), "3.0".to_string()];
+        let note = vec![
+            Some("(2006) (USA)".to_string()),
+            None,
+            Some("(TV)".to_string()),
+        ];
+
+        assert_eq!(rows(&Pred::lt(&info, "3.0").eval()), [0]);
+        assert_eq!(rows(&Pred::is_in(&info, ["8.7", "3.0"]).eval()), [1, 2]);
+        assert_eq!(
+            rows(&Pred::like(&note, LikePattern::compile("%(USA)%").unwrap()).eval()),
+            [0]
+        );
+        assert_eq!(
+            rows(&(!Pred::like(&note, LikePattern::compile("%(USA)%").unwrap())).eval()),
+            [2]
+        );
+        assert_eq!(rows(&Pred::between(&info, "2.0", "3.0").eval()), [0, 2]);
+    }
+}
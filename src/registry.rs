@@ -0,0 +1,213 @@
+use crate::data::Data;
+use polars::prelude::*;
+use std::fmt::{self, Display};
+
+/// A JOB query. Each query module declares its entry as `QUERY`, and build.rs collects the
+/// entries. Adding a query takes its module `src/o<id>.rs`, a `pub mod` line for it in lib.rs, its
+/// SQL in `queries/<id>.sql`, its answer in `queries/golden.json` from `--update-golden`, and its
+/// count in `queries/counts.csv` from `queries/counts.sh`.
+#[derive(Clone, Copy)]
+pub struct Entry {
+    /// `29a`
+    pub id: &'static str,
+    /// `29` for `29a`
+    pub family: u32,
+    pub sql: &'static str,
+    /// `(alias, table)` of the relations in the `FROM` clause.
+    pub relations: &'static [(&'static str, &'static str)],
+    pub run: fn(&Data) -> PolarsResult<Answer>,
+}
+
+/// Every JOB query, in the order of their ids.
+pub static QUERIES: &[Entry] = include!(concat!(env!("OUT_DIR"), "/entries.rs"));
+
+/// The operators with variants to compare, `Filter`, `GraceTable`, `I32Map` and `I32Set`, that
+/// each query's module names, by query id. build.rs finds them in the sources.
+static USES: &[(&str, &[&str])] = include!(concat!(env!("OUT_DIR"), "/uses.rs"));
+
+impl Entry {
+    /// Whether the query's module uses the operator `operator`, e.g. `GraceTable`.
+    pub fn uses(&self, operator: &str) -> bool {
+        USES.iter()
+            .any(|(id, operators)| *id == self.id && operators.contains(&operator))
+    }
+}
+
+/// The query with the id `id`.
+pub fn get(id: &str) -> PolarsResult<&'static Entry> {
+    QUERIES
+        .iter()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| polars_err!(InvalidOperation: "no query {id}"))
+}
+
+/// The result of a query: the values of its `MIN` columns, or `None` if no row qualifies and
+/// they are all NULL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Answer(pub Option<Vec<String>>);
+
+/// The `MIN` columns of a result row.
+pub trait Row {
+    fn values(&self) -> Vec<String>;
+}
+
+impl Row for &str {
+    fn values(&self) -> Vec<String> {
+        vec![self.to_string()]
+    }
+}
+
+impl Row for &i32 {
+    fn values(&self) -> Vec<String> {
+        vec![self.to_string()]
+    }
+}
+
+macro_rules! row {
+    ($($column:ident),+) => {
+        impl<$($column: Row),+> Row for ($($column,)+) {
+            #[allow(non_snake_case)]
+            fn values(&self) -> Vec<String> {
+                let ($($column,)+) = self;
+                [$($column.values()),+].concat()
+            }
+        }
+    };
+}
+
+row!(A, B);
+row!(A, B, C);
+row!(A, B, C, D);
+row!(A, B, C, D, E, F);
+
+impl<R: Row> From<Option<R>> for Answer {
+    fn from(row: Option<R>) -> Self {
+        Answer(row.map(|row| row.values()))
+    }
+}
+
+impl Display for Answer {
+    /// `NULL`, or the values quoted and separated by commas.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            None => write!(f, "NULL"),
+            Some(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{value:?}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Whether `pattern` selects the query `id`: a family like `17` selects `17a` to `17f`, and
+/// anything else is a glob where `*` matches any characters and `?` a single one.
+pub fn matches(pattern: &str, id: &str) -> bool {
+    if !pattern.is_empty() && pattern.bytes().all(|b| b.is_ascii_digit()) {
+        return id.trim_end_matches(|c: char| c.is_ascii_alphabetic()) == pattern;
+    }
+    fn glob(pattern: &[u8], text: &[u8]) -> bool {
+        match (pattern.first(), text.first()) {
+            (None, _) => text.is_empty(),
+            (Some(b'*'), _) => {
+                glob(&pattern[1..], text) || (!text.is_empty() && glob(pattern, &text[1..]))
+            }
+            (Some(b'?'), Some(_)) => glob(&pattern[1..], &text[1..]),
+            (Some(p), Some(t)) if p == t => glob(&pattern[1..], &text[1..]),
+            _ => false,
+        }
+    }
+    glob(pattern.as_bytes(), id.as_bytes())
+}
+
+/// The queries that any of `patterns` selects, in the order of `QUERIES`, or all of them if there
+/// are no patterns. Fails if a pattern selects nothing, which is most likely a typo.
+pub fn select(patterns: &[String]) -> PolarsResult<Vec<&'static Entry>> {
+    for pattern in patterns {
+        polars_ensure!(
+            QUERIES.iter().any(|entry| matches(pattern, entry.id)),
+            InvalidOperation: "no query matches {pattern}"
+        );
+    }
+    Ok(QUERIES
+        .iter()
+        .filter(|entry| patterns.is_empty() || patterns.iter().any(|p| matches(p, entry.id)))
+        .collect())
+}
+
+#[cfg(test)]
+mod test_registry {
+    use super::*;
+    use crate::sql::{Query, job_queries};
+
+    #[test]
+    fn test_entries() -> PolarsResult<()> {
+        let files = job_queries()?;
+        assert_eq!(QUERIES.len(), files.len());
+        for (entry, (name, sql)) in QUERIES.iter().zip(&files) {
+            assert_eq!(entry.id, name);
+            assert_eq!(entry.sql, sql);
+            assert!(matches(&entry.family.to_string(), entry.id), "{}", entry.id);
+            let relations: Vec<(&str, &str)> = Query::parse(entry.sql)?
+                .relations
+                .iter()
+                .map(|relation| (relation.alias, relation.table))
+                .collect();
+            assert_eq!(entry.relations, relations, "{}", entry.id);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_uses() -> PolarsResult<()> {
+        let using = |operator| -> Vec<&str> {
+            QUERIES
+                .iter()
+                .filter(|entry| entry.uses(operator))
+                .map(|entry| entry.id)
+                .collect()
+        };
+        assert_eq!(using("GraceTable"), ["2a"]);
+        assert_eq!(using("Filter"), ["17b", "17c"]);
+        assert_eq!(
+            using("I32Set"),
+            [
+                "15a", "15b", "15c", "15d", "17d", "17e", "17f", "23a", "23b", "23c"
+            ]
+        );
+        assert!(using("I32Map").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_select() -> PolarsResult<()> {
+        assert_eq!(QUERIES.len(), 113);
+        let ids = |patterns: &[&str]| -> PolarsResult<Vec<&str>> {
+            let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+            Ok(select(&patterns)?
+                .into_iter()
+                .map(|entry| entry.id)
+                .collect())
+        };
+        // 1a-1d, 10a-19d and 33c
+        assert_eq!(ids(&["33c", "1*"])?.len(), 43);
+        assert_eq!(ids(&["17"])?, ["17a", "17b", "17c", "17d", "17e", "17f"]);
+        assert_eq!(ids(&["33?", "32b"])?, ["32b", "33a", "33b", "33c"]);
+        assert_eq!(ids(&["3?"])?, ["3a", "3b", "3c"]);
+        assert_eq!(ids(&[])?.len(), 113);
+        assert!(ids(&["34a"]).is_err());
+        assert!(get("34a").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_answer() {
+        let answer = Answer::from(Some(("A \"B\"", &1934)));
+        assert_eq!(answer.to_string(), r#""A \"B\"", "1934""#);
+        assert_eq!(Answer::from(None::<&str>).to_string(), "NULL");
+    }
+}
@@ -0,0 +1,366 @@
+use crate::bench::{Options, Sample, Status, json_string, table};
+use crate::cancel;
+use crate::json::{self, Value};
+use crate::parallel;
+use crate::stats::Summary;
+use crate::timer::Phase;
+use polars::prelude::*;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The machine and build that produced a results file, so that results from different days or
+/// machines can be told apart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Machine {
+    /// The CPU model, `unknown` where `/proc/cpuinfo` does not name it.
+    pub cpu: String,
+    /// The available parallelism.
+    pub cores: usize,
+    /// The threads of the parallel operators.
+    pub threads: usize,
+    /// The commit the binary was built from, with `-dirty` if it had changes.
+    pub commit: String,
+    /// `debug` or `release`.
+    pub profile: String,
+    pub os: String,
+    pub arch: String,
+    pub host: String,
+    /// Seconds since the Unix epoch when the run started.
+    pub time: u64,
+}
+
+impl Machine {
+    pub fn detect() -> Machine {
+        let cpu = std::fs::read_to_string("/proc/cpuinfo")
+            .ok()
+            .and_then(|info| {
+                info.lines()
+                    .filter_map(|line| line.split_once(':'))
+                    .find(|(key, _)| key.trim() == "model name")
+                    .map(|(_, model)| model.trim().to_string())
+            });
+        let host = std::fs::read_to_string("/proc/sys/kernel/hostname").ok();
+        Machine {
+            cpu: cpu.unwrap_or_else(|| "unknown".to_string()),
+            cores: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            threads: parallel::threads(),
+            commit: env!("LFJ_COMMIT").to_string(),
+            profile: env!("LFJ_PROFILE").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            host: host.map_or_else(|| "unknown".to_string(), |host| host.trim().to_string()),
+            time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |now| now.as_secs()),
+        }
+    }
+}
+
+/// The samples of one query, in order of repetition.
+pub struct QueryResults<'a> {
+    pub query: &'static str,
+    pub samples: Vec<&'a Sample>,
+    /// The summary of the in-query times of the samples that finished.
+    pub summary: Option<Summary>,
+}
+
+/// Groups `samples`, sorted by query, by query and summarizes them.
+pub fn summarize(samples: &[Sample]) -> Vec<QueryResults<'_>> {
+    samples
+        .chunk_by(|a, b| a.query == b.query)
+        .map(|samples| {
+            let seconds: Vec<f64> = samples.iter().filter_map(|sample| sample.seconds).collect();
+            QueryResults {
+                query: samples[0].query,
+                samples: samples.iter().collect(),
+                summary: Summary::of(&seconds),
+            }
+        })
+        .collect()
+}
+
+/// Writes `query,n,min,median,mean,p95,stddev,median_ci,mean_ci,outliers` in aligned columns,
+/// with the times in seconds and the outliers as the repetitions they came from.
+pub fn write_summary(out: &mut impl Write, results: &[QueryResults]) -> PolarsResult<()> {
+    let header = [
+        "query",
+        "n",
+        "min",
+        "median",
+        "mean",
+        "p95",
+        "stddev",
+        "median_ci",
+        "mean_ci",
+        "outliers",
+    ];
+    let rows: Vec<Vec<String>> = results
+        .iter()
+        .map(|result| {
+            let mut row = vec![result.query.to_string()];
+            match &result.summary {
+                Some(s) => {
+                    let outliers: Vec<String> = s
+                        .outliers
+                        .iter()
+                        .map(|&i| result.samples[i].repetition.to_string())
+                        .collect();
+                    row.extend([
+                        s.n.to_string(),
+                        format!("{:.6}", s.min),
+                        format!("{:.6}", s.median),
+                        format!("{:.6}", s.mean),
+                        format!("{:.6}", s.p95),
+                        format!("{:.6}", s.stddev),
+                        format!("{:.6}-{:.6}", s.median_ci.0, s.median_ci.1),
+                        format!("{:.6}-{:.6}", s.mean_ci.0, s.mean_ci.1),
+                        outliers.join(" "),
+                    ]);
+                }
+                None => row.push("0".to_string()),
+            }
+            row
+        })
+        .collect();
+    table(out, &header, &rows)
+}
+
+/// Writes a results file: a JSON object with the `machine`, the `options` of the run, and per
+/// query its status, its result, every sample's in-query, total and load time in seconds and the
+/// time of each of its phases, and the summary of the in-query times. A query's status is `ok` if
+/// all its samples finished, and otherwise `timeout` or `failed` after the first sample that did
+/// not; the times of samples that did not finish are `null`, as are the load times of data
+/// loaded once for all queries.
+pub fn write(
+    out: &mut impl Write,
+    machine: &Machine,
+    options: &Options,
+    samples: &[Sample],
+) -> PolarsResult<()> {
+    writeln!(out, "{{")?;
+    writeln!(
+        out,
+        "  \"machine\": {{\"cpu\": {}, \"cores\": {}, \"threads\": {}, \"commit\": {}, \
+         \"profile\": {}, \"os\": {}, \"arch\": {}, \"host\": {}, \"time\": {}}},",
+        json_string(&machine.cpu),
+        machine.cores,
+        machine.threads,
+        json_string(&machine.commit),
+        json_string(&machine.profile),
+        json_string(&machine.os),
+        json_string(&machine.arch),
+        json_string(&machine.host),
+        machine.time
+    )?;
+    let timeout = cancel::timeout().map_or("null".to_string(), |t| t.as_secs_f64().to_string());
+    writeln!(
+        out,
+        "  \"options\": {{\"repetitions\": {}, \"warmups\": {}, \"order\": {}, \
+         \"timeout\": {timeout}, \"load\": {}}},",
+        options.repetitions,
+        options.warmup_rounds(),
+        json_string(&options.order.to_string()),
+        json_string(&options.load.to_string())
+    )?;
+    writeln!(out, "  \"queries\": [")?;
+    let results = summarize(samples);
+    for (i, result) in results.iter().enumerate() {
+        let (status, message) = result
+            .samples
+            .iter()
+            .find_map(|sample| match &sample.status {
+                Status::Done(_) => None,
+                Status::Timeout => Some(("timeout", "")),
+                Status::Failed(message) => Some(("failed", message.as_str())),
+            })
+            .unwrap_or(("ok", ""));
+        let answer = result
+            .samples
+            .iter()
+            .find_map(|sample| match &sample.status {
+                Status::Done(answer) => Some(json_string(answer)),
+                _ => None,
+            })
+            .unwrap_or_else(|| "null".to_string());
+        let times = |time: &dyn Fn(&Sample) -> Option<f64>| {
+            let times: Vec<String> = result
+                .samples
+                .iter()
+                .map(|&sample| time(sample).map_or("null".to_string(), |t| t.to_string()))
+                .collect();
+            format!("[{}]", times.join(", "))
+        };
+        let phases: Vec<String> = Phase::ALL
+            .iter()
+            .map(|&phase| {
+                let time = |sample: &Sample| sample.phases.map(|p| p.get(phase).as_secs_f64());
+                format!("{}: {}", json_string(&phase.to_string()), times(&time))
+            })
+            .collect();
+        write!(
+            out,
+            "    {{\"query\": {}, \"status\": {}, \"message\": {}, \"result\": {answer}, \
+             \"seconds\": {}, \"total_seconds\": {}, \"load_seconds\": {}, \"phases\": {{{}}}, \
+             \"summary\": {}}}",
+            json_string(result.query),
+            json_string(status),
+            json_string(message),
+            times(&|sample| sample.seconds),
+            times(&|sample| Some(sample.total_seconds)),
+            times(&|sample| sample.load_seconds),
+            phases.join(", "),
+            result
+                .summary
+                .as_ref()
+                .map_or("null".to_string(), summary_json)
+        )?;
+        writeln!(out, "{}", if i + 1 < results.len() { "," } else { "" })?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn summary_json(s: &Summary) -> String {
+    let outliers: Vec<String> = s.outliers.iter().map(|i| i.to_string()).collect();
+    format!(
+        "{{\"n\": {}, \"min\": {}, \"median\": {}, \"mean\": {}, \"p95\": {}, \"max\": {}, \
+         \"stddev\": {}, \"median_ci\": [{}, {}], \"mean_ci\": [{}, {}], \"outliers\": [{}]}}",
+        s.n,
+        s.min,
+        s.median,
+        s.mean,
+        s.p95,
+        s.max,
+        s.stddev,
+        s.median_ci.0,
+        s.median_ci.1,
+        s.mean_ci.0,
+        s.mean_ci.1,
+        outliers.join(", ")
+    )
+}
+
+/// A results file as read back by `read`.
+pub struct Results {
+    /// The `machine` object as written.
+    pub machine: Value,
+    pub queries: Vec<Recorded>,
+}
+
+/// A query of a results file.
+pub struct Recorded {
+    pub query: String,
+    /// `ok`, `timeout` or `failed`.
+    pub status: String,
+    /// The in-query times of the samples that finished.
+    pub seconds: Vec<f64>,
+}
+
+/// Reads a results file written by `write`.
+pub fn read(path: &Path) -> PolarsResult<Results> {
+    let context = |message: &str| polars_err!(ComputeError: "{}: {message}", path.display());
+    let text = std::fs::read_to_string(path).map_err(|e| context(&e.to_string()))?;
+    let document = json::parse(&text).map_err(|e| context(&e.to_string()))?;
+    let queries = document
+        .get("queries")
+        .and_then(Value::as_array)
+        .ok_or_else(|| context("no queries"))?
+        .iter()
+        .map(|query| {
+            let field = |name| {
+                query
+                    .get(name)
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| context(&format!("a query has no {name}")))
+            };
+            Ok(Recorded {
+                query: field("query")?.to_string(),
+                status: field("status")?.to_string(),
+                seconds: query
+                    .get("seconds")
+                    .and_then(Value::as_array)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(Value::as_f64)
+                    .collect(),
+            })
+        })
+        .collect::<PolarsResult<_>>()?;
+    Ok(Results {
+        machine: document.get("machine").cloned().unwrap_or(Value::Null),
+        queries,
+    })
+}
+
+#[cfg(test)]
+mod test_results {
+    use super::*;
+    use crate::timer::Phases;
+    use std::time::Duration;
+
+    #[test]
+    fn test_write() -> PolarsResult<()> {
+        let sample = |query, repetition, status, seconds: Option<f64>| Sample {
+            query,
+            repetition,
+            status,
+            seconds,
+            phases: seconds.map(|s| {
+                let mut phases = Phases::default();
+                phases.0[Phase::Probe as usize] = Duration::from_secs_f64(s);
+                phases
+            }),
+            total_seconds: 1.0,
+            load_seconds: None,
+        };
+        let samples = [
+            sample("1a", 0, Status::Done("\"x\"".to_string()), Some(0.5)),
+            sample("1a", 1, Status::Done("\"x\"".to_string()), Some(0.25)),
+            sample("2a", 0, Status::Timeout, None),
+        ];
+        let results = summarize(&samples);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].summary.as_ref().map(|s| s.median), Some(0.375));
+        assert_eq!(results[1].summary, None);
+
+        let mut out = Vec::new();
+        write(&mut out, &Machine::detect(), &Options::default(), &samples)?;
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(
+            r#"{"query": "1a", "status": "ok", "message": "", "result": "\"x\"", "seconds": [0.5, 0.25], "total_seconds": [1, 1], "load_seconds": [null, null], "phases": {"setup": [0, 0], "lookup": [0, 0], "build": [0, 0], "probe": [0.5, 0.25], "aggregate": [0, 0]}, "summary": {"n": 2, "min": 0.25"#
+        ));
+        assert!(out.contains(
+            r#"{"query": "2a", "status": "timeout", "message": "", "result": null, "seconds": [null], "total_seconds": [1], "load_seconds": [null], "phases": {"setup": [null], "lookup": [null], "build": [null], "probe": [null], "aggregate": [null]}, "summary": null}"#
+        ));
+        assert!(out.contains(r#""options": {"repetitions": 5, "warmups": 1, "order": "fixed""#));
+        assert!(out.contains(r#""load": "warm"}"#));
+
+        let path = std::env::temp_dir().join(format!("lfj-results-{}.json", std::process::id()));
+        std::fs::write(&path, &out)?;
+        let read = read(&path);
+        std::fs::remove_file(&path)?;
+        let read = read?;
+        assert_eq!(
+            read.machine.get("commit").and_then(Value::as_str),
+            Some(env!("LFJ_COMMIT"))
+        );
+        let queries: Vec<(&str, &str, &[f64])> = read
+            .queries
+            .iter()
+            .map(|q| (q.query.as_str(), q.status.as_str(), q.seconds.as_slice()))
+            .collect();
+        assert_eq!(
+            queries,
+            [("1a", "ok", &[0.5, 0.25][..]), ("2a", "timeout", &[][..])]
+        );
+
+        let mut table = Vec::new();
+        write_summary(&mut table, &results)?;
+        let table = String::from_utf8(table).unwrap();
+        assert!(table.starts_with("query  n  min"));
+        assert!(table.lines().nth(2).unwrap().starts_with("2a     0"));
+        Ok(())
+    }
+}
@@ -0,0 +1,466 @@
+use std::ops::{BitAnd, BitOr, Sub};
+
+/// Largest number of values an array container holds before it becomes a bitmap, the point at
+/// which both take 8 KiB.
+const ARRAY_MAX: usize = 4096;
+
+/// The values of a `RoaringSet` that share their upper 16 bits, stored by their lower 16 bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Container {
+    /// Sorted, distinct values.
+    Array(Vec<u16>),
+    /// One bit per value, with the number of values set.
+    Bitmap { words: Box<[u64; 1024]>, len: u32 },
+}
+
+impl Container {
+    /// The container for the values set in `words`, as an array if there are few of them.
+    fn from_words(words: Box<[u64; 1024]>) -> Self {
+        let len = words.iter().map(|w| w.count_ones()).sum::<u32>();
+        if len as usize <= ARRAY_MAX {
+            Container::Array(bits(&words).collect())
+        } else {
+            Container::Bitmap { words, len }
+        }
+    }
+
+    /// The container for `values`, which must be sorted and distinct.
+    fn from_sorted(values: Vec<u16>) -> Self {
+        if values.len() <= ARRAY_MAX {
+            Container::Array(values)
+        } else {
+            let mut words = Box::new([0; 1024]);
+            for value in &values {
+                words[*value as usize / 64] |= 1 << (value % 64);
+            }
+            Container::Bitmap {
+                words,
+                len: values.len() as u32,
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len(),
+            Container::Bitmap { len, .. } => *len as usize,
+        }
+    }
+
+    fn contains(&self, value: u16) -> bool {
+        match self {
+            Container::Array(values) => values.binary_search(&value).is_ok(),
+            Container::Bitmap { words, .. } => {
+                words[value as usize / 64] & (1 << (value % 64)) != 0
+            }
+        }
+    }
+
+    fn insert(&mut self, value: u16) -> bool {
+        match self {
+            Container::Array(values) => match values.binary_search(&value) {
+                Ok(_) => false,
+                Err(i) => {
+                    values.insert(i, value);
+                    if values.len() > ARRAY_MAX {
+                        *self = Container::from_sorted(std::mem::take(values));
+                    }
+                    true
+                }
+            },
+            Container::Bitmap { words, len } => {
+                let word = &mut words[value as usize / 64];
+                let bit = 1 << (value % 64);
+                let absent = *word & bit == 0;
+                *word |= bit;
+                *len += absent as u32;
+                absent
+            }
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        let (array, bitmap) = match self {
+            Container::Array(values) => (Some(values.iter().copied()), None),
+            Container::Bitmap { words, .. } => (None, Some(bits(words))),
+        };
+        array
+            .into_iter()
+            .flatten()
+            .chain(bitmap.into_iter().flatten())
+    }
+
+    fn words(&self) -> Box<[u64; 1024]> {
+        match self {
+            Container::Array(values) => {
+                let mut words = Box::new([0; 1024]);
+                for value in values {
+                    words[*value as usize / 64] |= 1 << (value % 64);
+                }
+                words
+            }
+            Container::Bitmap { words, .. } => words.clone(),
+        }
+    }
+
+    fn and(&self, other: &Container) -> Container {
+        match (self, other) {
+            (Container::Array(a), Container::Array(b)) => {
+                let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+                // Binary searching the larger side pays off once it is much larger than the other.
+                if small.len() * 32 < large.len() {
+                    Container::Array(
+                        small
+                            .iter()
+                            .copied()
+                            .filter(|value| large.binary_search(value).is_ok())
+                            .collect(),
+                    )
+                } else {
+                    Container::Array(merge(a, b, |in_a, in_b| in_a && in_b))
+                }
+            }
+            (Container::Array(values), bitmap) | (bitmap, Container::Array(values)) => {
+                Container::Array(
+                    values
+                        .iter()
+                        .copied()
+                        .filter(|&value| bitmap.contains(value))
+                        .collect(),
+                )
+            }
+            (Container::Bitmap { words: a, .. }, Container::Bitmap { words: b, .. }) => {
+                let mut words = a.clone();
+                for (word, other) in words.iter_mut().zip(b.iter()) {
+                    *word &= other;
+                }
+                Container::from_words(words)
+            }
+        }
+    }
+
+    fn or(&self, other: &Container) -> Container {
+        match (self, other) {
+            (Container::Array(a), Container::Array(b)) if a.len() + b.len() <= ARRAY_MAX => {
+                Container::Array(merge(a, b, |in_a, in_b| in_a || in_b))
+            }
+            _ => {
+                let mut words = self.words();
+                match other {
+                    Container::Array(values) => {
+                        for value in values {
+                            words[*value as usize / 64] |= 1 << (value % 64);
+                        }
+                    }
+                    Container::Bitmap { words: other, .. } => {
+                        for (word, other) in words.iter_mut().zip(other.iter()) {
+                            *word |= other;
+                        }
+                    }
+                }
+                Container::from_words(words)
+            }
+        }
+    }
+
+    fn sub(&self, other: &Container) -> Container {
+        match (self, other) {
+            (Container::Array(values), other) => Container::Array(
+                values
+                    .iter()
+                    .copied()
+                    .filter(|&value| !other.contains(value))
+                    .collect(),
+            ),
+            (Container::Bitmap { words, .. }, other) => {
+                let mut words = words.clone();
+                match other {
+                    Container::Array(values) => {
+                        for value in values {
+                            words[*value as usize / 64] &= !(1 << (value % 64));
+                        }
+                    }
+                    Container::Bitmap { words: other, .. } => {
+                        for (word, other) in words.iter_mut().zip(other.iter()) {
+                            *word &= !other;
+                        }
+                    }
+                }
+                Container::from_words(words)
+            }
+        }
+    }
+}
+
+/// The positions of the bits set in `words`, ascending.
+fn bits(words: &[u64; 1024]) -> impl Iterator<Item = u16> + '_ {
+    words.iter().enumerate().flat_map(|(i, &word)| {
+        let mut word = word;
+        std::iter::from_fn(move || {
+            (word != 0).then(|| {
+                let j = word.trailing_zeros() as usize;
+                word &= word - 1;
+                (i * 64 + j) as u16
+            })
+        })
+    })
+}
+
+/// Merges two sorted, distinct sequences, keeping the values for which `keep(in a, in b)` holds.
+fn merge(a: &[u16], b: &[u16], keep: impl Fn(bool, bool) -> bool) -> Vec<u16> {
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        let (value, in_a, in_b) = match (a.get(i), b.get(j)) {
+            (Some(&x), Some(&y)) if x == y => (x, true, true),
+            (Some(&x), Some(&y)) if x < y => (x, true, false),
+            (Some(&x), None) => (x, true, false),
+            (_, Some(&y)) => (y, false, true),
+            (None, None) => unreachable!(),
+        };
+        i += in_a as usize;
+        j += in_b as usize;
+        if keep(in_a, in_b) {
+            out.push(value);
+        }
+    }
+    out
+}
+
+/// A compressed set of ids in the style of Roaring bitmaps: ids are grouped by their upper 16
+/// bits, and each group is stored as a sorted array when sparse or a 65536-bit bitmap when
+/// dense. Ids are ordered as unsigned integers, so negative ids sort after positive ones.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoaringSet {
+    keys: Vec<u16>,
+    containers: Vec<Container>,
+}
+
+impl FromIterator<i32> for RoaringSet {
+    fn from_iter<I: IntoIterator<Item = i32>>(ids: I) -> Self {
+        let mut ids: Vec<u32> = ids.into_iter().map(|id| id as u32).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        let mut set = RoaringSet::default();
+        for group in ids.chunk_by(|a, b| a >> 16 == b >> 16) {
+            set.keys.push((group[0] >> 16) as u16);
+            set.containers.push(Container::from_sorted(
+                group.iter().map(|&id| id as u16).collect(),
+            ));
+        }
+        set
+    }
+}
+
+impl RoaringSet {
+    pub fn new() -> Self {
+        RoaringSet::default()
+    }
+
+    fn split(id: i32) -> (u16, u16) {
+        ((id as u32 >> 16) as u16, id as u16)
+    }
+
+    /// Adds `id`, returning whether it was absent.
+    pub fn insert(&mut self, id: i32) -> bool {
+        let (key, value) = RoaringSet::split(id);
+        match self.keys.binary_search(&key) {
+            Ok(i) => self.containers[i].insert(value),
+            Err(i) => {
+                self.keys.insert(i, key);
+                self.containers.insert(i, Container::Array(vec![value]));
+                true
+            }
+        }
+    }
+
+    pub fn contains(&self, id: i32) -> bool {
+        let (key, value) = RoaringSet::split(id);
+        self.keys
+            .binary_search(&key)
+            .is_ok_and(|i| self.containers[i].contains(value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.containers.iter().map(Container::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Iterates over the ids in ascending unsigned order.
+    pub fn iter(&self) -> impl Iterator<Item = i32> + '_ {
+        self.keys
+            .iter()
+            .zip(self.containers.iter())
+            .flat_map(|(&key, container)| {
+                container
+                    .iter()
+                    .map(move |value| ((key as u32) << 16 | value as u32) as i32)
+            })
+    }
+
+    /// The intersection of `sets`, computed from the smallest set up so that intermediate
+    /// results stay small. The intersection of no sets is empty.
+    pub fn intersect_all<'a>(sets: impl IntoIterator<Item = &'a RoaringSet>) -> RoaringSet {
+        let mut sets: Vec<&RoaringSet> = sets.into_iter().collect();
+        sets.sort_by_key(|set| set.len());
+        let Some((first, rest)) = sets.split_first() else {
+            return RoaringSet::default();
+        };
+        let mut result = (*first).clone();
+        for set in rest {
+            if result.is_empty() {
+                break;
+            }
+            result = &result & *set;
+        }
+        result
+    }
+
+    /// Combines the containers of `self` and `other` by key with `op`. Keys only in `self` are
+    /// kept if `keep_self`, keys only in `other` if `keep_other`; empty results are dropped.
+    fn combine(
+        &self,
+        other: &RoaringSet,
+        keep_self: bool,
+        keep_other: bool,
+        op: impl Fn(&Container, &Container) -> Container,
+    ) -> RoaringSet {
+        let mut result = RoaringSet::default();
+        let mut push = |key: u16, container: Container| {
+            if container.len() > 0 {
+                result.keys.push(key);
+                result.containers.push(container);
+            }
+        };
+        let (mut i, mut j) = (0, 0);
+        while i < self.keys.len() || j < other.keys.len() {
+            match (self.keys.get(i), other.keys.get(j)) {
+                (Some(&a), Some(&b)) if a == b => {
+                    push(a, op(&self.containers[i], &other.containers[j]));
+                    i += 1;
+                    j += 1;
+                }
+                (Some(&a), Some(&b)) if a < b => {
+                    if keep_self {
+                        push(a, self.containers[i].clone());
+                    }
+                    i += 1;
+                }
+                (Some(&a), None) => {
+                    if keep_self {
+                        push(a, self.containers[i].clone());
+                    }
+                    i += 1;
+                }
+                (_, Some(&b)) => {
+                    if keep_other {
+                        push(b, other.containers[j].clone());
+                    }
+                    j += 1;
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+        result
+    }
+}
+
+impl BitAnd for &RoaringSet {
+    type Output = RoaringSet;
+
+    fn bitand(self, other: &RoaringSet) -> RoaringSet {
+        self.combine(other, false, false, Container::and)
+    }
+}
+
+impl BitOr for &RoaringSet {
+    type Output = RoaringSet;
+
+    fn bitor(self, other: &RoaringSet) -> RoaringSet {
+        self.combine(other, true, true, Container::or)
+    }
+}
+
+impl Sub for &RoaringSet {
+    type Output = RoaringSet;
+
+    fn sub(self, other: &RoaringSet) -> RoaringSet {
+        self.combine(other, true, false, Container::sub)
+    }
+}
+
+#[cfg(test)]
+mod test_roaring {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Ids from a seeded LCG: a sparse spread over several keys plus a dense run that turns
+    /// into bitmap containers.
+    fn ids(seed: u64, dense: std::ops::Range<i32>) -> Vec<i32> {
+        let mut state = seed;
+        let mut ids: Vec<i32> = (0..3000)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 40) as i32 % 400_000
+            })
+            .collect();
+        ids.extend(dense.filter(|id| (id ^ seed as i32) % 3 != 0));
+        ids
+    }
+
+    #[test]
+    fn test_roaring_matches_btreeset() {
+        let a_ids = ids(1, 70_000..90_000);
+        let b_ids = ids(2, 60_000..80_000);
+        let a: RoaringSet = a_ids.iter().copied().collect();
+        let b: RoaringSet = b_ids.iter().copied().collect();
+        let a_set: BTreeSet<i32> = a_ids.into_iter().collect();
+        let b_set: BTreeSet<i32> = b_ids.into_iter().collect();
+
+        assert_eq!(a.len(), a_set.len());
+        assert!(a.iter().eq(a_set.iter().copied()));
+        assert!(
+            a.containers
+                .iter()
+                .any(|c| matches!(c, Container::Bitmap { .. }))
+        );
+        for id in -10..400_010 {
+            assert_eq!(a.contains(id), a_set.contains(&id), "{id}");
+        }
+
+        let and = &a & &b;
+        assert!(and.iter().eq(a_set.intersection(&b_set).copied()));
+        assert_eq!(and.len(), a_set.intersection(&b_set).count());
+        let or = &a | &b;
+        assert!(or.iter().eq(a_set.union(&b_set).copied()));
+        assert_eq!(or.len(), a_set.union(&b_set).count());
+        let sub = &a - &b;
+        assert!(sub.iter().eq(a_set.difference(&b_set).copied()));
+        assert_eq!(sub.len(), a_set.difference(&b_set).count());
+    }
+
+    #[test]
+    fn test_insert_and_intersect_all() {
+        let mut a = RoaringSet::new();
+        for id in (0..10_000).rev() {
+            assert!(a.insert(id * 2));
+        }
+        assert!(!a.insert(0));
+        assert_eq!(a.len(), 10_000);
+        assert!(a.iter().eq((0..10_000).map(|id| id * 2)));
+        assert!(a.insert(-1));
+        assert_eq!(a.iter().last(), Some(-1));
+
+        let b: RoaringSet = (0..20_000).filter(|id| id % 3 == 0).collect();
+        let c: RoaringSet = (5_000..30_000).collect();
+        let all = RoaringSet::intersect_all([&a, &b, &c]);
+        assert!(all.iter().eq((5_000..20_000).filter(|id| id % 6 == 0)));
+        assert!(RoaringSet::intersect_all([]).is_empty());
+        assert!(RoaringSet::intersect_all([&a, &RoaringSet::new()]).is_empty());
+    }
+}
@@ -0,0 +1,361 @@
+use ahash::HashMap;
+use polars::prelude::*;
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Estimated bytes per entry of a table in memory: the key and row ids in a hash map slot, plus
+/// the share of the row vectors' allocations.
+const ENTRY_BYTES: usize = 32;
+
+/// Bits of the key's hash that each level of partitioning consumes, and the number of
+/// partitions it splits its input into.
+const FANOUT_BITS: u32 = 4;
+const FANOUT: usize = 1 << FANOUT_BITS;
+
+/// Levels of partitioning after which a partition is built in memory whatever its size, as
+/// happens when a single key has more rows than fit in the budget.
+const MAX_DEPTH: u32 = 4;
+
+/// The memory budget of a hash table in bytes, 0 for none.
+static BUDGET: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The budget of the `with_scoped_budget` call running on this thread, 0 outside one.
+    static SCOPED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The memory budget of each `GraceTable` built on this thread: the one of the enclosing
+/// `with_scoped_budget` call if any, else the process-wide one, `None` unless set.
+pub fn budget() -> Option<usize> {
+    match SCOPED.get() {
+        0 => match BUDGET.load(Ordering::Relaxed) {
+            0 => None,
+            bytes => Some(bytes),
+        },
+        bytes => Some(bytes),
+    }
+}
+
+/// Calls `f` with the budget `bytes` for the tables it builds on this thread, leaving the
+/// process-wide budget and other threads alone.
+pub fn with_scoped_budget<T>(bytes: usize, f: impl FnOnce() -> T) -> T {
+    let outer = SCOPED.replace(bytes.max(1));
+    let result = f();
+    SCOPED.set(outer);
+    result
+}
+
+pub fn set_budget(bytes: Option<usize>) {
+    BUDGET.store(bytes.map_or(0, |bytes| bytes.max(1)), Ordering::Relaxed);
+}
+
+/// Bytes written to spill files since the last call to `take_spilled`.
+static SPILLED: AtomicU64 = AtomicU64::new(0);
+
+pub fn take_spilled() -> u64 {
+    SPILLED.swap(0, Ordering::Relaxed)
+}
+
+/// Numbers the spill files of this process.
+static NEXT_FILE: AtomicU64 = AtomicU64::new(0);
+
+/// A temporary file of `(key, row)` records, deleted when dropped.
+struct SpillFile {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    len: usize,
+}
+
+impl SpillFile {
+    fn create() -> PolarsResult<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "lfj-spill-{}-{}",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let writer = BufWriter::new(File::create(&path)?);
+        Ok(SpillFile {
+            path,
+            writer: Some(writer),
+            len: 0,
+        })
+    }
+
+    fn push(&mut self, key: i32, row: u32) -> PolarsResult<()> {
+        let writer = self.writer.as_mut().expect("spill file already finished");
+        writer.write_all(&key.to_le_bytes())?;
+        writer.write_all(&row.to_le_bytes())?;
+        self.len += 1;
+        SPILLED.fetch_add(8, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Flushes the records written so far, after which the file can only be read.
+    fn finish(&mut self) -> PolarsResult<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn records(&self) -> PolarsResult<impl Iterator<Item = PolarsResult<(i32, u32)>>> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        Ok((0..self.len).map(move |_| {
+            let mut record = [0; 8];
+            reader.read_exact(&mut record)?;
+            let (key, row) = record.split_at(4);
+            Ok((
+                i32::from_le_bytes(key.try_into().unwrap()),
+                u32::from_le_bytes(row.try_into().unwrap()),
+            ))
+        }))
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        self.writer = None;
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// The partition of `key` at level `depth`. Each level takes the next bits of the same hash, so
+/// the keys of one partition spread over all partitions of the next level.
+fn partition(key: i32, depth: u32) -> usize {
+    let hash = (key as u32 as u64 ^ 0x5851_f42d_4c95_7f2d).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    (hash >> (64 - FANOUT_BITS * (depth + 1))) as usize & (FANOUT - 1)
+}
+
+enum State {
+    Memory(HashMap<i32, Vec<u32>>),
+    /// The entries, partitioned by `partition` at this table's depth.
+    Spilled(Vec<SpillFile>),
+}
+
+/// A hash table from join keys to the rows that have them which stays within a memory budget.
+/// While its entries fit in the budget it is an ordinary hash table. Past that it partitions
+/// them into temporary files by hash, as the Grace hash join does, and `probe` partitions its
+/// input the same way and then joins the partitions one pair at a time, partitioning them again
+/// when they are still too large.
+///
+/// Tables hold row numbers rather than values, so that anything can be spilled. Probes see
+/// matches in no particular order once the table has spilled.
+pub struct GraceTable {
+    budget: usize,
+    depth: u32,
+    len: usize,
+    state: State,
+}
+
+impl GraceTable {
+    /// A table of `(key, row)` entries within the configured `budget()`.
+    pub fn build(entries: impl IntoIterator<Item = (i32, u32)>) -> PolarsResult<Self> {
+        GraceTable::with_budget(budget().unwrap_or(usize::MAX), entries)
+    }
+
+    /// A table of `(key, row)` entries that spills once they take more than `budget` bytes.
+    pub fn with_budget(
+        budget: usize,
+        entries: impl IntoIterator<Item = (i32, u32)>,
+    ) -> PolarsResult<Self> {
+        let mut table = GraceTable::empty(budget, 0);
+        for (key, row) in entries {
+            table.insert(key, row)?;
+        }
+        table.finish()?;
+        Ok(table)
+    }
+
+    fn empty(budget: usize, depth: u32) -> Self {
+        GraceTable {
+            budget,
+            depth,
+            len: 0,
+            state: State::Memory(HashMap::default()),
+        }
+    }
+
+    fn insert(&mut self, key: i32, row: u32) -> PolarsResult<()> {
+        self.len += 1;
+        match &mut self.state {
+            State::Memory(map) => {
+                map.entry(key).or_default().push(row);
+                if self.len.saturating_mul(ENTRY_BYTES) > self.budget && self.depth < MAX_DEPTH {
+                    self.spill()?;
+                }
+            }
+            State::Spilled(partitions) => partitions[partition(key, self.depth)].push(key, row)?,
+        }
+        Ok(())
+    }
+
+    /// Moves the entries in memory to a new set of partitions.
+    fn spill(&mut self) -> PolarsResult<()> {
+        let mut partitions = (0..FANOUT)
+            .map(|_| SpillFile::create())
+            .collect::<PolarsResult<Vec<_>>>()?;
+        if let State::Memory(map) = &mut self.state {
+            for (key, rows) in map.drain() {
+                let partition = &mut partitions[partition(key, self.depth)];
+                for row in rows {
+                    partition.push(key, row)?;
+                }
+            }
+        }
+        self.state = State::Spilled(partitions);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> PolarsResult<()> {
+        if let State::Spilled(partitions) = &mut self.state {
+            for partition in partitions {
+                partition.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.state, State::Spilled(_))
+    }
+
+    /// Calls `f` with the table's row and the probe row for every pair of entries with equal
+    /// keys, given `(key, row)` entries to probe with.
+    pub fn probe(
+        &self,
+        probe: impl IntoIterator<Item = (i32, u32)>,
+        mut f: impl FnMut(u32, u32),
+    ) -> PolarsResult<()> {
+        let mut probe = probe.into_iter().map(Ok);
+        self.probe_matches(&mut probe, &mut |rows, row| {
+            for &build in rows {
+                f(build, row);
+            }
+        })
+    }
+
+    /// Calls `f` once with every probe row whose key is in the table.
+    pub fn semijoin(
+        &self,
+        probe: impl IntoIterator<Item = (i32, u32)>,
+        mut f: impl FnMut(u32),
+    ) -> PolarsResult<()> {
+        let mut probe = probe.into_iter().map(Ok);
+        self.probe_matches(&mut probe, &mut |_, row| f(row))
+    }
+
+    /// Calls `f` with the table's rows of each probe entry's key and the probe row, for the
+    /// entries whose key is in the table.
+    fn probe_matches(
+        &self,
+        probe: &mut dyn Iterator<Item = PolarsResult<(i32, u32)>>,
+        f: &mut dyn FnMut(&[u32], u32),
+    ) -> PolarsResult<()> {
+        match &self.state {
+            State::Memory(map) => {
+                for entry in probe {
+                    let (key, row) = entry?;
+                    if let Some(rows) = map.get(&key) {
+                        f(rows, row);
+                    }
+                }
+            }
+            State::Spilled(partitions) => {
+                let mut probes = (0..FANOUT)
+                    .map(|_| SpillFile::create())
+                    .collect::<PolarsResult<Vec<_>>>()?;
+                for entry in probe {
+                    let (key, row) = entry?;
+                    probes[partition(key, self.depth)].push(key, row)?;
+                }
+                for (build, mut probe) in partitions.iter().zip(probes) {
+                    probe.finish()?;
+                    if build.len == 0 || probe.len == 0 {
+                        continue;
+                    }
+                    let mut table = GraceTable::empty(self.budget, self.depth + 1);
+                    for entry in build.records()? {
+                        let (key, row) = entry?;
+                        table.insert(key, row)?;
+                    }
+                    table.finish()?;
+                    table.probe_matches(&mut probe.records()?, f)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_spill {
+    use super::*;
+
+    #[test]
+    fn test_grace_table() -> PolarsResult<()> {
+        // Skewed keys, with key 0 on every fifth build row, more than fit in the budget alone.
+        let build: Vec<(i32, u32)> = (0..20_000u32)
+            .map(|row| {
+                (
+                    if row % 5 == 0 {
+                        0
+                    } else {
+                        (row * 7 % 3001) as i32
+                    },
+                    row,
+                )
+            })
+            .collect();
+        let probe: Vec<(i32, u32)> = (0..5_000u32).map(|row| (row as i32 - 50, row)).collect();
+
+        let expected = {
+            let table = GraceTable::with_budget(usize::MAX, build.iter().copied())?;
+            assert!(!table.is_spilled());
+            let mut pairs = Vec::new();
+            table.probe(probe.iter().copied(), |b, p| pairs.push((b, p)))?;
+            pairs.sort();
+            pairs
+        };
+        let mut nested = Vec::new();
+        for &(build_key, b) in &build {
+            for &(probe_key, p) in &probe {
+                if build_key == probe_key {
+                    nested.push((b, p));
+                }
+            }
+        }
+        nested.sort();
+        assert_eq!(expected, nested);
+
+        for budget in [1024, 64 * 1024] {
+            let table = GraceTable::with_budget(budget, build.iter().copied())?;
+            assert!(table.is_spilled());
+            assert_eq!(table.len(), build.len());
+            let mut pairs = Vec::new();
+            table.probe(probe.iter().copied(), |b, p| pairs.push((b, p)))?;
+            pairs.sort();
+            assert_eq!(pairs, expected, "budget {budget}");
+
+            let mut rows = Vec::new();
+            table.semijoin(probe.iter().copied(), |p| rows.push(p))?;
+            rows.sort();
+            let mut expected_rows: Vec<u32> = expected.iter().map(|&(_, p)| p).collect();
+            expected_rows.sort();
+            expected_rows.dedup();
+            assert_eq!(rows, expected_rows, "budget {budget}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,524 @@
+use crate::predicate::{CmpOp, Scalar};
+use polars::prelude::*;
+
+/// A JOB query: a `SELECT MIN(...), ... FROM` over aliased tables whose `WHERE` clause is a
+/// conjunction of single-relation filters and equi-joins.
+///
+/// Only the dialect the benchmark uses is supported, plus `SELECT *` for queries that are only
+/// enumerated.
+#[derive(Clone, Debug)]
+pub struct Query<'q> {
+    /// The columns of the `MIN` aggregates of the select list, empty for `SELECT *`.
+    pub select: Vec<ColumnRef<'q>>,
+    pub relations: Vec<Relation<'q>>,
+    /// Filters, each of which reads the columns of a single relation.
+    pub filters: Vec<Expr<'q>>,
+    /// `a.x = b.y` conditions.
+    pub joins: Vec<(ColumnRef<'q>, ColumnRef<'q>)>,
+}
+
+/// `table AS alias`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Relation<'q> {
+    pub table: &'q str,
+    pub alias: &'q str,
+}
+
+/// `alias.column`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColumnRef<'q> {
+    pub alias: &'q str,
+    pub column: &'q str,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr<'q> {
+    Cmp(ColumnRef<'q>, CmpOp, Scalar<'q>),
+    /// `a.x = b.y`, which is only allowed at the top level of the `WHERE` clause.
+    Join(ColumnRef<'q>, ColumnRef<'q>),
+    Between(ColumnRef<'q>, Scalar<'q>, Scalar<'q>),
+    In(ColumnRef<'q>, Vec<Scalar<'q>>),
+    Like(ColumnRef<'q>, &'q str),
+    IsNull(ColumnRef<'q>),
+    And(Vec<Expr<'q>>),
+    Or(Vec<Expr<'q>>),
+    Not(Box<Expr<'q>>),
+}
+
+impl<'q> Expr<'q> {
+    /// The alias of the relation the expression reads, or `None` if it reads several.
+    pub fn alias(&self) -> Option<&'q str> {
+        match self {
+            Expr::Cmp(column, _, _)
+            | Expr::Between(column, _, _)
+            | Expr::In(column, _)
+            | Expr::Like(column, _)
+            | Expr::IsNull(column) => Some(column.alias),
+            Expr::Join(left, right) => (left.alias == right.alias).then_some(left.alias),
+            Expr::And(exprs) | Expr::Or(exprs) => {
+                let alias = exprs.first()?.alias()?;
+                exprs
+                    .iter()
+                    .all(|expr| expr.alias() == Some(alias))
+                    .then_some(alias)
+            }
+            Expr::Not(expr) => expr.alias(),
+        }
+    }
+}
+
+impl<'q> Query<'q> {
+    pub fn parse(sql: &'q str) -> PolarsResult<Self> {
+        let tokens = tokenize(sql)?;
+        Parser { tokens, pos: 0 }.query()
+    }
+
+    /// Position of `alias` in `relations`.
+    pub fn relation(&self, alias: &str) -> PolarsResult<usize> {
+        self.relations
+            .iter()
+            .position(|relation| relation.alias == alias)
+            .ok_or_else(|| polars_err!(SQLInterface: "unknown alias {alias}"))
+    }
+}
+
+/// Reads the `(name, SQL)` of every query in `queries/`, ordered 1a, 1b, ..., 2a, ..., 33c.
+pub fn job_queries() -> PolarsResult<Vec<(String, String)>> {
+    let mut queries = Vec::new();
+    for entry in std::fs::read_dir("queries")? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "sql") {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            queries.push((name, std::fs::read_to_string(&path)?));
+        }
+    }
+    queries.sort_by_key(|(name, _)| {
+        let (number, variant) = name.split_at(name.len() - 1);
+        (
+            number.parse::<u32>().unwrap_or(u32::MAX),
+            variant.to_string(),
+        )
+    });
+    Ok(queries)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token<'q> {
+    Word(&'q str),
+    Str(&'q str),
+    Int(i32),
+    Punct(&'q str),
+}
+
+fn tokenize(sql: &str) -> PolarsResult<Vec<Token<'_>>> {
+    let bytes = sql.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        match bytes[i] {
+            b if b.is_ascii_whitespace() => i += 1,
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'\'' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'\'' {
+                    i += 1;
+                }
+                if i == bytes.len() {
+                    return Err(polars_err!(SQLSyntax: "unterminated string literal"));
+                }
+                if bytes.get(i + 1) == Some(&b'\'') {
+                    return Err(polars_err!(SQLSyntax: "escaped quotes are not supported"));
+                }
+                tokens.push(Token::Str(&sql[start + 1..i]));
+                i += 1;
+            }
+            b if b.is_ascii_digit() => {
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let value = sql[start..i].parse().map_err(
+                    |_| polars_err!(SQLSyntax: "integer out of range: {}", &sql[start..i]),
+                )?;
+                tokens.push(Token::Int(value));
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                tokens.push(Token::Word(&sql[start..i]));
+            }
+            b'!' | b'<' | b'>' if matches!(bytes.get(i + 1), Some(b'=' | b'>')) => {
+                i += 2;
+                tokens.push(Token::Punct(&sql[start..i]));
+            }
+            b'(' | b')' | b',' | b';' | b'.' | b'=' | b'<' | b'>' | b'*' => {
+                i += 1;
+                tokens.push(Token::Punct(&sql[start..i]));
+            }
+            _ => {
+                let c = sql[i..].chars().next().unwrap();
+                return Err(polars_err!(SQLSyntax: "unexpected character {c:?}"));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'q> {
+    tokens: Vec<Token<'q>>,
+    pos: usize,
+}
+
+impl<'q> Parser<'q> {
+    fn peek(&self) -> Option<Token<'q>> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> PolarsResult<Token<'q>> {
+        let token = self
+            .peek()
+            .ok_or_else(|| polars_err!(SQLSyntax: "unexpected end of query"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.at_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> PolarsResult<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(polars_err!(SQLSyntax: "expected {keyword}, found {:?}", self.peek()))
+        }
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> bool {
+        let found = self.peek() == Some(Token::Punct(punct));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> PolarsResult<()> {
+        if self.eat_punct(punct) {
+            Ok(())
+        } else {
+            Err(polars_err!(SQLSyntax: "expected {punct:?}, found {:?}", self.peek()))
+        }
+    }
+
+    fn word(&mut self) -> PolarsResult<&'q str> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            token => Err(polars_err!(SQLSyntax: "expected a name, found {token:?}")),
+        }
+    }
+
+    fn query(mut self) -> PolarsResult<Query<'q>> {
+        self.expect_keyword("SELECT")?;
+        let mut select = Vec::new();
+        if !self.eat_punct("*") {
+            loop {
+                self.expect_keyword("MIN")?;
+                self.expect_punct("(")?;
+                select.push(self.column()?);
+                self.expect_punct(")")?;
+                if self.eat_keyword("AS") {
+                    self.word()?;
+                }
+                if !self.eat_punct(",") {
+                    break;
+                }
+            }
+        }
+        self.expect_keyword("FROM")?;
+
+        let mut relations = Vec::new();
+        loop {
+            let table = self.word()?;
+            self.expect_keyword("AS")?;
+            let alias = self.word()?;
+            relations.push(Relation { table, alias });
+            if !self.eat_punct(",") {
+                break;
+            }
+        }
+
+        let mut filters = Vec::new();
+        let mut joins = Vec::new();
+        if self.eat_keyword("WHERE") {
+            let conjuncts = match self.or()? {
+                Expr::And(exprs) => exprs,
+                expr => vec![expr],
+            };
+            for expr in conjuncts {
+                match expr {
+                    Expr::Join(left, right) if left.alias != right.alias => {
+                        joins.push((left, right))
+                    }
+                    expr if expr.alias().is_some() => filters.push(expr),
+                    expr => {
+                        return Err(
+                            polars_err!(SQLInterface: "filter over several relations: {expr:?}"),
+                        );
+                    }
+                }
+            }
+        }
+        self.eat_punct(";");
+        if let Some(token) = self.peek() {
+            return Err(polars_err!(SQLSyntax: "unexpected {token:?} after query"));
+        }
+
+        let query = Query {
+            select,
+            relations,
+            filters,
+            joins,
+        };
+        for (left, right) in &query.joins {
+            query.relation(left.alias)?;
+            query.relation(right.alias)?;
+        }
+        for filter in &query.filters {
+            query.relation(filter.alias().unwrap())?;
+        }
+        for column in &query.select {
+            query.relation(column.alias)?;
+        }
+        Ok(query)
+    }
+
+    fn or(&mut self) -> PolarsResult<Expr<'q>> {
+        let mut exprs = vec![self.and()?];
+        while self.eat_keyword("OR") {
+            exprs.push(self.and()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.pop().unwrap()
+        } else {
+            Expr::Or(exprs)
+        })
+    }
+
+    fn and(&mut self) -> PolarsResult<Expr<'q>> {
+        let mut exprs = vec![self.atom()?];
+        while self.eat_keyword("AND") {
+            exprs.push(self.atom()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.pop().unwrap()
+        } else {
+            Expr::And(exprs)
+        })
+    }
+
+    fn atom(&mut self) -> PolarsResult<Expr<'q>> {
+        if self.eat_keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.atom()?)));
+        }
+        if self.eat_punct("(") {
+            let expr = self.or()?;
+            self.expect_punct(")")?;
+            return Ok(expr);
+        }
+
+        let column = self.column()?;
+        if self.eat_keyword("IS") {
+            let negated = self.eat_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(negate(Expr::IsNull(column), negated));
+        }
+        let negated = self.eat_keyword("NOT");
+        let expr = if self.eat_keyword("LIKE") {
+            match self.next()? {
+                Token::Str(pattern) => Expr::Like(column, pattern),
+                token => return Err(polars_err!(SQLSyntax: "expected a pattern, found {token:?}")),
+            }
+        } else if self.eat_keyword("IN") {
+            self.expect_punct("(")?;
+            let mut values = vec![self.scalar()?];
+            while self.eat_punct(",") {
+                values.push(self.scalar()?);
+            }
+            self.expect_punct(")")?;
+            Expr::In(column, values)
+        } else if self.eat_keyword("BETWEEN") {
+            let lo = self.scalar()?;
+            self.expect_keyword("AND")?;
+            let hi = self.scalar()?;
+            Expr::Between(column, lo, hi)
+        } else if negated {
+            return Err(polars_err!(SQLSyntax: "expected LIKE, IN or BETWEEN after NOT"));
+        } else {
+            let op = match self.next()? {
+                Token::Punct("=") => CmpOp::Eq,
+                Token::Punct("!=" | "<>") => CmpOp::Ne,
+                Token::Punct("<") => CmpOp::Lt,
+                Token::Punct("<=") => CmpOp::Le,
+                Token::Punct(">") => CmpOp::Gt,
+                Token::Punct(">=") => CmpOp::Ge,
+                token => {
+                    return Err(polars_err!(SQLSyntax: "expected an operator, found {token:?}"));
+                }
+            };
+            if let Some(Token::Word(_)) = self.peek() {
+                let other = self.column()?;
+                if op != CmpOp::Eq {
+                    return Err(polars_err!(SQLInterface: "only equi-joins are supported"));
+                }
+                Expr::Join(column, other)
+            } else {
+                Expr::Cmp(column, op, self.scalar()?)
+            }
+        };
+        Ok(negate(expr, negated))
+    }
+
+    fn column(&mut self) -> PolarsResult<ColumnRef<'q>> {
+        let alias = self.word()?;
+        self.expect_punct(".")?;
+        let column = self.word()?;
+        Ok(ColumnRef { alias, column })
+    }
+
+    fn scalar(&mut self) -> PolarsResult<Scalar<'q>> {
+        match self.next()? {
+            Token::Int(value) => Ok(Scalar::Int(value)),
+            Token::Str(value) => Ok(Scalar::Str(value)),
+            token => Err(polars_err!(SQLSyntax: "expected a constant, found {token:?}")),
+        }
+    }
+}
+
+fn negate(expr: Expr<'_>, negated: bool) -> Expr<'_> {
+    if negated {
+        Expr::Not(Box::new(expr))
+    } else {
+        expr
+    }
+}
+
+#[cfg(test)]
+mod test_sql {
+    use super::*;
+
+    fn col<'q>(alias: &'q str, column: &'q str) -> ColumnRef<'q> {
+        ColumnRef { alias, column }
+    }
+
+    #[test]
+    fn test_parse() -> PolarsResult<()> {
+        let query = Query::parse(include_str!("../queries/1a.sql"))?;
+        assert_eq!(query.relations.len(), 5);
+        assert_eq!(
+            query.relations[2],
+            Relation {
+                table: "movie_companies",
+                alias: "mc"
+            }
+        );
+        assert_eq!(query.joins.len(), 5);
+        assert_eq!(
+            query.joins[0],
+            (col("ct", "id"), col("mc", "company_type_id"))
+        );
+        assert_eq!(
+            query.filters,
+            vec![
+                Expr::Cmp(
+                    col("ct", "kind"),
+                    CmpOp::Eq,
+                    Scalar::Str("production companies")
+                ),
+                Expr::Cmp(col("it", "info"), CmpOp::Eq, Scalar::Str("top 250 rank")),
+                Expr::Not(Box::new(Expr::Like(
+                    col("mc", "note"),
+                    "%(as Metro-Goldwyn-Mayer Pictures)%"
+                ))),
+                Expr::Or(vec![
+                    Expr::Like(col("mc", "note"), "%(co-production)%"),
+                    Expr::Like(col("mc", "note"), "%(presents)%"),
+                ]),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_operators() -> PolarsResult<()> {
+        let query = Query::parse(
+            "SELECT MIN(t.title) FROM title AS t, kind_type AS kt \
+             WHERE t.production_year BETWEEN 2005 AND 2010 AND kt.kind IN ('movie', 'episode') \
+             AND t.title != '' AND t.episode_nr >= 5 AND t.series_years IS NOT NULL \
+             AND (kt.kind = 'a' OR (kt.kind = 'b' AND kt.kind NOT LIKE 'c%')) AND kt.id = t.kind_id",
+        )?;
+        assert_eq!(query.select, vec![col("t", "title")]);
+        assert_eq!(query.joins, vec![(col("kt", "id"), col("t", "kind_id"))]);
+        assert_eq!(
+            query.filters[..4],
+            [
+                Expr::Between(
+                    col("t", "production_year"),
+                    Scalar::Int(2005),
+                    Scalar::Int(2010)
+                ),
+                Expr::In(
+                    col("kt", "kind"),
+                    vec![Scalar::Str("movie"), Scalar::Str("episode")]
+                ),
+                Expr::Cmp(col("t", "title"), CmpOp::Ne, Scalar::Str("")),
+                Expr::Cmp(col("t", "episode_nr"), CmpOp::Ge, Scalar::Int(5)),
+            ]
+        );
+        assert_eq!(
+            query.filters[4],
+            Expr::Not(Box::new(Expr::IsNull(col("t", "series_years"))))
+        );
+        assert_eq!(query.filters[5].alias(), Some("kt"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_all_job_queries() -> PolarsResult<()> {
+        let queries = job_queries()?;
+        assert_eq!(queries.len(), 113);
+        assert_eq!(queries[0].0, "1a");
+        assert_eq!(queries[112].0, "33c");
+        for (name, sql) in &queries {
+            if let Err(err) = Query::parse(sql) {
+                panic!("{name}: {err}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Query::parse("SELECT * FROM title AS t WHERE t.id < x.id").is_err());
+        assert!(Query::parse("SELECT * FROM title AS t WHERE t.title = 'abc").is_err());
+        assert!(Query::parse("SELECT * FROM title AS t WHERE x.id = 1").is_err());
+        assert!(Query::parse("SELECT MIN(x.id) FROM title AS t").is_err());
+        assert!(Query::parse("SELECT COUNT(*) FROM title AS t").is_err());
+        assert!(
+            Query::parse("SELECT * FROM title AS t, kind_type AS kt WHERE t.id = 1 OR kt.id = 1")
+                .is_err()
+        );
+    }
+}
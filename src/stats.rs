@@ -0,0 +1,211 @@
+/// Resamples drawn to estimate each bootstrap confidence interval.
+const RESAMPLES: usize = 2000;
+
+/// Seed of the resampling, fixed so that the same samples always get the same intervals.
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Summary statistics of repeated measurements of one quantity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub n: usize,
+    pub min: f64,
+    pub median: f64,
+    pub mean: f64,
+    pub p95: f64,
+    pub max: f64,
+    /// The sample standard deviation, 0 for a single sample.
+    pub stddev: f64,
+    /// 95% bootstrap confidence intervals of the median and the mean.
+    pub median_ci: (f64, f64),
+    pub mean_ci: (f64, f64),
+    /// Positions of the samples outside Tukey's fences, more than 1.5 interquartile ranges below
+    /// the first quartile or above the third.
+    pub outliers: Vec<usize>,
+}
+
+impl Summary {
+    /// The summary of `samples`, `None` if there are none.
+    pub fn of(samples: &[f64]) -> Option<Summary> {
+        if samples.is_empty() {
+            return None;
+        }
+        let n = samples.len();
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let mean = average(samples);
+        let stddev = if n > 1 {
+            let squares: f64 = samples.iter().map(|x| (x - mean).powi(2)).sum();
+            (squares / (n - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        let (q1, q3) = (quantile(&sorted, 0.25), quantile(&sorted, 0.75));
+        let (low, high) = (q1 - 1.5 * (q3 - q1), q3 + 1.5 * (q3 - q1));
+        Some(Summary {
+            n,
+            min: sorted[0],
+            median: quantile(&sorted, 0.5),
+            mean,
+            p95: quantile(&sorted, 0.95),
+            max: sorted[n - 1],
+            stddev,
+            median_ci: bootstrap(samples, |resample| {
+                resample.sort_by(f64::total_cmp);
+                quantile(resample, 0.5)
+            }),
+            mean_ci: bootstrap(samples, |resample| average(resample)),
+            outliers: (0..n)
+                .filter(|&i| samples[i] < low || samples[i] > high)
+                .collect(),
+        })
+    }
+}
+
+fn average(samples: &[f64]) -> f64 {
+    samples.iter().sum::<f64>() / samples.len() as f64
+}
+
+/// The `q` quantile of non-empty `sorted` samples, interpolating linearly between the closest
+/// ranks.
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let rank = (sorted.len() - 1) as f64 * q;
+    let (below, fraction) = (rank.floor() as usize, rank.fract());
+    match sorted.get(below + 1) {
+        Some(above) => sorted[below] + (above - sorted[below]) * fraction,
+        None => sorted[below],
+    }
+}
+
+/// The 95% percentile bootstrap confidence interval of `statistic` over `samples`.
+fn bootstrap(samples: &[f64], statistic: impl Fn(&mut [f64]) -> f64) -> (f64, f64) {
+    let mut state = SEED;
+    let mut resample = vec![0.0; samples.len()];
+    let mut statistics: Vec<f64> = (0..RESAMPLES)
+        .map(|_| {
+            for x in resample.iter_mut() {
+                // xorshift64
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                *x = samples[(state % samples.len() as u64) as usize];
+            }
+            statistic(&mut resample)
+        })
+        .collect();
+    statistics.sort_by(f64::total_cmp);
+    (quantile(&statistics, 0.025), quantile(&statistics, 0.975))
+}
+
+/// The two-sided p-value of the Mann-Whitney U test of whether `a` and `b` are samples of the
+/// same distribution, from the normal approximation with corrections for ties and continuity. 1
+/// if either is empty or all values are equal.
+pub fn mann_whitney(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 1.0;
+    }
+    let mut all: Vec<(f64, bool)> = a
+        .iter()
+        .map(|&x| (x, true))
+        .chain(b.iter().map(|&x| (x, false)))
+        .collect();
+    all.sort_by(|x, y| x.0.total_cmp(&y.0));
+    // The rank sum of `a`, with tied values sharing the average of their ranks, and the tie
+    // correction term.
+    let (mut ranks, mut ties) = (0.0, 0.0);
+    let mut i = 0;
+    while i < all.len() {
+        let j = i + all[i..].iter().take_while(|x| x.0 == all[i].0).count();
+        let tied = (j - i) as f64;
+        ranks += (i + j + 1) as f64 / 2.0 * all[i..j].iter().filter(|x| x.1).count() as f64;
+        ties += tied.powi(3) - tied;
+        i = j;
+    }
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    let n = n1 + n2;
+    let u = ranks - n1 * (n1 + 1.0) / 2.0;
+    let variance = n1 * n2 / 12.0 * (n + 1.0 - ties / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return 1.0;
+    }
+    let z = ((u - n1 * n2 / 2.0).abs() - 0.5).max(0.0) / variance.sqrt();
+    erfc(z / std::f64::consts::SQRT_2).min(1.0)
+}
+
+/// The smallest p-value `mann_whitney` gives for samples of `n1` and `n2` distinct values, which
+/// it reaches when every value of one sample is below every value of the other.
+pub fn min_p_value(n1: usize, n2: usize) -> f64 {
+    let a: Vec<f64> = (0..n1).map(|x| x as f64).collect();
+    let b: Vec<f64> = (n1..n1 + n2).map(|x| x as f64).collect();
+    mann_whitney(&a, &b)
+}
+
+/// The complementary error function, with a relative error below 1.2e-7 (Numerical Recipes'
+/// `erfcc`).
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * x.abs());
+    let coefficients = [
+        -1.26551223,
+        1.00002368,
+        0.37409196,
+        0.09678418,
+        -0.18628806,
+        0.27886807,
+        -1.13520398,
+        1.48851587,
+        -0.82215223,
+        0.17087277,
+    ];
+    let polynomial = coefficients.iter().rev().fold(0.0, |acc, c| acc * t + c);
+    let y = t * (-x * x + polynomial).exp();
+    if x >= 0.0 { y } else { 2.0 - y }
+}
+
+#[cfg(test)]
+mod test_stats {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(Summary::of(&[]), None);
+
+        let summary = Summary::of(&[3.0, 1.0, 2.0, 4.0, 100.0]).unwrap();
+        assert_eq!(summary.n, 5);
+        assert_eq!((summary.min, summary.max), (1.0, 100.0));
+        assert_eq!(summary.median, 3.0);
+        assert_eq!(summary.mean, 22.0);
+        assert!((summary.p95 - 80.8).abs() < 1e-9, "{}", summary.p95);
+        assert!(
+            (summary.stddev - 43.6177).abs() < 1e-4,
+            "{}",
+            summary.stddev
+        );
+        assert_eq!(summary.outliers, [4]);
+        let (low, high) = summary.median_ci;
+        assert!((1.0..=3.0).contains(&low) && (3.0..=100.0).contains(&high));
+        let (low, high) = summary.mean_ci;
+        assert!((1.0..22.0).contains(&low) && high > 22.0 && high <= 100.0);
+
+        let single = Summary::of(&[0.5]).unwrap();
+        assert_eq!((single.median, single.p95, single.stddev), (0.5, 0.5, 0.0));
+        assert_eq!((single.median_ci, single.mean_ci), ((0.5, 0.5), (0.5, 0.5)));
+        assert!(single.outliers.is_empty());
+    }
+
+    #[test]
+    fn test_mann_whitney() {
+        let low: Vec<f64> = (1..=10).map(f64::from).collect();
+        let high: Vec<f64> = (11..=20).map(f64::from).collect();
+        // scipy.stats.mannwhitneyu(low, high, method="asymptotic").pvalue
+        assert!((mann_whitney(&low, &high) - 0.000182672).abs() < 1e-6);
+        assert_eq!(mann_whitney(&low, &high), mann_whitney(&high, &low));
+        assert_eq!(mann_whitney(&low, &low), 1.0);
+        assert_eq!(mann_whitney(&[1.0, 1.0], &[1.0]), 1.0);
+        assert_eq!(mann_whitney(&low, &[]), 1.0);
+        let interleaved: Vec<f64> = (1..=10).map(|x| f64::from(x) + 0.5).collect();
+        assert!(mann_whitney(&low, &interleaved) > 0.5);
+
+        assert_eq!(min_p_value(1, 1), 1.0);
+        assert!(min_p_value(3, 3) > 0.05 && min_p_value(4, 4) < 0.05);
+        assert_eq!(min_p_value(10, 10), mann_whitney(&low, &high));
+    }
+}
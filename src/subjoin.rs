@@ -0,0 +1,395 @@
+use crate::cancel::{self, Outcome};
+use crate::data::Data;
+use crate::enumerate::{Edge, Join, bind, value, variables};
+use crate::predicate::{Column, Selection};
+use crate::registry::QUERIES;
+use crate::sql::Query;
+use ahash::HashMap;
+use polars::prelude::*;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// The exact output size of a connected sub-join of a query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubJoin<'a> {
+    /// The aliases of the joined relations, in `FROM` order.
+    pub aliases: Vec<&'a str>,
+    pub cardinality: u64,
+}
+
+/// Computes the cardinality of every connected sub-join of `sql`, with each relation's filters
+/// applied, ordered by the number of relations joined.
+///
+/// Two relations are connected if a join condition relates them, directly or through the
+/// transitive closure of the equalities, so `mk` and `mi` are connected in a query that only says
+/// `t.id = mk.movie_id AND t.id = mi.movie_id`.
+pub fn subjoins<'a>(db: &'a Data, sql: &'a str) -> PolarsResult<Vec<SubJoin<'a>>> {
+    let query = Query::parse(sql)?;
+    if query.relations.len() > 32 {
+        return Err(polars_err!(SQLInterface: "too many relations: {}", query.relations.len()));
+    }
+    let (mut selections, edges) = bind(db, &query)?;
+    let variables = variables(&query, &mut selections, edges);
+    let mut counter = Counter::new(selections, variables.relations, variables.names.len());
+    Ok(counter
+        .all()
+        .into_iter()
+        .map(|(mask, cardinality)| SubJoin {
+            aliases: members(mask).map(|r| query.relations[r].alias).collect(),
+            cardinality,
+        })
+        .collect())
+}
+
+/// Prints `query,relations,cardinality` for every connected sub-join of every query in
+/// `QUERIES`, with the relations of a sub-join separated by spaces. A query that exceeds
+/// `cancel::timeout()` gets a single `query,,timeout` row.
+pub fn print_all(db: &Data) -> PolarsResult<()> {
+    println!("query,relations,cardinality");
+    for query in QUERIES {
+        let name = query.id;
+        let start = Instant::now();
+        let Outcome::Done(subjoins) = cancel::with_timeout(|| subjoins(db, query.sql))? else {
+            println!("{name},,timeout");
+            continue;
+        };
+        for subjoin in &subjoins {
+            println!(
+                "{name},{},{}",
+                subjoin.aliases.join(" "),
+                subjoin.cardinality
+            );
+        }
+        eprintln!(
+            "{name}: {} sub-joins in {:}s",
+            subjoins.len(),
+            start.elapsed().as_secs_f32()
+        );
+    }
+    Ok(())
+}
+
+/// Counts of join results grouped by the values of some variables.
+type Message = HashMap<u128, u64>;
+
+/// Upper bound on the entries of cached messages, to keep memory in check on the largest queries.
+const CACHE_LIMIT: usize = 1 << 24;
+
+/// Counts sub-joins without enumerating them.
+///
+/// Most sub-joins of JOB queries are acyclic once the transitive equalities are merged into
+/// variables, so a join tree is found by GYO reduction and the count is computed bottom-up: every
+/// relation sends its parent the number of results of its subtree grouped by the variables they
+/// share, which is a semijoin that counts instead of filtering. Messages are cached by subtree, so
+/// the many sub-joins that share a subtree, such as `k ⋈ mk`, reduce it only once. Cyclic
+/// sub-joins fall back to enumeration.
+struct Counter<'a> {
+    selections: Vec<Selection>,
+    /// The join columns of every relation, labelled by variable.
+    relations: Vec<Vec<(usize, Column<'a>)>>,
+    /// The variables of every relation, as a bitmask.
+    vars: Vec<u64>,
+    /// The relations every relation shares a variable with, as a bitmask.
+    adjacency: Vec<u32>,
+    cache: HashMap<(u32, usize, u64), Rc<Message>>,
+    cached: usize,
+}
+
+impl<'a> Counter<'a> {
+    fn new(
+        selections: Vec<Selection>,
+        relations: Vec<Vec<(usize, Column<'a>)>>,
+        var_count: usize,
+    ) -> Self {
+        assert!(var_count <= 64, "too many join variables: {var_count}");
+        let vars: Vec<u64> = relations
+            .iter()
+            .map(|columns| columns.iter().fold(0, |vars, (var, _)| vars | 1 << var))
+            .collect();
+        let adjacency = vars
+            .iter()
+            .enumerate()
+            .map(|(r, a)| {
+                vars.iter()
+                    .enumerate()
+                    .filter(|&(s, b)| s != r && a & b != 0)
+                    .fold(0, |adjacent, (s, _)| adjacent | 1 << s)
+            })
+            .collect();
+        Counter {
+            selections,
+            relations,
+            vars,
+            adjacency,
+            cache: HashMap::default(),
+            cached: 0,
+        }
+    }
+
+    /// The cardinality of every connected sub-join, keyed by the bitmask of its relations.
+    fn all(&mut self) -> Vec<(u32, u64)> {
+        let n = self.relations.len();
+        let mut masks: Vec<u32> = (1..1u64 << n)
+            .map(|mask| mask as u32)
+            .filter(|&mask| self.connected(mask))
+            .collect();
+        masks.sort_by_key(|mask| (mask.count_ones(), *mask));
+        masks
+            .into_iter()
+            .map(|mask| (mask, self.count(mask)))
+            .collect()
+    }
+
+    fn connected(&self, mask: u32) -> bool {
+        let mut reached: u32 = 1 << mask.trailing_zeros();
+        let mut frontier = reached;
+        while frontier != 0 {
+            let r = frontier.trailing_zeros() as usize;
+            frontier &= frontier - 1;
+            let next = self.adjacency[r] & mask & !reached;
+            reached |= next;
+            frontier |= next;
+        }
+        reached == mask
+    }
+
+    fn count(&mut self, mask: u32) -> u64 {
+        // Variables shared by at least two relations of the sub-join.
+        let mut seen = 0;
+        let mut shared = 0;
+        for r in members(mask) {
+            shared |= seen & self.vars[r];
+            seen |= self.vars[r];
+        }
+        let vars = |r: usize| self.vars[r] & shared;
+
+        // GYO reduction, trying the largest relations first so they end up as leaves whose
+        // messages can be cached.
+        let mut remaining: Vec<usize> = members(mask).collect();
+        remaining.sort_by_key(|&r| std::cmp::Reverse(self.selections[r].count()));
+        let mut removed: Vec<(usize, usize, u64)> = Vec::new();
+        while remaining.len() > 1 {
+            let ear = remaining.iter().enumerate().find_map(|(i, &r)| {
+                let others = remaining
+                    .iter()
+                    .filter(|&&s| s != r)
+                    .fold(0, |others, &s| others | vars(s));
+                let out = vars(r) & others;
+                remaining
+                    .iter()
+                    .find(|&&parent| parent != r && out & !vars(parent) == 0)
+                    .map(|&parent| (i, r, parent, out))
+            });
+            match ear {
+                Some((i, r, parent, out)) if out.count_ones() <= 4 => {
+                    remaining.remove(i);
+                    removed.push((r, parent, out));
+                }
+                _ => return self.enumerate(mask),
+            }
+        }
+
+        let mut subtrees: Vec<u32> = vec![0; self.relations.len()];
+        let mut messages: Vec<Option<Rc<Message>>> = vec![None; self.relations.len()];
+        for &(r, _, out) in &removed {
+            let children = self.children(&removed, r, &messages);
+            let subtree = children
+                .iter()
+                .fold(1 << r, |subtree, &(c, _, _)| subtree | subtrees[c]);
+            subtrees[r] = subtree;
+            let key = (subtree, r, out);
+            let message = match self.cache.get(&key) {
+                Some(message) => message.clone(),
+                None => {
+                    let mut message = Message::default();
+                    self.scan(r, out, &children, |key, weight| {
+                        let count = message.entry(pack(out, key)).or_default();
+                        *count = count.saturating_add(weight);
+                    });
+                    let message = Rc::new(message);
+                    if self.cached + message.len() <= CACHE_LIMIT {
+                        self.cached += message.len();
+                        self.cache.insert(key, message.clone());
+                    }
+                    message
+                }
+            };
+            messages[r] = Some(message);
+        }
+
+        let root = remaining[0];
+        let children = self.children(&removed, root, &messages);
+        let mut total: u64 = 0;
+        self.scan(root, 0, &children, |_, weight| {
+            total = total.saturating_add(weight)
+        });
+        total
+    }
+
+    /// The `(relation, shared variables, message)` of every child of `r` in the join tree.
+    fn children(
+        &self,
+        removed: &[(usize, usize, u64)],
+        r: usize,
+        messages: &[Option<Rc<Message>>],
+    ) -> Vec<(usize, u64, Rc<Message>)> {
+        removed
+            .iter()
+            .filter(|&&(_, parent, _)| parent == r)
+            .map(|&(c, _, out)| (c, out, messages[c].clone().unwrap()))
+            .collect()
+    }
+
+    /// Calls `emit` with the variable values and weight of every selected row of `r`, where the
+    /// weight is the number of results of the subtree below `r` that the row joins with. Rows with
+    /// a NULL in the `out` variables or those shared with children join with nothing. Weights past
+    /// `u64::MAX` saturate there, as do the counts summed from them.
+    fn scan(
+        &self,
+        r: usize,
+        out: u64,
+        children: &[(usize, u64, Rc<Message>)],
+        mut emit: impl FnMut(&[i32; 64], u64),
+    ) {
+        let needed = children
+            .iter()
+            .fold(out, |needed, (_, vars, _)| needed | vars);
+        let columns: Vec<(usize, Column<'a>)> = self.relations[r]
+            .iter()
+            .filter(|(var, _)| needed & 1 << var != 0)
+            .copied()
+            .collect();
+        let mut values = [0; 64];
+        'rows: for row in self.selections[r].iter() {
+            if cancel::poll() {
+                return;
+            }
+            for (var, column) in &columns {
+                match value(column, row) {
+                    Some(value) => values[*var] = value,
+                    None => continue 'rows,
+                }
+            }
+            let mut weight: u64 = 1;
+            for (_, vars, message) in children {
+                match message.get(&pack(*vars, &values)) {
+                    Some(count) => weight = weight.saturating_mul(*count),
+                    None => continue 'rows,
+                }
+            }
+            emit(&values, weight);
+        }
+    }
+
+    /// Counts a sub-join by enumerating its results.
+    fn enumerate(&self, mask: u32) -> u64 {
+        let members: Vec<usize> = members(mask).collect();
+        let mut edges: Vec<Edge<'a>> = Vec::new();
+        for (i, &r) in members.iter().enumerate() {
+            for (var, column) in &self.relations[r] {
+                // Join each column with the first earlier relation that has the same variable.
+                let earlier = members[..i].iter().enumerate().find_map(|(j, &s)| {
+                    self.relations[s]
+                        .iter()
+                        .find(|(v, _)| v == var)
+                        .map(|(_, other)| (j, *other))
+                });
+                if let Some((j, other)) = earlier {
+                    edges.push((j, other, i, *column));
+                }
+            }
+        }
+        let selections = members
+            .iter()
+            .map(|&r| self.selections[r].clone())
+            .collect();
+        let mut count = 0;
+        Join::new(selections, edges).for_each(|_| count += 1);
+        count
+    }
+}
+
+/// The relations in `mask`, in ascending order.
+fn members(mask: u32) -> impl Iterator<Item = usize> {
+    (0..32).filter(move |r| mask & 1 << r != 0)
+}
+
+/// Packs the values of up to four variables into a hash key.
+fn pack(vars: u64, values: &[i32; 64]) -> u128 {
+    let mut key = 0;
+    let mut vars = vars;
+    while vars != 0 {
+        let var = vars.trailing_zeros() as usize;
+        vars &= vars - 1;
+        key = key << 32 | values[var] as u32 as u128;
+    }
+    key
+}
+
+#[cfg(test)]
+mod test_subjoin {
+    use super::*;
+
+    #[test]
+    fn test_subjoin_counts_match_enumeration() {
+        // A small linear congruential generator, so the relations are the same on every run.
+        let mut state: u32 = 12345;
+        let mut next = |modulus: i32| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as i32 % modulus
+        };
+        let mut column = |len: usize| -> Vec<Option<i32>> {
+            (0..len)
+                .map(|_| match next(6) {
+                    0 => None,
+                    _ => Some(next(4)),
+                })
+                .collect()
+        };
+
+        // r0(a, c), r1(a, b), r2(b, c) form a triangle, r3(a) and r4(b, d), r5(d) hang off it.
+        let cols: Vec<Vec<Vec<Option<i32>>>> = [2, 2, 2, 1, 2, 1]
+            .iter()
+            .enumerate()
+            .map(|(r, &arity)| (0..arity).map(|_| column(10 + r)).collect())
+            .collect();
+        let schema: [&[usize]; 6] = [&[0, 2], &[0, 1], &[1, 2], &[0], &[1, 3], &[3]];
+        let relations: Vec<Vec<(usize, Column)>> = schema
+            .iter()
+            .zip(&cols)
+            .map(|(vars, cols)| {
+                vars.iter()
+                    .zip(cols)
+                    .map(|(&var, col)| (var, Column::from(col)))
+                    .collect()
+            })
+            .collect();
+        let selections: Vec<Selection> = cols
+            .iter()
+            .map(|cols| Selection::from_values(&cols[0], |v| *v != Some(3)))
+            .collect();
+
+        let mut counter = Counter::new(selections, relations, 4);
+        let counts = counter.all();
+        assert_eq!(counts.last().unwrap().0, 0b111111);
+        for (mask, count) in counts {
+            assert_eq!(count, counter.enumerate(mask), "{mask:b}");
+        }
+        assert!(counter.cached > 0);
+    }
+
+    #[test]
+    fn test_connected() {
+        let cols = [vec![1], vec![1], vec![1]];
+        let relations = vec![
+            vec![(0, Column::from(&cols[0]))],
+            vec![(0, Column::from(&cols[1])), (1, Column::from(&cols[1]))],
+            vec![(1, Column::from(&cols[2]))],
+        ];
+        let selections = vec![Selection::full(1); 3];
+        let counter = Counter::new(selections, relations, 2);
+        assert!(counter.connected(0b011));
+        assert!(counter.connected(0b111));
+        assert!(!counter.connected(0b101));
+    }
+}
@@ -0,0 +1,43 @@
+use crate::data::{Data, ImdbData};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Where the tests read the IMDB parquet files from, relative to the project root.
+const DIR: &str = "imdb";
+
+static DB: OnceLock<Option<ImdbData>> = OnceLock::new();
+static DATA: OnceLock<Data> = OnceLock::new();
+
+/// The IMDB tables, loaded once and shared by every test of the process, so that tests running
+/// in parallel do not each hold a copy. `None` if a parquet file is absent, after printing that
+/// the calling test is skipped; the test should then return early. Panics if the files are there
+/// but do not load.
+pub fn db() -> Option<&'static ImdbData> {
+    let db = DB.get_or_init(|| {
+        missing()
+            .is_none()
+            .then(|| ImdbData::load(DIR).unwrap_or_else(|e| panic!("{e}")))
+    });
+    if db.is_none() {
+        let test = std::thread::current();
+        eprintln!(
+            "skipping {}: {DIR}/{}.parquet is absent, see imdb/README",
+            test.name().unwrap_or("test"),
+            missing().unwrap_or_default()
+        );
+    }
+    db.as_ref()
+}
+
+/// The query-side `Data` of `db()`, built once.
+pub fn data() -> Option<&'static Data> {
+    let db = db()?;
+    Some(DATA.get_or_init(|| Data::new(db)))
+}
+
+/// The first table without a parquet file in `DIR`.
+fn missing() -> Option<&'static str> {
+    ImdbData::TABLES
+        .into_iter()
+        .find(|table| !Path::new(DIR).join(format!("{table}.parquet")).exists())
+}
@@ -0,0 +1,411 @@
+use crate::bench::{self, Format};
+use crate::cancel::{self, Outcome};
+use crate::data::Data;
+use crate::registry::{Entry, matches};
+use crate::stats::quantile;
+use crate::timer;
+use polars::prelude::*;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Seed of the query draws, fixed so that runs with the same mix draw the same sequences.
+const SEED: u64 = 0xd1b5_4a32_d192_ed03;
+
+/// Clock ticks per second of the CPU times in `/proc/self/stat`, `None` if the system does not
+/// say.
+fn ticks() -> Option<f64> {
+    // SAFETY: `sysconf` only reads a configuration value.
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    (ticks > 0).then_some(ticks as f64)
+}
+
+/// How the client threads pick their next query.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mix {
+    /// Every selected query equally often.
+    Uniform,
+    /// Query patterns, as `--query` takes them, with their relative weights. A query gets the
+    /// weight of the first pattern that selects it, and is left out if none does.
+    Weighted(Vec<(String, f64)>),
+}
+
+impl FromStr for Mix {
+    type Err = PolarsError;
+
+    /// `uniform`, or `<pattern>:<weight>,...` such as `17*:3,1a:1`.
+    fn from_str(s: &str) -> PolarsResult<Mix> {
+        if s == "uniform" {
+            return Ok(Mix::Uniform);
+        }
+        s.split(',')
+            .map(|part| {
+                let weight = part
+                    .split_once(':')
+                    .and_then(|(pattern, weight)| Some((pattern, weight.parse::<f64>().ok()?)));
+                match weight {
+                    Some((pattern, weight))
+                        if !pattern.is_empty() && weight > 0.0 && weight.is_finite() =>
+                    {
+                        Ok((pattern.to_string(), weight))
+                    }
+                    _ => polars_bail!(
+                        InvalidOperation: "bad mix {s}, expected uniform or <pattern>:<weight>,..."
+                    ),
+                }
+            })
+            .collect::<PolarsResult<_>>()
+            .map(Mix::Weighted)
+    }
+}
+
+impl Mix {
+    /// The weight of each of `queries`.
+    pub fn weights(&self, queries: &[&Entry]) -> Vec<f64> {
+        queries
+            .iter()
+            .map(|query| match self {
+                Mix::Uniform => 1.0,
+                Mix::Weighted(weights) => weights
+                    .iter()
+                    .find(|(pattern, _)| matches(pattern, query.id))
+                    .map_or(0.0, |(_, weight)| *weight),
+            })
+            .collect()
+    }
+}
+
+/// The configuration of `report`.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The most client threads to run at once.
+    pub threads: usize,
+    /// How long every step of the ladder issues queries.
+    pub duration: Duration,
+    pub mix: Mix,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            threads: 1,
+            duration: Duration::from_secs(10),
+            mix: Mix::Uniform,
+        }
+    }
+}
+
+/// 1, 2, 4 and so on client threads up to `max`, which is always included.
+pub fn thread_counts(max: usize) -> Vec<usize> {
+    let mut counts: Vec<usize> = std::iter::successors(Some(1usize), |n| n.checked_mul(2))
+        .take_while(|&n| n < max)
+        .collect();
+    counts.push(max);
+    counts
+}
+
+/// The CPU time and the minor page faults of this process, from `/proc/self/stat`. `None` where
+/// there is no such file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cpu {
+    pub user_seconds: f64,
+    pub system_seconds: f64,
+    pub minor_faults: u64,
+}
+
+impl Cpu {
+    pub fn read() -> Option<Cpu> {
+        Cpu::parse(&std::fs::read_to_string("/proc/self/stat").ok()?, ticks()?)
+    }
+
+    /// Parses `/proc/<pid>/stat`, whose second field, the command, is in parentheses and may
+    /// contain spaces, with CPU times in `ticks` per second.
+    fn parse(stat: &str, ticks: f64) -> Option<Cpu> {
+        // The fields after the command, starting with the third, `state`.
+        let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+        let field = |n: usize| fields.get(n - 3)?.parse::<u64>().ok();
+        Some(Cpu {
+            minor_faults: field(10)?,
+            user_seconds: field(14)? as f64 / ticks,
+            system_seconds: field(15)? as f64 / ticks,
+        })
+    }
+
+    fn since(self, start: Cpu) -> Cpu {
+        Cpu {
+            user_seconds: self.user_seconds - start.user_seconds,
+            system_seconds: self.system_seconds - start.system_seconds,
+            minor_faults: self.minor_faults - start.minor_faults,
+        }
+    }
+}
+
+/// What one step of the ladder measured.
+pub struct Step {
+    pub threads: usize,
+    /// From the start of the clients until the last one finished its last query.
+    pub seconds: f64,
+    /// The latencies of the queries that finished, per query, sorted.
+    pub latencies: Vec<Vec<f64>>,
+    /// The runs that failed or timed out, per query.
+    pub failed: Vec<usize>,
+    pub cpu: Option<Cpu>,
+}
+
+impl Step {
+    /// The sorted latencies of `query`, or of all queries.
+    fn latencies(&self, query: Option<usize>) -> Vec<f64> {
+        match query {
+            Some(query) => self.latencies[query].clone(),
+            None => {
+                let mut all = self.latencies.concat();
+                all.sort_by(f64::total_cmp);
+                all
+            }
+        }
+    }
+
+    /// The runs of `query`, or of all queries, that failed.
+    fn failed(&self, query: Option<usize>) -> usize {
+        match query {
+            Some(query) => self.failed[query],
+            None => self.failed.iter().sum(),
+        }
+    }
+
+    /// The queries that finished per second.
+    fn throughput(&self) -> f64 {
+        self.latencies.iter().map(Vec::len).sum::<usize>() as f64 / self.seconds
+    }
+}
+
+/// The index of the query that `draw`, uniform in `[0, 1)`, picks with `cumulative` weights.
+fn pick(cumulative: &[f64], draw: f64) -> usize {
+    let target = draw * cumulative[cumulative.len() - 1];
+    cumulative
+        .partition_point(|&weight| weight <= target)
+        .min(cumulative.len() - 1)
+}
+
+/// Runs `threads` clients that share `db` and run `queries`, drawn by `weights`, one after the
+/// other until `duration` has passed. Queries still running then are allowed to finish.
+pub fn step(
+    db: &Data,
+    queries: &[&Entry],
+    weights: &[f64],
+    threads: usize,
+    duration: Duration,
+) -> Step {
+    let cumulative: Vec<f64> = weights
+        .iter()
+        .scan(0.0, |sum, weight| {
+            *sum += weight;
+            Some(*sum)
+        })
+        .collect();
+    let cpu = Cpu::read();
+    let start = Instant::now();
+    let deadline = start + duration;
+    let runs: Vec<Vec<(usize, f64, bool)>> = std::thread::scope(|scope| {
+        let clients: Vec<_> = (0..threads)
+            .map(|client| {
+                let cumulative = &cumulative;
+                scope.spawn(move || {
+                    let mut state = SEED ^ (client as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                    let mut runs = Vec::new();
+                    while Instant::now() < deadline {
+                        // xorshift64
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        let query = pick(cumulative, (state >> 11) as f64 / (1u64 << 53) as f64);
+                        let start = Instant::now();
+                        let (outcome, _) =
+                            timer::capture(|| cancel::with_timeout(|| (queries[query].run)(db)));
+                        let finished = matches!(outcome, Ok(Outcome::Done(_)));
+                        runs.push((query, start.elapsed().as_secs_f64(), finished));
+                    }
+                    runs
+                })
+            })
+            .collect();
+        clients.into_iter().map(|c| c.join().unwrap()).collect()
+    });
+    let seconds = start.elapsed().as_secs_f64();
+    let cpu = cpu.zip(Cpu::read()).map(|(start, end)| end.since(start));
+
+    let mut latencies = vec![Vec::new(); queries.len()];
+    let mut failed = vec![0; queries.len()];
+    for (query, latency, finished) in runs.into_iter().flatten() {
+        if finished {
+            latencies[query].push(latency);
+        } else {
+            failed[query] += 1;
+        }
+    }
+    for latencies in &mut latencies {
+        latencies.sort_by(f64::total_cmp);
+    }
+    Step {
+        threads,
+        seconds,
+        latencies,
+        failed,
+        cpu,
+    }
+}
+
+/// Runs the mix with 1, 2, 4 and so on up to `options.threads` client threads, and writes
+/// `threads,query,completed,failed,qps,p50,p95,p99,max,slowdown,efficiency,cpu_utilization,
+/// user_seconds,system_seconds,minor_faults` rows to `out` in `format`, in seconds, per query
+/// that ran and for `all` of them.
+///
+/// `slowdown` is the median latency over the one with a single client. `efficiency` is the
+/// throughput over `threads` times the throughput with a single client. `cpu_utilization` is the
+/// CPU time of the process over the time of all cores. Efficiency that drops while the CPUs stay
+/// busy means the clients slow each other down, through shared memory bandwidth or caches; a
+/// growing share of system time and page faults points to the allocator, which maps and faults
+/// in memory for the hash tables of every client. Efficiency that drops with idle CPUs means the
+/// clients wait on each other.
+pub fn report(
+    db: &Data,
+    queries: &[&Entry],
+    options: &Options,
+    out: &mut impl Write,
+    format: Format,
+) -> PolarsResult<()> {
+    let weights = options.mix.weights(queries);
+    polars_ensure!(
+        weights.iter().any(|&weight| weight > 0.0),
+        InvalidOperation: "the mix selects none of the queries"
+    );
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let header = [
+        "threads",
+        "query",
+        "completed",
+        "failed",
+        "qps",
+        "p50",
+        "p95",
+        "p99",
+        "max",
+        "slowdown",
+        "efficiency",
+        "cpu_utilization",
+        "user_seconds",
+        "system_seconds",
+        "minor_faults",
+    ];
+    let mut rows = Vec::new();
+    let show = |x: Option<f64>| x.map_or(String::new(), |x| x.to_string());
+    let median = |latencies: &[f64]| (!latencies.is_empty()).then(|| quantile(latencies, 0.5));
+    let mut first: Option<Step> = None;
+    for threads in thread_counts(options.threads) {
+        let step = step(db, queries, &weights, threads, options.duration);
+        let ran = (0..queries.len())
+            .filter(|&i| !step.latencies[i].is_empty() || step.failed[i] > 0)
+            .map(Some);
+        let single = if threads == 1 {
+            Some(&step)
+        } else {
+            first.as_ref()
+        };
+        for query in ran.chain([None]) {
+            let latencies = step.latencies(query);
+            let percentile = |q| show((!latencies.is_empty()).then(|| quantile(&latencies, q)));
+            let slowdown = single
+                .and_then(|single| median(&single.latencies(query)))
+                .zip(median(&latencies))
+                .map(|(single, median)| median / single);
+            let mut row = vec![
+                threads.to_string(),
+                query.map_or("all", |i| queries[i].id).to_string(),
+                latencies.len().to_string(),
+                step.failed(query).to_string(),
+                (latencies.len() as f64 / step.seconds).to_string(),
+                percentile(0.5),
+                percentile(0.95),
+                percentile(0.99),
+                percentile(1.0),
+                show(slowdown),
+            ];
+            // The process-wide columns only on the `all` row.
+            let efficiency =
+                single.map(|single| step.throughput() / (threads as f64 * single.throughput()));
+            match step.cpu.filter(|_| query.is_none()) {
+                Some(cpu) => row.extend([
+                    show(efficiency),
+                    ((cpu.user_seconds + cpu.system_seconds) / (step.seconds * cores as f64))
+                        .to_string(),
+                    cpu.user_seconds.to_string(),
+                    cpu.system_seconds.to_string(),
+                    cpu.minor_faults.to_string(),
+                ]),
+                None if query.is_none() => {
+                    row.push(show(efficiency));
+                    row.extend(std::iter::repeat_n(String::new(), 4));
+                }
+                None => row.extend(std::iter::repeat_n(String::new(), 5)),
+            }
+            rows.push(row);
+        }
+        eprintln!(
+            "{threads} threads: {:.1} queries/s over {:.1}s",
+            step.throughput(),
+            step.seconds
+        );
+        if threads == 1 {
+            first = Some(step);
+        }
+    }
+    bench::write_rows(out, format, &header, &rows)
+}
+
+#[cfg(test)]
+mod test_throughput {
+    use super::*;
+    use crate::registry;
+
+    #[test]
+    fn test_mix() -> PolarsResult<()> {
+        let queries = registry::select(&["1".to_string(), "2a".to_string()])?;
+        assert_eq!(Mix::Uniform.weights(&queries), vec![1.0; queries.len()]);
+        let mix: Mix = "1a:3,1:1".parse()?;
+        let weights = mix.weights(&queries);
+        assert_eq!(&weights[..2], [3.0, 1.0]);
+        assert_eq!(weights.last(), Some(&0.0));
+        assert_eq!("uniform".parse::<Mix>()?, Mix::Uniform);
+        for invalid in ["", "1a", "1a:0", "1a:x", ":1", "1a:1,"] {
+            assert!(invalid.parse::<Mix>().is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_pick() {
+        let cumulative = [1.0, 1.0, 4.0];
+        assert_eq!(pick(&cumulative, 0.0), 0);
+        assert_eq!(pick(&cumulative, 0.24), 0);
+        assert_eq!(pick(&cumulative, 0.25), 2);
+        assert_eq!(pick(&cumulative, 0.999), 2);
+        assert_eq!(thread_counts(1), [1]);
+        assert_eq!(thread_counts(6), [1, 2, 4, 6]);
+        assert_eq!(thread_counts(8), [1, 2, 4, 8]);
+    }
+
+    #[test]
+    fn test_cpu() {
+        let stat = "42 (a (b) c) S 1 42 42 0 -1 4194560 1234 0 0 0 250 75 0 0 20 0 1 0 100";
+        assert_eq!(
+            Cpu::parse(stat, 100.0),
+            Some(Cpu {
+                user_seconds: 2.5,
+                system_seconds: 0.75,
+                minor_faults: 1234
+            })
+        );
+        assert_eq!(Cpu::parse("42 (a) S 1", 100.0), None);
+        assert!(ticks().is_some_and(|ticks| ticks > 0.0));
+    }
+}
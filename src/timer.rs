@@ -0,0 +1,213 @@
+use crate::explain;
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::iter::Sum;
+use std::ops::AddAssign;
+use std::time::{Duration, Instant};
+
+/// A phase of a hand-written query plan. Queries mark their phases with a `QueryTimer` by one
+/// policy:
+///
+/// - The timer starts with the query, in `Setup`: binding the tables and compiling the `LIKE`
+///   patterns and other constants, anything that does not read the data.
+/// - `Lookup` resolves the query's constants to ids in the dimension tables, such as the id of
+///   an `info_type` or the `kind_type` ids of a list of kinds.
+/// - `Build` builds the sets, maps and selections that the joins probe.
+/// - `Probe` runs the final join, usually over the largest relation, updating the `MIN`s as it
+///   goes.
+/// - `Aggregate` takes the `MIN`s, where a plan does so apart from the probe.
+///
+/// A query may go back to an earlier phase, whose times then add up. The driver measures every
+/// phase but `Setup`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Setup,
+    Lookup,
+    Build,
+    Probe,
+    Aggregate,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 5] = [
+        Phase::Setup,
+        Phase::Lookup,
+        Phase::Build,
+        Phase::Probe,
+        Phase::Aggregate,
+    ];
+
+    /// Whether the driver counts the phase in the time of a query.
+    pub fn is_measured(self) -> bool {
+        self != Phase::Setup
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Setup => "setup",
+            Phase::Lookup => "lookup",
+            Phase::Build => "build",
+            Phase::Probe => "probe",
+            Phase::Aggregate => "aggregate",
+        })
+    }
+}
+
+/// The time spent in each phase, indexed by `Phase as usize`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Phases(pub [Duration; Phase::ALL.len()]);
+
+impl Phases {
+    pub fn get(&self, phase: Phase) -> Duration {
+        self.0[phase as usize]
+    }
+
+    /// The time of the measured phases.
+    pub fn measured(&self) -> Duration {
+        Phase::ALL
+            .into_iter()
+            .filter(|phase| phase.is_measured())
+            .map(|phase| self.get(phase))
+            .sum()
+    }
+}
+
+impl AddAssign for Phases {
+    fn add_assign(&mut self, other: Phases) {
+        for (time, other) in self.0.iter_mut().zip(other.0) {
+            *time += other;
+        }
+    }
+}
+
+impl Sum for Phases {
+    fn sum<I: Iterator<Item = Phases>>(iter: I) -> Phases {
+        iter.fold(Phases::default(), |mut sum, phases| {
+            sum += phases;
+            sum
+        })
+    }
+}
+
+thread_local! {
+    /// The phases of the queries finished on this thread while `capture` runs.
+    static RECORDED: RefCell<Option<Vec<Phases>>> = const { RefCell::new(None) };
+}
+
+/// Times the phases of a query, and explains the query with a plan node per phase when
+/// explaining is on. A query that fails drops its timer unfinished, recording nothing.
+pub struct QueryTimer {
+    query: &'static str,
+    phase: Phase,
+    start: Instant,
+    phases: Phases,
+    explaining: bool,
+    /// Reports the query's plan when the timer is dropped.
+    _explain: explain::Query,
+}
+
+impl QueryTimer {
+    /// Starts timing the query `query`, in `Setup`.
+    pub fn start(query: &'static str) -> QueryTimer {
+        let explain = explain::query(query);
+        QueryTimer {
+            query,
+            phase: Phase::Setup,
+            start: Instant::now(),
+            phases: Phases::default(),
+            explaining: explain::open(Phase::Setup, None),
+            _explain: explain,
+        }
+    }
+
+    /// Ends the current phase and starts `phase`.
+    pub fn phase(&mut self, phase: Phase) {
+        self.end_phase();
+        if self.explaining {
+            explain::open(phase, None);
+        }
+        self.phase = phase;
+    }
+
+    /// Adds the time since the current phase started to it and ends its plan node.
+    fn end_phase(&mut self) {
+        let now = Instant::now();
+        self.phases.0[self.phase as usize] += now - self.start;
+        self.start = now;
+        if self.explaining {
+            explain::close(None);
+        }
+    }
+
+    /// Ends the current phase and records the phases. Under `capture` they are summed for the
+    /// caller; otherwise the measured time is printed as `query,seconds`.
+    pub fn finish(mut self) {
+        self.end_phase();
+        self.explaining = false;
+        let phases = self.phases;
+        let recorded = RECORDED.with_borrow_mut(|recorded| {
+            recorded
+                .as_mut()
+                .map(|recorded| recorded.push(phases))
+                .is_some()
+        });
+        if !recorded {
+            println!("{},{:}", self.query, phases.measured().as_secs_f32());
+        }
+    }
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        if self.explaining {
+            explain::close(None);
+        }
+    }
+}
+
+/// Calls `f` and returns its result with the summed phases of the queries it finished, instead
+/// of printing their times.
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Phases) {
+    let outer = RECORDED.replace(Some(Vec::new()));
+    let result = f();
+    let recorded = RECORDED.replace(outer).unwrap_or_default();
+    (result, recorded.into_iter().sum())
+}
+
+#[cfg(test)]
+mod test_timer {
+    use super::*;
+    use std::thread::sleep;
+
+    #[test]
+    fn test_phases() {
+        let ((), phases) = capture(|| {
+            let mut timer = QueryTimer::start("test");
+            sleep(Duration::from_millis(20));
+            timer.phase(Phase::Build);
+            sleep(Duration::from_millis(10));
+            timer.phase(Phase::Probe);
+            sleep(Duration::from_millis(10));
+            timer.phase(Phase::Build);
+            sleep(Duration::from_millis(10));
+            timer.finish();
+            // Not finished, so not recorded.
+            QueryTimer::start("failed").phase(Phase::Probe);
+        });
+        let millis = |phase| phases.get(phase).as_millis();
+        assert!(millis(Phase::Setup) >= 20);
+        assert!(millis(Phase::Build) >= 20, "{phases:?}");
+        assert!(millis(Phase::Probe) >= 10, "{phases:?}");
+        assert_eq!(phases.get(Phase::Lookup), Duration::ZERO);
+        assert_eq!(
+            phases.measured(),
+            phases.get(Phase::Build) + phases.get(Phase::Probe)
+        );
+
+        let twice: Phases = [phases, phases].into_iter().sum();
+        assert_eq!(twice.measured(), phases.measured() * 2);
+        assert_eq!(Phase::ALL.map(|phase| phase as usize), [0, 1, 2, 3, 4]);
+    }
+}